@@ -0,0 +1,145 @@
+use helpmate_tb::{
+    for_each_legal_position, get_estimate_nb_pos, EncoderDecoder, Material, TableFormat, TableName,
+};
+use log::{info, warn};
+
+use std::{collections::HashMap, fs::File, io, path::PathBuf, process::ExitCode, str::FromStr};
+
+use clap::{ArgAction, Args};
+
+use crate::error::{exit_code, missing_table, CliError, RunSummary};
+
+/// Check that the tables of a directory can be read by this version of the crate,
+/// and list the ones to regenerate or repack. Only block headers are read, nothing is decompressed
+#[derive(Args, Debug)]
+pub struct CheckCompat {
+    #[arg(default_value = "table/")]
+    tb_dir: PathBuf,
+    #[arg(
+        long,
+        help = "Compare the number of indexes of each table with the one expected from its legal positions, instead of only checking it is in the range of the indexer. Enumerates all positions of each material",
+        action = ArgAction::SetTrue
+    )]
+    deep: bool,
+}
+
+#[derive(Debug)]
+enum Compat {
+    Ok,
+    /// Readable, but stored under a legacy name
+    Repack {
+        new_name: String,
+    },
+    /// Not readable by this version of the crate
+    Regenerate {
+        reason: String,
+    },
+}
+
+impl CheckCompat {
+    /// Fails if any table needs to be regenerated
+    pub fn run(&self) -> ExitCode {
+        let mut entries: Vec<String> = match self.tb_dir.read_dir() {
+            Ok(entries) => entries
+                .filter_map(|entry_res| entry_res.ok()?.file_name().into_string().ok())
+                .collect(),
+            Err(e) => {
+                return exit_code(Err(CliError::from(e)));
+            }
+        };
+        entries.sort();
+        let (mut nb_ok, mut nb_repack, mut nb_regenerate) = (0, 0, 0);
+        let mut summary = RunSummary::default();
+        // shared by both winners of a material, only filled with `--deep`
+        let mut expected_lens: HashMap<Material, u64> = HashMap::new();
+        for file_name in entries {
+            let Ok(table_name) = TableName::from_str(&file_name) else {
+                info!("{file_name}: not a table, skipped");
+                continue;
+            };
+            match self.check_one_table(&file_name, &table_name, &mut expected_lens) {
+                Compat::Ok => {
+                    nb_ok += 1;
+                    summary.succeed();
+                }
+                Compat::Repack { new_name } => {
+                    warn!("{file_name}: legacy name, rename it to {new_name}");
+                    nb_repack += 1;
+                    summary.succeed();
+                }
+                Compat::Regenerate { reason } => {
+                    summary.fail(
+                        file_name,
+                        CliError::CorruptTable(format!("{reason}, regenerate it")),
+                    );
+                    nb_regenerate += 1;
+                }
+            }
+        }
+        info!("{nb_ok} compatible, {nb_repack} to repack, {nb_regenerate} to regenerate");
+        summary.finish()
+    }
+
+    fn check_one_table(
+        &self,
+        file_name: &str,
+        table_name: &TableName,
+        expected_lens: &mut HashMap<Material, u64>,
+    ) -> Compat {
+        let table_format = match self.table_format(file_name) {
+            Ok(table_format) => table_format,
+            Err(e) => {
+                return Compat::Regenerate {
+                    reason: format!("unreadable ({e})"),
+                }
+            }
+        };
+        let material = &table_name.mat_win.material;
+        // the number of indexes only changes along with the indexer
+        if self.deep {
+            let expected_len = *expected_lens
+                .entry(material.clone())
+                .or_insert_with(|| legal_len(material));
+            if table_format.len != expected_len {
+                return Compat::Regenerate {
+                    reason: format!(
+                        "{} indexes instead of {expected_len}, generated with another indexer",
+                        table_format.len
+                    ),
+                };
+            }
+        } else if table_format.len > get_estimate_nb_pos(material) as u64 {
+            return Compat::Regenerate {
+                reason: format!(
+                    "{} indexes, more than the indexer can produce, generated with another indexer",
+                    table_format.len
+                ),
+            };
+        }
+        info!(
+            "{file_name}: {:?}, {} block(s) {:?}",
+            table_name.metric,
+            table_format.blocks.len(),
+            table_format.blocks
+        );
+        let new_name = table_name.to_string();
+        if file_name == new_name {
+            Compat::Ok
+        } else {
+            Compat::Repack { new_name }
+        }
+    }
+
+    fn table_format(&self, file_name: &str) -> io::Result<TableFormat> {
+        EncoderDecoder::new(File::open(self.tb_dir.join(file_name)).map_err(missing_table)?)
+            .table_format()
+    }
+}
+
+/// Number of indexes of a table of `material` generated with the current indexer,
+/// trailing indexes of illegal positions being dropped
+fn legal_len(material: &Material) -> u64 {
+    let mut len = 0;
+    for_each_legal_position(material, |_, idx| len = len.max(idx.idx + 1));
+    len
+}
@@ -2,6 +2,7 @@
 //! Given a material config, it ouputs:
 //! - The maximum index for the config
 //! - All the positions which are the same modulo symetry, but yield different indexes
+//!
 //! Run with `cargo tb check-indexer`
 
 use std::{
@@ -15,13 +16,13 @@ use from_str_sequential::FromStrSequential;
 use itertools::Itertools;
 use log::{debug, info, warn};
 use retroboard::{
-    shakmaty::{Bitboard, Board, Chess, Color, Color::*, Position, Setup, Square},
+    shakmaty::{fen::Fen, Bitboard, Board, Chess, Color, Color::*, Position, Setup, Square},
     RetroBoard,
 };
 
 use helpmate_tb::{
-    to_chess_with_illegal_checks, Common, Descendants, Generator, IndexWithTurn, Indexer, Material,
-    MaterialWinner, NaiveIndexer, PosHandler, Table,
+    to_chess_with_illegal_checks, Common, CompactIndexer, Descendants, Generator, IndexWithTurn,
+    Indexer, Material, MaterialWinner, NaiveIndexer, PosHandler, Table,
 };
 
 type Transfo = (
@@ -58,7 +59,7 @@ const ALL_TRANSFO: [Transfo; 7] = [
 ];
 
 #[derive(Debug, Clone, Default)]
-struct CheckIndexerPosHandler {
+pub struct CheckIndexerPosHandler {
     // key is the canonical index, and the `Vec` contain all
     // key is only added when at least one duplicate is found
     duplicate_indexes: HashMap<usize, HashSet<usize>>,
@@ -98,6 +99,24 @@ impl<I: Indexer> PosHandler<I> for CheckIndexerPosHandler {
     }
 }
 
+impl CheckIndexerPosHandler {
+    pub fn log_result(&self, mat: &Material) {
+        if !self.duplicate_indexes.is_empty() {
+            warn!(
+                "For {:?}, Found {:?} duplicates",
+                mat,
+                self.duplicate_indexes.len()
+            );
+        }
+        info!("Max index is {:?}", self.max_index);
+        let naive = NaiveIndexer::from(mat.clone()).index_space(mat);
+        let compact = CompactIndexer::from(mat.clone()).index_space(mat);
+        #[allow(clippy::cast_precision_loss)]
+        let ratio = compact as f64 / naive as f64;
+        info!("Index space of naive indexer: {naive}, of compact indexer: {compact} ({ratio:.3} times)");
+    }
+}
+
 #[derive(Debug, Clone, FromStrSequential)]
 pub enum MatOrNbPieces {
     Mat(Material),
@@ -138,6 +157,7 @@ impl MatOrNbPieces {
 enum CliIndexer {
     Naive,
     Syzygy,
+    Compact,
 }
 
 /// Custom target that perform sanity checks and stats on the indexer
@@ -148,9 +168,10 @@ enum CliIndexer {
 pub struct CheckIndexer {
     #[arg(
         value_parser = MatOrNbPieces::from_str_sequential,
+        required_unless_present = "explain",
         help = "maximum number of pieces on the board, will check all pawnless material config up to this number included.\nOr just a particular material configuration"
     )]
-    mat_or_nb_pieces: MatOrNbPieces,
+    mat_or_nb_pieces: Option<MatOrNbPieces>,
 
     #[arg(short, long, action = clap::ArgAction::Count, default_value_t = 3)]
     verbose: u8,
@@ -158,10 +179,13 @@ pub struct CheckIndexer {
     tb_dir: PathBuf,
     #[arg(short, long, default_value = "naive", value_parser = CliIndexer::from_str_sequential)]
     indexer: CliIndexer,
+    /// Instead of checking materials, print every step of the naive indexer on this position
+    #[arg(long, value_name = "FEN", conflicts_with = "mat_or_nb_pieces")]
+    explain: Option<Fen>,
 }
 
 fn gen_all_pawnless_mat_up_to(nb_pieces: usize) -> Vec<Material> {
-    let iter_pieces = vec![
+    let iter_pieces = [
         Black.bishop(),
         Black.knight(),
         Black.rook(),
@@ -199,14 +223,7 @@ macro_rules! check_index {
             );
             gen.generate_positions();
             let (_, syzygy_res) = gen.get_result();
-            if !syzygy_res.duplicate_indexes.is_empty() {
-                warn!(
-                    "For {:?}, Found {:?} duplicates",
-                    mat,
-                    syzygy_res.duplicate_indexes.len()
-                );
-            }
-            info!("Max index is {:?}", syzygy_res.max_index);
+            syzygy_res.log_result(&mat);
         }
         }
     };
@@ -214,17 +231,29 @@ macro_rules! check_index {
 
 impl CheckIndexer {
     pub fn run(&self) {
-        let all_mats_config = self.mat_or_nb_pieces.materials();
+        if let Some(fen) = &self.explain {
+            let board = fen.as_setup().board.clone();
+            let indexer = NaiveIndexer::from(Material::from_board(&board));
+            info!("Index of {fen}:\n{}", indexer.explain(&board));
+            return;
+        }
+        let all_mats_config = self
+            .mat_or_nb_pieces
+            .as_ref()
+            .expect("required without --explain")
+            .materials();
         all_mats_config
             .into_iter()
             .for_each(|mat| match self.indexer {
                 CliIndexer::Naive => self.check_mat_naive(mat),
                 CliIndexer::Syzygy => self.check_mat_syzygy(mat),
+                CliIndexer::Compact => self.check_mat_compact(mat),
             })
     }
 
     check_index! {NaiveIndexer, "naive"}
     check_index! {Table, "syzygy"}
+    check_index! {CompactIndexer, "compact"}
 }
 
 fn transformed_chess(chess: &Chess, transfo: Transfo) -> Chess {
@@ -0,0 +1,38 @@
+use std::io::{self, Write};
+
+use clap::{Args, CommandFactory};
+use clap_complete::Shell;
+use clap_mangen::Man;
+
+use crate::Cli;
+
+/// Print shell completions or the man page of the CLI on stdout
+///
+/// Example: `helpmate_tb_cli completions bash > /etc/bash_completion.d/helpmate_tb_cli`
+#[derive(Args, Debug)]
+pub struct Completions {
+    #[arg(required_unless_present = "man")]
+    shell: Option<Shell>,
+    #[arg(
+        long,
+        conflicts_with = "shell",
+        help = "Print the man page, in roff format, instead of completions"
+    )]
+    man: bool,
+}
+
+impl Completions {
+    pub fn run(&self) -> io::Result<()> {
+        let mut cmd = Cli::command();
+        // `clap_complete` panics on write errors, so render in memory first
+        let mut buf = Vec::new();
+        if self.man {
+            Man::new(cmd).render(&mut buf)?;
+        } else {
+            let shell = self.shell.expect("Shell required unless `--man`");
+            let bin_name = cmd.get_name().to_string();
+            clap_complete::generate(shell, &mut cmd, bin_name, &mut buf);
+        }
+        io::stdout().write_all(&buf)
+    }
+}
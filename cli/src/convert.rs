@@ -1,14 +1,10 @@
 use from_str_sequential::FromStrSequential;
-pub use helpmate_tb::{
-    Common, EncoderDecoder, Material, MaterialWinner, Outcome, SideToMoveGetter, TableBaseBuilder,
-    UNDEFINED_OUTCOME_BYCOLOR,
-};
 
-use std::path::PathBuf;
+use std::{path::PathBuf, process::ExitCode};
 
 use clap::Args;
 
-use crate::explore::MatOrAll;
+use crate::{error::exit_code, explore::MatOrAll};
 
 /// Convert back and forth helpmate files using the naive indexer and syzygy indexer
 #[derive(Args, Debug)]
@@ -22,7 +18,12 @@ pub struct Convert {
 }
 
 impl Convert {
-    pub fn run(&self) {
-        for _mat_win in self.mat_or_all.mat_winners(&self.tb_dir, None) {}
+    pub fn run(&self) -> ExitCode {
+        let mat_winners = match self.mat_or_all.mat_winners(&self.tb_dir, None) {
+            Ok(mat_winners) => mat_winners,
+            Err(e) => return exit_code(Err(e)),
+        };
+        for _mat_win in mat_winners {}
+        ExitCode::SUCCESS
     }
 }
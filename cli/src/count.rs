@@ -0,0 +1,131 @@
+use from_str_sequential::FromStrSequential;
+use helpmate_tb::{
+    for_each_legal_position, iter_defined, FileHandler, IndexWithTurn, MaterialWinner, Outcome,
+    ResourceLimits, TableName,
+};
+use log::{error, info};
+
+use std::{collections::BTreeMap, path::PathBuf, process::ExitCode};
+
+use retroboard::shakmaty::{ByColor, Color};
+
+use clap::{ArgAction, Args};
+
+use crate::error::{exit_code, missing_table, CliError, RunSummary};
+use crate::explore::MatOrAll;
+
+/// Count the positions defined in helpmate files, in total, per outcome and per side to move
+#[derive(Args, Debug)]
+pub struct Count {
+    #[arg(help = "example \"KQvK\", use special value 'all' to count all tables", value_parser = MatOrAll::from_str_sequential)]
+    mat_or_all: MatOrAll,
+    #[arg(
+        short,
+        long,
+        help = "Color of the expected winner. If no color is provided, will count both"
+    )]
+    winner: Option<Color>,
+    #[arg(long, default_value = "table/")]
+    tb_dir: PathBuf,
+    /// Also count the legal positions by enumerating them forward, without the tables,
+    /// and fail if both counts differ
+    #[arg(long, action = ArgAction::SetTrue)]
+    recount: bool,
+}
+
+impl Count {
+    pub fn run(&self) -> ExitCode {
+        let mat_winners = match self.mat_or_all.mat_winners(&self.tb_dir, self.winner) {
+            Ok(mat_winners) => mat_winners,
+            Err(e) => return exit_code(Err(e)),
+        };
+        let mut summary = RunSummary::default();
+        for mat_win in mat_winners {
+            summary.record(format!("{mat_win:?}"), self.count_one_mat(&mat_win));
+        }
+        summary.finish()
+    }
+
+    /// Fails as well if the recount, if any, does not match
+    fn count_one_mat(&self, mat_win: &MaterialWinner) -> Result<(), CliError> {
+        let file_handler: FileHandler = FileHandler::try_new_with_limits(
+            &TableName::helpmate(mat_win),
+            &self.tb_dir,
+            &ResourceLimits::default(),
+        )
+        .map_err(missing_table)?;
+        let mut defined: ByColor<u64> = ByColor::default();
+        let mut by_outcome: BTreeMap<Outcome, ByColor<u64>> = BTreeMap::new();
+        for (IndexWithTurn { turn, .. }, outcome) in iter_defined(&file_handler.outcomes, 0) {
+            *defined.get_mut(turn) += 1;
+            *by_outcome.entry(outcome).or_default().get_mut(turn) += 1;
+        }
+        info!(
+            "{:?} with winner: {}, {} positions, white to move: {}, black to move: {}",
+            mat_win.material,
+            mat_win.winner,
+            defined.white + defined.black,
+            defined.white,
+            defined.black
+        );
+        // best outcomes first
+        for (outcome, nb) in by_outcome.iter().rev() {
+            info!(
+                "{outcome:?}: {}, white to move: {}, black to move: {}",
+                nb.white + nb.black,
+                nb.white,
+                nb.black
+            );
+        }
+        if !self.recount || Self::recount(mat_win, &file_handler, defined) {
+            Ok(())
+        } else {
+            Err(CliError::CorruptTable(
+                "recount does not match the table".to_string(),
+            ))
+        }
+    }
+
+    /// Positions visited more than once by the enumeration are only counted once
+    fn recount(
+        mat_win: &MaterialWinner,
+        file_handler: &FileHandler,
+        defined: ByColor<u64>,
+    ) -> bool {
+        let mut visited: Vec<ByColor<bool>> = vec![ByColor::default(); file_handler.outcomes.len()];
+        let mut recounted: ByColor<u64> = ByColor::default();
+        let mut nb_undefined_in_table: u64 = 0;
+        let mut nb_out_of_table: u64 = 0;
+        for_each_legal_position(&mat_win.material, |_, idx_with_turn| {
+            let outcome = match file_handler.outcome_at(idx_with_turn) {
+                Ok(outcome) => outcome,
+                Err(e) => {
+                    // only the first one, the others most probably share the cause
+                    if nb_out_of_table == 0 {
+                        error!("{e}");
+                    }
+                    nb_out_of_table += 1;
+                    return;
+                }
+            };
+            let seen = visited[idx_with_turn.usize()].get_mut(idx_with_turn.turn);
+            if !*seen {
+                *seen = true;
+                *recounted.get_mut(idx_with_turn.turn) += 1;
+                if outcome == Outcome::Undefined {
+                    nb_undefined_in_table += 1;
+                }
+            }
+        });
+        if recounted == defined && nb_undefined_in_table == 0 && nb_out_of_table == 0 {
+            info!("Recount matches");
+            true
+        } else {
+            error!(
+                "Recount mismatch for {:?} with winner: {}, enumerated white to move: {}, black to move: {}, of which {nb_undefined_in_table} are undefined in the table, and {nb_out_of_table} more out of it",
+                mat_win.material, mat_win.winner, recounted.white, recounted.black
+            );
+            false
+        }
+    }
+}
@@ -1,20 +1,28 @@
-pub use helpmate_tb::{
-    Common, EncoderDecoder, Material, MaterialWinner, Outcome, SideToMoveGetter, TableBaseBuilder,
-    UNDEFINED_OUTCOME_BYCOLOR,
+use helpmate_tb::{
+    DeIndexer, FileHandler, IndexWithTurn, Material, MaterialWinner, ResourceLimits, TableName,
+    TablebaseProber,
 };
-use helpmate_tb::{DeIndexer, FileHandler, IndexWithTurn};
 use log::{debug, error, info, warn};
-use std::{path::PathBuf, str::FromStr};
+use std::{
+    collections::HashSet,
+    io,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    str::FromStr,
+};
 
-use retroboard::shakmaty::Color;
+use retroboard::shakmaty::{fen::Fen, Board, Chess, Color, EnPassantMode, Position};
 
 use clap::Args;
 
+use crate::error::{missing_table, RunSummary};
+use crate::probe::from_fen;
+
 /// Compare two different helpmate files and ouput positions where the outcome differs
 #[derive(Args, Debug)]
 pub struct Diff {
-    #[arg(help = "example \"KQvK\"", value_parser = Material::from_str)]
-    material: Material,
+    #[arg(help = "example \"KQvK\"", value_parser = Material::from_str, required_unless_present = "root_fen", conflicts_with = "root_fen")]
+    material: Option<Material>,
     #[arg(long, default_value = "old_table/")]
     old_tb_dir: PathBuf,
     #[arg(long, default_value = "table/")]
@@ -32,24 +40,109 @@ pub struct Diff {
         help = "Max number of differences to look for"
     )]
     number: usize,
+    #[arg(
+        long,
+        value_parser = from_fen,
+        help = "Only compare the positions reachable from this one within --depth plies, probed in both tablebase directories, captures and promotions included. Their FEN is reported, instead of their index"
+    )]
+    root_fen: Option<Chess>,
+    #[arg(
+        long,
+        default_value_t = 2,
+        requires = "root_fen",
+        help = "Number of plies played from --root-fen"
+    )]
+    depth: usize,
 }
 
 impl Diff {
-    pub fn run(&self) {
-        for winner in self
-            .winner
+    pub fn run(&self) -> ExitCode {
+        if let Some(root) = &self.root_fen {
+            return self.run_from_root(root);
+        }
+        let material = self
+            .material
+            .as_ref()
+            .expect("material required without --root-fen");
+        let mut summary = RunSummary::default();
+        for winner in self.winners() {
+            info!("Diff-ing {material:?} with winner: {winner}");
+            let mat_win = MaterialWinner::new(material, winner);
+            let file_handlers = load(&mat_win, &self.old_tb_dir)
+                .and_then(|old| Ok((old, load(&mat_win, &self.tb_dir)?)));
+            if let Some((old_file_handler, file_handler)) =
+                summary.record(format!("{mat_win:?}"), file_handlers)
+            {
+                if let Err(e) = self.diff(material, &old_file_handler, &file_handler) {
+                    summary.fail(format!("{mat_win:?}"), e);
+                }
+            }
+        }
+        summary.finish()
+    }
+
+    fn winners(&self) -> Vec<Color> {
+        self.winner
             .map(|w| vec![w])
             .unwrap_or_else(|| Color::ALL.into())
-        {
-            info!("Diff-ing {:?} with winner: {winner}", self.material);
-            let mat_win = MaterialWinner::new(&self.material, winner);
-            let old_file_handler: FileHandler = FileHandler::new(&mat_win, &self.old_tb_dir);
-            let file_handler: FileHandler = FileHandler::new(&mat_win, &self.tb_dir);
-            self.diff(old_file_handler, file_handler);
+    }
+
+    /// Both tablebases must have the tables of the root material and all its descendants
+    fn run_from_root(&self, root: &Chess) -> ExitCode {
+        let mut summary = RunSummary::default();
+        let material = Material::from_board(root.board());
+        let probers: io::Result<(TablebaseProber, TablebaseProber)> =
+            TablebaseProber::try_new(&material, &self.old_tb_dir)
+                .and_then(|old| Ok((old, TablebaseProber::try_new(&material, &self.tb_dir)?)))
+                .map_err(missing_table);
+        let Some((old_prober, prober)) = summary.record(format!("{material:?}"), probers) else {
+            return summary.finish();
+        };
+        let positions = reachable_positions(root, self.depth);
+        info!(
+            "{} positions reachable within {} plies",
+            positions.len(),
+            self.depth
+        );
+        for winner in self.winners() {
+            let mut old_better = 0;
+            let mut new_better = 0;
+            for chess in &positions {
+                let fen = Fen::from_position(chess.clone(), EnPassantMode::Legal);
+                let outcomes = old_prober
+                    .probe_outcome(chess, winner)
+                    .and_then(|old| Ok((old, prober.probe_outcome(chess, winner)?)));
+                // only failures are recorded, not to count each position as a success
+                let (old_outcome, outcome) = match outcomes {
+                    Ok(outcomes) => outcomes,
+                    Err(e) => {
+                        summary.fail(format!("{fen} with winner {winner}"), e);
+                        continue;
+                    }
+                };
+                if old_outcome != outcome {
+                    old_better += usize::from(old_outcome > outcome);
+                    new_better += usize::from(old_outcome < outcome);
+                    info!("{fen} with winner {winner}, outcome differs: old {old_outcome:?}, new {outcome:?}");
+                }
+                if self.number <= old_better + new_better {
+                    break;
+                }
+            }
+            warn!(
+                "Found {} differences with winner {winner}\nOld is better: {old_better} cases New is better: {new_better}",
+                old_better + new_better,
+            );
         }
+        summary.finish()
     }
 
-    fn diff(&self, old_file_handler: FileHandler, file_handler: FileHandler) {
+    fn diff(
+        &self,
+        material: &Material,
+        old_file_handler: &FileHandler,
+        file_handler: &FileHandler,
+    ) -> io::Result<()> {
         let mut old_better = 0;
         let mut new_better = 0;
         if old_file_handler.outcomes.len() != file_handler.outcomes.len() {
@@ -59,27 +152,21 @@ impl Diff {
                 file_handler.outcomes.len()
             )
         }
-        for (idx, (old_outcome_bc, outcome_bc)) in old_file_handler
+        // indexes past the end of the shorter table are not compared
+        let len = old_file_handler
             .outcomes
-            .iter()
-            .zip(file_handler.outcomes)
-            .enumerate()
-        {
+            .len()
+            .min(file_handler.outcomes.len());
+        for idx in 0..len as u64 {
             for turn in Color::ALL {
-                // could be faster to look at the OutcomeU8
-                let old_outcome = old_outcome_bc.get_outcome_by_color(turn);
-                let outcome = outcome_bc.get_outcome_by_color(turn);
+                let idx_with_turn = IndexWithTurn { idx, turn };
+                let old_outcome = old_file_handler.outcome_at(idx_with_turn)?;
+                let outcome = file_handler.outcome_at(idx_with_turn)?;
                 if old_outcome != outcome {
                     old_better += usize::from(old_outcome > outcome);
                     new_better += usize::from(old_outcome < outcome);
 
-                    let pos = file_handler.indexer.restore(
-                        &self.material,
-                        IndexWithTurn {
-                            idx: idx as u64,
-                            turn,
-                        },
-                    );
+                    let pos = file_handler.indexer.restore(material, idx_with_turn);
                     debug!("idx: {idx}, Outcome differs: old {old_outcome:?}, new {outcome:?}");
                     debug!("pos: {pos:?}");
                 }
@@ -92,5 +179,37 @@ impl Diff {
             "Found {} differences\nOld is better: {old_better} cases New is better: {new_better}",
             old_better + new_better,
         );
+        Ok(())
     }
 }
+
+/// `root` included, each position once whatever the line leading to it.
+/// Game over positions are kept, but not played from
+fn reachable_positions(root: &Chess, depth: usize) -> Vec<Chess> {
+    let mut seen: HashSet<(Board, Color)> = HashSet::from([(root.board().clone(), root.turn())]);
+    let mut positions = vec![root.clone()];
+    let mut frontier = 0;
+    for _ in 0..depth {
+        let next = positions.len();
+        for i in frontier..next {
+            for m in positions[i].legal_moves() {
+                let mut after = positions[i].clone();
+                after.play_unchecked(&m);
+                if seen.insert((after.board().clone(), after.turn())) {
+                    positions.push(after);
+                }
+            }
+        }
+        frontier = next;
+    }
+    positions
+}
+
+fn load(mat_win: &MaterialWinner, tb_dir: &Path) -> io::Result<FileHandler> {
+    FileHandler::try_new_with_limits(
+        &TableName::helpmate(mat_win),
+        tb_dir,
+        &ResourceLimits::default(),
+    )
+    .map_err(missing_table)
+}
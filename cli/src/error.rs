@@ -0,0 +1,245 @@
+use helpmate_tb::{ResourceLimitExceeded, TableFormatError};
+use log::{error, info};
+
+use std::{fmt, io, process::ExitCode};
+
+/// Why a subcommand failed. Each kind has its own exit code, so that wrapping scripts can branch on it
+#[derive(Debug)]
+pub enum CliError {
+    /// Invalid arguments or options, exit code 2 like the usage errors reported by clap
+    Config(String),
+    /// Table not found, exit code 3
+    MissingTable(String),
+    /// Table found, but malformed or with wrong outcomes, exit code 4
+    CorruptTable(String),
+    /// Anything else, panics included, exit code 10
+    Internal(String),
+    /// Stopped by Ctrl-C before completion, exit code 130 like shells report processes killed by SIGINT
+    Interrupted(String),
+}
+
+impl CliError {
+    pub const INTERNAL_EXIT_CODE: u8 = 10;
+    pub const INTERRUPTED_EXIT_CODE: u8 = 130;
+
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Self::Config(_) => 2,
+            Self::MissingTable(_) => 3,
+            Self::CorruptTable(_) => 4,
+            Self::Internal(_) => Self::INTERNAL_EXIT_CODE,
+            Self::Interrupted(_) => Self::INTERRUPTED_EXIT_CODE,
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            Self::Config(_) => "configuration error",
+            Self::MissingTable(_) => "missing table",
+            Self::CorruptTable(_) => "corrupt table",
+            Self::Internal(_) => "internal error",
+            Self::Interrupted(_) => "interrupted",
+        }
+    }
+}
+
+/// Table not found, see `missing_table`
+#[derive(Debug)]
+struct TableNotFound(io::Error);
+
+impl fmt::Display for TableNotFound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::error::Error for TableNotFound {}
+
+/// To be called on the errors of opening tables, so that a `io::ErrorKind::NotFound` one is reported as
+/// `CliError::MissingTable`. Other files not found, like an output directory, are internal errors
+pub fn missing_table(err: io::Error) -> io::Error {
+    if err.kind() == io::ErrorKind::NotFound {
+        io::Error::new(io::ErrorKind::NotFound, TableNotFound(err))
+    } else {
+        err
+    }
+}
+
+impl From<io::Error> for CliError {
+    fn from(err: io::Error) -> Self {
+        let msg = err.to_string();
+        if TableFormatError::of(&err).is_some() {
+            return Self::CorruptTable(msg);
+        }
+        // the limit set by `--max-ram`
+        if ResourceLimitExceeded::of(&err).is_some() {
+            return Self::Config(msg);
+        }
+        if err
+            .get_ref()
+            .is_some_and(|inner| inner.is::<TableNotFound>())
+        {
+            return Self::MissingTable(msg);
+        }
+        match err.kind() {
+            io::ErrorKind::InvalidData => Self::CorruptTable(msg),
+            io::ErrorKind::InvalidInput => Self::Config(msg),
+            io::ErrorKind::Interrupted => Self::Interrupted(msg),
+            _ => Self::Internal(msg),
+        }
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Config(msg)
+            | Self::MissingTable(msg)
+            | Self::CorruptTable(msg)
+            | Self::Internal(msg)
+            | Self::Interrupted(msg) => write!(f, "{}: {msg}", self.kind()),
+        }
+    }
+}
+
+/// Outcome of a subcommand run on several items, eg tables, logged at the end by `RunSummary::finish`
+#[derive(Debug, Default)]
+pub struct RunSummary {
+    nb_succeeded: usize,
+    failures: Vec<(String, CliError)>,
+}
+
+impl RunSummary {
+    pub fn succeed(&mut self) {
+        self.nb_succeeded += 1;
+    }
+
+    /// Logs the failure of `item` as soon as it happens
+    pub fn fail(&mut self, item: impl fmt::Display, err: impl Into<CliError>) {
+        let err = err.into();
+        error!("{item}: {err}");
+        self.failures.push((item.to_string(), err));
+    }
+
+    /// The value of `res` if it succeeded, see `RunSummary::fail` otherwise
+    pub fn record<T, E: Into<CliError>>(
+        &mut self,
+        item: impl fmt::Display,
+        res: Result<T, E>,
+    ) -> Option<T> {
+        match res {
+            Ok(t) => {
+                self.succeed();
+                Some(t)
+            }
+            Err(err) => {
+                self.fail(item, err);
+                None
+            }
+        }
+    }
+
+    pub fn has_failed(&self) -> bool {
+        !self.failures.is_empty()
+    }
+
+    /// Logs the number of items succeeded and failed, by kind of failure.
+    /// The exit code is the highest one of the failures, the most severe
+    pub fn finish(self) -> ExitCode {
+        if self.failures.is_empty() {
+            if self.nb_succeeded > 1 {
+                info!("All {} succeeded", self.nb_succeeded);
+            }
+            return ExitCode::SUCCESS;
+        }
+        let mut by_kind: Vec<(&'static str, usize)> = Vec::new();
+        for (_, err) in &self.failures {
+            match by_kind.iter_mut().find(|(kind, _)| *kind == err.kind()) {
+                Some((_, nb)) => *nb += 1,
+                None => by_kind.push((err.kind(), 1)),
+            }
+        }
+        let by_kind: Vec<String> = by_kind
+            .into_iter()
+            .map(|(kind, nb)| format!("{nb} {kind}"))
+            .collect();
+        error!(
+            "{} succeeded, {} failed ({}): {}",
+            self.nb_succeeded,
+            self.failures.len(),
+            by_kind.join(", "),
+            self.failures
+                .iter()
+                .map(|(item, _)| item.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        let exit_code = self
+            .failures
+            .iter()
+            .map(|(_, err)| err.exit_code())
+            .max()
+            .unwrap_or(CliError::INTERNAL_EXIT_CODE);
+        ExitCode::from(exit_code)
+    }
+}
+
+/// Exit code of a subcommand run on a single item, logging the error if any
+pub fn exit_code(res: Result<(), CliError>) -> ExitCode {
+    match res {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            error!("{err}");
+            ExitCode::from(err.exit_code())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cli_error_from_io_error() {
+        let exit_code_of = |err: io::Error| CliError::from(err).exit_code();
+        assert_eq!(exit_code_of(io::ErrorKind::InvalidInput.into()), 2);
+        assert_eq!(
+            exit_code_of(missing_table(io::ErrorKind::NotFound.into())),
+            3
+        );
+        // eg an output directory
+        assert_eq!(exit_code_of(io::ErrorKind::NotFound.into()), 10);
+        assert_eq!(
+            exit_code_of(missing_table(io::ErrorKind::InvalidInput.into())),
+            2
+        );
+        assert_eq!(
+            exit_code_of(TableFormatError::TruncatedHeader { byte_offset: 0 }.into()),
+            4
+        );
+        assert_eq!(exit_code_of(io::ErrorKind::OutOfMemory.into()), 10);
+        assert_eq!(
+            exit_code_of(
+                ResourceLimitExceeded {
+                    what: "KQvK".to_string(),
+                    bytes: 2,
+                    max_ram_bytes: 1,
+                }
+                .into()
+            ),
+            2
+        );
+        assert_eq!(exit_code_of(io::ErrorKind::Interrupted.into()), 130);
+    }
+
+    #[test]
+    fn test_run_summary() {
+        let mut summary = RunSummary::default();
+        assert_eq!(summary.record("a", Ok::<_, CliError>(1)), Some(1));
+        assert!(!summary.has_failed());
+        summary.fail("b", CliError::MissingTable("b".to_string()));
+        summary.fail("c", CliError::CorruptTable("c".to_string()));
+        assert!(summary.has_failed());
+        assert_eq!(summary.finish(), ExitCode::from(4));
+    }
+}
@@ -1,22 +1,29 @@
 use from_str_sequential::FromStrSequential;
-pub use helpmate_tb::{
-    Common, EncoderDecoder, Material, MaterialWinner, Outcome, SideToMoveGetter, TableBaseBuilder,
-    UNDEFINED_OUTCOME_BYCOLOR,
+use helpmate_tb::{
+    anomalous_squares, joint_stats, stats_by_white_king_square, zugzwangs, Coverage, DeIndexer,
+    DefaultIndexer, EncoderDecoder, FileHandler, FortressTable, IndexWithTurn, Indexer, Material,
+    MaterialWinner, Outcome, OutcomeCounts, OutcomeDistrib, Outcomes, ResourceLimits,
+    SideToMoveGetter, StatsAccumulator, TableName, TablePath, UndefinedSpans, Wdl, WdlOutcomes,
+    WinObjective, ZugzwangKind,
 };
-use helpmate_tb::{DeIndexer, DefaultIndexer, FileHandler, IndexWithTurn, Indexer};
-use log::{debug, info};
-use rustc_hash::FxHashMap;
+use log::{debug, error, info, warn};
+use rayon::prelude::*;
 use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
     path::{Path, PathBuf},
+    process::ExitCode,
     str::FromStr,
 };
 
 use retroboard::{
-    shakmaty::{ByColor, Color},
+    shakmaty::{fen::Fen, ByColor, Chess, Color, EnPassantMode},
     RetroBoard,
 };
 
-use clap::{ArgAction, Args};
+use clap::{ArgAction, ArgGroup, Args};
+
+use crate::error::{exit_code, missing_table, CliError, RunSummary};
 
 #[derive(Debug, Clone, FromStrSequential)]
 pub enum MatOrAll {
@@ -25,27 +32,29 @@ pub enum MatOrAll {
 }
 
 impl MatOrAll {
-    pub fn mat_winners(&self, tb_dir: &Path, winner: Option<Color>) -> Vec<MaterialWinner> {
+    /// Fails with `CliError::Config` if `tb_dir` cannot be read, when all its tables are selected
+    pub fn mat_winners(
+        &self,
+        tb_dir: &Path,
+        winner: Option<Color>,
+    ) -> Result<Vec<MaterialWinner>, CliError> {
         match self {
-            MatOrAll::All => {
-                let entries = tb_dir.read_dir().expect("read_dir call failed");
-                entries
-                    .map(|entry_res| {
-                        let mat_win_str = entry_res.unwrap().file_name().into_string().unwrap();
-                        MaterialWinner::from_str(&mat_win_str).expect("invalid file name")
-                    })
-                    .collect()
-            }
-            MatOrAll::Mat(mat) => winner
+            MatOrAll::All => Ok(scan_tb_dir(tb_dir)?.mat_winners()),
+            MatOrAll::Mat(mat) => Ok(winner
                 .map(|w| vec![w])
                 .unwrap_or_else(|| Color::ALL.into())
                 .into_iter()
                 .map(|w| MaterialWinner::new(mat, w))
-                .collect(),
+                .collect()),
         }
     }
 }
 
+/// `Coverage::scan`, a `--tb-dir` that cannot be read being a `CliError::Config`
+fn scan_tb_dir(tb_dir: &Path) -> Result<Coverage, CliError> {
+    Coverage::scan(tb_dir).map_err(|e| CliError::Config(format!("{}: {e}", tb_dir.display())))
+}
+
 #[derive(Debug, Clone)]
 pub enum Query {
     Outcome(Outcome),
@@ -66,6 +75,7 @@ impl FromStr for Query {
 
 /// Return statistics of selected helpmate files
 #[derive(Args, Debug)]
+#[command(group(ArgGroup::new("dumped").args(["query", "zugzwang"])))]
 pub struct Explore {
     #[arg(help = "example \"KQvK\", use special value 'all' to search across all positions", value_parser = MatOrAll::from_str_sequential)]
     material: MatOrAll,
@@ -84,18 +94,107 @@ pub struct Explore {
     exclude_summary: bool,
     #[arg(long, default_value = "table/")]
     tb_dir: PathBuf,
+    /// Only compute win/draw/lose summary, loading tables with half the memory
+    #[arg(long, action = ArgAction::SetTrue, conflicts_with = "query")]
+    wdl_only: bool,
+    /// Directory of the fortress tables written by `generate --fortress-dir`.
+    /// Summarise the kind of draws, and the one of the queried position if any
+    #[arg(long, conflicts_with = "wdl_only")]
+    fortress_dir: Option<PathBuf>,
+    /// Write the positions matching `--query`, or found by `--zugzwang`, to this file instead of logging them,
+    /// one `FEN; outcome; index` line by position
+    #[arg(long, requires = "dumped", conflicts_with = "wdl_only")]
+    dump_fens: Option<PathBuf>,
+    /// Maximum number of positions written by `--dump-fens`, across all tables
+    #[arg(long, requires = "dump_fens")]
+    limit: Option<usize>,
+    /// Win/draw/lose counts for each square of the canonical white king,
+    /// warning about squares with an unusual density of undefined indexes
+    #[arg(long, conflicts_with = "wdl_only")]
+    by_king_square: bool,
+    /// Instead of looking at each table separately, pair the outcomes of the tables of both winners,
+    /// eg to count positions where both white and black can be helped to mate
+    #[arg(long, conflicts_with_all = ["winner", "query", "wdl_only", "fortress_dir", "by_king_square"])]
+    joint: bool,
+    /// Only list the tables present in `--tb-dir`, with their number of pieces and whether they are complete.
+    /// With a material, also list the tables missing to probe it
+    #[arg(long, conflicts_with_all = ["winner", "query", "wdl_only", "fortress_dir", "by_king_square", "joint"])]
+    coverage: bool,
+    /// Only show the compressed and uncompressed sizes of each block of the tables, read from their headers,
+    /// to compare codecs and pre-filters on real tables
+    #[arg(long, conflicts_with_all = ["query", "wdl_only", "fortress_dir", "by_king_square", "joint", "coverage"])]
+    block_stats: bool,
+    /// Count the positions whose outcome is not the same kind depending on the side to move,
+    /// eg mate with white to move but draw with black to move. Both sides to move are written by `--dump-fens`
+    #[arg(long, conflicts_with_all = ["wdl_only", "joint", "coverage", "block_stats"])]
+    zugzwang: bool,
+    /// Only summarise the number of entries of each outcome stored in the tables by `generate --outcome-distrib`,
+    /// without decompressing them
+    #[arg(long, conflicts_with_all = ["query", "wdl_only", "fortress_dir", "by_king_square", "joint", "coverage", "block_stats", "zugzwang"])]
+    from_trailer: bool,
+}
+
+/// Positions written by `--dump-fens`, streamed to the file as they are found
+struct FenDump {
+    writer: BufWriter<File>,
+    remaining: usize,
+}
+
+impl FenDump {
+    fn create(path: &Path, limit: Option<usize>) -> Result<Self, CliError> {
+        let file =
+            File::create(path).map_err(|e| CliError::Config(format!("{}: {e}", path.display())))?;
+        Ok(Self {
+            writer: BufWriter::new(file),
+            remaining: limit.unwrap_or(usize::MAX),
+        })
+    }
+
+    fn is_full(&self) -> bool {
+        self.remaining == 0
+    }
+
+    fn write(&mut self, chess: Chess, outcome: Outcome, idx: u64) -> io::Result<()> {
+        let fen = Fen::from_position(chess, EnPassantMode::Legal);
+        writeln!(self.writer, "{fen}; {outcome:?}; {idx}")?;
+        self.remaining -= 1;
+        Ok(())
+    }
 }
 
 impl Explore {
-    pub fn run(&self) {
+    /// See `CliError` for the exit codes
+    pub fn run(&self) -> ExitCode {
+        if self.joint {
+            return self.run_joint();
+        }
+        if self.coverage {
+            return exit_code(self.run_coverage());
+        }
+        if self.block_stats {
+            return self.run_block_stats();
+        }
+        let mut summary = RunSummary::default();
+        let mut dump = match self
+            .dump_fens
+            .as_ref()
+            .map(|path| FenDump::create(path, self.limit))
+            .transpose()
+        {
+            Ok(dump) => dump,
+            Err(e) => return exit_code(Err(e)),
+        };
         match self.material {
             MatOrAll::All => {
-                let entries = self.tb_dir.read_dir().expect("read_dir call failed");
-                for entry_res in entries {
-                    let mat_win_str = entry_res.unwrap().file_name().into_string().unwrap();
-                    let mat_win =
-                        MaterialWinner::from_str(&mat_win_str).expect("invalid file name");
-                    self.stats_one_mat(mat_win);
+                let mat_winners = match self.material.mat_winners(&self.tb_dir, None) {
+                    Ok(mat_winners) => mat_winners,
+                    Err(e) => return exit_code(Err(e)),
+                };
+                for mat_win in mat_winners {
+                    summary.record(
+                        format!("{mat_win:?}"),
+                        self.stats_one_mat(&mat_win, dump.as_mut()),
+                    );
                 }
             }
             MatOrAll::Mat(ref mat) => {
@@ -105,45 +204,419 @@ impl Explore {
                     .unwrap_or_else(|| Color::ALL.into())
                 {
                     let mat_win = MaterialWinner::new(mat, winner);
-                    self.stats_one_mat(mat_win);
+                    summary.record(
+                        format!("{mat_win:?}"),
+                        self.stats_one_mat(&mat_win, dump.as_mut()),
+                    );
                 }
             }
         }
+        if let (Some(mut dump), Some(path)) = (dump, self.dump_fens.as_ref()) {
+            if let Err(e) = dump.writer.flush() {
+                summary.fail(path.display(), e);
+            }
+        }
+        summary.finish()
+    }
+
+    fn run_coverage(&self) -> Result<(), CliError> {
+        let coverage = scan_tb_dir(&self.tb_dir)?;
+        info!("{coverage}");
+        if let MatOrAll::Mat(ref mat) = self.material {
+            let missing = coverage.missing_for(mat);
+            if missing.is_empty() {
+                info!("All tables needed to probe {mat:?} are present");
+            } else {
+                warn!("Tables missing or incomplete to probe {mat:?}: {missing:?}");
+            }
+        }
+        Ok(())
+    }
+
+    fn run_block_stats(&self) -> ExitCode {
+        let mat_winners = match self.material.mat_winners(&self.tb_dir, self.winner) {
+            Ok(mat_winners) => mat_winners,
+            Err(e) => return exit_code(Err(e)),
+        };
+        let mut summary = RunSummary::default();
+        for mat_win in mat_winners {
+            let path = TableName::helpmate(&mat_win).path_in(&self.tb_dir);
+            let Some(blocks_stats) = summary.record(
+                path.display(),
+                File::open(&path)
+                    .map_err(missing_table)
+                    .and_then(|file| EncoderDecoder::new(file).block_stats()),
+            ) else {
+                continue;
+            };
+            for (i, stats) in blocks_stats.iter().enumerate() {
+                info!(
+                    "{mat_win:?} block {i}, indexes {:?}, {:?}: {} -> {} bytes, ratio {:.2}",
+                    stats.range,
+                    stats.options,
+                    stats.uncompressed_size,
+                    stats.compressed_size,
+                    stats.ratio()
+                );
+            }
+            let (uncompressed, compressed) = blocks_stats.iter().fold((0, 0), |(u, c), stats| {
+                (u + stats.uncompressed_size, c + stats.compressed_size)
+            });
+            #[allow(clippy::cast_precision_loss)]
+            let ratio = uncompressed as f64 / compressed.max(1) as f64;
+            info!(
+                "{mat_win:?}: {} block(s), {uncompressed} -> {compressed} bytes, ratio {ratio:.2}",
+                blocks_stats.len()
+            );
+        }
+        summary.finish()
+    }
+
+    /// Fails for materials whose table of either winner is missing or malformed
+    fn run_joint(&self) -> ExitCode {
+        let mat_winners = match self.material.mat_winners(&self.tb_dir, None) {
+            Ok(mat_winners) => mat_winners,
+            Err(e) => return exit_code(Err(e)),
+        };
+        let mut materials: Vec<Material> = Vec::new();
+        for mat_win in mat_winners {
+            if !materials.contains(&mat_win.material) {
+                materials.push(mat_win.material);
+            }
+        }
+        let mut summary = RunSummary::default();
+        for material in materials {
+            summary.record(format!("{material:?}"), self.joint_one_mat(&material));
+        }
+        summary.finish()
+    }
+
+    fn joint_one_mat(&self, material: &Material) -> Result<(), CliError> {
+        let load = |winner| {
+            FileHandler::try_new_with_limits(
+                &TableName::helpmate(&MaterialWinner::new(material, winner)),
+                &self.tb_dir,
+                &ResourceLimits::default(),
+            )
+            .map_err(missing_table)
+        };
+        let white: FileHandler = load(Color::White)?;
+        let black: FileHandler = load(Color::Black)?;
+        let joint = joint_stats(&white.outcomes, &black.outcomes);
+        info!(
+            "{material:?}: both can be helped to mate in {} positions, only white: {}, only black: {}, neither: {}",
+            joint.both_win(),
+            joint.count(Wdl::Win, Wdl::Draw) + joint.count(Wdl::Win, Wdl::Lose),
+            joint.count(Wdl::Draw, Wdl::Win) + joint.count(Wdl::Lose, Wdl::Win),
+            joint.count(Wdl::Draw, Wdl::Draw)
+                + joint.count(Wdl::Draw, Wdl::Lose)
+                + joint.count(Wdl::Lose, Wdl::Draw)
+                + joint.count(Wdl::Lose, Wdl::Lose),
+        );
+        match joint.dtm_correlation {
+            Some(correlation) => info!(
+                "Correlation of the plies to mate when both can be helped to mate: {correlation:.3}"
+            ),
+            None => info!("Correlation of the plies to mate undefined"),
+        }
+        for white_wdl in [Wdl::Win, Wdl::Draw, Wdl::Lose] {
+            for black_wdl in [Wdl::Win, Wdl::Draw, Wdl::Lose] {
+                debug!(
+                    "White winner {white_wdl:?}, black winner {black_wdl:?}: {}",
+                    joint.count(white_wdl, black_wdl)
+                );
+            }
+        }
+        Ok(())
     }
 
-    fn stats_one_mat(&self, mat_win: MaterialWinner) {
+    fn stats_one_mat(
+        &self,
+        mat_win: &MaterialWinner,
+        dump: Option<&mut FenDump>,
+    ) -> Result<(), CliError> {
         info!(
             "Looking at {:?} with winner: {}",
             mat_win.material, mat_win.winner
         );
-        let file_handler: FileHandler = FileHandler::new(&mat_win, &self.tb_dir);
+        if self.from_trailer {
+            return self.trailer_stats(mat_win);
+        }
+        if self.wdl_only {
+            let file_handler: FileHandler<DefaultIndexer, WdlOutcomes> =
+                FileHandler::try_new_wdl_only(mat_win, &self.tb_dir).map_err(missing_table)?;
+            if !self.exclude_summary {
+                wdl_stats(mat_win, &file_handler.outcomes);
+            }
+            return Ok(());
+        }
+        // matches are written to the dump file rather than logged
+        let query = self.query.as_ref().filter(|_| dump.is_none());
+        let indexer = DefaultIndexer::from(mat_win.material.clone());
+        let searched_idx = searched_idx(Some(&indexer), query);
+        let mut total = StatsAccumulator::new(mat_win.winner);
+        // read from the table trailer if written by `generate --undefined-spans`, otherwise built block by block
+        let table_path = TableName::helpmate(mat_win).path_in(&self.tb_dir);
+        let decoder = EncoderDecoder::new(File::open(&table_path).map_err(missing_table)?);
+        let stored = decoder.undefined_spans()?;
+        // written by `generate --outcome-distrib`, compared with the stats computed
+        let stored_distrib = decoder.outcome_distrib()?;
+        let stored_spans = stored.is_some();
+        let mut undefined_spans = stored.unwrap_or_default();
+        // stats are computed block by block, while the next ones are still being decompressed
+        let file_handler: FileHandler = FileHandler::try_new_streaming(
+            &TableName::helpmate(mat_win),
+            &self.tb_dir,
+            |block, progress| {
+                info!(
+                    "{mat_win:?}: {} blocks decompressed, {} bytes read",
+                    progress.blocks_done, progress.bytes_read
+                );
+                if !self.exclude_summary {
+                    if !stored_spans {
+                        undefined_spans.extend_from(block);
+                    }
+                    let block_stats = range_stats(
+                        mat_win,
+                        Some(&indexer),
+                        block,
+                        block.index_from() as usize,
+                        &undefined_spans,
+                        query,
+                        searched_idx,
+                    );
+                    total.merge(block_stats);
+                }
+            },
+        )?;
         if !self.exclude_summary {
-            stats(
-                mat_win,
-                Some(&file_handler.indexer),
-                &file_handler.outcomes,
-                self.query.as_ref(),
-            )
+            log_stats(mat_win, &total);
+            if stored_distrib.is_some_and(|distrib| distrib != total.outcome_distrib()) {
+                warn!("{mat_win:?}: the outcome counts stored in the table differ from its outcomes, it is likely corrupted, see `verify`");
+            }
+        }
+        if self.by_king_square {
+            king_square_stats(mat_win, &file_handler.outcomes);
+        }
+        if self.zugzwang {
+            zugzwang_stats(mat_win, &file_handler, dump)?;
+        } else if let (Some(dump), Some(query)) = (dump, self.query.as_ref()) {
+            dump_matches(mat_win, &file_handler, query, dump)?;
+        }
+        if let Some(fortress_dir) = self.fortress_dir.as_ref() {
+            // generated with `generate --fortress-dir`
+            let fortress_table = FortressTable::read(
+                File::open(TablePath::new(fortress_dir).fortress(mat_win))
+                    .map_err(missing_table)?,
+            )?;
+            let [fortress, zugzwang_dependent, no_pass] = fortress_table.counts();
+            info!("Draws, fortress: {fortress}, zugzwang dependent: {zugzwang_dependent}, passing impossible: {no_pass}");
+            if let Some(Query::Pos(pos)) = self.query.as_ref() {
+                let draw_kind = fortress_table.draw_kind(file_handler.indexer.encode(pos));
+                info!("Draw kind of {pos:?}: {draw_kind:?}");
+            }
+        }
+        Ok(())
+    }
+
+    fn trailer_stats(&self, mat_win: &MaterialWinner) -> Result<(), CliError> {
+        let table_path = TableName::helpmate(mat_win).path_in(&self.tb_dir);
+        let decoder = EncoderDecoder::new(File::open(&table_path).map_err(missing_table)?);
+        // recorded by `generate` since it exists
+        if let Some(win_objective) = decoder.win_objective()? {
+            debug!("{mat_win:?}: aiming for {win_objective:?}");
+        }
+        let distrib = decoder.outcome_distrib()?.ok_or_else(|| {
+            CliError::Config(format!(
+                "{} has no outcome counts, it was generated without `--outcome-distrib`",
+                table_path.display()
+            ))
+        })?;
+        log_distrib(mat_win, &distrib);
+        Ok(())
+    }
+}
+
+/// Same as `log_stats`, from the counts stored in the table
+fn log_distrib(mat_win: &MaterialWinner, distrib: &OutcomeDistrib) {
+    for (perspective, turn) in [("Winner", mat_win.winner), ("Loser", !mat_win.winner)] {
+        let counts = distrib.counts(turn);
+        debug!(
+            "{perspective} to move, win: {:?}, draw: {:?}, lost: {:?}, unknown: {:?}, undefined: {:?}",
+            counts.win, counts.draw, counts.lose, counts.unknown, counts.undefined
+        );
+    }
+    for outcome in (0..u8::MAX)
+        .map(Outcome::Win)
+        .chain((0..u8::MAX).map(Outcome::Lose))
+    {
+        let by_turn = distrib
+            .by_turn
+            .as_ref()
+            .map(|by_outcome| by_outcome.get(&outcome).copied().unwrap_or_default());
+        if by_turn.white + by_turn.black > 0 {
+            debug!(
+                "{outcome:?}, {:?} (white to move: {:?}, black to move: {:?})",
+                by_turn.white + by_turn.black,
+                by_turn.white,
+                by_turn.black
+            );
+        }
+    }
+}
+
+fn king_square_stats(mat_win: &MaterialWinner, outcomes: &Outcomes) {
+    let stats = stats_by_white_king_square(&mat_win.material, outcomes);
+    for s in &stats {
+        info!(
+            "White king on {}, win: {}, draw: {}, lose: {}, undefined: {:.1}%",
+            s.square,
+            s.win,
+            s.draw,
+            s.lose,
+            s.undefined_density() * 100.
+        );
+    }
+    for square in anomalous_squares(&stats) {
+        warn!(
+            "{mat_win:?}: anomalous density of undefined positions with the white king on {square}"
+        );
+    }
+}
+
+fn dump_matches(
+    mat_win: &MaterialWinner,
+    file_handler: &FileHandler,
+    query: &Query,
+    dump: &mut FenDump,
+) -> io::Result<()> {
+    match query {
+        Query::Pos(pos) => {
+            if dump.is_full() {
+                return Ok(());
+            }
+            let idx = file_handler.indexer.encode(pos);
+            match file_handler.outcome_at(idx) {
+                Ok(outcome) => dump.write(pos.clone().into(), outcome, idx.idx)?,
+                Err(e) => error!("{mat_win:?}: {e}"),
+            }
+        }
+        Query::Outcome(searched_outcome) => {
+            for (idx, by_color_outcome) in file_handler.outcomes.iter().enumerate() {
+                for turn in Color::ALL {
+                    if dump.is_full() {
+                        return Ok(());
+                    }
+                    if by_color_outcome.get_outcome_by_color(turn) == *searched_outcome {
+                        let idx_with_turn = IndexWithTurn {
+                            idx: idx as u64,
+                            turn,
+                        };
+                        let pos = file_handler
+                            .indexer
+                            .restore(&mat_win.material, idx_with_turn);
+                        dump.write(pos.into(), *searched_outcome, idx_with_turn.idx)?;
+                    }
+                }
+            }
         }
     }
+    Ok(())
 }
 
-pub fn stats<T>(
+fn zugzwang_stats(
+    mat_win: &MaterialWinner,
+    file_handler: &FileHandler,
+    mut dump: Option<&mut FenDump>,
+) -> io::Result<()> {
+    let mut counts: Vec<(ZugzwangKind, u64)> = Vec::new();
+    for zugzwang in zugzwangs(&file_handler.outcomes) {
+        match counts.iter_mut().find(|(kind, _)| *kind == zugzwang.kind) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((zugzwang.kind, 1)),
+        }
+        for turn in Color::ALL {
+            let idx_with_turn = IndexWithTurn {
+                idx: zugzwang.idx,
+                turn,
+            };
+            let pos: Chess = file_handler
+                .indexer
+                .restore(&mat_win.material, idx_with_turn)
+                .into();
+            let outcome = *zugzwang.outcomes.get(turn);
+            match dump.as_deref_mut() {
+                Some(dump) if !dump.is_full() => dump.write(pos, outcome, zugzwang.idx)?,
+                Some(_) => (),
+                None => debug!(
+                    "{:?}: {}, {outcome:?}",
+                    zugzwang.kind,
+                    Fen::from_position(pos, EnPassantMode::Legal)
+                ),
+            }
+        }
+    }
+    counts.sort_by_key(|(kind, _)| *kind as u8);
+    info!("{mat_win:?} zugzwangs: {counts:?}");
+    Ok(())
+}
+
+pub fn wdl_stats(mat_win: &MaterialWinner, outcomes: &WdlOutcomes) {
+    let mut stats = StatsAccumulator::new(mat_win.winner);
+    for by_color_wdl in outcomes.iter() {
+        stats.add_wdl(by_color_wdl);
+    }
+    log_stats(mat_win, &stats);
+}
+
+/// Counts of the outcomes of a chunk of a table, merged in order by `stats`
+struct ChunkStats {
+    stats: StatsAccumulator,
+    /// Log lines of the positions matching the query, in index order
+    matches: Vec<String>,
+}
+
+impl ChunkStats {
+    fn new(winner: Color) -> Self {
+        Self {
+            stats: StatsAccumulator::new(winner),
+            matches: Vec::new(),
+        }
+    }
+}
+
+// number of indexes processed by each task of `stats`
+const STATS_CHUNK: usize = 1 << 20;
+// number of chunks processed in parallel before their matches are logged, bounding the ones kept in memory
+const STATS_BATCH: usize = 64;
+
+/// Chunks of the table are processed in parallel, by batches of `STATS_BATCH`.
+/// Positions matching `query` are logged in index order, as soon as their batch is over
+pub fn stats<T: Sync>(
     mat_win: MaterialWinner,
     indexer: Option<&DefaultIndexer>,
-    outcomes: &Vec<ByColor<T>>,
+    outcomes: &[ByColor<T>],
     query: Option<&Query>,
 ) where
     ByColor<T>: SideToMoveGetter,
 {
-    let mut draw: usize = 0;
-    let mut win: usize = 0;
-    let mut lose: usize = 0;
-    let mut unkown: usize = 0;
-    let mut distrib: FxHashMap<Outcome, u64> = FxHashMap::default();
-    let mut undefined_outcome: usize = 0;
-
-    let searched_idx = query.and_then(|q| {
+    let searched_idx = searched_idx(indexer, query);
+    let undefined_spans = UndefinedSpans::from_outcomes(outcomes);
+    let total = range_stats(
+        &mat_win,
+        indexer,
+        outcomes,
+        0,
+        &undefined_spans,
+        query,
+        searched_idx,
+    );
+    log_stats(&mat_win, &total);
+}
+
+/// Index of the position searched by `query`, if any
+fn searched_idx(indexer: Option<&DefaultIndexer>, query: Option<&Query>) -> Option<IndexWithTurn> {
+    query.and_then(|q| {
         if let Query::Pos(pos) = q {
             let s_idx = indexer
                 .expect("No indexer given despite specific position being searched")
@@ -153,64 +626,150 @@ pub fn stats<T>(
         } else {
             None
         }
-    });
+    })
+}
 
-    for (idx, by_color_outcome) in outcomes.iter().enumerate() {
-        for turn in Color::ALL {
-            let outcome = by_color_outcome.get_outcome_by_color(turn);
-            match query {
-                Some(Query::Outcome(searched_outcome)) if &outcome == searched_outcome => {
-                    let pos = indexer
-                        .expect("No indexer given despite specific outcome being searched")
-                        .restore(
-                            &mat_win.material,
-                            IndexWithTurn {
-                                idx: idx as u64,
-                                turn,
-                            },
-                        );
-                    info!("Macthing {outcome:?}, position {:?}", pos)
-                }
-                Some(Query::Pos(pos))
-                    if {
-                        searched_idx
-                            == Some(IndexWithTurn {
-                                idx: idx as u64,
-                                turn,
-                            })
-                    } =>
-                {
-                    info!("Macthing {pos:?}, outcome {outcome:?}",)
-                }
-                _ => (),
-            }
-            distrib.insert(outcome, *distrib.get(&outcome).unwrap_or(&0) + 1);
-            match outcome {
-                Outcome::Draw => draw += 1,
-                Outcome::Win(_) => win += 1,
-                Outcome::Lose(_) => lose += 1,
-                Outcome::Undefined => undefined_outcome += 1,
-                Outcome::Unknown => unkown += 1,
+/// Stats of `outcomes`, the ones of the table from index `start` onwards, see `stats`.
+/// Entries in `undefined_spans`, indexed from the start of the table, are counted without being read.
+/// Matches are logged before returning
+fn range_stats<T: Sync>(
+    mat_win: &MaterialWinner,
+    indexer: Option<&DefaultIndexer>,
+    outcomes: &[ByColor<T>],
+    start: usize,
+    undefined_spans: &UndefinedSpans,
+    query: Option<&Query>,
+    searched_idx: Option<IndexWithTurn>,
+) -> StatsAccumulator
+where
+    ByColor<T>: SideToMoveGetter,
+{
+    let mut total = StatsAccumulator::new(mat_win.winner);
+    for (batch_idx, batch) in outcomes.chunks(STATS_CHUNK * STATS_BATCH).enumerate() {
+        let batch_stats: Vec<ChunkStats> = batch
+            .par_chunks(STATS_CHUNK)
+            .enumerate()
+            .map(|(chunk_idx, chunk)| {
+                let chunk_start = start + (batch_idx * STATS_BATCH + chunk_idx) * STATS_CHUNK;
+                chunk_stats(
+                    mat_win,
+                    indexer,
+                    chunk,
+                    chunk_start,
+                    undefined_spans,
+                    query,
+                    searched_idx,
+                )
+            })
+            .collect();
+        for chunk_stats in batch_stats {
+            for line in chunk_stats.matches {
+                info!("{line}");
             }
+            total.merge(chunk_stats.stats);
         }
     }
+    total
+}
+
+/// Summary of the stats of a whole table, with the breakdown by side to move
+fn log_stats(mat_win: &MaterialWinner, stats: &StatsAccumulator) {
+    let OutcomeCounts {
+        win,
+        draw,
+        lose,
+        unknown,
+        ..
+    } = stats.counts();
     debug!(
-        "From {:?} perspective, win: {win:?}, draw: {draw:?}, lost: {lose:?}, unkown: {unkown:?}",
-        mat_win.winner
+        "From {:?} perspective, aiming for {:?}, win: {win:?}, draw: {draw:?}, lost: {lose:?}, unkown: {unknown:?}",
+        mat_win.winner,
+        WinObjective::of(mat_win)
     );
+    for (perspective, counts) in [
+        ("Winner", stats.winner_to_move()),
+        ("Loser", stats.loser_to_move()),
+    ] {
+        debug!(
+            "{perspective} to move, win: {:?}, draw: {:?}, lost: {:?}, unknown: {:?}",
+            counts.win, counts.draw, counts.lose, counts.unknown
+        );
+    }
+    debug!(
+        "Only won with white to move: {:?}, only won with black to move: {:?}",
+        stats.only_wins_with.white, stats.only_wins_with.black
+    );
+    let counts = stats.counts();
     debug!(
         "Index density = {:?}%",
-        (outcomes.len() * 2 - undefined_outcome) * 100 / (outcomes.len() * 2)
+        counts.defined() * 100 / counts.total().max(1)
     );
-    for i in 0..u8::MAX {
-        if let Some(nb_win) = distrib.get(&Outcome::Win(i)) {
-            debug!("Win({}), {:?}", i, nb_win);
+    for outcome in (0..u8::MAX)
+        .map(Outcome::Win)
+        .chain((0..u8::MAX).map(Outcome::Lose))
+    {
+        let nb = stats.count_of(outcome);
+        if nb > 0 {
+            debug!(
+                "{outcome:?}, {nb:?} (white to move: {:?}, black to move: {:?})",
+                stats.distrib.white.get(&outcome).unwrap_or(&0),
+                stats.distrib.black.get(&outcome).unwrap_or(&0)
+            );
         }
     }
+}
 
-    for i in 0..u8::MAX {
-        if let Some(nb_win) = distrib.get(&Outcome::Lose(i)) {
-            debug!("Lose({}), {:?}", i, nb_win);
+/// Stats of `chunk`, the outcomes from index `chunk_start` onwards
+fn chunk_stats<T>(
+    mat_win: &MaterialWinner,
+    indexer: Option<&DefaultIndexer>,
+    chunk: &[ByColor<T>],
+    chunk_start: usize,
+    undefined_spans: &UndefinedSpans,
+    query: Option<&Query>,
+    searched_idx: Option<IndexWithTurn>,
+) -> ChunkStats
+where
+    ByColor<T>: SideToMoveGetter,
+{
+    let mut chunk_stats = ChunkStats::new(mat_win.winner);
+    let chunk_range = chunk_start..chunk_start + chunk.len();
+    // undefined entries can be searched for, then none is skipped
+    let defined_ranges = if matches!(query, Some(Query::Outcome(Outcome::Undefined))) {
+        vec![chunk_range]
+    } else {
+        undefined_spans.defined_ranges(chunk_range)
+    };
+    let nb_defined: usize = defined_ranges.iter().map(ExactSizeIterator::len).sum();
+    chunk_stats
+        .stats
+        .add_undefined((chunk.len() - nb_defined) as u64);
+    for idx in defined_ranges.into_iter().flatten() {
+        let by_color_outcome = &chunk[idx - chunk_start];
+        chunk_stats.stats.add(by_color_outcome);
+        for turn in Color::ALL {
+            let outcome = by_color_outcome.get_outcome_by_color(turn);
+            let idx_with_turn = IndexWithTurn {
+                idx: idx as u64,
+                turn,
+            };
+            match query {
+                Some(Query::Outcome(searched_outcome)) if &outcome == searched_outcome => {
+                    let pos = indexer
+                        .expect("No indexer given despite specific outcome being searched")
+                        .restore(&mat_win.material, idx_with_turn);
+                    chunk_stats
+                        .matches
+                        .push(format!("Macthing {outcome:?}, position {pos:?}"));
+                }
+                Some(Query::Pos(pos)) if searched_idx == Some(idx_with_turn) => {
+                    chunk_stats
+                        .matches
+                        .push(format!("Macthing {pos:?}, outcome {outcome:?}"));
+                }
+                _ => (),
+            }
         }
     }
+    chunk_stats
 }
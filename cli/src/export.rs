@@ -0,0 +1,479 @@
+use from_str_sequential::FromStrSequential;
+use helpmate_tb::{
+    iter_defined, FileHandler, IndexWithTurn, Indexer, Material, MaterialWinner, Outcome,
+    OutcomesSlice, ProbeOptions, ResourceLimits, TableName, TablebaseProber, Wdl, WinLine,
+};
+use log::info;
+use rayon::prelude::*;
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+    process::ExitCode,
+};
+
+use retroboard::shakmaty::{
+    fen::Fen, san::SanPlus, Color, EnPassantMode, Move, Position, Role, Square,
+};
+
+use clap::{ArgAction, Args};
+
+use crate::error::{exit_code, missing_table, CliError};
+use crate::probe::CliMoveOrdering;
+
+/// Number of indexes whose lines are probed by the same thread
+const EXPORT_CHUNK: usize = 1 << 12;
+/// Number of chunks probed in parallel before their lines are written, in index order
+const EXPORT_BATCH: usize = 64;
+
+#[derive(Debug, Clone, Copy, FromStrSequential)]
+enum ExportFormat {
+    Pgn,
+    Binary,
+    Csv,
+    Parquet,
+}
+
+/// Formats of `ExportFormat` writing the best lines
+#[derive(Debug, Clone, Copy)]
+enum LineFormat {
+    Pgn,
+    Binary,
+}
+
+/// Export the best line of every winning position of a table, as a solutions database,
+/// or with `--format csv` or `--format parquet` the outcome of every entry, for data analysis
+#[derive(Args, Debug)]
+pub struct Export {
+    #[arg(help = "example \"KQvK\"")]
+    material: Material,
+    #[arg(short, long)]
+    winner: Color,
+    #[arg(long, default_value = "table/")]
+    tb_dir: PathBuf,
+    #[arg(short, long)]
+    output: PathBuf,
+    #[arg(
+        long,
+        default_value = "pgn",
+        value_parser = ExportFormat::from_str_sequential,
+        help = "`pgn` writes one game by line, the root position in the FEN tag. `binary` writes for each line the index (u64), the side to move (u8, 0 for white), the number of plies (u8), then each move as a u16: origin square, destination square shifted by 6, promotion role shifted by 12, all little-endian. `csv` writes no line, but an `index,turn,wdl,dtm` row by defined entry of the table, readable as is by pandas, the distance to mate in plies being empty for draws and positions left unknown, see `generate --unknown-policy`. `parquet` writes the same columns as a Parquet file, and needs the `parquet` feature"
+    )]
+    format: ExportFormat,
+    /// Skip the lines whose root position is reached by another line, up to symmetry, since its line is then
+    /// the end of the other one. All the lines are probed twice
+    #[arg(long, action = ArgAction::SetTrue)]
+    dedup: bool,
+    #[arg(
+        long,
+        default_value = "exhaustive",
+        value_parser = CliMoveOrdering::from_str_sequential,
+        help = "`exhaustive` keeps the optimal move with the lowest UCI notation at each ply, `heuristic` the first optimal one found, which is much faster"
+    )]
+    move_ordering: CliMoveOrdering,
+}
+
+impl Export {
+    pub fn run(&self, limits: &ResourceLimits) -> ExitCode {
+        exit_code(self.export(limits))
+    }
+
+    fn export(&self, limits: &ResourceLimits) -> Result<(), CliError> {
+        let mat_win = MaterialWinner::new(&self.material, self.winner);
+        let file_handler: FileHandler =
+            FileHandler::try_new_with_limits(&TableName::helpmate(&mat_win), &self.tb_dir, limits)
+                .map_err(missing_table)?;
+        let line_format = match self.format {
+            ExportFormat::Pgn => LineFormat::Pgn,
+            ExportFormat::Binary => LineFormat::Binary,
+            ExportFormat::Csv | ExportFormat::Parquet => {
+                return self.export_entries(&mat_win, &file_handler)
+            }
+        };
+        let tb_prober: TablebaseProber =
+            TablebaseProber::try_new(&self.material, &self.tb_dir).map_err(missing_table)?;
+        let options = ProbeOptions {
+            move_ordering: (&self.move_ordering).into(),
+            max_plies: None,
+            prefer_capture_free: false,
+            mated_king_on: None,
+        };
+        let reached = if self.dedup {
+            let mut reached: HashSet<IndexWithTurn> = HashSet::new();
+            for_each_line(&mat_win, &tb_prober, &file_handler, &options, |line| {
+                reached.extend(
+                    line.positions
+                        .iter()
+                        .filter(|pos| Material::from_board(pos.board()) == self.material)
+                        .map(|pos| file_handler.indexer.encode(pos)),
+                );
+                Ok(())
+            })?;
+            Some(reached)
+        } else {
+            None
+        };
+        let mut writer = self.create_output()?;
+        let mut nb_lines: u64 = 0;
+        let mut nb_skipped: u64 = 0;
+        for_each_line(&mat_win, &tb_prober, &file_handler, &options, |line| {
+            if reached.as_ref().is_some_and(|r| r.contains(&line.idx)) {
+                nb_skipped += 1;
+                return Ok(());
+            }
+            nb_lines += 1;
+            match line_format {
+                LineFormat::Pgn => write_pgn(&mut writer, line, self.winner),
+                LineFormat::Binary => write_binary(&mut writer, &line),
+            }
+        })?;
+        writer.flush()?;
+        info!(
+            "{mat_win:?}: {nb_lines} lines written to {}, {nb_skipped} transposed ones skipped",
+            self.output.display()
+        );
+        Ok(())
+    }
+
+    /// See `--format csv` and `--format parquet`, no line is probed
+    fn export_entries(
+        &self,
+        mat_win: &MaterialWinner,
+        file_handler: &FileHandler,
+    ) -> Result<(), CliError> {
+        if self.dedup {
+            return Err(CliError::Config(
+                format!(
+                    "--dedup only applies to the lines, not to `--format {:?}`",
+                    self.format
+                )
+                .to_lowercase(),
+            ));
+        }
+        let nb_rows = if let ExportFormat::Parquet = self.format {
+            self.export_entries_parquet(file_handler)?
+        } else {
+            let mut writer = self.create_output()?;
+            let nb_rows = write_entries_csv(&mut writer, &file_handler.outcomes)?;
+            writer.flush()?;
+            nb_rows
+        };
+        info!(
+            "{mat_win:?}: {nb_rows} entries written to {}",
+            self.output.display()
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "parquet")]
+    fn export_entries_parquet(&self, file_handler: &FileHandler) -> Result<u64, CliError> {
+        let writer = self.create_output()?;
+        Ok(write_entries_parquet(writer, &file_handler.outcomes)?)
+    }
+
+    #[cfg(not(feature = "parquet"))]
+    fn export_entries_parquet(&self, _: &FileHandler) -> Result<u64, CliError> {
+        Err(CliError::Config(
+            "`--format parquet` needs the cli to be built with `--features parquet`".to_string(),
+        ))
+    }
+
+    fn create_output(&self) -> Result<BufWriter<File>, CliError> {
+        File::create(&self.output)
+            .map(BufWriter::new)
+            .map_err(|e| CliError::Config(format!("{}: {e}", self.output.display())))
+    }
+}
+
+/// `wdl` and `dtm` columns of a defined entry
+fn entry_columns(outcome: Outcome) -> (&'static str, Option<u8>) {
+    let wdl = match Wdl::from(outcome) {
+        Wdl::Win => "win",
+        Wdl::Draw => "draw",
+        Wdl::Lose => "lose",
+        Wdl::Unknown => "unknown",
+        Wdl::Undefined => unreachable!("only defined entries are iterated"),
+    };
+    let dtm = match outcome {
+        Outcome::Win(plies) | Outcome::Lose(plies) => Some(plies),
+        _ => None,
+    };
+    (wdl, dtm)
+}
+
+/// See `--format csv`, returns the number of rows written, header excluded
+fn write_entries_csv(writer: &mut impl Write, outcomes: OutcomesSlice<'_>) -> io::Result<u64> {
+    writeln!(writer, "index,turn,wdl,dtm")?;
+    let mut nb_rows = 0;
+    for (idx_with_turn, outcome) in iter_defined(outcomes, 0) {
+        let (wdl, dtm) = entry_columns(outcome);
+        let dtm = dtm.map(|plies| plies.to_string()).unwrap_or_default();
+        writeln!(
+            writer,
+            "{},{},{wdl},{dtm}",
+            idx_with_turn.idx, idx_with_turn.turn
+        )?;
+        nb_rows += 1;
+    }
+    Ok(nb_rows)
+}
+
+/// Number of entries by row group of `--format parquet`
+#[cfg(feature = "parquet")]
+const PARQUET_ROW_GROUP: usize = 1 << 20;
+
+/// See `--format parquet`, returns the number of rows written
+#[cfg(feature = "parquet")]
+fn write_entries_parquet(
+    writer: impl Write + Send,
+    outcomes: OutcomesSlice<'_>,
+) -> io::Result<u64> {
+    use itertools::Itertools;
+    use parquet::{
+        basic::Compression,
+        data_type::{ByteArray, ByteArrayType, Int32Type, Int64Type},
+        file::{properties::WriterProperties, writer::SerializedFileWriter},
+        schema::parser::parse_message_type,
+    };
+    use std::sync::Arc;
+
+    let schema = parse_message_type(
+        "message entry {
+            REQUIRED INT64 index (INTEGER(64, false));
+            REQUIRED BYTE_ARRAY turn (STRING);
+            REQUIRED BYTE_ARRAY wdl (STRING);
+            OPTIONAL INT32 dtm (INTEGER(8, false));
+        }",
+    )?;
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+    let mut file_writer =
+        SerializedFileWriter::new(writer, Arc::new(schema), Arc::new(properties))?;
+    let mut nb_rows = 0;
+    for row_group in &iter_defined(outcomes, 0).chunks(PARQUET_ROW_GROUP) {
+        let (mut index, mut turn, mut wdl, mut dtm, mut dtm_defined) =
+            (Vec::new(), Vec::new(), Vec::new(), Vec::new(), Vec::new());
+        for (idx_with_turn, outcome) in row_group {
+            let (entry_wdl, entry_dtm) = entry_columns(outcome);
+            index.push(idx_with_turn.idx as i64);
+            turn.push(ByteArray::from(
+                idx_with_turn.turn.fold_wb("white", "black"),
+            ));
+            wdl.push(ByteArray::from(entry_wdl));
+            dtm.extend(entry_dtm.map(i32::from));
+            dtm_defined.push(i16::from(entry_dtm.is_some()));
+        }
+        nb_rows += index.len() as u64;
+        let mut row_group_writer = file_writer.next_row_group()?;
+        let mut column = row_group_writer.next_column()?.expect("index column");
+        column
+            .typed::<Int64Type>()
+            .write_batch(&index, None, None)?;
+        column.close()?;
+        for strings in [turn, wdl] {
+            let mut column = row_group_writer.next_column()?.expect("string column");
+            column
+                .typed::<ByteArrayType>()
+                .write_batch(&strings, None, None)?;
+            column.close()?;
+        }
+        let mut column = row_group_writer.next_column()?.expect("dtm column");
+        column
+            .typed::<Int32Type>()
+            .write_batch(&dtm, Some(&dtm_defined), None)?;
+        column.close()?;
+        row_group_writer.close()?;
+    }
+    file_writer.close()?;
+    Ok(nb_rows)
+}
+
+/// Calls `f` on the line of each `Outcome::Win` entry of the table, in index order.
+/// Chunks of the table are probed in parallel, by batches of `EXPORT_BATCH`
+fn for_each_line(
+    mat_win: &MaterialWinner,
+    tb_prober: &TablebaseProber,
+    file_handler: &FileHandler,
+    options: &ProbeOptions,
+    mut f: impl FnMut(WinLine) -> io::Result<()>,
+) -> io::Result<()> {
+    for (batch_idx, batch) in file_handler
+        .outcomes
+        .chunks(EXPORT_CHUNK * EXPORT_BATCH)
+        .enumerate()
+    {
+        let batch_lines: Vec<io::Result<Vec<WinLine>>> = batch
+            .par_chunks(EXPORT_CHUNK)
+            .enumerate()
+            .map(|(chunk_idx, chunk)| {
+                let chunk_start = (batch_idx * EXPORT_BATCH + chunk_idx) * EXPORT_CHUNK;
+                tb_prober
+                    .win_lines(
+                        mat_win,
+                        &file_handler.indexer,
+                        chunk,
+                        chunk_start as u64,
+                        options,
+                    )
+                    .collect()
+            })
+            .collect();
+        for lines in batch_lines {
+            for line in lines? {
+                f(line)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// `[SetUp "1"] [FEN "..."] [Result "1-0"] 1. Qb7# 1-0`
+fn write_pgn(writer: &mut impl Write, line: WinLine, winner: Color) -> io::Result<()> {
+    let result = winner.fold_wb("1-0", "0-1");
+    let mut pos = line.root;
+    let fen = Fen::from_position(pos.clone(), EnPassantMode::Legal);
+    let mut movetext = Vec::with_capacity(line.moves.len() * 2);
+    for (ply, chess_move) in line.moves.iter().enumerate() {
+        let fullmoves = pos.fullmoves();
+        if pos.turn().is_white() {
+            movetext.push(format!("{fullmoves}."));
+        } else if ply == 0 {
+            movetext.push(format!("{fullmoves}..."));
+        }
+        movetext.push(SanPlus::from_move_and_play_unchecked(&mut pos, chess_move).to_string());
+    }
+    writeln!(
+        writer,
+        "[SetUp \"1\"] [FEN \"{fen}\"] [Result \"{result}\"] {} {result}",
+        movetext.join(" ")
+    )
+}
+
+fn write_binary(writer: &mut impl Write, line: &WinLine) -> io::Result<()> {
+    writer.write_all(&line.idx.idx.to_le_bytes())?;
+    writer.write_all(&[u8::from(line.idx.turn.is_black()), line.plies])?;
+    for chess_move in &line.moves {
+        writer.write_all(&encode_move(chess_move).to_le_bytes())?;
+    }
+    Ok(())
+}
+
+/// See `--format binary`. Castling moves are encoded as the king taking its own rook
+fn encode_move(chess_move: &Move) -> u16 {
+    let from = chess_move
+        .from()
+        .map_or(0, |sq: Square| u16::from(u8::from(sq)));
+    let to = u16::from(u8::from(chess_move.to()));
+    let promotion = chess_move.promotion().map_or(0, |role: Role| role as u16);
+    from | to << 6 | promotion << 12
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_move() {
+        let promotion = Move::Normal {
+            role: Role::Pawn,
+            from: Square::G7,
+            capture: None,
+            to: Square::G8,
+            promotion: Some(Role::Queen),
+        };
+        assert_eq!(encode_move(&promotion), 54 | 62 << 6 | 5 << 12);
+    }
+
+    #[test]
+    fn test_write_entries_csv() {
+        use helpmate_tb::{OutcomeU8, UNDEFINED_OUTCOME_BYCOLOR};
+        use retroboard::shakmaty::ByColor;
+        let outcomes = [
+            ByColor {
+                black: Outcome::Win(0).into(),
+                white: OutcomeU8::from(Outcome::Undefined),
+            },
+            UNDEFINED_OUTCOME_BYCOLOR,
+            ByColor {
+                black: Outcome::Draw.into(),
+                white: Outcome::Lose(3).into(),
+            },
+        ];
+        let mut csv = Vec::new();
+        assert_eq!(write_entries_csv(&mut csv, &outcomes).unwrap(), 3);
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "index,turn,wdl,dtm\n0,black,win,0\n2,white,lose,3\n2,black,draw,\n"
+        );
+    }
+
+    #[test]
+    fn test_write_entries_csv_unknown_policy() {
+        use helpmate_tb::{BuildOptions, Objective, TableBaseBuilder, UnknownPolicy};
+        use std::str::FromStr;
+        let tb_dir: PathBuf = ["..", "table"].iter().collect();
+        let options = BuildOptions {
+            objective: Objective::Selfmate,
+            unknown_policy: UnknownPolicy::Unreachable,
+            ..Default::default()
+        };
+        let (common, _) = TableBaseBuilder::build_with_options(
+            MaterialWinner::from_str("KRvKw").unwrap(),
+            &tb_dir,
+            &options,
+        )
+        .unwrap();
+        let unknown = common
+            .iter_defined()
+            .filter(|(_, outcome)| *outcome == Outcome::Unknown)
+            .count();
+        assert!(unknown > 0);
+        let mut csv = Vec::new();
+        write_entries_csv(&mut csv, common.all_pos()).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert_eq!(
+            csv.lines().filter(|l| l.ends_with(",unknown,")).count(),
+            unknown
+        );
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn test_write_entries_parquet() {
+        use helpmate_tb::{OutcomeU8, UNDEFINED_OUTCOME_BYCOLOR};
+        use parquet::file::reader::{FileReader, SerializedFileReader};
+        use retroboard::shakmaty::ByColor;
+        let outcomes = [
+            ByColor {
+                black: Outcome::Win(0).into(),
+                white: OutcomeU8::from(Outcome::Undefined),
+            },
+            UNDEFINED_OUTCOME_BYCOLOR,
+            ByColor {
+                black: Outcome::Draw.into(),
+                white: Outcome::Lose(3).into(),
+            },
+        ];
+        let file = tempfile::tempfile().unwrap();
+        assert_eq!(
+            write_entries_parquet(file.try_clone().unwrap(), &outcomes).unwrap(),
+            3
+        );
+        let reader = SerializedFileReader::new(file).unwrap();
+        let rows: Vec<String> = reader
+            .get_row_iter(None)
+            .unwrap()
+            .map(|row| row.unwrap().to_string())
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                r#"{index: 0, turn: "black", wdl: "win", dtm: 0}"#,
+                r#"{index: 2, turn: "white", wdl: "lose", dtm: 3}"#,
+                r#"{index: 2, turn: "black", wdl: "draw", dtm: null}"#,
+            ]
+        );
+    }
+}
@@ -1,15 +1,125 @@
 use from_str_sequential::FromStrSequential;
-pub use helpmate_tb::{Common, EncoderDecoder, Material, MaterialWinner, TableBaseBuilder};
+use helpmate_tb::{
+    get_estimate_nb_pos, AnomalySink, BuildOptions, BuildReport, Codec, Common, CompressionOptions,
+    DeIndexer, Descendants, EncoderDecoder, EnumerationOrder, FortressTable, IndexWithTurn,
+    KingSquares, MaterialWinner, NaiveIndexer, Objective, OutcomeDistrib, Outcomes, ParanoidCheck,
+    PosHandler, PreFilter, ResourceLimits, TableBaseBuilder, TableCache, TableName, TablePath,
+    UndefinedSpans, UnknownPolicy, WinCondition, WinObjective,
+};
 
-use log::info;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{debug, info, warn};
 
-use retroboard::shakmaty::Color;
-use std::{fs::File, path::PathBuf};
+use retroboard::shakmaty::{Chess, Color, Position};
+use std::{
+    any::Any,
+    collections::HashSet,
+    fs::{self, File},
+    io,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
 
 use clap::{ArgAction, Args};
 
-use crate::check_indexer::MatOrNbPieces;
+use crate::check_indexer::{CheckIndexerPosHandler, MatOrNbPieces};
+use crate::error::{exit_code, missing_table, CliError, RunSummary};
 use crate::explore::stats;
+use crate::interrupt;
+
+#[derive(Debug, Clone, FromStrSequential)]
+enum CliCodec {
+    Zstd,
+    Lz4,
+}
+
+impl From<&CliCodec> for Codec {
+    fn from(codec: &CliCodec) -> Self {
+        match codec {
+            CliCodec::Zstd => Self::Zstd,
+            CliCodec::Lz4 => Self::Lz4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, FromStrSequential)]
+pub(crate) enum CliObjective {
+    Helpmate,
+    Selfmate,
+}
+
+impl From<&CliObjective> for Objective {
+    fn from(objective: &CliObjective) -> Self {
+        match objective {
+            CliObjective::Helpmate => Self::Helpmate,
+            CliObjective::Selfmate => Self::Selfmate,
+        }
+    }
+}
+
+#[derive(Debug, Clone, FromStrSequential)]
+enum CliUnknownPolicy {
+    Draw,
+    Unreachable,
+    Error,
+}
+
+impl From<&CliUnknownPolicy> for UnknownPolicy {
+    fn from(policy: &CliUnknownPolicy) -> Self {
+        match policy {
+            CliUnknownPolicy::Draw => Self::Draw,
+            CliUnknownPolicy::Unreachable => Self::Unreachable,
+            CliUnknownPolicy::Error => Self::Error,
+        }
+    }
+}
+
+#[derive(Debug, Clone, FromStrSequential)]
+enum CliObserver {
+    None,
+    Symmetries,
+    Stats,
+}
+
+/// Counts of the positions enumerated, see `--observer stats`
+#[derive(Debug, Default)]
+struct StatsPosHandler {
+    positions: u64,
+    checks: u64,
+    game_over: u64,
+    legal_moves: u64,
+}
+
+impl<I> PosHandler<I> for StatsPosHandler {
+    fn handle_position(
+        &mut self,
+        _: &mut Common<I>,
+        _: &Descendants,
+        chess: &Chess,
+        _: IndexWithTurn,
+        _: usize,
+    ) {
+        self.positions += 1;
+        self.checks += u64::from(chess.is_check());
+        self.game_over += u64::from(chess.is_game_over());
+        self.legal_moves += chess.legal_moves().len() as u64;
+    }
+}
+
+impl StatsPosHandler {
+    fn log_result(&self, mat_win: &MaterialWinner) {
+        #[allow(clippy::cast_precision_loss)]
+        let average_moves = self.legal_moves as f64 / self.positions.max(1) as f64;
+        info!(
+            "{mat_win:?}: {} positions enumerated, {} in check, {} where the game is over, {average_moves:.1} legal moves on average",
+            self.positions, self.checks, self.game_over
+        );
+    }
+}
 
 /// Generate helpmate files
 #[derive(Args, Debug)]
@@ -26,39 +136,647 @@ pub struct Generate {
         help = "Color of the expected winner. If no color is provided, will search for both"
     )]
     winner: Option<Color>,
-    #[arg(long, help = "Do not regenerate existing tables")]
+    #[arg(
+        long,
+        help = "Do not regenerate existing tables, except the ones whose build was stopped by Ctrl-C, marked by a `<table>.interrupted` file in --tb-dir. Resumes an interrupted run, tagging continuing from the `<table>.checkpoint` saved next to the marker"
+    )]
     do_not_regenerate: bool,
+    #[arg(
+        long,
+        help = "Instead of panicking on inconsistencies, record them and go on. A report is written in this directory for each table found dirty, alongside the table itself, which is not written in --tb-dir. The command then fails"
+    )]
+    quarantine_dir: Option<PathBuf>,
+    #[arg(
+        long,
+        help = "Append the inconsistencies met while building, with the table, index, FEN and context, to `anomalies.jsonl` in this directory. Each is written as soon as met, even without --quarantine-dir, where the build panics right after"
+    )]
+    anomalies_dir: Option<PathBuf>,
+    #[arg(
+        long,
+        help = "Classify drawn positions as fortress or zugzwang-dependent, and write the result in this directory, to be queried with `explore --fortress-dir`"
+    )]
+    fortress_dir: Option<PathBuf>,
+    #[arg(
+        long,
+        help = "Write the metadata of each table built in this directory, as `<table>.json`, eg its density"
+    )]
+    metadata_dir: Option<PathBuf>,
+    #[arg(
+        long,
+        default_value_t = 0.5,
+        help = "Warn when the share of the entries of a table corresponding to a legal position is below this, a sign the indexer is poorly suited to the material"
+    )]
+    min_density: f64,
+    #[arg(
+        long,
+        help = "Enumerate pieces in index-significance order, so that writes to the table are more sequential"
+    )]
+    index_order: bool,
+    #[arg(
+        long,
+        default_value = "zstd",
+        value_parser = CliCodec::from_str_sequential,
+        help = "Compression of the tables. `lz4` is much faster to decompress but produces bigger files. Detected automatically when reading"
+    )]
+    codec: CliCodec,
+    #[arg(
+        long,
+        help = "Only store the outcomes of indexes defined for at least one side to move, after a bitmap of them. Smaller tables, especially with pawns, slightly slower to decompress. Detected automatically when reading"
+    )]
+    dense: bool,
+    #[arg(
+        long,
+        help = "Append to each table the spans of entries undefined for both sides to move, so that tools scanning it, eg `explore`, skip them without reading them first. Ignored by older readers"
+    )]
+    undefined_spans: bool,
+    #[arg(
+        long,
+        help = "Append to each table the number of entries of each outcome, so that `explore --from-trailer` summarises it without decompressing it, and `verify` checks it against the outcomes. Rejected by older readers"
+    )]
+    outcome_distrib: bool,
+    #[arg(
+        long,
+        help = "Only consider the winner winning if it mates without losing any piece. Tables are written as `<mat>.<winner>.hm.keep.dtm`, and need the regular descendants tables as well as the `.keep` ones"
+    )]
+    keep_all_pieces: bool,
+    #[arg(
+        long,
+        value_parser = crate::parse_king_squares,
+        conflicts_with = "keep_all_pieces",
+        help = "Only consider the winner winning if it mates the loser's king on one of these squares, `corners` or a list like `d4,e4,d5,e5`, unchanged by the board symmetries. Other mates are draws. Tables are written as `<mat>.<winner>.hm.on<squares>.dtm`, and need the regular descendants tables as well as the ones generated with the same squares"
+    )]
+    mated_king_on: Option<KingSquares>,
+    #[arg(
+        long,
+        default_value = "helpmate",
+        value_parser = CliObjective::from_str_sequential,
+        help = "With `selfmate`, the winner forces the loser to mate it instead. Tables are written as `<mat>.<winner>.sm.dtm`, and need the selfmate descendants tables. Not compatible with --keep-all-pieces, --mated-king-on nor --fortress-dir"
+    )]
+    objective: CliObjective,
+    #[arg(
+        long,
+        default_value = "draw",
+        value_parser = CliUnknownPolicy::from_str_sequential,
+        help = "Outcome of the positions still unknown once tagging is over, ie from which no mate was reached. `unreachable` stores them as `Unknown` instead of draws, `error` treats them as inconsistencies, see --quarantine-dir. Recorded in the --metadata-dir files"
+    )]
+    unknown_policy: CliUnknownPolicy,
+    #[arg(
+        long,
+        help = "Once tagging is over, restore the positions of each table and check with shakmaty that the ones tagged `Win(0)` or `Lose(0)` are mates by the right color, and that the terminal ones got the outcome expected by --objective and --mated-king-on. Mismatches are inconsistencies, see --quarantine-dir"
+    )]
+    paranoid: bool,
+    #[arg(
+        long,
+        requires = "paranoid",
+        help = "Only check this number of positions tagged `Win(0)`, `Lose(0)` or `Draw` per table, spread across it, instead of every position"
+    )]
+    paranoid_samples: Option<usize>,
+    #[arg(
+        long,
+        default_value = "none",
+        value_parser = CliObserver::from_str_sequential,
+        help = "Additional analysis run on each position enumerated. `symmetries` checks all symmetric positions share the same index, like `check-indexer`, `stats` counts checks, game overs and legal moves. Other analyses can be run from the library with `TableBaseBuilder::build_with_observer`"
+    )]
+    observer: CliObserver,
+    #[arg(
+        long,
+        help = "Do not keep decompressed descendants tables in memory between builds. Saves RAM, but tables shared by several builds (eg KQvK) are decompressed again each time. Tables kept for other builds are not counted by --max-ram"
+    )]
+    no_table_cache: bool,
+    #[arg(
+        short,
+        long,
+        default_value_t = 1,
+        help = "Number of tables built concurrently, at most --max-threads. A table is only started once all its descendants are built. Memory usage grows accordingly"
+    )]
+    jobs: usize,
 }
 
 impl Generate {
-    pub fn run(self) {
-        for mat in self
+    /// Fails if any table could not be written
+    pub fn run(self, limits: &ResourceLimits) -> ExitCode {
+        if Objective::from(&self.objective) == Objective::Selfmate
+            && (self.keep_all_pieces || self.mated_king_on.is_some() || self.fortress_dir.is_some())
+        {
+            return exit_code(Err(CliError::Config(
+                "selfmates support neither --keep-all-pieces, --mated-king-on nor --fortress-dir"
+                    .to_string(),
+            )));
+        }
+        let mat_wins: Vec<MaterialWinner> = self
             .mat_or_nb_pieces
             .list_of_materials_with_recursive(self.recursive)
+            .into_iter()
+            .flat_map(|mat| {
+                self.winner
+                    .map(|w| vec![w])
+                    .unwrap_or_else(|| Color::ALL.into())
+                    .into_iter()
+                    .map(move |winner| MaterialWinner::new(&mat, winner))
+            })
+            .collect();
+        let anomaly_sink = match self
+            .anomalies_dir
+            .as_deref()
+            .map(open_anomaly_sink)
+            .transpose()
         {
-            self.gen_one_material(mat)
+            Ok(anomaly_sink) => anomaly_sink,
+            Err(e) => {
+                return exit_code(Err(CliError::Config(format!(
+                    "could not open the anomalies file: {e}"
+                ))))
+            }
+        };
+        let jobs = limits.threads(self.jobs);
+        let options = BuildOptions {
+            quarantine: self.quarantine_dir.is_some(),
+            enumeration_order: if self.index_order {
+                EnumerationOrder::IndexSignificance
+            } else {
+                EnumerationOrder::Material
+            },
+            win_condition: win_condition(self.keep_all_pieces, self.mated_king_on),
+            objective: (&self.objective).into(),
+            unknown_policy: (&self.unknown_policy).into(),
+            paranoid: self.paranoid.then_some(
+                self.paranoid_samples
+                    .map_or(ParanoidCheck::All, ParanoidCheck::Sample),
+            ),
+            table_cache: (!self.no_table_cache).then(TableCache::default),
+            // each concurrent build gets its share of the RAM
+            resource_limits: ResourceLimits {
+                max_threads: Some(jobs),
+                ..limits.clone()
+            }
+            .per_thread(),
+            anomaly_sink,
+            stop: Some(interrupt::install()),
+            checkpoint_dir: Some(self.tb_dir.clone()),
+            #[cfg(feature = "dhat")]
+            on_phase: Some(crate::heap_profile::snapshot_build_phase),
+            ..Default::default()
+        };
+        let global_pb = options.multi_progress.add(global_progress_bar(&mat_wins));
+        let (timings, mut summary) = self.schedule(&mat_wins, &options, jobs, &global_pb);
+        global_pb.finish_and_clear();
+        let not_started = mat_wins.len() - timings.len();
+        if interrupt::is_requested() && not_started > 0 {
+            summary.fail(
+                format!("{not_started} tables"),
+                CliError::Interrupted(
+                    "not started, rerun with --do-not-regenerate to resume".to_string(),
+                ),
+            );
         }
+        if jobs > 1 {
+            print_gantt(&timings);
+        }
+        summary.finish()
     }
 
-    fn gen_one_material(&self, mat: Material) {
-        for winner in self
-            .winner
-            .map(|w| vec![w])
-            .unwrap_or_else(|| Color::ALL.into())
-        {
-            let mat_win = MaterialWinner::new(&mat, winner);
-            let table_path = self.tb_dir.join(format!("{mat_win:?}"));
-            if !table_path.exists() || !self.do_not_regenerate {
-                info!("Building {mat:?} with winner: {winner}");
-                // white first, most interesting
-                let common = TableBaseBuilder::build(mat_win.clone(), &self.tb_dir);
-                let mut encoder = EncoderDecoder::new(File::create(table_path).unwrap());
-                encoder
-                    .compress(&common.all_pos)
-                    .expect("Compression failed for mat {mat:?}");
-                stats(mat_win, None, &common.all_pos, None)
+    /// Build all tables, at most `jobs` at once, only starting a table when
+    /// all of its descendants present in `mat_wins` have been built.
+    /// Returns the start and end of each build, relative to the start of the first one,
+    /// and the builds failed. After a failure, or Ctrl-C, no new build is started.
+    /// Cached descendants are released once no pending build needs them
+    fn schedule(
+        &self,
+        mat_wins: &[MaterialWinner],
+        options: &BuildOptions,
+        jobs: usize,
+        global_pb: &ProgressBar,
+    ) -> (Vec<(MaterialWinner, Duration, Duration)>, RunSummary) {
+        let dependencies: Vec<Vec<usize>> = mat_wins
+            .iter()
+            .map(|mat_win| {
+                let descendants: HashSet<_> = mat_win.material.descendants_not_draw().collect();
+                mat_wins
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, other)| descendants.contains(&other.material))
+                    .map(|(i, _)| i)
+                    .collect()
+            })
+            .collect();
+        let start = Instant::now();
+        let mut timings = Vec::with_capacity(mat_wins.len());
+        let mut done = vec![false; mat_wins.len()];
+        let mut started = vec![false; mat_wins.len()];
+        let mut summary = RunSummary::default();
+        let (tx, rx) = mpsc::channel();
+        thread::scope(|s| {
+            let mut running = 0;
+            while timings.len() < mat_wins.len() {
+                for (i, mat_win) in mat_wins.iter().enumerate() {
+                    if running >= jobs || summary.has_failed() || interrupt::is_requested() {
+                        break;
+                    }
+                    if !started[i] && dependencies[i].iter().all(|&j| done[j]) {
+                        started[i] = true;
+                        running += 1;
+                        let tx = tx.clone();
+                        s.spawn(move || {
+                            let begin = start.elapsed();
+                            // a panicking build must still report back, otherwise the scheduler would wait forever
+                            let res = panic::catch_unwind(AssertUnwindSafe(|| {
+                                self.gen_one_material_winner(mat_win, options)
+                            }))
+                            .unwrap_or_else(|payload| Err(build_panicked(&*payload)));
+                            tx.send((i, begin, start.elapsed(), res))
+                                .expect("Scheduler stopped listening");
+                        });
+                    }
+                }
+                if running == 0 {
+                    break;
+                }
+                let (i, begin, end, res) = rx.recv().expect("All build threads disconnected");
+                running -= 1;
+                summary.record(format!("{:?}", mat_wins[i]), res);
+                done[i] = true;
+                if let Some(table_cache) = &options.table_cache {
+                    // release the descendants only needed by builds already over
+                    let needed: Vec<TableName> = (0..mat_wins.len())
+                        .filter(|&j| !done[j])
+                        .flat_map(|j| {
+                            TableBaseBuilder::descendants_table_names(&mat_wins[j], options)
+                        })
+                        .collect();
+                    table_cache.release_except(&needed, &self.tb_dir);
+                }
+                global_pb.inc(estimated_entries(&mat_wins[i]));
+                global_pb.set_message(format!(
+                    "{}/{} tables, {running} running",
+                    timings.len() + 1,
+                    mat_wins.len()
+                ));
+                timings.push((mat_wins[i].clone(), begin, end));
+            }
+        });
+        (timings, summary)
+    }
+
+    /// Build the table of `mat_win`, running `--observer` alongside.
+    /// The only files read are the descendants tables, so any not found is a missing table
+    fn build(
+        &self,
+        mat_win: &MaterialWinner,
+        options: &BuildOptions,
+    ) -> io::Result<(Common, BuildReport)> {
+        Ok(match self.observer {
+            CliObserver::None => {
+                TableBaseBuilder::build_with_options(mat_win.clone(), &self.tb_dir, options)
+                    .map_err(missing_table)?
+            }
+            CliObserver::Symmetries => {
+                let (common, build_report, observer) = TableBaseBuilder::build_with_observer(
+                    mat_win.clone(),
+                    &self.tb_dir,
+                    options,
+                    CheckIndexerPosHandler::default(),
+                )
+                .map_err(missing_table)?;
+                observer.log_result(&mat_win.material);
+                (common, build_report)
+            }
+            CliObserver::Stats => {
+                let (common, build_report, observer) = TableBaseBuilder::build_with_observer(
+                    mat_win.clone(),
+                    &self.tb_dir,
+                    options,
+                    StatsPosHandler::default(),
+                )
+                .map_err(missing_table)?;
+                observer.log_result(mat_win);
+                (common, build_report)
+            }
+        })
+    }
+
+    fn gen_one_material_winner(
+        &self,
+        mat_win: &MaterialWinner,
+        options: &BuildOptions,
+    ) -> io::Result<()> {
+        let MaterialWinner {
+            material: mat,
+            winner,
+        } = mat_win;
+        let table_name = TableName::new(mat_win, options.objective, options.win_condition);
+        let interrupted_path = TablePath::new(&self.tb_dir).interrupted(&table_name);
+        let interrupted = interrupted_path.exists();
+        if !table_name.exists_in(&self.tb_dir) || !self.do_not_regenerate || interrupted {
+            let checkpoint_path = TablePath::new(&self.tb_dir).checkpoint(&table_name);
+            // only resumed with --do-not-regenerate, otherwise the table is built from scratch
+            if !self.do_not_regenerate && checkpoint_path.exists() {
+                fs::remove_file(&checkpoint_path)?;
+            }
+            if interrupted {
+                info!(
+                    "Building {mat:?} with winner: {winner} again, interrupted by a previous run"
+                );
             } else {
-                info!("Skipping {mat:?} with winner: {winner}")
+                info!("Building {mat:?} with winner: {winner}");
+            }
+            TableBaseBuilder::check_resources(mat_win, &self.tb_dir, options)?;
+            let (common, build_report) = match self.build(mat_win, options) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {
+                    warn!("{e}, marked by {}", interrupted_path.display());
+                    fs::write(&interrupted_path, format!("{e}\n"))?;
+                    return Err(e);
+                }
+                res => res?,
+            };
+            log_build_report(mat_win, &build_report);
+            if build_report.density() < self.min_density {
+                warn!(
+                    "{mat_win:?}: only {:.1}% of the entries are legal positions, below --min-density",
+                    build_report.density() * 100.
+                );
+            }
+            if let Some(metadata_dir) = self.metadata_dir.as_ref() {
+                std::fs::create_dir_all(metadata_dir)?;
+                let mut metadata = serde_json::json!({
+                    "material": format!("{mat:?}"),
+                    "winner": winner.char().to_string(),
+                    "max_index": build_report.max_index,
+                    "defined": build_report.defined,
+                    "density": build_report.density(),
+                    "unknown_policy": format!("{:?}", options.unknown_policy),
+                });
+                if let Some(win_objective) = win_objective(mat_win, options.objective) {
+                    metadata["win_objective"] = format!("{win_objective:?}").into();
+                }
+                fs::write(
+                    TablePath::new(metadata_dir).metadata(&table_name),
+                    format!("{metadata:#}\n"),
+                )?;
+            }
+            // dirty tables are kept out of the tablebase directory, so that they are never loaded as descendants
+            let mut table_dir = &self.tb_dir;
+            let mut dirty_error = None;
+            if let (Some(quarantine), Some(quarantine_dir)) =
+                (common.quarantine(), self.quarantine_dir.as_ref())
+            {
+                if common.is_dirty() {
+                    let report_path = TablePath::new(quarantine_dir).quarantine_report(mat_win);
+                    warn!(
+                        "{mat_win:?} is dirty, {} inconsistencies recorded in {}",
+                        quarantine.len(),
+                        report_path.display()
+                    );
+                    std::fs::create_dir_all(quarantine_dir)?;
+                    serde_json::to_writer_pretty(File::create(report_path)?, quarantine)?;
+                    table_dir = quarantine_dir;
+                    dirty_error = Some(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "{} inconsistencies recorded, table written in {} instead",
+                            quarantine.len(),
+                            quarantine_dir.display()
+                        ),
+                    ));
+                }
+            }
+            if let Some(fortress_dir) = self.fortress_dir.as_ref() {
+                std::fs::create_dir_all(fortress_dir)?;
+                FortressTable::from_common(&common).write(File::create(
+                    TablePath::new(fortress_dir).fortress(mat_win),
+                )?)?;
+            }
+            #[cfg(feature = "dhat")]
+            crate::heap_profile::snapshot(mat_win, "3-compression");
+            write_table(
+                &TablePath::new(table_dir).table_to_write(&table_name),
+                common.all_pos(),
+                CompressionOptions {
+                    codec: (&self.codec).into(),
+                    pre_filter: if self.dense {
+                        PreFilter::Dense
+                    } else {
+                        PreFilter::None
+                    },
+                },
+                Trailers {
+                    undefined_spans: self.undefined_spans,
+                    outcome_distrib: self.outcome_distrib,
+                    win_objective: win_objective(mat_win, options.objective),
+                },
+                &options.resource_limits,
+            )?;
+            if interrupted {
+                fs::remove_file(&interrupted_path)?;
+            }
+            #[cfg(feature = "dhat")]
+            crate::heap_profile::snapshot(mat_win, "4-end");
+            if let Some(dirty_error) = dirty_error {
+                return Err(dirty_error);
+            }
+            stats(mat_win.clone(), None, common.all_pos(), None);
+        } else {
+            info!("Skipping {mat:?} with winner: {winner}");
+        }
+        Ok(())
+    }
+}
+
+/// Condition set by `--keep-all-pieces` and `--mated-king-on`
+pub fn win_condition(keep_all_pieces: bool, mated_king_on: Option<KingSquares>) -> WinCondition {
+    if keep_all_pieces {
+        WinCondition::KeepAllPieces
+    } else if let Some(squares) = mated_king_on {
+        WinCondition::MatedKingOn(squares)
+    } else {
+        WinCondition::AnyMate
+    }
+}
+
+/// Only recorded for helpmates, selfmates being tagged regardless of the `WinObjective` of the material
+pub fn win_objective(mat_win: &MaterialWinner, objective: Objective) -> Option<WinObjective> {
+    (objective == Objective::Helpmate).then(|| WinObjective::of(mat_win))
+}
+
+/// Trailers appended to a table after its blocks, see `write_table`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Trailers {
+    pub undefined_spans: bool,
+    pub outcome_distrib: bool,
+    pub win_objective: Option<WinObjective>,
+}
+
+/// Write the table to `ResourceLimits::tmp_path`, check its structure and only then move it to `table_path`,
+/// so that a crash while compressing never leaves a truncated table under a valid name
+pub fn write_table(
+    table_path: &Path,
+    all_pos: &Outcomes,
+    options: CompressionOptions,
+    trailers: Trailers,
+    limits: &ResourceLimits,
+) -> io::Result<()> {
+    let tmp_path = limits.tmp_path(table_path);
+    let mut file = File::create(&tmp_path)?;
+    let mut encoder = EncoderDecoder::new(&mut file);
+    encoder.compress_with_options(all_pos, options)?;
+    if trailers.undefined_spans {
+        encoder.write_undefined_spans(&UndefinedSpans::from_outcomes(all_pos))?;
+    }
+    if trailers.outcome_distrib {
+        encoder.write_outcome_distrib(&OutcomeDistrib::from_outcomes(all_pos))?;
+    }
+    if let Some(win_objective) = trailers.win_objective {
+        encoder.write_win_objective(win_objective)?;
+    }
+    file.sync_all()?;
+    EncoderDecoder::new(File::open(&tmp_path)?).check_structure(all_pos.len())?;
+    if fs::rename(&tmp_path, table_path).is_err() {
+        // the scratch directory is on another filesystem, copy next to the table so that the final rename stays atomic
+        let local_tmp_path = ResourceLimits::default().tmp_path(table_path);
+        fs::copy(&tmp_path, &local_tmp_path)?;
+        fs::remove_file(&tmp_path)?;
+        fs::rename(local_tmp_path, table_path)?;
+    }
+    Ok(())
+}
+
+pub fn log_build_report(mat_win: &MaterialWinner, build_report: &BuildReport) {
+    let indexer = NaiveIndexer::from(mat_win.material.clone());
+    info!(
+        "{mat_win:?}: {} tagging passes, {} draws ({} through captures/promotions only), density {:.1}%",
+        build_report.tagging_passes,
+        build_report.draws,
+        build_report.capture_draws,
+        build_report.density() * 100.
+    );
+    if build_report.duplicates > 0 {
+        debug!(
+            "{mat_win:?}: {} duplicate indexes, {} reconciled",
+            build_report.duplicates, build_report.reconciled_duplicates
+        );
+    }
+    for (kind, counts, records) in [
+        ("Win", &build_report.win_counts, &build_report.win_records),
+        (
+            "Lose",
+            &build_report.lose_counts,
+            &build_report.lose_records,
+        ),
+    ] {
+        if let Some(record) = records.first() {
+            info!(
+                "Longest {kind}: {} ply, {} positions, e.g {:?}",
+                counts.len() - 1,
+                records.len(),
+                indexer.restore(&mat_win.material, *record)
+            );
+        }
+    }
+}
+
+fn open_anomaly_sink(anomalies_dir: &Path) -> io::Result<AnomalySink> {
+    fs::create_dir_all(anomalies_dir)?;
+    AnomalySink::open(&TablePath::new(anomalies_dir).anomalies())
+}
+
+/// Gantt-like summary of when each table was being built
+fn print_gantt(timings: &[(MaterialWinner, Duration, Duration)]) {
+    const WIDTH: f64 = 60.;
+    let total = timings
+        .iter()
+        .map(|(_, _, end)| end.as_secs_f64())
+        .fold(f64::EPSILON, f64::max);
+    let name_width = timings
+        .iter()
+        .map(|(mat_win, _, _)| format!("{mat_win:?}").len())
+        .max()
+        .unwrap_or(0);
+    for (mat_win, begin, end) in timings {
+        // truncation is fine, this is only for display
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let (offset, length) = (
+            (begin.as_secs_f64() / total * WIDTH) as usize,
+            (((end.as_secs_f64() - begin.as_secs_f64()) / total * WIDTH) as usize).max(1),
+        );
+        println!(
+            "{:<name_width$} |{}{}{}| {:.1}s -> {:.1}s",
+            format!("{mat_win:?}"),
+            " ".repeat(offset),
+            "#".repeat(length),
+            " ".repeat((WIDTH as usize).saturating_sub(offset + length)),
+            begin.as_secs_f64(),
+            end.as_secs_f64()
+        );
+    }
+}
+
+fn build_panicked(payload: &(dyn Any + Send)) -> io::Error {
+    let msg = payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown cause");
+    io::Error::other(format!("build panicked: {msg}"))
+}
+
+/// Estimated number of entries processed when generating the table, (generation and tagging phases)
+fn estimated_entries(mat_win: &MaterialWinner) -> u64 {
+    (get_estimate_nb_pos(&mat_win.material) * 4) as u64
+}
+
+/// Progress across all tables, weighted by the estimated number of entries of each
+/// so that the ETA reflects the observed per-entry throughput
+fn global_progress_bar(mat_wins: &[MaterialWinner]) -> ProgressBar {
+    let pb = ProgressBar::new(mat_wins.iter().map(estimated_entries).sum());
+    pb.set_style(
+        ProgressStyle::with_template(
+            "{msg} [{elapsed_precise}] [{wide_bar:.green/white}] {percent}% (total ETA {eta})",
+        )
+        .expect("Invalid indicatif template syntax")
+        .progress_chars("#>-"),
+    );
+    pb
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::str::FromStr;
+
+    #[test]
+    fn test_selfmate_trailer_has_no_win_objective() {
+        let tb_dir = PathBuf::from("../table");
+        let tmp = tempfile::tempdir().unwrap();
+        for (mat_win, objective) in [
+            ("KRvKw", Objective::Selfmate),
+            ("KQvKw", Objective::Helpmate),
+        ] {
+            let mat_win = MaterialWinner::from_str(mat_win).unwrap();
+            let options = BuildOptions {
+                objective,
+                ..Default::default()
+            };
+            let (common, _) =
+                TableBaseBuilder::build_with_options(mat_win.clone(), &tb_dir, &options).unwrap();
+            let table_path =
+                TableName::new(&mat_win, objective, WinCondition::AnyMate).path_in(tmp.path());
+            write_table(
+                &table_path,
+                common.all_pos(),
+                CompressionOptions::default(),
+                Trailers {
+                    win_objective: win_objective(&mat_win, objective),
+                    ..Default::default()
+                },
+                &ResourceLimits::default(),
+            )
+            .unwrap();
+            let stored = EncoderDecoder::new(File::open(&table_path).unwrap())
+                .win_objective()
+                .unwrap();
+            match objective {
+                Objective::Selfmate => assert_eq!(stored, None),
+                Objective::Helpmate => assert_eq!(stored, Some(WinObjective::of(&mat_win))),
             }
         }
     }
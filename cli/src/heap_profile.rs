@@ -0,0 +1,52 @@
+//! Heap snapshots taken between the phases of each build with the `dhat` feature, so that the memory
+//! usage of the whole run, profiled in `dhat-heap.json`, can be attributed to generation, tagging or compression.
+//! Builds run concurrently with `generate --jobs` share the same heap, so their snapshots are only meaningful with one job
+use std::{fs, io, path::Path};
+
+use helpmate_tb::{BuildPhase, MaterialWinner};
+use log::{info, warn};
+
+/// Next to `dhat-heap.json`, in the working directory
+const SNAPSHOTS_DIR: &str = "dhat-phases";
+
+/// See `BuildOptions::on_phase`
+pub fn snapshot_build_phase(mat_win: &MaterialWinner, phase: BuildPhase) {
+    snapshot(
+        mat_win,
+        match phase {
+            BuildPhase::Generation => "1-generation",
+            BuildPhase::Tagging => "2-tagging",
+        },
+    );
+}
+
+/// Writes the heap stats when `label` starts to `dhat-phases/<mat_win>.<label>.json`. `max_bytes` being the
+/// peak of the whole run so far, the phase before reached a new peak if it grew since the previous snapshot
+pub fn snapshot(mat_win: &MaterialWinner, label: &str) {
+    let stats = dhat::HeapStats::get();
+    info!(
+        "{mat_win:?} heap before {label}: {} bytes in {} blocks, peak {} bytes",
+        stats.curr_bytes, stats.curr_blocks, stats.max_bytes
+    );
+    let snapshot = serde_json::json!({
+        "material_winner": format!("{mat_win:?}"),
+        "phase": label,
+        "curr_bytes": stats.curr_bytes,
+        "curr_blocks": stats.curr_blocks,
+        "max_bytes": stats.max_bytes,
+        "max_blocks": stats.max_blocks,
+        "total_bytes": stats.total_bytes,
+        "total_blocks": stats.total_blocks,
+    });
+    if let Err(e) = write_snapshot(&format!("{mat_win:?}.{label}.json"), &snapshot) {
+        warn!("Could not write the {label} heap snapshot of {mat_win:?}: {e}");
+    }
+}
+
+fn write_snapshot(file_name: &str, snapshot: &serde_json::Value) -> io::Result<()> {
+    fs::create_dir_all(SNAPSHOTS_DIR)?;
+    fs::write(
+        Path::new(SNAPSHOTS_DIR).join(file_name),
+        format!("{snapshot:#}\n"),
+    )
+}
@@ -0,0 +1,61 @@
+//! Graceful stop on Ctrl-C. The first one only requests a stop, builds honouring it at their next safe boundary,
+//! see `BuildOptions::stop`. A second one exits right away, with the exit code of `CliError::Interrupted`
+use std::process;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, OnceLock,
+};
+
+use log::warn;
+
+use crate::error::CliError;
+
+static STOP_REQUESTED: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+
+/// Flag set by the first Ctrl-C from now on. If the handler cannot be installed, Ctrl-C keeps killing
+/// the process right away, and the flag is never set
+pub fn install() -> Arc<AtomicBool> {
+    Arc::clone(STOP_REQUESTED.get_or_init(|| {
+        if let Err(e) = ctrlc::set_handler(on_ctrl_c) {
+            warn!("Ctrl-C will not stop gracefully: {e}");
+        }
+        Arc::default()
+    }))
+}
+
+/// `true` once a stop has been requested
+pub fn is_requested() -> bool {
+    STOP_REQUESTED
+        .get()
+        .is_some_and(|stop| stop.load(Ordering::Relaxed))
+}
+
+fn on_ctrl_c() {
+    if request_stop() {
+        process::exit(CliError::INTERRUPTED_EXIT_CODE.into());
+    }
+}
+
+/// Sets the flag, returning whether a stop had already been requested
+fn request_stop() -> bool {
+    STOP_REQUESTED
+        .get()
+        .is_some_and(|stop| stop.swap(true, Ordering::Relaxed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a single test, the flag being process-wide
+    #[test]
+    fn test_install() {
+        let stop = install();
+        assert!(Arc::ptr_eq(&stop, &install()));
+        assert!(!is_requested());
+        assert!(!request_stop());
+        assert!(stop.load(Ordering::Relaxed));
+        assert!(is_requested());
+        assert!(request_stop());
+    }
+}
@@ -1,27 +1,53 @@
+// `FromStrSequential` derive generates code triggering this lint
+#![allow(clippy::manual_ignore_case_cmp)]
+
+mod check_compat;
 mod check_indexer;
+mod completions;
 mod convert;
+mod count;
 mod diff;
+mod error;
 mod explore;
+mod export;
 mod generate;
+#[cfg(feature = "dhat")]
+mod heap_profile;
+mod interrupt;
 mod probe;
+mod retag;
 mod verify;
+mod win_within;
 
+use completions::Completions;
 use convert::Convert;
+use count::Count;
 use diff::Diff;
+use helpmate_tb::KingSquares;
 pub use helpmate_tb::{
-    Common, EncoderDecoder, Material, MaterialWinner, Outcome, SideToMoveGetter, TableBaseBuilder,
-    UNDEFINED_OUTCOME_BYCOLOR,
+    Common, EncoderDecoder, Material, MaterialWinner, Outcome, ResourceLimits, SideToMoveGetter,
+    TableBaseBuilder, UNDEFINED_OUTCOME_BYCOLOR,
 };
+use retroboard::shakmaty::{Bitboard, Square};
 
 use env_logger::{Builder, Target};
 use log::LevelFilter;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::str::FromStr;
 
 use clap::{ArgAction, Parser, Subcommand};
 use probe::Probe;
+use retag::Retag;
 use verify::Verify;
+use win_within::WinWithin;
 
+use crate::check_compat::CheckCompat;
 use crate::check_indexer::CheckIndexer;
+use crate::error::CliError;
 use crate::explore::Explore;
+use crate::export::Export;
 use crate::generate::Generate;
 
 #[cfg(feature = "dhat")]
@@ -43,34 +69,107 @@ struct Cli {
         action = ArgAction::SetTrue
     )]
     no_time: bool,
+    #[arg(
+        long,
+        value_parser = parse_bytes,
+        help = "RAM allowed for tables, in bytes, with an optional K, M, G or T suffix, eg `8G`. Builds needing more fail instead of starting, and the block cache used when probing is shrunk to fit. Split evenly among `generate --jobs`"
+    )]
+    max_ram: Option<u64>,
+    #[arg(
+        long,
+        help = "Maximum number of threads, `generate --jobs` being capped by it"
+    )]
+    max_threads: Option<usize>,
+    #[arg(
+        long,
+        help = "Directory where tables are written before being moved in place, instead of next to them, eg on a faster disk"
+    )]
+    scratch_dir: Option<PathBuf>,
+}
+
+/// Number of bytes, with an optional decimal unit, eg `512M`
+fn parse_bytes(s: &str) -> Result<u64, String> {
+    let (digits, multiplier) = match s.chars().last().map(|c| c.to_ascii_uppercase()) {
+        Some('K') => (&s[..s.len() - 1], 1_000),
+        Some('M') => (&s[..s.len() - 1], 1_000_000),
+        Some('G') => (&s[..s.len() - 1], 1_000_000_000),
+        Some('T') => (&s[..s.len() - 1], 1_000_000_000_000),
+        _ => (s, 1),
+    };
+    digits
+        .parse::<u64>()
+        .map_err(|e| e.to_string())?
+        .checked_mul(multiplier)
+        .ok_or_else(|| "too big".to_string())
+}
+
+/// `corners`, or squares separated by commas, eg `d4,e4,d5,e5`. They must be symmetric, see `KingSquares::new`
+fn parse_king_squares(s: &str) -> Result<KingSquares, String> {
+    if s == "corners" {
+        return Ok(KingSquares::CORNERS);
+    }
+    let squares = s
+        .split(',')
+        .map(|square| Square::from_str(square.trim()).map_err(|e| format!("{square}: {e}")))
+        .collect::<Result<Bitboard, _>>()?;
+    KingSquares::new(squares).ok_or_else(|| {
+        "squares must be unchanged by mirroring the board vertically, horizontally and along the a1-h8 diagonal".to_string()
+    })
 }
 
 #[derive(Subcommand, Debug)]
 enum Cmd {
     Generate(Generate),
     Explore(Explore),
+    Export(Export),
     Probe(Probe),
     CheckIndexer(CheckIndexer),
+    CheckCompat(CheckCompat),
     Diff(Diff),
     Verify(Verify),
     Convert(Convert),
+    Count(Count),
+    Retag(Retag),
+    WinWithin(WinWithin),
+    Completions(Completions),
 }
 
 impl Cmd {
-    fn run(self) {
+    /// Stderr for the subcommands whose answers are read from stdout as they come
+    fn log_target(&self) -> Target {
+        match self {
+            Self::Probe(probe) if probe.answers_on_stdout() => Target::Stderr,
+            _ => Target::Stdout,
+        }
+    }
+
+    /// See `CliError` for the exit codes
+    fn run(self, limits: &ResourceLimits) -> ExitCode {
         match self {
-            Self::Generate(gen) => gen.run(),
-            Self::Explore(expl) => expl.run(),
-            Self::Probe(probe) => probe.run(),
+            Self::Generate(gen) => return gen.run(limits),
+            Self::Retag(retag) => return retag.run(limits),
+            Self::WinWithin(win_within) => return win_within.run(),
+            Self::CheckCompat(check_compat) => return check_compat.run(),
+            Self::Count(count) => return count.run(),
+            Self::Explore(expl) => return expl.run(),
+            Self::Export(export) => return export.run(limits),
+            Self::Probe(probe) => return probe.run(),
             Self::CheckIndexer(check_index) => check_index.run(),
-            Self::Diff(diff) => diff.run(),
-            Self::Verify(verify) => verify.run(),
-            Self::Convert(convert) => convert.run(),
+            Self::Diff(diff) => return diff.run(),
+            Self::Verify(verify) => return verify.run(),
+            Self::Convert(convert) => return convert.run(),
+            Self::Completions(completions) => {
+                if let Err(e) = completions.run() {
+                    eprintln!("Failed to write completions: {e}");
+                    return ExitCode::from(CliError::from(e).exit_code());
+                }
+            }
         }
+        ExitCode::SUCCESS
     }
 }
 
-fn main() {
+fn main() -> ExitCode {
     #[cfg(feature = "dhat")]
     let _profiler = dhat::Profiler::new_heap();
     let args = Cli::parse();
@@ -86,11 +185,19 @@ fn main() {
             },
         )
         .default_format()
-        .target(Target::Stdout);
+        .target(args.cmd.log_target());
 
     if args.no_time {
         builder.format_timestamp(None);
     }
     builder.init();
-    args.cmd.run()
+    let limits = ResourceLimits {
+        max_ram_bytes: args.max_ram,
+        max_threads: args.max_threads,
+        scratch_dir: args.scratch_dir,
+    };
+    helpmate_tb::limit_block_cache(&limits);
+    // the panic message is already printed by the default hook
+    panic::catch_unwind(AssertUnwindSafe(|| args.cmd.run(&limits)))
+        .unwrap_or_else(|_| ExitCode::from(CliError::INTERNAL_EXIT_CODE))
 }
@@ -1,19 +1,28 @@
-pub use helpmate_tb::{
-    to_chess_with_illegal_checks, Common, EncoderDecoder, Material, MaterialWinner, TablebaseProber,
+use from_str_sequential::FromStrSequential;
+use helpmate_tb::{
+    to_chess_with_illegal_checks, CastlingPolicy, Indexer, KingSquares, Material, MoveOrdering,
+    NaiveIndexer, Outcome, PartialProbe, ProbeOptions, ProbeResponse, TablebaseProber,
 };
-use helpmate_tb::{Indexer, NaiveIndexer, RetrieveOutcome};
 
-use log::{debug, info};
+use log::{debug, info, warn};
 use retroboard::shakmaty::fen::Fen;
 
-use retroboard::shakmaty::{Chess, Color, Position};
+use retroboard::shakmaty::{CastlingMode, Chess, Color, EnPassantMode, Position};
 use retroboard::RetroBoard;
 
-use std::path::PathBuf;
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
 
 use clap::{ArgAction, Args};
 
-fn from_fen(fen: &str) -> Result<Chess, &'static str> {
+use crate::error::{exit_code, missing_table, CliError, RunSummary};
+
+pub(crate) fn from_fen(fen: &str) -> Result<Chess, &'static str> {
     Fen::from_ascii(fen.as_bytes())
         .map_err(|_| "statically invalid fen")
         .and_then(|fen| {
@@ -21,50 +30,577 @@ fn from_fen(fen: &str) -> Result<Chess, &'static str> {
         })
 }
 
-/// Retrieve the outcome of a specific position
+#[derive(Debug, Clone, FromStrSequential)]
+enum CliCastling {
+    Reject,
+    Search,
+}
+
+impl From<&CliCastling> for CastlingPolicy {
+    fn from(castling: &CliCastling) -> Self {
+        match castling {
+            CliCastling::Reject => Self::Reject,
+            CliCastling::Search => Self::Search,
+        }
+    }
+}
+
+#[derive(Debug, Clone, FromStrSequential)]
+pub enum CliMoveOrdering {
+    Exhaustive,
+    Heuristic,
+}
+
+impl From<&CliMoveOrdering> for MoveOrdering {
+    fn from(move_ordering: &CliMoveOrdering) -> Self {
+        match move_ordering {
+            CliMoveOrdering::Exhaustive => Self::Exhaustive,
+            CliMoveOrdering::Heuristic => Self::Heuristic,
+        }
+    }
+}
+
+/// Retrieve the outcome of a specific position, or of many positions with `--input` or `--stdin`
 #[derive(Args, Debug)]
 pub struct Probe {
-    #[arg(short, long, value_parser = from_fen, name = "fen")]
-    chess: Chess,
+    #[arg(short, long, value_parser = from_fen, name = "fen", required_unless_present_any = ["input", "diagnose", "stdin"], conflicts_with = "input")]
+    chess: Option<Chess>,
     #[arg(short, long)]
     winner: Color,
     #[arg(long, default_value = "table/")]
     tb_dir: PathBuf,
     #[arg(long, action = ArgAction::SetTrue)]
     expanded: bool,
+    /// Display all the moves leading to the best outcome at each ply, instead of only one
+    #[arg(long, action = ArgAction::SetTrue)]
+    all_optimal: bool,
+    /// Report whether the optimal first move is unique, and list all of them otherwise ("cooks")
+    #[arg(long, action = ArgAction::SetTrue)]
+    cooks: bool,
+    /// Also display the outcome if the other color was the winner
+    #[arg(long, action = ArgAction::SetTrue)]
+    both_winners: bool,
+    /// Stop the line after that many plies
+    #[arg(long)]
+    max_plies: Option<usize>,
+    /// Tolerate missing descendant tables, their positions being considered of unknown outcome
+    #[arg(long, action = ArgAction::SetTrue)]
+    partial: bool,
+    /// File of positions to probe, one FEN or EPD per line. Empty lines and lines starting with `#` are skipped
+    #[arg(long, requires = "output")]
+    input: Option<PathBuf>,
+    /// Where to write the results of `--input`, as JSON lines if the extension is `jsonl`, one `ProbeResponse` by line,
+    /// CSV otherwise
+    #[arg(long, requires = "input")]
+    output: Option<PathBuf>,
+    /// With `--input`, also output the best move of each position, and the whole best line in JSON lines
+    #[arg(long, action = ArgAction::SetTrue)]
+    best_move: bool,
+    /// Show how a position, legal or not, is looked up in the tables: canonical index,
+    /// symmetry applied and raw stored byte. Missing tables are tolerated
+    #[arg(long, conflicts_with_all = ["fen", "input"])]
+    diagnose: Option<Fen>,
+    /// Read one FEN per line from stdin until it is closed, answering each with a
+    /// `outcome,best_move,dtm` line on stdout as soon as it is probed. Tables are kept open between lines.
+    /// Logs are written to stderr instead, not to be mixed with the answers
+    #[arg(long, action = ArgAction::SetTrue, conflicts_with_all = ["fen", "input", "diagnose"])]
+    stdin: bool,
+    #[arg(
+        long,
+        default_value = "reject",
+        value_parser = CliCastling::from_str_sequential,
+        help = "Positions with castling rights are not part of the tables. `search` also considers castling moves, assuming no castling rights are left after them"
+    )]
+    castling: CliCastling,
+    #[arg(
+        long,
+        default_value = "exhaustive",
+        value_parser = CliMoveOrdering::from_str_sequential,
+        help = "`heuristic` probes checks, captures and moves bringing the kings closer first, and plays the first optimal move found. Much faster for long lines, but only one optimal move is shown at each ply, not necessarily the lowest one"
+    )]
+    move_ordering: CliMoveOrdering,
+    /// Among the optimal moves of each ply, play a non-capture if there is one, and tell whether
+    /// the line shown ends up without any capture
+    #[arg(long, action = ArgAction::SetTrue)]
+    prefer_capture_free: bool,
+    /// Follow an optimal line mating the loser's king on these squares, `corners` or a list like
+    /// `d4,e4,d5,e5`, if there is one, and tell whether the line shown ends that way. Only searched with
+    /// `--move-ordering exhaustive`. The regular tables are probed, so a mate on these squares is only
+    /// found if it is among the shortest ones, see `generate --mated-king-on`
+    #[arg(long, value_parser = crate::parse_king_squares)]
+    mated_king_on: Option<KingSquares>,
+}
+
+/// `--stdin` answer of a line that cannot be probed, commas being replaced so that the columns stay aligned
+fn error_answer(err: impl std::fmt::Display) -> String {
+    format!("error: {},,", err.to_string().replace(',', ";"))
+}
+
+/// Result of probing one line of the `--input` file
+struct BulkResult {
+    fen: String,
+    outcome: Result<Outcome, String>,
+    best_move: Option<String>,
+    /// Only filled for JSON lines outputs
+    best_line: Vec<String>,
 }
 
 impl Probe {
-    pub fn run(self) {
-        let material = Material::from_board(self.chess.board());
-        let tb_prober: TablebaseProber = TablebaseProber::new(&material, &self.tb_dir);
+    /// With `--stdin`, answers are streamed on stdout, logs must not go there
+    pub fn answers_on_stdout(&self) -> bool {
+        self.stdin
+    }
+
+    /// See `CliError` for the exit codes. With `--input`, positions that cannot be probed
+    /// are reported in the output, only the materials whose tables cannot be opened fail the run
+    pub fn run(self) -> ExitCode {
+        if let (Some(input), Some(output)) = (&self.input, &self.output) {
+            return self.run_bulk(input, output);
+        }
+        if self.stdin {
+            return exit_code(self.run_stdin(io::stdin().lock(), io::stdout().lock()));
+        }
+        if let Some(fen) = &self.diagnose {
+            return exit_code(self.run_diagnose(fen));
+        }
+        exit_code(self.run_one())
+    }
+
+    fn run_one(&self) -> Result<(), CliError> {
+        let chess = self.chess.clone().expect("fen required without --input");
+        let material = Material::from_board(chess.board());
+        let tb_prober = self.prober(&material)?;
+        // with `--partial`, the table of the position may be missing
         let outcome = tb_prober
-            .retrieve_outcome(&self.chess, self.winner)
-            .unwrap();
-        let (move_list, pos_list) = tb_prober.probe(&self.chess, self.winner).unwrap();
-        let uci_movelist: Vec<String> = move_list
+            .probe_outcome(&chess, self.winner)
+            .map_err(missing_table)?;
+        let PartialProbe {
+            optimal_moves_list,
+            positions: pos_list,
+            complete,
+            truncated,
+            capture_free,
+            mated_king_on,
+            symmetry,
+        } = match tb_prober.probe_with_options(
+            &chess,
+            self.winner,
+            &ProbeOptions {
+                move_ordering: (&self.move_ordering).into(),
+                max_plies: self.max_plies,
+                prefer_capture_free: self.prefer_capture_free,
+                mated_king_on: self.mated_king_on,
+            },
+        ) {
+            Ok(partial_probe) => partial_probe,
+            // eg the winner cannot mate
+            Err(e) => {
+                info!("Outcome is {outcome:?}, no line to show: {e}");
+                return Ok(());
+            }
+        };
+        debug!("Symmetry to the canonical position looked up: {symmetry:?}");
+        if !complete {
+            warn!("Some needed tables are missing, the line may not be optimal nor complete");
+        }
+        if truncated {
+            warn!("Line cut after {} plies", optimal_moves_list.len());
+        }
+        if self.prefer_capture_free {
+            if capture_free {
+                info!("The line is capture-free");
+            } else {
+                info!("The line has captures, at plies where every optimal move is one");
+            }
+        }
+        match mated_king_on {
+            Some(true) => info!("The line ends with the loser's king mated on one of the squares"),
+            Some(false) => {
+                info!("The line does not end with the loser's king mated on one of the squares")
+            }
+            None => (),
+        }
+        let uci_movelist: Vec<String> = optimal_moves_list
             .into_iter()
-            .map(|m| {
-                m.to_uci(retroboard::shakmaty::CastlingMode::Standard)
-                    .to_string()
+            .map(|optimal_moves| {
+                let ucis: Vec<String> = optimal_moves
+                    .iter()
+                    .take(if self.all_optimal { usize::MAX } else { 1 })
+                    .map(|m| {
+                        m.to_uci(retroboard::shakmaty::CastlingMode::Standard)
+                            .to_string()
+                    })
+                    .collect();
+                ucis.join("|")
             })
             .collect();
-        let rboard = RetroBoard::from(self.chess);
+        if self.both_winners {
+            let outcomes = tb_prober.retrieve_outcome_both_winners(&chess)?;
+            info!(
+                "Outcome if white wins: {:?}, if black wins: {:?}",
+                outcomes.white, outcomes.black
+            );
+        }
+        if self.cooks {
+            match tb_prober.cooks(&chess, self.winner)? {
+                None => info!("Optimal first move is unique"),
+                Some(cooks) => {
+                    let uci_cooks: Vec<String> = cooks
+                        .iter()
+                        .map(|m| {
+                            m.to_uci(retroboard::shakmaty::CastlingMode::Standard)
+                                .to_string()
+                        })
+                        .collect();
+                    info!("Cooks, optimal first moves: {uci_cooks:?}");
+                }
+            }
+        }
+        let rboard = RetroBoard::from(chess);
         info!(
             "For {:?}\nOutcome is {outcome:?}, Moves: {uci_movelist:?}",
             rboard,
         );
-        debug!("Naive indexer idx: {:?}", NaiveIndexer.encode(&rboard));
+        debug!(
+            "Naive indexer idx: {:?}",
+            NaiveIndexer::from(material.clone()).encode(&rboard)
+        );
         if self.expanded {
             let rboards_fmt: Vec<String> = pos_list
                 .into_iter()
                 .map(|p| {
                     let r = RetroBoard::from(p);
-                    let idx = NaiveIndexer.encode(&r);
+                    let idx = NaiveIndexer::from(material.clone()).encode(&r);
                     format!("{r:?}, idx: {idx:?}")
                 })
                 .collect();
             info!("{}", rboards_fmt.join("\n"));
         }
+        Ok(())
+    }
+
+    /// Without `--partial`, fails if a needed table is missing
+    fn prober(&self, material: &Material) -> io::Result<TablebaseProber> {
+        let tb_prober = if self.partial {
+            TablebaseProber::try_new_partial(material, &self.tb_dir)?
+        } else {
+            TablebaseProber::try_new(material, &self.tb_dir).map_err(missing_table)?
+        };
+        Ok(tb_prober.with_castling_policy((&self.castling).into()))
+    }
+
+    fn run_diagnose(&self, fen: &Fen) -> Result<(), CliError> {
+        let material = Material::from_board(&fen.as_setup().board);
+        let tb_prober: TablebaseProber = TablebaseProber::try_new_partial(&material, &self.tb_dir)?;
+        let diagnosis = tb_prober.diagnose(fen)?;
+        if let Some(reason) = &diagnosis.illegal {
+            warn!("Illegal position: {reason}");
+        }
+        info!(
+            "Table {:?}, {:?}, index {:?}\nCanonical board:\n{:?}",
+            diagnosis.material, diagnosis.symmetry, diagnosis.index, diagnosis.canonical_board
+        );
+        for winner in Color::ALL {
+            match diagnosis.raw_outcomes.get(winner) {
+                Some(raw) => info!(
+                    "If {winner:?} wins: raw byte {raw}, outcome {:?}",
+                    diagnosis.outcome(winner)
+                ),
+                None => info!("If {winner:?} wins: no table"),
+            }
+        }
+        Ok(())
+    }
+
+    /// `None` as well when the winner cannot mate
+    fn best_move_uci(&self, tb_prober: &TablebaseProber, chess: &Chess) -> Option<String> {
+        tb_prober
+            .best_move(chess, self.winner)
+            .ok()
+            .flatten()
+            .map(|m| m.to_uci(CastlingMode::Standard).to_string())
+    }
+
+    /// Every line gets an answer, empty ones and ones not valid UTF-8 included, so that callers can match them
+    /// one to one. The distance to mate is in plies, empty for draws and errors. Ends once `output` is closed
+    fn run_stdin(&self, mut input: impl BufRead, mut output: impl Write) -> Result<(), CliError> {
+        let mut probers: HashMap<Material, Result<TablebaseProber, String>> = HashMap::new();
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            if input.read_until(b'\n', &mut line)? == 0 {
+                return Ok(());
+            }
+            let answer = match from_fen(String::from_utf8_lossy(&line).trim()) {
+                Ok(chess) => {
+                    let material = Material::from_board(chess.board());
+                    match probers
+                        .entry(material.clone())
+                        .or_insert_with(|| self.prober(&material).map_err(|e| e.to_string()))
+                    {
+                        Ok(tb_prober) => match tb_prober.probe_outcome(&chess, self.winner) {
+                            Ok(outcome) => {
+                                let best_move =
+                                    self.best_move_uci(tb_prober, &chess).unwrap_or_default();
+                                let dtm = match outcome {
+                                    Outcome::Win(plies) | Outcome::Lose(plies) => plies.to_string(),
+                                    _ => String::new(),
+                                };
+                                format!("{outcome:?},{best_move},{dtm}")
+                            }
+                            Err(e) => error_answer(e),
+                        },
+                        Err(e) => error_answer(e),
+                    }
+                }
+                Err(e) => error_answer(e),
+            };
+            match writeln!(output, "{answer}").and_then(|()| output.flush()) {
+                // whoever reads the answers is gone, eg `head`
+                Err(e) if e.kind() == io::ErrorKind::BrokenPipe => return Ok(()),
+                res => res?,
+            }
+        }
+    }
+
+    /// Positions are grouped by material, so that the tables of each material are only opened once
+    fn run_bulk(&self, input: &Path, output: &Path) -> ExitCode {
+        let mut summary = RunSummary::default();
+        let jsonl = output.extension().is_some_and(|ext| ext == "jsonl");
+        let file = match File::open(input) {
+            Ok(file) => file,
+            Err(e) => {
+                summary.fail(input.display(), CliError::Config(e.to_string()));
+                return summary.finish();
+            }
+        };
+        // unreadable lines get an error record, like unparsable ones
+        let lines: Vec<io::Result<String>> = BufReader::new(file)
+            .lines()
+            .filter(|l| {
+                l.as_ref()
+                    .map_or(true, |l| !l.trim().is_empty() && !l.starts_with('#'))
+            })
+            .collect();
+        let mut results: Vec<Option<BulkResult>> = lines.iter().map(|_| None).collect();
+        let mut by_material: HashMap<Material, Vec<(usize, Chess)>> = HashMap::new();
+        for (i, line) in lines.iter().enumerate() {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => {
+                    results[i] = Some(BulkResult {
+                        fen: String::new(),
+                        outcome: Err(e.to_string()),
+                        best_move: None,
+                        best_line: Vec::new(),
+                    });
+                    continue;
+                }
+            };
+            // EPD operations, if any, come after the 4 first fields
+            let fen = line
+                .split_whitespace()
+                .take(4)
+                .collect::<Vec<_>>()
+                .join(" ");
+            match from_fen(&fen) {
+                Ok(chess) => by_material
+                    .entry(Material::from_board(chess.board()))
+                    .or_default()
+                    .push((i, chess)),
+                Err(e) => {
+                    results[i] = Some(BulkResult {
+                        fen,
+                        outcome: Err(e.to_string()),
+                        best_move: None,
+                        best_line: Vec::new(),
+                    });
+                }
+            }
+        }
+        for (material, positions) in by_material {
+            info!("Probing {} positions of {material:?}", positions.len());
+            let tb_prober = match self.prober(&material) {
+                Ok(tb_prober) => {
+                    summary.succeed();
+                    tb_prober
+                }
+                Err(e) => {
+                    let msg = e.to_string();
+                    summary.fail(format!("{material:?}"), e);
+                    for (i, chess) in positions {
+                        results[i] = Some(BulkResult {
+                            fen: Fen::from_position(chess, EnPassantMode::Legal).to_string(),
+                            outcome: Err(msg.clone()),
+                            best_move: None,
+                            best_line: Vec::new(),
+                        });
+                    }
+                    continue;
+                }
+            };
+            for (i, chess) in positions {
+                let outcome = tb_prober
+                    .probe_outcome(&chess, self.winner)
+                    .map_err(|e| e.to_string());
+                // `None` as well when the winner cannot mate
+                let best_move = (self.best_move && outcome.is_ok())
+                    .then(|| self.best_move_uci(&tb_prober, &chess));
+                let best_line = if jsonl && best_move.is_some() {
+                    best_line_uci(&tb_prober, &chess, self.winner)
+                } else {
+                    Vec::new()
+                };
+                results[i] = Some(BulkResult {
+                    fen: Fen::from_position(chess.clone(), EnPassantMode::Legal).to_string(),
+                    outcome,
+                    best_move: best_move.flatten(),
+                    best_line,
+                });
+            }
+            #[cfg(feature = "cached")]
+            debug!(
+                "Block cache after {material:?}: {:?}",
+                helpmate_tb::block_cache_stats()
+            );
+        }
+        let results = results.into_iter().flatten();
+        let written = File::create(output).map_err(|e| CliError::Config(e.to_string()));
+        if let Err(e) = written.and_then(|file| {
+            let writer = BufWriter::new(file);
+            if jsonl {
+                self.write_bulk_jsonl(writer, results)
+            } else {
+                write_bulk_csv(writer, results)
+            }
+            .map_err(CliError::from)
+        }) {
+            summary.fail(output.display(), e);
+        }
+        summary.finish()
+    }
+
+    fn write_bulk_jsonl(
+        &self,
+        mut writer: impl Write,
+        results: impl Iterator<Item = BulkResult>,
+    ) -> io::Result<()> {
+        for result in results {
+            let response = ProbeResponse {
+                best_move: result.best_move,
+                best_line: result.best_line,
+                ..ProbeResponse::new(result.fen, self.winner, result.outcome)
+            };
+            serde_json::to_writer(&mut writer, &response)?;
+            writeln!(writer)?;
+        }
+        writer.flush()
+    }
+}
+
+/// `fen,outcome,best_move` rows, fields being quoted if needed, eg error messages containing commas
+fn write_bulk_csv(writer: impl Write, results: impl Iterator<Item = BulkResult>) -> io::Result<()> {
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record(["fen", "outcome", "best_move"])?;
+    for result in results {
+        let outcome = match result.outcome {
+            Ok(outcome) => format!("{outcome:?}"),
+            Err(e) => format!("error: {e}"),
+        };
+        let best_move = result.best_move.unwrap_or_default();
+        writer.write_record([&result.fen, &outcome, &best_move])?;
+    }
+    writer.flush()
+}
+
+/// Moves of `TablebaseProber::probe`, empty if it fails
+fn best_line_uci(tb_prober: &TablebaseProber, chess: &Chess, winner: Color) -> Vec<String> {
+    tb_prober
+        .probe(chess, winner)
+        .map(|(move_list, _)| {
+            move_list
+                .iter()
+                .map(|m| m.to_uci(CastlingMode::Standard).to_string())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_bulk_csv() {
+        let results = [
+            BulkResult {
+                fen: "8/8/8/8/8/2k5/8/KQ6 w - -".to_string(),
+                outcome: Ok(Outcome::Win(7)),
+                best_move: Some("a1a2".to_string()),
+                best_line: Vec::new(),
+            },
+            BulkResult {
+                fen: "not a fen".to_string(),
+                outcome: Err("invalid fen, \"not a fen\"".to_string()),
+                best_move: None,
+                best_line: Vec::new(),
+            },
+        ];
+        let mut csv = Vec::new();
+        write_bulk_csv(&mut csv, results.into_iter()).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "fen,outcome,best_move\n8/8/8/8/8/2k5/8/KQ6 w - -,Win(7),a1a2\nnot a fen,\"error: invalid fen, \"\"not a fen\"\"\",\n"
+        );
+    }
+
+    fn stdin_probe() -> Probe {
+        #[derive(clap::Parser)]
+        struct Cli {
+            #[command(flatten)]
+            probe: Probe,
+        }
+
+        <Cli as clap::Parser>::parse_from([
+            "probe", "--stdin", "-w", "white", "--tb-dir", "../table",
+        ])
+        .probe
+    }
+
+    #[test]
+    fn test_run_stdin() {
+        let probe = stdin_probe();
+        let input: &[u8] =
+            b"8/8/8/8/8/2k5/8/KQ6 w - - 0 1\n\xff\xfe\n\n8/8/8/8/8/2k5/8/KQ6 w - - 0 1\n";
+        let mut output = Vec::new();
+        probe.run_stdin(input, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let answers: Vec<&str> = output.lines().collect();
+        assert_eq!(answers.len(), 4, "{output}");
+        assert_eq!(answers[0], answers[3]);
+        assert!(answers[0].starts_with("Win(7),"), "{output}");
+        for answer in &answers[1..3] {
+            assert!(answer.starts_with("error: "), "{output}");
+            assert_eq!(answer.matches(',').count(), 2, "{output}");
+        }
+        assert_eq!(error_answer("a, b"), "error: a; b,,");
+    }
+
+    #[test]
+    fn test_run_stdin_broken_pipe() {
+        struct Closed;
+
+        impl Write for Closed {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::ErrorKind::BrokenPipe.into())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let probe = stdin_probe();
+        assert!(probe.run_stdin(&b"not a fen\n"[..], Closed).is_ok());
     }
 }
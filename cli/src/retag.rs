@@ -0,0 +1,136 @@
+use from_str_sequential::FromStrSequential;
+use helpmate_tb::{
+    BuildOptions, EncoderDecoder, FortressTable, KingSquares, Objective, ResourceLimits,
+    TableBaseBuilder, TableName, TablePath, WinCondition,
+};
+use log::info;
+
+use std::{fs::File, io, path::PathBuf, process::ExitCode};
+
+use clap::{ArgAction, Args};
+use retroboard::shakmaty::Color;
+
+use crate::error::{exit_code, missing_table, CliError, RunSummary};
+use crate::explore::MatOrAll;
+use crate::generate::{
+    log_build_report, win_condition, win_objective, write_table, CliObjective, Trailers,
+};
+use crate::MaterialWinner;
+
+/// Re-run the tagging on existing tables, without enumerating the positions again.
+/// Useful after a fix in the tagging, the tables are overwritten unless `--output-dir` is given.
+/// They are written with the compression and the trailers they were generated with
+#[derive(Args, Debug)]
+pub struct Retag {
+    #[arg(help = "example \"KQvK\", use special value 'all' to retag all tables", value_parser = MatOrAll::from_str_sequential)]
+    mat_or_all: MatOrAll,
+    #[arg(
+        short,
+        long,
+        help = "Color of the expected winner. If no color is provided, will retag both"
+    )]
+    winner: Option<Color>,
+    #[arg(long, default_value = "table/")]
+    tb_dir: PathBuf,
+    #[arg(
+        long,
+        help = "Directory to write the retagged tables in, defaults to `--tb-dir`"
+    )]
+    output_dir: Option<PathBuf>,
+    #[arg(
+        long,
+        help = "Directory of the fortress tables written by `generate --fortress-dir`. The fortress table of each table retagged is written again there"
+    )]
+    fortress_dir: Option<PathBuf>,
+    #[arg(long, action = ArgAction::SetTrue, help = "Retag the tables generated with `generate --keep-all-pieces`")]
+    keep_all_pieces: bool,
+    #[arg(
+        long,
+        value_parser = crate::parse_king_squares,
+        conflicts_with = "keep_all_pieces",
+        help = "Retag the tables generated with `generate --mated-king-on` and the same squares"
+    )]
+    mated_king_on: Option<KingSquares>,
+    #[arg(
+        long,
+        default_value = "helpmate",
+        value_parser = CliObjective::from_str_sequential,
+        help = "Retag the tables generated with `generate --objective`. Not compatible with --keep-all-pieces, --mated-king-on nor --fortress-dir"
+    )]
+    objective: CliObjective,
+}
+
+impl Retag {
+    /// Fails if any table could not be written
+    pub fn run(&self, limits: &ResourceLimits) -> ExitCode {
+        let options = BuildOptions {
+            win_condition: win_condition(self.keep_all_pieces, self.mated_king_on),
+            objective: (&self.objective).into(),
+            resource_limits: limits.clone(),
+            ..Default::default()
+        };
+        if options.objective == Objective::Selfmate
+            && (options.win_condition != WinCondition::AnyMate || self.fortress_dir.is_some())
+        {
+            return exit_code(Err(CliError::Config(
+                "selfmates support neither --keep-all-pieces, --mated-king-on nor --fortress-dir"
+                    .to_string(),
+            )));
+        }
+        let mat_winners = match self.mat_or_all.mat_winners(&self.tb_dir, self.winner) {
+            Ok(mat_winners) => mat_winners,
+            Err(e) => return exit_code(Err(e)),
+        };
+        let mut summary = RunSummary::default();
+        for mat_win in mat_winners {
+            summary.record(
+                format!("{mat_win:?}"),
+                self.retag_one_mat(&mat_win, &options),
+            );
+        }
+        summary.finish()
+    }
+
+    fn retag_one_mat(&self, mat_win: &MaterialWinner, options: &BuildOptions) -> io::Result<()> {
+        info!("Retagging {mat_win:?}");
+        TableBaseBuilder::check_resources(mat_win, &self.tb_dir, options).map_err(missing_table)?;
+        let table_name = TableName::new(mat_win, options.objective, options.win_condition);
+        let decoder = EncoderDecoder::new(
+            File::open(table_name.path_in(&self.tb_dir)).map_err(missing_table)?,
+        );
+        let outcomes = decoder.decompress_file()?;
+        // the blocks of a table are all written with the same options
+        let compression_options = decoder
+            .table_format()?
+            .blocks
+            .first()
+            .copied()
+            .unwrap_or_default();
+        let trailers = Trailers {
+            undefined_spans: decoder.undefined_spans()?.is_some(),
+            outcome_distrib: decoder.outcome_distrib()?.is_some(),
+            win_objective: decoder
+                .win_objective()?
+                .and(win_objective(mat_win, options.objective)),
+        };
+        let (common, build_report) =
+            TableBaseBuilder::retag(mat_win.clone(), &outcomes, &self.tb_dir, options)
+                .map_err(missing_table)?;
+        log_build_report(mat_win, &build_report);
+        if let Some(fortress_dir) = self.fortress_dir.as_ref() {
+            std::fs::create_dir_all(fortress_dir)?;
+            FortressTable::from_common(&common).write(File::create(
+                TablePath::new(fortress_dir).fortress(mat_win),
+            )?)?;
+        }
+        let output_dir = self.output_dir.as_ref().unwrap_or(&self.tb_dir);
+        std::fs::create_dir_all(output_dir)?;
+        write_table(
+            &TablePath::new(output_dir).table_to_write(&table_name),
+            common.all_pos(),
+            compression_options,
+            trailers,
+            &options.resource_limits,
+        )
+    }
+}
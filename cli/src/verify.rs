@@ -1,12 +1,13 @@
 use from_str_sequential::FromStrSequential;
-pub use helpmate_tb::{
-    Common, EncoderDecoder, Material, MaterialWinner, Outcome, SideToMoveGetter, TableBaseBuilder,
-    UNDEFINED_OUTCOME_BYCOLOR,
+use helpmate_tb::{
+    check_board, check_reports, iter_defined, swap_color_board, BuildOptions, DeIndexer,
+    Descendants, EncoderDecoder, FileHandler, IndexWithTurn, Indexer, MaterialWinner, Objective,
+    Outcome, OutcomeDistrib, RetrieveOutcome, TableBaseBuilder, TableFormatError, TableName,
+    TablebaseProber, Transformation, WinObjective,
 };
-use helpmate_tb::{DeIndexer, Descendants, FileHandler, IndexWithTurn, Indexer, RetrieveOutcome};
 use log::{debug, error, info};
 
-use std::path::PathBuf;
+use std::{fs::File, io, iter, path::PathBuf, process::ExitCode};
 
 use retroboard::{
     shakmaty::{Chess, Color, Position},
@@ -15,6 +16,7 @@ use retroboard::{
 
 use clap::Args;
 
+use crate::error::{exit_code, missing_table, CliError, RunSummary};
 use crate::explore::MatOrAll;
 
 /// Perform sanity checks on given helpmate files
@@ -22,64 +24,432 @@ use crate::explore::MatOrAll;
 pub struct Verify {
     #[arg(help = "example \"KQvK\", use special value 'all' to search across all positions", value_parser = MatOrAll::from_str_sequential)]
     mat_or_all: MatOrAll,
+    #[arg(
+        short,
+        long,
+        help = "Color of the expected winner. If no color is provided, will verify both"
+    )]
+    winner: Option<Color>,
     #[arg(long, default_value = "table/")]
     tb_dir: PathBuf,
+    #[arg(
+        long,
+        default_value_t = 1000,
+        help = "Number of indexes of each table, spread evenly, whose positions are probed again once transformed by a symmetry of the board, and once with colors swapped. The outcome must not change. 0 to skip"
+    )]
+    symmetry_samples: usize,
+    #[arg(
+        long,
+        help = "Instead of checking every move of every position, only check positions with captures or promotions against the descendants tables: the outcome stored must be the one re-derived from them when all moves are captures or promotions, and never worse otherwise. Catches descendants tables regenerated since, see --descendants-samples"
+    )]
+    descendants: bool,
+    #[arg(
+        long,
+        default_value_t = 10_000,
+        help = "With --descendants, number of positions checked per table, the first one with a capture or a promotion after indexes spread evenly"
+    )]
+    descendants_samples: usize,
+    #[arg(
+        long,
+        help = "Succeed only if every table is rejected as malformed when loading, logging why. Used on corrupted tables, to check they fail with a meaningful error"
+    )]
+    expect_fail: bool,
+    #[arg(
+        long,
+        help = "Stop at the first truncated or non-contiguous block of a table instead of failing, verifying only the blocks before it"
+    )]
+    lenient: bool,
 }
 
 impl Verify {
-    pub fn run(&self) {
-        for mat_win in self.mat_or_all.mat_winners(&self.tb_dir, None) {
-            self.verify_one_mat(mat_win)
+    /// Fails if any table could not be loaded, or with `--expect-fail` if any was not rejected as malformed
+    pub fn run(&self) -> ExitCode {
+        let mat_winners = match self.mat_or_all.mat_winners(&self.tb_dir, self.winner) {
+            Ok(mat_winners) => mat_winners,
+            Err(e) => return exit_code(Err(e)),
+        };
+        let mut summary = RunSummary::default();
+        for mat_win in mat_winners {
+            match (self.load(&mat_win), self.expect_fail) {
+                (Ok(file_handler), false) => {
+                    summary.record(
+                        format!("{mat_win:?}"),
+                        self.verify_one_mat(&mat_win, &file_handler),
+                    );
+                }
+                (Ok(_), true) => summary.fail(
+                    format!("{mat_win:?}"),
+                    CliError::Internal("loaded, but was expected to fail".to_string()),
+                ),
+                (Err(e), true) if TableFormatError::of(&e).is_some() => {
+                    info!("{mat_win:?} failed to load as expected: {e}");
+                    summary.succeed();
+                }
+                (Err(e), _) => summary.fail(format!("{mat_win:?}"), e),
+            }
         }
+        summary.finish()
     }
 
-    fn verify_one_mat(&self, mat_win: MaterialWinner) {
+    fn load(&self, mat_win: &MaterialWinner) -> io::Result<FileHandler> {
+        let path = TableName::helpmate(mat_win).path_in(&self.tb_dir);
+        let mut encoder_decoder = EncoderDecoder::new(File::open(&path).map_err(missing_table)?);
+        if self.lenient {
+            encoder_decoder = encoder_decoder.lenient();
+        }
+        Ok(FileHandler {
+            indexer: mat_win.material.clone().into(),
+            outcomes: encoder_decoder.decompress_file()?,
+            path: Some(path),
+        })
+    }
+
+    /// Fails if the outcome counts stored in the table differ from its outcomes, or with `--descendants`,
+    /// if a descendant table is missing or inconsistent with the table
+    fn verify_one_mat(
+        &self,
+        mat_win: &MaterialWinner,
+        file_handler: &FileHandler,
+    ) -> Result<(), CliError> {
         info!(
             "Verifying {:?} with winner: {}",
             mat_win.material, mat_win.winner
         );
-        let file_handler: FileHandler = FileHandler::new(&mat_win, &self.tb_dir);
-        let descendants: Descendants = Descendants::new(&mat_win, &self.tb_dir);
+        // only the salvaged blocks are loaded with `--lenient`
+        if !self.lenient {
+            Self::verify_outcome_distrib(mat_win, file_handler)?;
+            Self::verify_win_objective(mat_win, file_handler)?;
+        }
+        check_reports(
+            &file_handler.outcomes,
+            0,
+            mat_win.winner,
+            Objective::Helpmate,
+        )
+        .map_err(|violation| CliError::CorruptTable(format!("{mat_win:?}: {violation}")))?;
+        if self.symmetry_samples > 0 {
+            self.verify_symmetries(mat_win, file_handler)?;
+        }
+        if self.descendants {
+            return self.verify_descendants(mat_win, file_handler);
+        }
+        let descendants: Descendants = Descendants::new(mat_win, &self.tb_dir);
         debug!("outcomes len: {}", file_handler.outcomes.len());
-        for (idx, by_color_outcome) in file_handler.outcomes.iter().enumerate() {
-            for turn in Color::ALL {
-                let outcome = by_color_outcome.get_by_color(turn);
-                assert_ne!(outcome, Outcome::Unknown);
-                if outcome != Outcome::Undefined {
-                    let idx_with_turn = IndexWithTurn {
+        for (idx_with_turn, outcome) in iter_defined(&file_handler.outcomes, 0) {
+            assert_ne!(outcome, Outcome::Unknown);
+            let rboard = file_handler
+                .indexer
+                .restore(&mat_win.material, idx_with_turn);
+            if let Err(violation) = check_board(rboard.board(), mat_win.material.has_pawns()) {
+                error!("idx: {idx_with_turn:?}, pos: {rboard:?}: {violation}");
+            }
+            let chess: Chess = rboard.clone().into();
+            for m in chess.legal_moves() {
+                let mut chess_after_move = chess.clone();
+                chess_after_move.play_unchecked(&m);
+                let outcome_after_m = if m.is_capture() {
+                    descendants.retrieve_outcome(&chess_after_move, mat_win.winner)
+                } else {
+                    let idx_after_m = file_handler.indexer.encode(&chess_after_move);
+                    file_handler.outcome_at(idx_after_m)
+                };
+                let outcome_after_m = match outcome_after_m {
+                    Ok(outcome_after_m) => outcome_after_m,
+                    Err(e) => {
+                        error!("idx: {idx_with_turn:?}, pos: {rboard:?}, after {m:?}: {e}");
+                        continue;
+                    }
+                };
+                assert_ne!(outcome_after_m, Outcome::Undefined);
+
+                if outcome_after_m + 1 > outcome {
+                    error!("idx: {idx_with_turn:?}, pos: {rboard:?} outcome is {outcome:?}, but after {m:?}, outcome is {outcome_after_m:?}");
+                    debug!(
+                        "unmoves after the move: {:?}",
+                        RetroBoard::from(chess_after_move).legal_unmoves()
+                    );
+                }
+            }
+            if idx_with_turn.idx % 100_000 == 0 {
+                debug!("idx: {idx_with_turn:?}")
+            }
+        }
+        Ok(())
+    }
+
+    /// Counting the outcomes is much cheaper than checking them, and catches corruptions of the table
+    /// when it was generated with `--outcome-distrib`
+    fn verify_outcome_distrib(
+        mat_win: &MaterialWinner,
+        file_handler: &FileHandler,
+    ) -> Result<(), CliError> {
+        let path = file_handler.path.as_ref().expect("loaded from a file");
+        let Some(stored) = EncoderDecoder::new(File::open(path)?).outcome_distrib()? else {
+            return Ok(());
+        };
+        let counted = OutcomeDistrib::from_outcomes(&file_handler.outcomes);
+        for turn in Color::ALL {
+            if stored.by_turn.get(turn) != counted.by_turn.get(turn) {
+                error!(
+                    "{mat_win:?} with {turn} to move, stored outcome counts {:?}, counted {:?}",
+                    stored.counts(turn),
+                    counted.counts(turn)
+                );
+            }
+        }
+        if stored == counted {
+            Ok(())
+        } else {
+            Err(CliError::CorruptTable(format!(
+                "{mat_win:?}: outcome counts stored in the table differ from its outcomes"
+            )))
+        }
+    }
+
+    /// The objective recorded by `generate` must be the one of the material, otherwise the table was tagged
+    /// for another winner or material
+    fn verify_win_objective(
+        mat_win: &MaterialWinner,
+        file_handler: &FileHandler,
+    ) -> Result<(), CliError> {
+        let path = file_handler.path.as_ref().expect("loaded from a file");
+        match EncoderDecoder::new(File::open(path)?).win_objective()? {
+            Some(stored) if stored != WinObjective::of(mat_win) => {
+                Err(CliError::CorruptTable(format!(
+                    "{mat_win:?}: the table aims for {stored:?}, but the material for {:?}",
+                    WinObjective::of(mat_win)
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Re-derives the outcome of positions with captures or promotions from the descendants tables,
+    /// as when generating, see `Descendants::outcome_from_captures_promotion`
+    fn verify_descendants(
+        &self,
+        mat_win: &MaterialWinner,
+        file_handler: &FileHandler,
+    ) -> Result<(), CliError> {
+        TableBaseBuilder::check_descendants(mat_win, &self.tb_dir, &BuildOptions::default())
+            .map_err(missing_table)?;
+        let descendants: Descendants = Descendants::new(mat_win, &self.tb_dir);
+        let len = file_handler.outcomes.len();
+        let step = (len / self.descendants_samples.max(1)).max(1);
+        let (mut nb_checked, mut nb_mismatches) = (0, 0);
+        for sample_start in (0..len).step_by(step).take(self.descendants_samples) {
+            let with_captures = (sample_start..(sample_start + step).min(len))
+                .flat_map(|idx| {
+                    Color::ALL.into_iter().map(move |turn| IndexWithTurn {
                         idx: idx as u64,
                         turn,
-                    };
-                    let rboard = file_handler
+                    })
+                })
+                .find_map(|idx_with_turn| {
+                    let outcome = file_handler.outcome_at(idx_with_turn).ok()?;
+                    if matches!(outcome, Outcome::Undefined | Outcome::Unknown) {
+                        return None;
+                    }
+                    let chess: Chess = file_handler
                         .indexer
-                        .restore(&mat_win.material, idx_with_turn);
-                    let chess: Chess = rboard.clone().into();
-                    for m in chess.legal_moves() {
-                        let mut chess_after_move = chess.clone();
-                        chess_after_move.play_unchecked(&m);
-                        let outcome_after_m = if m.is_capture() {
-                            descendants
-                                .retrieve_outcome(&chess_after_move, mat_win.winner)
-                                .unwrap()
-                        } else {
-                            let idx_after_m = file_handler.indexer.encode(&chess_after_move);
-                            file_handler.outcomes[idx_after_m.usize()].get_by_pos(&chess_after_move)
-                        };
-                        assert_ne!(outcome_after_m, Outcome::Undefined);
-
-                        if outcome_after_m + 1 > outcome {
-                            error!("idx: {idx_with_turn:?}, pos: {rboard:?} outcome is {outcome:?}, but after {m:?}, outcome is {outcome_after_m:?}");
-                            debug!(
-                                "unmoves after the move: {:?}",
-                                RetroBoard::from(chess_after_move).legal_unmoves()
-                            );
-                        }
+                        .restore(&mat_win.material, idx_with_turn)
+                        .into();
+                    chess
+                        .legal_moves()
+                        .iter()
+                        .any(|m| m.is_capture() || m.is_promotion())
+                        .then_some((idx_with_turn, chess, outcome))
+                });
+            let Some((idx_with_turn, chess, outcome)) = with_captures else {
+                continue;
+            };
+            nb_checked += 1;
+            let from_captures =
+                match descendants.outcome_from_captures_promotion(&chess, mat_win.winner) {
+                    Ok(from_captures) => from_captures,
+                    Err(e) => {
+                        error!("idx: {idx_with_turn:?}, pos: {}: {e}", chess.board());
+                        nb_mismatches += 1;
+                        continue;
                     }
+                };
+            if !is_consistent_with_captures(outcome, from_captures) {
+                error!(
+                    "idx: {idx_with_turn:?}, pos: {} outcome is {outcome:?}, but {from_captures:?} from the descendants tables (outcome, all moves are captures or promotions)",
+                    chess.board()
+                );
+                nb_mismatches += 1;
+            }
+        }
+        info!("{nb_checked} positions with captures or promotions checked, {nb_mismatches} inconsistent with the descendants tables");
+        if nb_mismatches > 0 {
+            return Err(CliError::CorruptTable(format!(
+                "{nb_mismatches} positions inconsistent with the descendants tables, which may have been regenerated since"
+            )));
+        }
+        Ok(())
+    }
+
+    /// Goes through `TablebaseProber`, as a user would, so that the stored outcomes are checked,
+    /// and not only the symmetries handled by the indexer
+    fn verify_symmetries(
+        &self,
+        mat_win: &MaterialWinner,
+        file_handler: &FileHandler,
+    ) -> Result<(), CliError> {
+        let prober: TablebaseProber =
+            TablebaseProber::try_new(&mat_win.material, &self.tb_dir).map_err(missing_table)?;
+        let (nb_checked, nb_mismatches) =
+            symmetry_mismatches(mat_win, file_handler, &prober, self.symmetry_samples)?;
+        info!("{nb_checked} symmetric positions probed, {nb_mismatches} with a different outcome");
+        if nb_mismatches > 0 {
+            return Err(CliError::CorruptTable(format!(
+                "{mat_win:?}: {nb_mismatches} positions with a different outcome once transformed by a symmetry"
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Number of symmetric positions probed with `prober`, and of the ones whose outcome differs from the one
+/// stored in `file_handler`, for `nb_samples` indexes spread evenly. Fails if a symmetric position cannot be probed
+fn symmetry_mismatches(
+    mat_win: &MaterialWinner,
+    file_handler: &FileHandler,
+    prober: &TablebaseProber,
+    nb_samples: usize,
+) -> io::Result<(usize, usize)> {
+    let transformations: &[Transformation] = if mat_win.material.has_pawns() {
+        &[Transformation::FlipHorizontal]
+    } else {
+        &Transformation::ALL[1..]
+    };
+    let len = file_handler.outcomes.len();
+    let (mut nb_checked, mut nb_mismatches) = (0, 0);
+    for idx in (0..len)
+        .step_by((len / nb_samples.max(1)).max(1))
+        .take(nb_samples)
+    {
+        for turn in Color::ALL {
+            let idx_with_turn = IndexWithTurn {
+                idx: idx as u64,
+                turn,
+            };
+            let outcome = file_handler.outcome_at(idx_with_turn)?;
+            if outcome == Outcome::Undefined {
+                continue;
+            }
+            let board = file_handler
+                .indexer
+                .restore(&mat_win.material, idx_with_turn)
+                .board()
+                .clone();
+            let transformed = transformations.iter().map(|transformation| {
+                let mut transformed_board = board.clone();
+                transformation.apply(&mut transformed_board);
+                (
+                    format!("{transformation:?}"),
+                    transformed_board,
+                    turn,
+                    mat_win.winner,
+                )
+            });
+            let colors_swapped = iter::once((
+                "colors swapped".to_string(),
+                swap_color_board(board.clone()),
+                !turn,
+                !mat_win.winner,
+            ));
+            for (symmetry, symmetric_board, symmetric_turn, winner) in
+                transformed.chain(colors_swapped)
+            {
+                let symmetric_outcome = prober
+                    .retrieve_outcome(&(symmetric_board.clone(), symmetric_turn), winner)
+                    .map_err(|e| {
+                        io::Error::new(
+                            e.kind(),
+                            format!("idx: {idx_with_turn:?}, board: {board} with {symmetry}: {e}"),
+                        )
+                    })?;
+                if symmetric_outcome != outcome {
+                    error!("idx: {idx_with_turn:?}, board: {board} outcome is {outcome:?}, but {symmetric_outcome:?} with {symmetry}: {symmetric_board}");
+                    nb_mismatches += 1;
                 }
+                nb_checked += 1;
             }
-            if idx % 100_000 == 0 {
-                debug!("idx: {idx}")
+        }
+    }
+    Ok((nb_checked, nb_mismatches))
+}
+
+/// `from_captures` being the outcome derived from the captures and promotions, already one move further, and
+/// whether they are all the legal moves, see `Descendants::outcome_from_captures_promotion`. Both sides
+/// cooperating, the stored outcome is the best of all moves, so it can only be better than the one derived
+/// from some of them
+fn is_consistent_with_captures(outcome: Outcome, from_captures: Option<(Outcome, bool)>) -> bool {
+    match from_captures {
+        None => true,
+        Some((from_captures, true)) => outcome == from_captures,
+        Some((from_captures, false)) => outcome >= from_captures,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use helpmate_tb::{OutcomeU8, SideToMoveGetter};
+    use std::str::FromStr;
+
+    #[test]
+    fn test_symmetry_mismatches() {
+        let tb_dir = PathBuf::from("../table");
+        let mat_win = MaterialWinner::from_str("KQvKw").unwrap();
+        let prober: TablebaseProber = TablebaseProber::try_new(&mat_win.material, &tb_dir).unwrap();
+        let mut file_handler: FileHandler = FileHandler::new(&mat_win, &tb_dir);
+        let nb_samples = 500;
+        let (nb_checked, nb_mismatches) =
+            symmetry_mismatches(&mat_win, &file_handler, &prober, nb_samples).unwrap();
+        assert!(nb_checked > nb_samples);
+        assert_eq!(nb_mismatches, 0);
+        // the prober still reads the table, not the corrupted outcomes
+        for by_color in &mut file_handler.outcomes {
+            if by_color.get_by_color(Color::White) != Outcome::Undefined {
+                by_color.white = OutcomeU8::from(Outcome::Win(60));
             }
         }
+        let (_, nb_mismatches) =
+            symmetry_mismatches(&mat_win, &file_handler, &prober, nb_samples).unwrap();
+        assert!(nb_mismatches > 0);
+    }
+
+    #[test]
+    fn test_is_consistent_with_captures() {
+        assert!(is_consistent_with_captures(Outcome::Win(3), None));
+        assert!(is_consistent_with_captures(
+            Outcome::Win(3),
+            Some((Outcome::Win(3), true))
+        ));
+        assert!(!is_consistent_with_captures(
+            Outcome::Win(5),
+            Some((Outcome::Win(3), true))
+        ));
+        // other moves can do better than the captures
+        assert!(is_consistent_with_captures(
+            Outcome::Win(3),
+            Some((Outcome::Win(5), false))
+        ));
+        assert!(is_consistent_with_captures(
+            Outcome::Win(3),
+            Some((Outcome::Draw, false))
+        ));
+        assert!(!is_consistent_with_captures(
+            Outcome::Draw,
+            Some((Outcome::Win(5), false))
+        ));
+        assert!(!is_consistent_with_captures(
+            Outcome::Lose(2),
+            Some((Outcome::Draw, true))
+        ));
     }
 }
@@ -0,0 +1,74 @@
+use from_str_sequential::FromStrSequential;
+use helpmate_tb::{TableName, WinWithinTable};
+use log::info;
+
+use std::{fs::File, io, path::PathBuf, process::ExitCode};
+
+use clap::Args;
+use retroboard::shakmaty::Color;
+
+use crate::error::{exit_code, missing_table, RunSummary};
+use crate::explore::MatOrAll;
+use crate::MaterialWinner;
+
+/// Derive from existing helpmate files the tables telling whether the winner mates within a number of plies,
+/// one bit by position, probed with `TablebaseProber::win_within`
+#[derive(Args, Debug)]
+pub struct WinWithin {
+    #[arg(help = "example \"KQvK\", use special value 'all' to derive all tables", value_parser = MatOrAll::from_str_sequential)]
+    mat_or_all: MatOrAll,
+    #[arg(
+        short,
+        long,
+        help = "Color of the expected winner. If no color is provided, will derive both"
+    )]
+    winner: Option<Color>,
+    #[arg(
+        short = 'n',
+        long,
+        help = "Positions are won if the winner mates in at most that many plies"
+    )]
+    max_plies: u8,
+    #[arg(long, default_value = "table/")]
+    tb_dir: PathBuf,
+    #[arg(
+        long,
+        help = "Directory to write the tables in, as `<mat><winner>.win<max plies>`, defaults to `--tb-dir`"
+    )]
+    output_dir: Option<PathBuf>,
+}
+
+impl WinWithin {
+    /// Fails if any table could not be written
+    pub fn run(&self) -> ExitCode {
+        let mat_winners = match self.mat_or_all.mat_winners(&self.tb_dir, self.winner) {
+            Ok(mat_winners) => mat_winners,
+            Err(e) => return exit_code(Err(e)),
+        };
+        let mut summary = RunSummary::default();
+        for mat_win in mat_winners {
+            summary.record(format!("{mat_win:?}"), self.derive_one_mat(&mat_win));
+        }
+        summary.finish()
+    }
+
+    fn derive_one_mat(&self, mat_win: &MaterialWinner) -> io::Result<()> {
+        let table = WinWithinTable::from_dtm_table(
+            File::open(TableName::helpmate(mat_win).path_in(&self.tb_dir))
+                .map_err(missing_table)?,
+            self.max_plies,
+        )?;
+        info!(
+            "{mat_win:?}: {} positions won within {} plies",
+            table.count(),
+            self.max_plies
+        );
+        let output_dir = self.output_dir.as_ref().unwrap_or(&self.tb_dir);
+        std::fs::create_dir_all(output_dir)?;
+        table.write(File::create(WinWithinTable::path_in(
+            output_dir,
+            mat_win,
+            self.max_plies,
+        ))?)
+    }
+}
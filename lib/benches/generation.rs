@@ -0,0 +1,36 @@
+use std::{path::PathBuf, str::FromStr};
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+
+use helpmate_tb::{EnumerationOrder, Generator, Material, MaterialWinner, NaiveIndexer};
+use retroboard::shakmaty::Color;
+
+fn bench_enumeration_order(c: &mut Criterion) {
+    let tb_dir: PathBuf = ["..", "table"].iter().collect();
+    let mat_win = MaterialWinner::new(&Material::from_str("KQRvK").unwrap(), Color::White);
+    let mut group = c.benchmark_group("GeneratePositions");
+    group.sample_size(10);
+    for enumeration_order in [
+        EnumerationOrder::Material,
+        EnumerationOrder::IndexSignificance,
+    ] {
+        group.bench_with_input(
+            BenchmarkId::new("KQRvK", format!("{enumeration_order:?}")),
+            &enumeration_order,
+            |b, enumeration_order| {
+                b.iter_batched(
+                    || {
+                        Generator::<_, NaiveIndexer>::new(mat_win.clone(), &tb_dir)
+                            .with_enumeration_order(*enumeration_order)
+                    },
+                    |mut generator| generator.generate_positions(),
+                    BatchSize::LargeInput,
+                );
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_enumeration_order);
+criterion_main!(benches);
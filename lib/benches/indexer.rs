@@ -3,6 +3,7 @@ use criterion::{BenchmarkId, Criterion};
 use helpmate_tb::{handle_symetry, Indexer, Material, NaiveIndexer, SideToMove, Table};
 use retroboard::RetroBoard;
 
+#[allow(dead_code)] // benchmark currently disabled, see below
 fn bench_indexers(c: &mut Criterion) {
     let fens = [
         "8/8/8/8/8/8/8/KNBk4 w - - 0 1",
@@ -32,7 +33,7 @@ fn bench_indexers(c: &mut Criterion) {
         let mut group = c.benchmark_group("CheckedIndexer");
         for (i, (rboard, syzygy)) in rboards_and_syzygy.into_iter().enumerate() {
             group.bench_with_input(BenchmarkId::new("Naive", i), &rboard, |b, rboard_ref| {
-                b.iter(|| NaiveIndexer.encode(rboard_ref))
+                b.iter(|| NaiveIndexer::default().encode(rboard_ref))
             });
             group.bench_with_input(BenchmarkId::new("Syzygy", i), &rboard, |b, rboard_ref| {
                 b.iter(|| syzygy.encode(rboard_ref))
@@ -44,7 +45,7 @@ fn bench_indexers(c: &mut Criterion) {
     let checked_boards_and_syzygy = fens.map(|fen| {
         let rboard = RetroBoard::new_no_pockets(fen).unwrap();
         let mat = Material::from_board(rboard.board());
-        let (board_check, is_black_stronger) = handle_symetry(rboard.board());
+        let (board_check, is_black_stronger) = handle_symetry(rboard.board(), &mat);
         (
             (board_check, rboard.side_to_move() ^ is_black_stronger),
             Table::from(mat),
@@ -57,7 +58,9 @@ fn bench_indexers(c: &mut Criterion) {
         group.bench_with_input(
             BenchmarkId::new("Naive", i),
             &side_to_move,
-            |b, side_to_move_ref| b.iter(|| NaiveIndexer.encode_unchecked(side_to_move_ref)),
+            |b, side_to_move_ref| {
+                b.iter(|| NaiveIndexer::default().encode_unchecked(side_to_move_ref))
+            },
         );
         group.bench_with_input(
             BenchmarkId::new("Syzygy", i),
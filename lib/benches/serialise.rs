@@ -1,3 +1,6 @@
+// `DekuRead` derive generates code triggering this lint
+#![allow(clippy::manual_div_ceil)]
+
 use std::io::{Cursor, IoSliceMut, Read, Write};
 
 use binrw::{
@@ -19,9 +22,9 @@ struct Test {
 
 impl Test {
     fn to_bytes_custom<T: Write>(&self, writer: &mut T) {
-        writer.write(&self.a.to_ne_bytes()).unwrap();
-        writer.write(&self.b.to_ne_bytes()).unwrap();
-        writer.write(&self.c.to_ne_bytes()).unwrap();
+        writer.write_all(&self.a.to_ne_bytes()).unwrap();
+        writer.write_all(&self.b.to_ne_bytes()).unwrap();
+        writer.write_all(&self.c.to_ne_bytes()).unwrap();
     }
 
     fn from_bytes_custom<T: Read>(reader: &mut T) -> Self {
@@ -31,7 +34,8 @@ impl Test {
             IoSliceMut::new(&mut b),
             IoSliceMut::new(&mut c),
         ];
-        reader.read_vectored(&mut buf).unwrap();
+        let read = reader.read_vectored(&mut buf).unwrap();
+        assert_eq!(read, 24);
         Self {
             a: u64::from_ne_bytes(a),
             b: u64::from_ne_bytes(b),
@@ -135,7 +139,7 @@ fn bench_deserialise(c: &mut Criterion) {
             || binrw.clone(),
             |mut binrw_input| {
                 for i in 0..10_0000 {
-                    Test::read_le(&mut binrw_input).expect(&format!("{i}"));
+                    Test::read_le(&mut binrw_input).unwrap_or_else(|_| panic!("{i}"));
                 }
             },
             BatchSize::SmallInput,
@@ -0,0 +1,54 @@
+//! Annotate the FENs read from stdin, one per line, with their outcome for both winners.
+//! The tables of each material met are loaded once, from the directory given as first argument.
+//!
+//! Run with `cargo run --release -p helpmate_tb --example bulk_annotate -- table/ < fens.txt`
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    io::{self, BufRead},
+    path::{Path, PathBuf},
+};
+
+use helpmate_tb::{Material, TablebaseProber};
+use retroboard::shakmaty::{fen::Fen, CastlingMode, Chess, Color, Position};
+
+fn annotate(
+    probers: &mut HashMap<Material, TablebaseProber>,
+    tb_dir: &Path,
+    fen: &str,
+) -> io::Result<String> {
+    let pos: Chess = fen
+        .parse::<Fen>()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?
+        .into_position(CastlingMode::Standard)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let material = Material::from_board(pos.board());
+    let prober = match probers.entry(material) {
+        Entry::Occupied(entry) => entry.into_mut(),
+        Entry::Vacant(entry) => {
+            let prober = TablebaseProber::try_new(entry.key(), tb_dir)?;
+            entry.insert(prober)
+        }
+    };
+    let white = prober.probe_outcome(&pos, Color::White)?;
+    let black = prober.probe_outcome(&pos, Color::Black)?;
+    Ok(format!("{fen};{white:?};{black:?}"))
+}
+
+fn main() -> io::Result<()> {
+    let tb_dir: PathBuf = std::env::args_os()
+        .nth(1)
+        .map_or_else(|| PathBuf::from("table"), PathBuf::from);
+    let mut probers = HashMap::new();
+    for line in io::stdin().lock().lines() {
+        let line = line?;
+        let fen = line.trim();
+        if fen.is_empty() {
+            continue;
+        }
+        match annotate(&mut probers, &tb_dir, fen) {
+            Ok(annotated) => println!("{annotated}"),
+            Err(e) => eprintln!("{fen}: {e}"),
+        }
+    }
+    Ok(())
+}
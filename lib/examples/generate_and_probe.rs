@@ -0,0 +1,47 @@
+//! Build the `KQvK` tables in a temporary directory, then probe a few positions from the files written,
+//! only through the stable API.
+//!
+//! Run with `cargo run --release -p helpmate_tb --example generate_and_probe`
+use std::{fs::File, io, str::FromStr};
+
+use helpmate_tb::{
+    CompressionOptions, Material, MaterialWinner, TableBaseBuilder, TableName, TablebaseProber,
+};
+use retroboard::shakmaty::{fen::Fen, CastlingMode, Chess, Color};
+
+fn main() -> io::Result<()> {
+    let tb_dir = std::env::temp_dir().join("helpmate_tb_generate_and_probe");
+    std::fs::create_dir_all(&tb_dir)?;
+    let material = Material::from_str("KQvK").expect("valid material");
+    for winner in Color::ALL {
+        let mat_win = MaterialWinner::new(&material, winner);
+        let (common, build_report) = TableBaseBuilder::build(mat_win.clone(), &tb_dir)?;
+        let table_path = TableName::helpmate(&mat_win).path_in(&tb_dir);
+        common.write_table(File::create(&table_path)?, CompressionOptions::default())?;
+        println!(
+            "{mat_win:?}: {} positions, written to {}",
+            build_report.defined,
+            table_path.display()
+        );
+    }
+
+    let prober: TablebaseProber = TablebaseProber::try_new(&material, &tb_dir)?;
+    for fen in [
+        "k7/8/1K6/8/8/8/7Q/8 w - - 0 1",
+        "k7/8/1K6/8/8/8/7Q/8 b - - 0 1",
+        "8/8/8/3k4/8/8/8/Q3K3 w - - 0 1",
+    ] {
+        let pos: Chess = fen
+            .parse::<Fen>()
+            .expect("valid fen")
+            .into_position(CastlingMode::Standard)
+            .expect("legal position");
+        let result = prober.probe_result(&pos, Color::White)?;
+        println!(
+            "{fen}: {:?} for white, {:?} for black",
+            result.outcome,
+            result.from_perspective(Color::Black)
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,42 @@
+//! Run a custom analysis on each position enumerated while building a table,
+//! without enumerating them again. Needs the `unstable-internals` feature.
+//!
+//! Run with `cargo run --release -p helpmate_tb --features unstable-internals --example observer`
+use std::{path::Path, str::FromStr};
+
+use helpmate_tb::{
+    BuildOptions, Common, Descendants, IndexWithTurn, MaterialWinner, PosHandler, TableBaseBuilder,
+};
+use retroboard::shakmaty::{Chess, Position};
+
+/// Number of positions where the side to move has a single legal move
+#[derive(Default)]
+struct ForcedMoves(u64);
+
+impl<I> PosHandler<I> for ForcedMoves {
+    fn handle_position(
+        &mut self,
+        _: &mut Common<I>,
+        _: &Descendants,
+        chess: &Chess,
+        _: IndexWithTurn,
+        _: usize,
+    ) {
+        self.0 += u64::from(chess.legal_moves().len() == 1);
+    }
+}
+
+fn main() {
+    let mat_win = MaterialWinner::from_str("KQvKw").unwrap();
+    let (_, build_report, forced_moves) = TableBaseBuilder::build_with_observer(
+        mat_win,
+        Path::new("../table"),
+        &BuildOptions::default(),
+        ForcedMoves::default(),
+    )
+    .unwrap();
+    println!(
+        "{} positions out of {} with a single legal move",
+        forced_moves.0, build_report.defined
+    );
+}
@@ -0,0 +1,20 @@
+//! Probe a position without any table file, the `KQvK` tables being built in memory.
+//!
+//! Run with `cargo run --release -p helpmate_tb --example quickstart`
+use helpmate_tb::kqk_table;
+use retroboard::shakmaty::{fen::Fen, CastlingMode, Chess, Color};
+
+fn main() {
+    let table = kqk_table();
+    let pos: Chess = "k7/8/1K6/8/8/8/7Q/8 w - - 0 1"
+        .parse::<Fen>()
+        .unwrap()
+        .into_position(CastlingMode::Standard)
+        .unwrap();
+    let result = table.probe_result(&pos, Color::White).unwrap();
+    println!(
+        "White wins: {:?}, as seen by black: {:?}",
+        result.outcome,
+        result.from_perspective(Color::Black)
+    );
+}
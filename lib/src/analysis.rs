@@ -0,0 +1,627 @@
+use retroboard::shakmaty::{ByColor, Color, Square};
+use rustc_hash::FxHashMap;
+
+use crate::{Material, NaiveIndexer, Outcome, SideToMoveGetter, Wdl};
+
+/// Number of entries of each kind of outcome, see `StatsAccumulator`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OutcomeCounts {
+    pub win: u64,
+    pub draw: u64,
+    pub lose: u64,
+    pub unknown: u64,
+    pub undefined: u64,
+}
+
+impl OutcomeCounts {
+    fn add(&mut self, outcome: Outcome) {
+        self.add_nb(outcome, 1);
+    }
+
+    fn add_nb(&mut self, outcome: Outcome, nb: u64) {
+        match outcome {
+            Outcome::Win(_) => self.win += nb,
+            Outcome::Draw => self.draw += nb,
+            Outcome::Lose(_) => self.lose += nb,
+            Outcome::Unknown => self.unknown += nb,
+            Outcome::Undefined => self.undefined += nb,
+        }
+    }
+
+    fn add_wdl(&mut self, wdl: Wdl) {
+        match wdl {
+            Wdl::Win => self.win += 1,
+            Wdl::Draw => self.draw += 1,
+            Wdl::Lose => self.lose += 1,
+            Wdl::Unknown => self.unknown += 1,
+            Wdl::Undefined => self.undefined += 1,
+        }
+    }
+
+    fn merge(&mut self, other: Self) {
+        self.win += other.win;
+        self.draw += other.draw;
+        self.lose += other.lose;
+        self.unknown += other.unknown;
+        self.undefined += other.undefined;
+    }
+
+    #[must_use]
+    pub fn total(&self) -> u64 {
+        self.win + self.draw + self.lose + self.unknown + self.undefined
+    }
+
+    /// Entries corresponding to a legal position
+    #[must_use]
+    pub fn defined(&self) -> u64 {
+        self.total() - self.undefined
+    }
+}
+
+/// Number of entries of each outcome by side to move, undefined ones included. Computed when compressing
+/// a table and stored in its trailer, to summarise it without decompressing it, see `EncoderDecoder::outcome_distrib`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OutcomeDistrib {
+    pub by_turn: ByColor<FxHashMap<Outcome, u64>>,
+}
+
+impl OutcomeDistrib {
+    #[must_use]
+    pub fn from_outcomes<T>(outcomes: &[ByColor<T>]) -> Self
+    where
+        ByColor<T>: SideToMoveGetter,
+    {
+        let mut distrib = Self::default();
+        for by_color in outcomes {
+            for turn in Color::ALL {
+                *distrib
+                    .by_turn
+                    .get_mut(turn)
+                    .entry(by_color.get_outcome_by_color(turn))
+                    .or_default() += 1;
+            }
+        }
+        distrib
+    }
+
+    /// Number of indexes counted, with white to move. The same as with black to move when read from a table
+    #[must_use]
+    pub fn nb_indexes(&self) -> u64 {
+        self.by_turn.white.values().sum()
+    }
+
+    /// Outcome counts with `turn` to move
+    #[must_use]
+    pub fn counts(&self, turn: Color) -> OutcomeCounts {
+        let mut counts = OutcomeCounts::default();
+        for (outcome, nb) in self.by_turn.get(turn) {
+            counts.add_nb(*outcome, *nb);
+        }
+        counts
+    }
+}
+
+/// Statistics of a table of `winner`, fed index by index, or merged when chunks of the table
+/// are counted in parallel. Shared by every tool summarising tables, so that they count the same way
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatsAccumulator {
+    winner: Color,
+    /// Outcome counts by side to move
+    pub by_turn: ByColor<OutcomeCounts>,
+    /// Number of entries of each outcome by side to move, only fed with full outcomes, see `StatsAccumulator::add`
+    pub distrib: ByColor<FxHashMap<Outcome, u64>>,
+    /// Indexes whose position is won with that side to move, and legal but not won with the other one
+    pub only_wins_with: ByColor<u64>,
+}
+
+impl StatsAccumulator {
+    #[must_use]
+    pub fn new(winner: Color) -> Self {
+        Self {
+            winner,
+            by_turn: ByColor::default(),
+            distrib: ByColor::default(),
+            only_wins_with: ByColor::default(),
+        }
+    }
+
+    #[must_use]
+    pub fn winner(&self) -> Color {
+        self.winner
+    }
+
+    /// Count the outcomes of one index, for both sides to move
+    pub fn add<T>(&mut self, by_color: &ByColor<T>)
+    where
+        ByColor<T>: SideToMoveGetter,
+    {
+        let outcomes = ByColor::new_with(|turn| by_color.get_outcome_by_color(turn));
+        for turn in Color::ALL {
+            self.by_turn.get_mut(turn).add(*outcomes.get(turn));
+            *self
+                .distrib
+                .get_mut(turn)
+                .entry(*outcomes.get(turn))
+                .or_default() += 1;
+        }
+        self.add_only_wins(outcomes.map(Wdl::from));
+    }
+
+    /// Same as calling `StatsAccumulator::add` on `nb` indexes undefined for both sides to move
+    pub fn add_undefined(&mut self, nb: u64) {
+        for turn in Color::ALL {
+            self.by_turn.get_mut(turn).undefined += nb;
+            *self
+                .distrib
+                .get_mut(turn)
+                .entry(Outcome::Undefined)
+                .or_default() += nb;
+        }
+    }
+
+    /// Same as `StatsAccumulator::add`, for tables only holding Win/Draw/Lose information.
+    /// `StatsAccumulator::distrib` is left untouched
+    pub fn add_wdl(&mut self, by_color: ByColor<Wdl>) {
+        for turn in Color::ALL {
+            self.by_turn.get_mut(turn).add_wdl(*by_color.get(turn));
+        }
+        self.add_only_wins(by_color);
+    }
+
+    fn add_only_wins(&mut self, by_color: ByColor<Wdl>) {
+        for turn in Color::ALL {
+            if *by_color.get(turn) == Wdl::Win
+                && !matches!(by_color.get(!turn), Wdl::Win | Wdl::Undefined)
+            {
+                *self.only_wins_with.get_mut(turn) += 1;
+            }
+        }
+    }
+
+    /// # Panics
+    /// If `other` is not about the same winner
+    pub fn merge(&mut self, other: Self) {
+        assert_eq!(self.winner, other.winner, "stats of different winners");
+        for turn in Color::ALL {
+            self.by_turn.get_mut(turn).merge(*other.by_turn.get(turn));
+            *self.only_wins_with.get_mut(turn) += other.only_wins_with.get(turn);
+        }
+        for (distrib, other_distrib) in self.distrib.iter_mut().zip(other.distrib) {
+            for (outcome, nb) in other_distrib {
+                *distrib.entry(outcome).or_default() += nb;
+            }
+        }
+    }
+
+    /// Outcome counts of both sides to move
+    #[must_use]
+    pub fn counts(&self) -> OutcomeCounts {
+        let mut counts = self.by_turn.white;
+        counts.merge(self.by_turn.black);
+        counts
+    }
+
+    /// Outcome counts of the positions where the winner is to move
+    #[must_use]
+    pub fn winner_to_move(&self) -> OutcomeCounts {
+        *self.by_turn.get(self.winner)
+    }
+
+    /// Outcome counts of the positions where the loser is to move
+    #[must_use]
+    pub fn loser_to_move(&self) -> OutcomeCounts {
+        *self.by_turn.get(!self.winner)
+    }
+
+    /// Number of entries of `outcome`, both sides to move
+    #[must_use]
+    pub fn count_of(&self, outcome: Outcome) -> u64 {
+        self.distrib
+            .iter()
+            .filter_map(|distrib| distrib.get(&outcome))
+            .sum()
+    }
+
+    /// `StatsAccumulator::distrib`, to compare with the one stored in a table.
+    /// Empty if only fed with Win/Draw/Lose information
+    #[must_use]
+    pub fn outcome_distrib(&self) -> OutcomeDistrib {
+        OutcomeDistrib {
+            by_turn: self
+                .distrib
+                .clone()
+                .map(|distrib| distrib.into_iter().filter(|(_, nb)| *nb > 0).collect()),
+        }
+    }
+}
+
+/// Outcome counts of all positions whose canonical white king is on `square`, both sides to move
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KingSquareStats {
+    pub square: Square,
+    pub win: usize,
+    pub draw: usize,
+    pub lose: usize,
+    pub unknown: usize,
+    pub undefined: usize,
+}
+
+impl KingSquareStats {
+    fn new(square: Square) -> Self {
+        Self {
+            square,
+            win: 0,
+            draw: 0,
+            lose: 0,
+            unknown: 0,
+            undefined: 0,
+        }
+    }
+
+    #[must_use]
+    pub fn total(&self) -> usize {
+        self.win + self.draw + self.lose + self.unknown + self.undefined
+    }
+
+    /// Share of indexes not corresponding to a legal position, between 0 and 1
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn undefined_density(&self) -> f64 {
+        self.undefined as f64 / self.total().max(1) as f64
+    }
+}
+
+/// Outcome counts grouped by the square of the white king in the canonical position,
+/// ie in the `A1_D1_D4` triangle (or on the a-d files with pawns), ordered by square.
+/// `outcomes` must have been indexed with `NaiveIndexer`
+#[must_use]
+pub fn stats_by_white_king_square<T>(
+    material: &Material,
+    outcomes: &[ByColor<T>],
+) -> Vec<KingSquareStats>
+where
+    ByColor<T>: SideToMoveGetter,
+{
+    let indexer = NaiveIndexer::from(material.clone());
+    let mut by_square: [Option<KingSquareStats>; 64] = [None; 64];
+    for (idx, by_color_outcome) in outcomes.iter().enumerate() {
+        let square = indexer.white_king_square(material, idx as u64);
+        let stats = by_square[square as usize].get_or_insert_with(|| KingSquareStats::new(square));
+        for turn in Color::ALL {
+            match by_color_outcome.get_outcome_by_color(turn) {
+                Outcome::Win(_) => stats.win += 1,
+                Outcome::Draw => stats.draw += 1,
+                Outcome::Lose(_) => stats.lose += 1,
+                Outcome::Unknown => stats.unknown += 1,
+                Outcome::Undefined => stats.undefined += 1,
+            }
+        }
+    }
+    by_square.into_iter().flatten().collect()
+}
+
+/// Squares whose undefined density is more than 2 standard deviations away from the mean
+/// of all squares. Canonicalization bugs tend to show up as such outliers
+#[must_use]
+#[allow(clippy::cast_precision_loss)]
+pub fn anomalous_squares(stats: &[KingSquareStats]) -> Vec<Square> {
+    let densities: Vec<f64> = stats
+        .iter()
+        .map(KingSquareStats::undefined_density)
+        .collect();
+    let n = densities.len().max(1) as f64;
+    let mean = densities.iter().sum::<f64>() / n;
+    let std_dev = (densities.iter().map(|d| (d - mean).powi(2)).sum::<f64>() / n).sqrt();
+    stats
+        .iter()
+        .zip(densities)
+        .filter(|(_, density)| (density - mean).abs() > 2. * std_dev)
+        .map(|(s, _)| s.square)
+        .collect()
+}
+
+/// Outcomes of the same positions in the tables of both winners of a material, see `joint_stats`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct JointStats {
+    /// Number of positions by pair of outcomes, `counts[white winner][black winner]`,
+    /// indexed by `Wdl as usize`. Illegal positions and unknown ones are not counted
+    pub counts: [[u64; 3]; 3],
+    /// Pearson correlation between the plies to mate of both winners, among the positions
+    /// both can be helped to mate. `None` if it is undefined, eg with less than 2 such positions
+    pub dtm_correlation: Option<f64>,
+}
+
+impl JointStats {
+    #[must_use]
+    pub fn count(&self, white_winner: Wdl, black_winner: Wdl) -> u64 {
+        self.counts[white_winner as usize][black_winner as usize]
+    }
+
+    /// Positions where both white and black can be helped to mate
+    #[must_use]
+    pub fn both_win(&self) -> u64 {
+        self.count(Wdl::Win, Wdl::Win)
+    }
+}
+
+/// Pair the outcomes of each position in the tables of both winners of the same material,
+/// which must have been indexed with the same indexer.
+///
+/// # Panics
+/// If the tables are not of the same length
+#[must_use]
+#[allow(clippy::cast_precision_loss)]
+pub fn joint_stats<T, U>(white_winner: &[ByColor<T>], black_winner: &[ByColor<U>]) -> JointStats
+where
+    ByColor<T>: SideToMoveGetter,
+    ByColor<U>: SideToMoveGetter,
+{
+    assert_eq!(
+        white_winner.len(),
+        black_winner.len(),
+        "tables of different materials"
+    );
+    let mut stats = JointStats::default();
+    // sums of x, y, x^2, y^2 and xy, x and y being the plies to mate of white and black
+    let (mut n, mut sx, mut sy, mut sxx, mut syy, mut sxy) = (0., 0., 0., 0., 0., 0.);
+    for (white_bc, black_bc) in white_winner.iter().zip(black_winner) {
+        for turn in Color::ALL {
+            let white_outcome = white_bc.get_outcome_by_color(turn);
+            let black_outcome = black_bc.get_outcome_by_color(turn);
+            let (white_wdl, black_wdl) = (Wdl::from(white_outcome), Wdl::from(black_outcome));
+            if [white_wdl, black_wdl]
+                .iter()
+                .any(|wdl| matches!(wdl, Wdl::Undefined | Wdl::Unknown))
+            {
+                continue;
+            }
+            stats.counts[white_wdl as usize][black_wdl as usize] += 1;
+            if let (Outcome::Win(x), Outcome::Win(y)) = (white_outcome, black_outcome) {
+                let (x, y) = (f64::from(x), f64::from(y));
+                n += 1.;
+                sx += x;
+                sy += y;
+                sxx += x * x;
+                syy += y * y;
+                sxy += x * y;
+            }
+        }
+    }
+    let denominator = ((n * sxx - sx * sx) * (n * syy - sy * sy)).sqrt();
+    stats.dtm_correlation =
+        (n >= 2. && denominator > 0.).then(|| (n * sxy - sx * sy) / denominator);
+    stats
+}
+
+/// How the outcome of a position changes with the side to move, see `zugzwangs`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ZugzwangKind {
+    /// Mate with one side to move, draw with the other
+    WinDraw,
+    /// Mate with one side to move, the winner is mated with the other
+    WinLose,
+    /// Draw with one side to move, the winner is mated with the other
+    DrawLose,
+}
+
+/// Position whose outcome is not the same kind depending on the side to move,
+/// eg mate with white to move but draw with black to move. Such mutual zugzwangs are prized by composers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Zugzwang {
+    pub idx: u64,
+    pub kind: ZugzwangKind,
+    /// Outcome by side to move
+    pub outcomes: ByColor<Outcome>,
+}
+
+impl Zugzwang {
+    /// Side to move with which the outcome is the best for the winner
+    #[must_use]
+    pub fn better_to_move(&self) -> Color {
+        if self.outcomes.white > self.outcomes.black {
+            Color::White
+        } else {
+            Color::Black
+        }
+    }
+}
+
+/// Zugzwangs of a table, in index order. Entries where either outcome is
+/// `Outcome::Unknown` or `Outcome::Undefined` are skipped, as nothing can be told from them
+pub fn zugzwangs<T>(outcomes: &[ByColor<T>]) -> impl Iterator<Item = Zugzwang> + '_
+where
+    ByColor<T>: SideToMoveGetter,
+{
+    outcomes.iter().enumerate().filter_map(|(idx, by_color)| {
+        let outcomes = ByColor::new_with(|turn| by_color.get_outcome_by_color(turn));
+        let kind = match Color::ALL.map(|turn| Wdl::from(*outcomes.get(turn))) {
+            [Wdl::Win, Wdl::Draw] | [Wdl::Draw, Wdl::Win] => ZugzwangKind::WinDraw,
+            [Wdl::Win, Wdl::Lose] | [Wdl::Lose, Wdl::Win] => ZugzwangKind::WinLose,
+            [Wdl::Draw, Wdl::Lose] | [Wdl::Lose, Wdl::Draw] => ZugzwangKind::DrawLose,
+            _ => return None,
+        };
+        Some(Zugzwang {
+            idx: idx as u64,
+            kind,
+            outcomes,
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{indexer::A1_D1_D4, FileHandler, MaterialWinner, OutcomeU8};
+    use std::{path::PathBuf, str::FromStr};
+
+    #[test]
+    fn test_stats_by_white_king_square() {
+        let mat_win = MaterialWinner::from_str("KQvKw").unwrap();
+        let tb_dir: PathBuf = ["..", "table"].iter().collect();
+        let file_handler: FileHandler = FileHandler::new(&mat_win, &tb_dir);
+        let stats = stats_by_white_king_square(&mat_win.material, &file_handler.outcomes);
+        assert_eq!(stats.len(), 10);
+        assert!(stats.iter().all(|s| A1_D1_D4.contains(s.square)));
+        assert_eq!(
+            stats.iter().map(KingSquareStats::total).sum::<usize>(),
+            file_handler.outcomes.len() * 2
+        );
+        assert!(anomalous_squares(&stats).is_empty());
+    }
+
+    #[test]
+    fn test_joint_stats() {
+        let material = Material::from_str("KQvK").unwrap();
+        let tb_dir: PathBuf = ["..", "table"].iter().collect();
+        let [white, black]: [FileHandler; 2] = Color::ALL
+            .map(|winner| FileHandler::new(&MaterialWinner::new(&material, winner), &tb_dir));
+        let stats = joint_stats(&white.outcomes, &black.outcomes);
+        let defined = stats_by_white_king_square(&material, &white.outcomes)
+            .iter()
+            .map(|s| s.total() - s.undefined)
+            .sum::<usize>();
+        assert_eq!(stats.counts.iter().flatten().sum::<u64>(), defined as u64);
+        // black cannot mate with a lone king
+        assert_eq!(stats.both_win(), 0);
+        assert!(stats.counts.iter().all(|row| row[Wdl::Win as usize] == 0));
+        assert!(stats.count(Wdl::Win, Wdl::Draw) > 0);
+        assert_eq!(stats.dtm_correlation, None);
+    }
+
+    #[test]
+    fn test_joint_stats_dtm_correlation() {
+        let by_color = |white: Outcome, black: Outcome| ByColor {
+            white: OutcomeU8::from(white),
+            black: OutcomeU8::from(black),
+        };
+        let white_winner = vec![
+            by_color(Outcome::Win(1), Outcome::Win(2)),
+            by_color(Outcome::Win(3), Outcome::Undefined),
+        ];
+        let black_winner = vec![
+            by_color(Outcome::Win(5), Outcome::Win(6)),
+            by_color(Outcome::Win(7), Outcome::Undefined),
+        ];
+        let stats = joint_stats(&white_winner, &black_winner);
+        assert_eq!(stats.both_win(), 3);
+        assert!((stats.dtm_correlation.unwrap() - 1.).abs() < 1e-9);
+
+        let black_winner = vec![
+            by_color(Outcome::Win(7), Outcome::Draw),
+            by_color(Outcome::Win(5), Outcome::Undefined),
+        ];
+        let stats = joint_stats(&white_winner, &black_winner);
+        assert_eq!(stats.count(Wdl::Win, Wdl::Draw), 1);
+        assert!((stats.dtm_correlation.unwrap() + 1.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_stats_accumulator() {
+        let by_color = |white: Outcome, black: Outcome| ByColor {
+            white: OutcomeU8::from(white),
+            black: OutcomeU8::from(black),
+        };
+        let mut stats = StatsAccumulator::new(Color::White);
+        stats.add(&by_color(Outcome::Win(1), Outcome::Draw));
+        stats.add(&by_color(Outcome::Win(3), Outcome::Undefined));
+        let mut other = StatsAccumulator::new(Color::White);
+        other.add(&by_color(Outcome::Lose(2), Outcome::Win(1)));
+        other.add(&by_color(Outcome::Win(1), Outcome::Win(2)));
+        stats.merge(other);
+        assert_eq!(stats.counts().total(), 8);
+        assert_eq!(stats.counts().defined(), 7);
+        assert_eq!(stats.winner_to_move().win, 3);
+        assert_eq!(stats.loser_to_move().win, 2);
+        assert_eq!(stats.loser_to_move().undefined, 1);
+        assert_eq!(stats.count_of(Outcome::Win(1)), 3);
+        assert_eq!(stats.distrib.black.get(&Outcome::Win(1)), Some(&1));
+        // the undefined black to move position does not count
+        assert_eq!(stats.only_wins_with.white, 1);
+        assert_eq!(stats.only_wins_with.black, 1);
+
+        let mut wdl_stats = StatsAccumulator::new(Color::White);
+        wdl_stats.add_wdl(ByColor {
+            white: Wdl::Win,
+            black: Wdl::Lose,
+        });
+        assert_eq!(wdl_stats.only_wins_with.white, 1);
+        assert!(wdl_stats.distrib.white.is_empty());
+    }
+
+    #[test]
+    fn test_stats_accumulator_add_undefined() {
+        let undefined = ByColor {
+            white: OutcomeU8::from(Outcome::Undefined),
+            black: OutcomeU8::from(Outcome::Undefined),
+        };
+        let mut stats = StatsAccumulator::new(Color::White);
+        for _ in 0..3 {
+            stats.add(&undefined);
+        }
+        let mut bulk = StatsAccumulator::new(Color::White);
+        bulk.add_undefined(3);
+        assert_eq!(bulk.by_turn, stats.by_turn);
+        assert_eq!(bulk.distrib, stats.distrib);
+        assert_eq!(bulk.only_wins_with, stats.only_wins_with);
+    }
+
+    #[test]
+    fn test_anomalous_squares() {
+        let material = Material::from_str("KQvK").unwrap();
+        let draw = OutcomeU8::from(Outcome::Draw);
+        let mut outcomes = vec![
+            ByColor {
+                white: draw,
+                black: draw,
+            };
+            462 * 64
+        ];
+        // positions of kk index 0, with the white king on b1
+        let undefined = OutcomeU8::from(Outcome::Undefined);
+        for by_color in &mut outcomes[..64] {
+            *by_color = ByColor {
+                white: undefined,
+                black: undefined,
+            };
+        }
+        let stats = stats_by_white_king_square(&material, &outcomes);
+        assert_eq!(anomalous_squares(&stats), vec![Square::B1]);
+    }
+
+    #[test]
+    fn test_zugzwangs() {
+        let by_color = |white: Outcome, black: Outcome| ByColor {
+            white: OutcomeU8::from(white),
+            black: OutcomeU8::from(black),
+        };
+        let outcomes = vec![
+            by_color(Outcome::Win(3), Outcome::Win(4)),
+            by_color(Outcome::Win(3), Outcome::Draw),
+            by_color(Outcome::Lose(2), Outcome::Win(5)),
+            by_color(Outcome::Lose(2), Outcome::Draw),
+            by_color(Outcome::Win(3), Outcome::Undefined),
+            by_color(Outcome::Win(3), Outcome::Unknown),
+            by_color(Outcome::Draw, Outcome::Draw),
+        ];
+        let found: Vec<Zugzwang> = zugzwangs(&outcomes).collect();
+        assert_eq!(
+            found
+                .iter()
+                .map(|zugzwang| (zugzwang.idx, zugzwang.kind, zugzwang.better_to_move()))
+                .collect::<Vec<_>>(),
+            vec![
+                (1, ZugzwangKind::WinDraw, Color::White),
+                (2, ZugzwangKind::WinLose, Color::Black),
+                (3, ZugzwangKind::DrawLose, Color::Black),
+            ]
+        );
+        assert_eq!(found[0].outcomes.black, Outcome::Draw);
+
+        // the winner cannot be mated by a lone king
+        let tb_dir: PathBuf = ["..", "table"].iter().collect();
+        let file_handler: FileHandler =
+            FileHandler::new(&MaterialWinner::from_str("KQvKw").unwrap(), &tb_dir);
+        assert!(zugzwangs(&file_handler.outcomes)
+            .all(|zugzwang| zugzwang.kind == ZugzwangKind::WinDraw));
+    }
+}
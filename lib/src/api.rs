@@ -0,0 +1,48 @@
+//! Curated public API, meant to stay stable across releases.
+//! Everything else is only exposed with the `unstable-internals` feature, and can change at any time
+
+// material
+pub use crate::file_handler::MaterialWinner;
+pub use crate::material::{is_black_stronger, normalize_material, Material};
+
+// outcome
+pub use crate::outcome::{Outcome, Wdl};
+
+// prober
+#[cfg(feature = "cached")]
+pub use crate::block_cache::{BlockCacheConfig, BlockCacheStats, TierStats};
+pub use crate::compression::limit_block_cache;
+#[cfg(feature = "cached")]
+pub use crate::compression::{block_cache_stats, configure_block_cache};
+pub use crate::coverage::{Coverage, CoveredTable};
+pub use crate::demo::{kqk_table, InMemoryTable};
+pub use crate::file_handler::{
+    KingSquares, Metric, Objective, RetrieveOutcome, TableCache, TableName, TablePath, WinCondition,
+};
+pub use crate::fortress::{DrawKind, FortressTable};
+pub use crate::generation::{IndexWithTurn, SideToMove};
+pub use crate::indexer::{Symmetry, Transformation};
+pub use crate::kings::{decode_kings, encode_kings, KK_NB};
+pub use crate::probe::{
+    CastlingPolicy, Diagnosis, MoveOrdering, PartialProbe, ProbeOptions, ProbeResponse,
+    ProbeResult, TableRegistry, TablebaseProber, WinLine, PROBE_RESPONSE_VERSION,
+};
+pub use crate::router::{Route, Router};
+pub use crate::win_within::WinWithinTable;
+
+// builder
+pub use crate::common::{Anomaly, AnomalySink, Common, QuarantineEntry, WinObjective};
+pub use crate::compression::{
+    BlockStats, Codec, CompressionOptions, PreFilter, TableFormat, TableFormatError,
+};
+pub use crate::generation::{
+    BuildOptions, BuildPhase, BuildReport, EnumerationOrder, ParanoidCheck, TableBaseBuilder,
+    UnknownPolicy,
+};
+pub use crate::limits::{ResourceLimitExceeded, ResourceLimits};
+
+// analysis
+pub use crate::analysis::{
+    anomalous_squares, joint_stats, stats_by_white_king_square, zugzwangs, JointStats,
+    KingSquareStats, OutcomeCounts, OutcomeDistrib, StatsAccumulator, Zugzwang, ZugzwangKind,
+};
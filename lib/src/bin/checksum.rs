@@ -1,14 +1,13 @@
 use std::{path::Path, process::Command};
 
 fn main() {
-    let entries = Path::new("./table")
-        .read_dir()
-        .expect("read_dir call failed");
+    let table_dir = Path::new("table");
+    let entries = table_dir.read_dir().expect("read_dir call failed");
     let mut md5_buf: Vec<u8> = Vec::new();
     for entry_res in entries {
         let table_name = entry_res.unwrap().file_name().into_string().unwrap();
         let cmd_output = Command::new("md5sum")
-            .arg(format!("table/{table_name}"))
+            .arg(table_dir.join(table_name))
             .output()
             .expect("failed to execute process");
         md5_buf.extend_from_slice(&cmd_output.stdout);
@@ -0,0 +1,275 @@
+//! Cache of decompressed blocks used when probing, in two tiers so that scanning a big table does not evict
+//! the small ones probed all the time, like `KQvK` or `KRvK`. Blocks of small tables are pinned, never evicted,
+//! up to a budget of their own, while blocks of bigger tables share the rest, least recently used first evicted
+use std::collections::BTreeMap;
+use std::hash::Hash;
+use std::mem::size_of_val;
+use std::sync::Arc;
+
+use rustc_hash::FxHashMap;
+
+use crate::compression::to_u64;
+use crate::Outcomes;
+
+/// Budgets of the tiers of the block cache, shared by the whole process,
+/// see `configure_block_cache`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockCacheConfig {
+    /// In bytes, tables at most this big once decompressed are small, their block is pinned
+    pub small_table_bytes: u64,
+    /// In bytes, budget of the pinned tier. Once it is full, blocks of small tables go to the LRU tier
+    pub pinned_bytes: u64,
+    /// In bytes, budget of the LRU tier, holding the blocks of the other tables
+    pub lru_bytes: u64,
+}
+
+impl Default for BlockCacheConfig {
+    /// 4-pieces tables are small, 5-pieces ones are not
+    fn default() -> Self {
+        Self {
+            small_table_bytes: 16 * 1_000_000,
+            pinned_bytes: 512 * 1_000_000,
+            lru_bytes: 4 * 1_000_000_000,
+        }
+    }
+}
+
+impl BlockCacheConfig {
+    /// Tiers shrunk so that the cache holds at most `max_bytes`, the pinned one being served first
+    #[must_use]
+    pub fn within(self, max_bytes: u64) -> Self {
+        let pinned_bytes = self.pinned_bytes.min(max_bytes);
+        Self {
+            pinned_bytes,
+            lru_bytes: self.lru_bytes.min(max_bytes - pinned_bytes),
+            ..self
+        }
+    }
+}
+
+/// Counters of one tier of the block cache, since the process started or the cache was last configured
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TierStats {
+    pub hits: u64,
+    /// Blocks decompressed then kept in this tier, or that did not fit in it
+    pub misses: u64,
+    /// Blocks dropped to make room for others, always 0 for the pinned tier
+    pub evictions: u64,
+    /// Blocks currently held
+    pub entries: usize,
+    /// In bytes, of the blocks currently held
+    pub bytes: u64,
+}
+
+impl TierStats {
+    /// Share of the lookups answered without decompressing, `None` before the first one
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn hit_rate(&self) -> Option<f64> {
+        let lookups = self.hits + self.misses;
+        (lookups > 0).then(|| self.hits as f64 / lookups as f64)
+    }
+}
+
+/// See `block_cache_stats`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BlockCacheStats {
+    pub pinned: TierStats,
+    pub lru: TierStats,
+}
+
+#[derive(Debug)]
+pub(crate) struct BlockCache<K> {
+    config: BlockCacheConfig,
+    pinned: FxHashMap<K, Arc<Outcomes>>,
+    // with the tick of their last use
+    lru: FxHashMap<K, (Arc<Outcomes>, u64)>,
+    // keys of `lru` by the tick of their last use, the least recently used first
+    recency: BTreeMap<u64, K>,
+    tick: u64,
+    stats: BlockCacheStats,
+}
+
+impl<K: Hash + Eq + Clone> BlockCache<K> {
+    pub fn new(config: BlockCacheConfig) -> Self {
+        Self {
+            config,
+            pinned: FxHashMap::default(),
+            lru: FxHashMap::default(),
+            recency: BTreeMap::new(),
+            tick: 0,
+            stats: BlockCacheStats::default(),
+        }
+    }
+
+    pub fn config(&self) -> BlockCacheConfig {
+        self.config
+    }
+
+    pub fn stats(&self) -> BlockCacheStats {
+        self.stats
+    }
+
+    /// Both tiers are disabled, blocks are not worth looking up
+    pub fn is_bypassed(&self) -> bool {
+        self.config.pinned_bytes == 0 && self.config.lru_bytes == 0
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<Arc<Outcomes>> {
+        if let Some(outcomes) = self.pinned.get(key) {
+            self.stats.pinned.hits += 1;
+            return Some(Arc::clone(outcomes));
+        }
+        self.tick += 1;
+        let (outcomes, last_used) = self.lru.get_mut(key)?;
+        let key = self
+            .recency
+            .remove(last_used)
+            .expect("every key of the LRU tier has a tick");
+        *last_used = self.tick;
+        self.recency.insert(self.tick, key);
+        self.stats.lru.hits += 1;
+        Some(Arc::clone(outcomes))
+    }
+
+    /// Keep the decompressed block `outcomes`, `whole_table` if it is the only block of its table.
+    /// It is not kept at all if bigger than the budget of its tier
+    pub fn insert(&mut self, key: K, outcomes: Arc<Outcomes>, whole_table: bool) {
+        let bytes = to_u64(size_of_val(outcomes.as_slice()));
+        let pinned = &mut self.stats.pinned;
+        if whole_table
+            && bytes <= self.config.small_table_bytes
+            && pinned.bytes + bytes <= self.config.pinned_bytes
+        {
+            pinned.misses += 1;
+            if self.pinned.insert(key, outcomes).is_none() {
+                pinned.entries += 1;
+                pinned.bytes += bytes;
+            }
+            return;
+        }
+        let lru = &mut self.stats.lru;
+        lru.misses += 1;
+        if bytes > self.config.lru_bytes {
+            return;
+        }
+        while lru.bytes + bytes > self.config.lru_bytes {
+            let (_, oldest) = self
+                .recency
+                .pop_first()
+                .expect("LRU tier over budget while empty");
+            let (evicted, _) = self.lru.remove(&oldest).expect("every tick has a key");
+            lru.evictions += 1;
+            lru.entries -= 1;
+            lru.bytes -= to_u64(size_of_val(evicted.as_slice()));
+        }
+        self.tick += 1;
+        // another thread may have decompressed the same block meanwhile
+        self.recency.insert(self.tick, key.clone());
+        if let Some((replaced, last_used)) = self.lru.insert(key, (outcomes, self.tick)) {
+            self.recency.remove(&last_used);
+            lru.bytes -= to_u64(size_of_val(replaced.as_slice()));
+        } else {
+            lru.entries += 1;
+        }
+        lru.bytes += bytes;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::UNDEFINED_OUTCOME_BYCOLOR;
+
+    // 2 bytes by index
+    fn block(nb_indexes: usize) -> Arc<Outcomes> {
+        Arc::new(vec![UNDEFINED_OUTCOME_BYCOLOR; nb_indexes])
+    }
+
+    fn cache() -> BlockCache<u8> {
+        BlockCache::new(BlockCacheConfig {
+            small_table_bytes: 10,
+            pinned_bytes: 20,
+            lru_bytes: 100,
+        })
+    }
+
+    #[test]
+    fn test_small_tables_pinned() {
+        let mut cache = cache();
+        cache.insert(0, block(5), true);
+        cache.insert(1, block(5), true);
+        // the pinned tier is full
+        cache.insert(2, block(5), true);
+        // not the only block of its table
+        cache.insert(3, block(5), false);
+        assert_eq!(cache.stats().pinned.entries, 2);
+        assert_eq!(cache.stats().pinned.bytes, 20);
+        assert_eq!(cache.stats().lru.entries, 2);
+        assert!(cache.get(&0).is_some());
+        assert!(cache.get(&2).is_some());
+        assert!(cache.get(&4).is_none());
+        assert_eq!(cache.stats().pinned.hits, 1);
+        assert_eq!(cache.stats().lru.hits, 1);
+    }
+
+    #[test]
+    fn test_scan_does_not_evict_pinned() {
+        let mut cache = cache();
+        cache.insert(0, block(5), true);
+        for key in 1..=10 {
+            cache.insert(key, block(20), false);
+        }
+        let stats = cache.stats();
+        assert_eq!(stats.lru.entries, 2);
+        assert_eq!(stats.lru.evictions, 8);
+        assert_eq!(stats.lru.bytes, 80);
+        assert!(cache.get(&0).is_some());
+        assert!(cache.get(&1).is_none());
+        assert!(cache.get(&10).is_some());
+    }
+
+    #[test]
+    fn test_least_recently_used_evicted() {
+        let mut cache = cache();
+        cache.insert(1, block(20), false);
+        cache.insert(2, block(20), false);
+        assert!(cache.get(&1).is_some());
+        cache.insert(3, block(20), false);
+        assert!(cache.get(&1).is_some());
+        assert!(cache.get(&2).is_none());
+        // bigger than the whole tier
+        cache.insert(4, block(51), false);
+        assert!(cache.get(&4).is_none());
+        assert_eq!(cache.stats().lru.hits, 2);
+        assert_eq!(cache.stats().lru.misses, 4);
+        let hit_rate = cache.stats().lru.hit_rate().unwrap();
+        assert!((hit_rate - 1. / 3.).abs() < f64::EPSILON);
+        assert_eq!(TierStats::default().hit_rate(), None);
+    }
+
+    #[test]
+    fn test_reinserted_block_keeps_one_tick() {
+        let mut cache = cache();
+        cache.insert(1, block(20), false);
+        cache.insert(2, block(20), false);
+        // decompressed by another thread meanwhile
+        cache.insert(1, block(20), false);
+        assert_eq!(cache.recency.len(), 2);
+        assert_eq!(cache.stats().lru.bytes, 80);
+        cache.insert(3, block(20), false);
+        assert!(cache.get(&2).is_none());
+        assert!(cache.get(&1).is_some());
+        assert_eq!(cache.recency.len(), cache.lru.len());
+    }
+
+    #[test]
+    fn test_within() {
+        let config = BlockCacheConfig::default();
+        assert_eq!(config.within(u64::MAX), config);
+        let within = config.within(1_000_000_000);
+        assert_eq!(within.pinned_bytes, config.pinned_bytes);
+        assert_eq!(within.lru_bytes, 1_000_000_000 - config.pinned_bytes);
+        assert_eq!(config.within(0).pinned_bytes, 0);
+    }
+}
@@ -0,0 +1,154 @@
+//! Tagging state saved when a build is stopped, so that the next build of the table resumes it
+//! instead of starting over, see `BuildOptions::checkpoint_dir`
+use std::{
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use retroboard::shakmaty::ByColor;
+use serde::{Deserialize, Serialize};
+
+use crate::{Common, MaterialWinner, Outcome, OutcomeU8, ProcessedBits, QuarantineEntry};
+
+/// First line of a checkpoint, as JSON. It is followed by the entries of `Common::all_pos`,
+/// the white to move byte of each index first, then by the packed `Common::processed` flags
+#[derive(Debug, Serialize, Deserialize)]
+struct Header {
+    table: String,
+    /// Raw `OutcomeU8` of the ply tagging stopped before
+    stopped_at: u8,
+    tagging_passes: usize,
+    capture_draws: u64,
+    duplicates: u64,
+    reconciled_duplicates: u64,
+    quarantine: Option<Vec<QuarantineEntry>>,
+    nb_indexes: usize,
+}
+
+#[derive(Debug)]
+pub(crate) struct Checkpoint {
+    pub common: Common,
+    /// Ply tagging stopped before, and resumes from
+    pub stopped_at: Outcome,
+    pub tagging_passes: usize,
+}
+
+impl Checkpoint {
+    /// Written to a temporary file first, so that a checkpoint found is always complete
+    pub fn write(
+        path: &Path,
+        common: &Common,
+        stopped_at: Outcome,
+        tagging_passes: usize,
+    ) -> io::Result<()> {
+        let header = Header {
+            table: format!("{:?}", common.material_winner()),
+            stopped_at: OutcomeU8::from(stopped_at).as_raw_u8(),
+            tagging_passes,
+            capture_draws: common.capture_draws,
+            duplicates: common.duplicates,
+            reconciled_duplicates: common.reconciled_duplicates,
+            quarantine: common.quarantine.clone(),
+            nb_indexes: common.all_pos.len(),
+        };
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        let mut encoder = zstd::stream::Encoder::new(BufWriter::new(File::create(&tmp_path)?), 1)?;
+        serde_json::to_writer(&mut encoder, &header)?;
+        encoder.write_all(b"\n")?;
+        for by_color in &common.all_pos {
+            encoder.write_all(&[by_color.white.as_raw_u8(), by_color.black.as_raw_u8()])?;
+        }
+        encoder.write_all(common.processed.packed())?;
+        encoder.finish()?.flush()?;
+        fs::rename(&tmp_path, path)
+    }
+
+    /// Fails with `io::ErrorKind::InvalidData` if the checkpoint is not one of `mat_win`
+    pub fn read(path: &Path, mat_win: MaterialWinner) -> io::Result<Self> {
+        let mut reader = BufReader::new(zstd::stream::Decoder::new(File::open(path)?)?);
+        let mut header_line = String::new();
+        reader.read_line(&mut header_line)?;
+        let header: Header = serde_json::from_str(&header_line)?;
+        let invalid = |reason: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("checkpoint {}: {reason}", path.display()),
+            )
+        };
+        if header.table != format!("{mat_win:?}") {
+            return Err(invalid(format!("of {}, not of {mat_win:?}", header.table)));
+        }
+        let outcome_u8 = |raw: u8| {
+            OutcomeU8::from_raw_u8(raw)
+                .ok_or_else(|| invalid(format!("invalid outcome byte {raw}")))
+        };
+        let stopped_at = Outcome::from(outcome_u8(header.stopped_at)?);
+        let mut all_pos = Vec::with_capacity(header.nb_indexes);
+        let mut bytes = [0; 2];
+        for _ in 0..header.nb_indexes {
+            reader.read_exact(&mut bytes)?;
+            all_pos.push(ByColor {
+                white: outcome_u8(bytes[0])?,
+                black: outcome_u8(bytes[1])?,
+            });
+        }
+        let mut packed = vec![0; ProcessedBits::packed_len(header.nb_indexes)];
+        reader.read_exact(&mut packed)?;
+        let mut common = Common::from_parts(mat_win, all_pos, ProcessedBits::from_packed(packed));
+        common.capture_draws = header.capture_draws;
+        common.duplicates = header.duplicates;
+        common.reconciled_duplicates = header.reconciled_duplicates;
+        common.quarantine = header.quarantine;
+        Ok(Self {
+            common,
+            stopped_at,
+            tagging_passes: header.tagging_passes,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Report;
+    use retroboard::shakmaty::Color;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_checkpoint_roundtrip() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let path = tmp_dir.path().join("KQvKw.checkpoint");
+        let mat_win = MaterialWinner::from_str("KQvKw").unwrap();
+        let mut common: Common = Common::new(mat_win.clone());
+        common.all_pos.truncate(3);
+        common.processed.truncate(3);
+        common.set_report(0, Color::White, Report::Processed(Outcome::Win(3)));
+        common.set_report(1, Color::Black, Report::Unprocessed(Outcome::Lose(2)));
+        common.duplicates = 4;
+        common.quarantine = Some(vec![QuarantineEntry {
+            idx: 1,
+            fen: "8/8/8/8/8/8/8/kQK5 b - - 0 1".to_string(),
+            reason: "test".to_string(),
+        }]);
+        Checkpoint::write(&path, &common, Outcome::Win(4), 5).unwrap();
+        let checkpoint = Checkpoint::read(&path, mat_win).unwrap();
+        assert_eq!(checkpoint.stopped_at, Outcome::Win(4));
+        assert_eq!(checkpoint.tagging_passes, 5);
+        assert_eq!(checkpoint.common.all_pos, common.all_pos);
+        assert_eq!(
+            checkpoint.common.report(0, Color::White),
+            Report::Processed(Outcome::Win(3))
+        );
+        assert_eq!(
+            checkpoint.common.report(1, Color::Black),
+            Report::Unprocessed(Outcome::Lose(2))
+        );
+        assert_eq!(checkpoint.common.processed, common.processed);
+        assert_eq!(checkpoint.common.duplicates, 4);
+        assert_eq!(checkpoint.common.quarantine, common.quarantine);
+        let err = Checkpoint::read(&path, MaterialWinner::from_str("KQvKb").unwrap()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
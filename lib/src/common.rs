@@ -1,28 +1,136 @@
 use crate::{
-    indexer::Indexer, DefaultIndexer, Material, MaterialWinner, Reports, UNDEFINED_OUTCOME_BYCOLOR,
+    compression::to_usize, outcome::iter_defined, CompressionOptions, DefaultIndexer,
+    EncoderDecoder, IndexWithTurn, Material, MaterialWinner, Outcome, Outcomes, ProcessedBits,
+    Report, SideToMove, SideToMoveGetter, UNDEFINED_OUTCOME_BYCOLOR,
+};
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    sync::{Arc, Mutex},
 };
 
 use indicatif::{ProgressBar, ProgressStyle};
-use log::trace;
-use retroboard::shakmaty::Color;
+use log::{error, trace, warn};
+use retroboard::shakmaty::{Bitboard, Color};
+use serde::{Deserialize, Serialize};
+
+/// Inconsistency found while building a table, recorded instead of panicking
+/// when the quarantine mode is enabled, see `Common::record_inconsistency`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuarantineEntry {
+    pub idx: u64,
+    pub fen: String,
+    pub reason: String,
+}
+
+/// Position met while building a table which deserves investigation, see `AnomalySink`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Anomaly {
+    /// Material and winner of the table being built, eg `KQvKw`
+    pub table: String,
+    pub idx: u64,
+    pub fen: String,
+    pub context: String,
+}
+
+/// JSON lines file anomalies are appended to, one `Anomaly` per line. Each line is written
+/// as soon as the anomaly is met, so that it is kept even if the build panics or is interrupted.
+/// Clones share the same file
+#[derive(Debug, Clone)]
+pub struct AnomalySink(Arc<Mutex<File>>);
+
+impl AnomalySink {
+    /// The file is created if it does not exist, and appended to otherwise
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self(Arc::new(Mutex::new(file))))
+    }
+
+    pub fn record(&self, anomaly: &Anomaly) -> io::Result<()> {
+        let mut line = serde_json::to_string(anomaly)?;
+        line.push('\n');
+        // single write, so that lines of concurrent builds are not interleaved
+        self.0
+            .lock()
+            .expect("anomaly sink poisoned")
+            .write_all(line.as_bytes())
+    }
+}
+
+/// What the winner of a table aims for, which depends on whether its material is sufficient to mate
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WinObjective {
+    /// Outcomes are distances to mate, `Win` for the winner mating, `Lose` for the winner being mated
+    Mate,
+    /// The winner cannot mate, so no outcome is a `Win`: the positions processed first are the draws,
+    /// and `Lose` are still distances to the winner being mated
+    Draw,
+}
+
+impl WinObjective {
+    #[must_use]
+    pub fn of(mat_win: &MaterialWinner) -> Self {
+        if mat_win.material.can_mate(mat_win.winner) {
+            Self::Mate
+        } else {
+            Self::Draw
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct Common<T = DefaultIndexer> {
-    pub all_pos: Reports,
-    pub counter: u64,
+    pub(crate) all_pos: Outcomes,
+    /// Whether each entry of `all_pos` has been processed, see `Common::report`. Dropped once tagging is over
+    pub(crate) processed: ProcessedBits,
+    pub(crate) counter: u64,
+    /// Number of positions whose legal moves are all captures/promotions leading at best to a draw,
+    /// which are exact draws known before tagging
+    pub(crate) capture_draws: u64,
+    /// Number of indexes generated more than once, only tolerated for like pieces without pawns,
+    /// see `Generator::reconcile_duplicate`
+    pub(crate) duplicates: u64,
+    /// Among `duplicates`, those whose initial reports differed, one of them being `Outcome::Unknown`
+    pub(crate) reconciled_duplicates: u64,
+    pub(crate) quarantine: Option<Vec<QuarantineEntry>>, // if `None`, inconsistencies panic
+    /// Where inconsistencies are also written, whether they panic or not
+    pub(crate) anomaly_sink: Option<AnomalySink>,
     mat_win: MaterialWinner,
-    can_mate: bool, // if `true`, the desired outcome is winning, otherwise it's to draw
-    indexer: T,
+    win_objective: WinObjective,
+    pub(crate) indexer: T,
 }
 
 impl<T: From<Material>> Common<T> {
     #[must_use]
     pub fn new(mat_win: MaterialWinner) -> Self {
         trace!("Creating a new `Common` instance");
+        let nb_pos = get_estimate_nb_pos(&mat_win.material);
+        Self::from_parts(
+            mat_win,
+            vec![UNDEFINED_OUTCOME_BYCOLOR; nb_pos],
+            ProcessedBits::new_processed(nb_pos),
+        )
+    }
+
+    /// Same as `Common::new`, with outcomes and processed flags already known, eg read from a checkpoint,
+    /// rather than allocated for every index of the material
+    pub(crate) fn from_parts(
+        mat_win: MaterialWinner,
+        all_pos: Outcomes,
+        processed: ProcessedBits,
+    ) -> Self {
         Self {
-            all_pos: vec![UNDEFINED_OUTCOME_BYCOLOR; get_estimate_nb_pos(&mat_win.material)],
+            all_pos,
+            processed,
             counter: 0,
-            can_mate: mat_win.material.can_mate(mat_win.winner),
+            capture_draws: 0,
+            duplicates: 0,
+            reconciled_duplicates: 0,
+            quarantine: None,
+            anomaly_sink: None,
+            win_objective: WinObjective::of(&mat_win),
             indexer: T::from(mat_win.material.clone()),
             mat_win,
         }
@@ -31,8 +139,8 @@ impl<T: From<Material>> Common<T> {
 
 impl<T> Common<T> {
     #[must_use]
-    pub fn get_progress_bar(&self) -> ProgressBar {
-        let pb = ProgressBar::new((get_estimate_nb_pos(&self.material()) * 2) as u64);
+    pub(crate) fn get_progress_bar(&self) -> ProgressBar {
+        let pb = ProgressBar::new((get_estimate_nb_pos(self.material()) * 2) as u64);
         pb.set_style(
             ProgressStyle::with_template(
                 "{msg} {spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {pos}/{len} ({eta})",
@@ -43,9 +151,88 @@ impl<T> Common<T> {
         pb
     }
 
+    /// Outcome of the position at `idx` with `turn` to move, and whether it has been processed
+    #[inline]
+    #[must_use]
+    pub(crate) fn report(&self, idx: usize, turn: Color) -> Report {
+        let outcome = self.all_pos[idx].get_outcome_by_color(turn);
+        if self.processed.get(idx, turn) {
+            Report::Processed(outcome)
+        } else {
+            Report::Unprocessed(outcome)
+        }
+    }
+
+    /// Entries defined for their side to move, along with their outcome, see `iter_defined`
+    pub fn iter_defined(&self) -> impl Iterator<Item = (IndexWithTurn, Outcome)> + '_ {
+        iter_defined(&self.all_pos, 0)
+    }
+
+    #[inline]
+    pub(crate) fn set_report(&mut self, idx: usize, turn: Color, report: Report) {
+        let (outcome, processed) = match report {
+            Report::Unprocessed(outcome) => (outcome, false),
+            Report::Processed(outcome) => (outcome, true),
+        };
+        *self.all_pos[idx].get_mut(turn) = outcome.into();
+        self.processed.set(idx, turn, processed);
+    }
+
+    /// Once tagging is over, the flags are not needed anymore
+    pub(crate) fn drop_processed_flags(&mut self) {
+        self.processed = ProcessedBits::default();
+    }
+
+    /// Panics, unless the quarantine mode is enabled, in which case the inconsistency
+    /// is recorded and the generation goes on. In both cases it is first written to the
+    /// anomaly sink, if any
+    pub fn record_inconsistency(&mut self, idx: u64, pos: &impl SideToMove, reason: String) {
+        let fen = format!(
+            "{} {}",
+            pos.board().board_fen(Bitboard::EMPTY),
+            pos.side_to_move().char()
+        );
+        if let Some(ref sink) = self.anomaly_sink {
+            let anomaly = Anomaly {
+                table: format!("{:?}", self.mat_win),
+                idx,
+                fen: fen.clone(),
+                context: reason.clone(),
+            };
+            if let Err(e) = sink.record(&anomaly) {
+                error!("Could not record anomaly {anomaly:?}: {e}");
+            }
+        }
+        match self.quarantine {
+            Some(ref mut quarantine) => {
+                warn!("Quarantined index {idx}, position {fen}: {reason}");
+                quarantine.push(QuarantineEntry { idx, fen, reason });
+            }
+            None => panic!("{reason}, index: {idx}, position: {fen}"),
+        }
+    }
+
+    /// Inconsistencies recorded, `None` if they panic instead, see `BuildOptions::quarantine`
+    #[must_use]
+    pub fn quarantine(&self) -> Option<&[QuarantineEntry]> {
+        self.quarantine.as_deref()
+    }
+
+    /// `true` if at least one inconsistency has been recorded, meaning the table cannot be trusted
+    #[must_use]
+    pub fn is_dirty(&self) -> bool {
+        self.quarantine.as_ref().is_some_and(|q| !q.is_empty())
+    }
+
+    #[must_use]
+    pub fn win_objective(&self) -> WinObjective {
+        self.win_objective
+    }
+
+    /// Same as `self.win_objective() == WinObjective::Mate`
     #[must_use]
     pub fn can_mate(&self) -> bool {
-        self.can_mate
+        self.win_objective == WinObjective::Mate
     }
 
     #[must_use]
@@ -62,20 +249,41 @@ impl<T> Common<T> {
     pub fn material_winner(&self) -> &MaterialWinner {
         &self.mat_win
     }
+
+    /// Compress the outcomes of the table into `writer`, in the format read by `TablebaseProber`,
+    /// see `EncoderDecoder::compress_with_options`
+    pub fn write_table(&self, writer: impl Write, options: CompressionOptions) -> io::Result<()> {
+        EncoderDecoder::new(writer).compress_with_options(&self.all_pos, options)
+    }
 }
 
-impl<T: Indexer> Common<T> {
+#[cfg(feature = "unstable-internals")]
+impl<T> Common<T> {
     #[must_use]
     pub fn indexer(&self) -> &T {
         &self.indexer
     }
+
+    /// Outcomes of the table, in the layout written on disk
+    #[must_use]
+    pub fn all_pos(&self) -> &Outcomes {
+        &self.all_pos
+    }
 }
 
 #[inline]
-fn get_estimate_nb_pos(mat: &Material) -> usize {
+#[must_use]
+pub fn get_estimate_nb_pos(mat: &Material) -> usize {
+    if mat.has_pawns() {
+        // with pawns the white king can be on 32 squares and the index is not compact,
+        // take the maximum index of `NaiveIndexer`
+        return to_usize(
+            32 * 64_u64.pow(u32::try_from(mat.count() - 1).expect("at most 32 pieces")),
+        );
+    }
     // white king is already included in `material.count()`, so substract it, and multiply by 10 instead, real number of cases the white king can go on
     // heuristic, less than 92% of pos are legals.
-    (pow_minus_1(63, mat.count() - 1) * 10) as usize / 100 * 92
+    to_usize(pow_minus_1(63, mat.count() - 1) * 10) / 100 * 92
 }
 
 // instead of 64**4 get 64*63*62*61
@@ -91,6 +299,81 @@ const fn pow_minus_1(exp: u64, left: usize) -> u64 {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use retroboard::shakmaty::{Board, Color::White};
+    use std::str::FromStr;
+
+    fn kqvk_common() -> Common {
+        Common::new(MaterialWinner::new(
+            &Material::from_str("KQvK").unwrap(),
+            White,
+        ))
+    }
+
+    #[test]
+    fn test_win_objective() {
+        assert_eq!(kqvk_common().win_objective(), WinObjective::Mate);
+        for (mat_win, win_objective) in [
+            ("KQvKb", WinObjective::Draw),
+            ("KQvKBb", WinObjective::Draw),
+            ("KRvKNb", WinObjective::Mate),
+        ] {
+            let common: Common = Common::new(MaterialWinner::from_str(mat_win).unwrap());
+            assert_eq!(common.win_objective(), win_objective, "{mat_win}");
+            assert_eq!(common.can_mate(), win_objective == WinObjective::Mate);
+        }
+    }
+
+    #[test]
+    fn test_record_inconsistency_quarantine() {
+        let mut common = kqvk_common();
+        common.quarantine = Some(Vec::new());
+        assert!(!common.is_dirty());
+        common.record_inconsistency(42, &(Board::new(), White), "test".to_string());
+        assert!(common.is_dirty());
+        assert_eq!(
+            common.quarantine.unwrap(),
+            vec![QuarantineEntry {
+                idx: 42,
+                fen: "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w".to_string(),
+                reason: "test".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "test, index: 42")]
+    fn test_record_inconsistency_strict() {
+        kqvk_common().record_inconsistency(42, &(Board::new(), White), "test".to_string());
+    }
+
+    #[test]
+    fn test_record_inconsistency_anomaly_sink() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("anomalies.jsonl");
+        let mut common = kqvk_common();
+        common.quarantine = Some(Vec::new());
+        common.anomaly_sink = Some(AnomalySink::open(&path).unwrap());
+        for idx in [42, 43] {
+            common.record_inconsistency(idx, &(Board::new(), White), "test".to_string());
+        }
+        let anomalies: Vec<Anomaly> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(
+            anomalies,
+            [42, 43]
+                .map(|idx| Anomaly {
+                    table: "KQvKw".to_string(),
+                    idx,
+                    fen: "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w".to_string(),
+                    context: "test".to_string()
+                })
+                .to_vec()
+        );
+    }
+
     #[test]
     fn test_pow_minus_1() {
         assert_eq!(pow_minus_1(64, 1), 64);
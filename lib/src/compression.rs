@@ -1,17 +1,29 @@
-use std::io::{self, ErrorKind::InvalidData, Write};
+// `DekuRead` derive generates code triggering `manual_div_ceil`
+#![allow(clippy::manual_div_ceil)]
 
+use std::cell::Cell;
+use std::fmt;
+use std::io::{self, ErrorKind::InvalidData, Write};
+use std::ops::{Deref, Range};
+use std::sync::Arc;
 #[cfg(feature = "cached")]
-use cached::proc_macro::cached;
-use deku::bitvec::BitView;
+use std::sync::{LazyLock, Mutex};
+
 use deku::ctx::BitSize;
-use deku::{ctx::Limit, prelude::*};
+use deku::prelude::*;
 use log::trace;
 use positioned_io::ReadAt;
-use retroboard::shakmaty::ByColor;
-use zstd::stream::{decode_all, encode_all};
+use retroboard::shakmaty::{ByColor, Color};
+use rustc_hash::FxHashMap;
+use zstd::stream::{copy_decode, encode_all};
 
+#[cfg(feature = "cached")]
+use crate::block_cache::{BlockCache, BlockCacheConfig, BlockCacheStats};
+use crate::{
+    DenseOutcomes, MaterialWinner, OutcomeDistrib, OutcomeU8, Outcomes, OutcomesSlice,
+    ResourceLimits, UndefinedSpans, WdlOutcomes, WinObjective,
+};
 use crate::{IndexWithTurn, Outcome};
-use crate::{MaterialWinner, OutcomeU8, Outcomes, Report, ReportU8, Reports, ReportsSlice};
 
 // in bytes, the size of the uncompressed block we want
 const BLOCK_SIZE: usize = 500 * 1_000_000;
@@ -21,14 +33,36 @@ const BLOCK_SIZE: usize = 500 * 1_000_000;
 // considering each elements takes 2byte
 const BLOCK_ELEMENTS: usize = BLOCK_SIZE / 2;
 
-// in bytes, the size of the cache in RAM when probing
-// ideally should be set by the user, but for now good as is
+// decompressed blocks kept when probing, shared by all tables, see `configure_block_cache`
+// and `limit_block_cache`.
+// A block header is unique to a block given a material configuration and a winner
 #[cfg(feature = "cached")]
-const CACHE_SIZE: usize = 4 * 1_000_000_000;
+static BLOCK_CACHE: LazyLock<Mutex<BlockCache<(MaterialWinner, BlockHeader)>>> =
+    LazyLock::new(|| Mutex::new(BlockCache::new(BlockCacheConfig::default())));
 
-// max number of elements we can set in the cache for it not to exceed `CACHE_SIZE`
-#[cfg(feature = "cached")]
-const CACHE_ELEMENTS: usize = CACHE_SIZE / BLOCK_SIZE;
+// in bytes, the biggest decompression buffer kept by each thread between decompressions.
+// Bigger ones are freed, not to hold up to `BLOCK_SIZE` per thread for the whole run
+const MAX_KEPT_BUFFER_SIZE: usize = 64 * 1_000_000;
+
+thread_local! {
+    // reused across the decompressions of a thread, see `Block::with_decompressed_bytes`
+    static DECOMPRESSION_BUFFER: Cell<Vec<u8>> = const { Cell::new(Vec::new()) };
+}
+
+// first bytes of the optional trailer following the blocks, see `EncoderDecoder::write_undefined_spans`.
+// Read as the `index_from` of a block header, it would be far beyond any table length
+const TRAILER_MAGIC: [u8; 8] = *b"HMTBSPAN";
+
+// first bytes of the optional trailer holding the number of entries of each outcome, following the blocks
+// and the trailer of undefined spans if any, see `EncoderDecoder::write_outcome_distrib`
+const DISTRIB_TRAILER_MAGIC: [u8; 8] = *b"HMTBDIST";
+
+// first bytes of the optional trailer holding the `WinObjective` of the table, following all the other trailers,
+// see `EncoderDecoder::write_win_objective`
+const OBJECTIVE_TRAILER_MAGIC: [u8; 8] = *b"HMTBOBJV";
+
+// bytes of a `RawOutcome` undefined for both sides to move
+const UNDEFINED_RAW_OUTCOME: [u8; 2] = [127, 127];
 
 /// Deku compatible struct
 #[derive(Debug, Copy, Clone, PartialEq, DekuRead, DekuWrite, Eq)]
@@ -37,12 +71,11 @@ struct RawOutcome {
     white: u8,
 }
 
-impl From<&ByColor<ReportU8>> for RawOutcome {
-    // TODO make this more efficient
-    fn from(c: &ByColor<ReportU8>) -> Self {
+impl From<&ByColor<OutcomeU8>> for RawOutcome {
+    fn from(c: &ByColor<OutcomeU8>) -> Self {
         Self {
-            black: OutcomeU8::from(Report::from(c.black).outcome()).as_raw_u8(),
-            white: OutcomeU8::from(Report::from(c.white).outcome()).as_raw_u8(),
+            black: c.black.as_raw_u8(),
+            white: c.white.as_raw_u8(),
         }
     }
 }
@@ -58,43 +91,731 @@ impl From<RawOutcome> for ByColor<OutcomeU8> {
     }
 }
 
-impl From<&RawOutcome> for ByColor<OutcomeU8> {
-    fn from(c: &RawOutcome) -> Self {
-        (*c).into()
+/// Transformation applied to the raw outcome bytes of a block before zstd compression,
+/// to make them easier to compress. Recorded in the `BlockHeader`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum PreFilter {
+    /// Bytes are compressed as is. Tables generated before the introduction of pre-filters use it
+    #[default]
+    None = 0,
+    /// Each outcome is replaced by its (wrapping) difference with the outcome of the previous index
+    /// for the same side to move. Neighbouring indexes tend to have correlated outcomes
+    Delta = 1,
+    /// A bitmap of the indexes defined for at least one side to move, followed by their outcomes only.
+    /// The indexer leaves large gaps of undefined ones, see `DenseOutcomes` for the in-memory equivalent
+    Dense = 2,
+}
+
+impl PreFilter {
+    fn from_u8(u: u8) -> io::Result<Self> {
+        match u {
+            0 => Ok(Self::None),
+            1 => Ok(Self::Delta),
+            2 => Ok(Self::Dense),
+            _ => Err(TableFormatError::UnknownPreFilter(u).into()),
+        }
+    }
+
+    // `RawOutcome` bytes are laid out as `[black, white, black, white, ...]`
+    fn apply(self, bytes: &mut Vec<u8>) {
+        match self {
+            Self::None => (),
+            Self::Delta => {
+                for i in (2..bytes.len()).rev() {
+                    bytes[i] = bytes[i].wrapping_sub(bytes[i - 2]);
+                }
+            }
+            Self::Dense => {
+                let mut dense = vec![0; (bytes.len() / 2).div_ceil(8)];
+                for (i, raw) in bytes.chunks_exact(2).enumerate() {
+                    if raw != UNDEFINED_RAW_OUTCOME {
+                        dense[i / 8] |= 1 << (i % 8);
+                    }
+                }
+                dense.extend(
+                    bytes
+                        .chunks_exact(2)
+                        .filter(|raw| *raw != UNDEFINED_RAW_OUTCOME)
+                        .flatten(),
+                );
+                *bytes = dense;
+            }
+        }
+    }
+
+    /// `nb_elements` is the number of outcomes of the block, once reverted
+    fn revert(self, bytes: &mut Vec<u8>, nb_elements: usize) -> io::Result<()> {
+        match self {
+            Self::None => (),
+            Self::Delta => {
+                for i in 2..bytes.len() {
+                    bytes[i] = bytes[i].wrapping_add(bytes[i - 2]);
+                }
+            }
+            Self::Dense => {
+                let invalid = || {
+                    io::Error::from(TableFormatError::Decompression(
+                        "dense block shorter than its bitmap".to_string(),
+                    ))
+                };
+                let (bitmap, dense) = bytes
+                    .split_at_checked(nb_elements.div_ceil(8))
+                    .ok_or_else(invalid)?;
+                let mut dense = dense.chunks_exact(2);
+                let mut expanded = Vec::with_capacity(2 * nb_elements);
+                for i in 0..nb_elements {
+                    if bitmap[i / 8] >> (i % 8) & 1 == 1 {
+                        expanded.extend_from_slice(dense.next().ok_or_else(invalid)?);
+                    } else {
+                        expanded.extend_from_slice(&UNDEFINED_RAW_OUTCOME);
+                    }
+                }
+                *bytes = expanded;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Compression algorithm of a block, recorded in the `BlockHeader`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Codec {
+    /// Level 21, best ratio but slow. Tables generated before the introduction of codecs use it
+    #[default]
+    Zstd = 0,
+    /// Bigger tables, but much faster to decompress
+    Lz4 = 1,
+}
+
+impl Codec {
+    fn from_u8(u: u8) -> io::Result<Self> {
+        match u {
+            0 => Ok(Self::Zstd),
+            1 => Ok(Self::Lz4),
+            _ => Err(TableFormatError::UnknownCodec(u).into()),
+        }
+    }
+
+    fn compress(self, bytes: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Self::Zstd => encode_all(bytes, 21),
+            Self::Lz4 => Ok(lz4_flex::compress_prepend_size(bytes)),
+        }
+    }
+
+    /// Replace the content of `buffer` by the decompressed `bytes`, reusing its allocation
+    fn decompress_into(self, bytes: &[u8], buffer: &mut Vec<u8>) -> io::Result<()> {
+        buffer.clear();
+        match self {
+            Self::Zstd => copy_decode(bytes, &mut *buffer)
+                .map_err(|e| TableFormatError::Decompression(e.to_string()).into()),
+            Self::Lz4 => {
+                let decompression_error = |e: lz4_flex::block::DecompressError| {
+                    TableFormatError::Decompression(e.to_string())
+                };
+                let (size, compressed) =
+                    lz4_flex::block::uncompressed_size(bytes).map_err(decompression_error)?;
+                buffer.resize(size, 0);
+                lz4_flex::decompress_into(compressed, buffer)
+                    .map_err(|e| decompression_error(e).into())
+                    .map(|written| buffer.truncate(written))
+            }
+        }
+    }
+}
+
+/// How the blocks of a table are compressed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct CompressionOptions {
+    pub pre_filter: PreFilter,
+    pub codec: Codec,
+}
+
+/// Layout of a table file, read from its block headers only, see `EncoderDecoder::table_format`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TableFormat {
+    /// Number of indexes covered by the table, which depends on the indexer used to generate it
+    pub len: u64,
+    /// Compression options of each block, in order
+    pub blocks: Vec<CompressionOptions>,
+}
+
+/// Sizes of a block, read from its header only, see `EncoderDecoder::block_stats`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockStats {
+    /// Indexes covered by the block
+    pub range: Range<u64>,
+    pub options: CompressionOptions,
+    /// In bytes, header excluded
+    pub compressed_size: u64,
+    /// In bytes, of the outcomes once decompressed, 2 by index
+    pub uncompressed_size: u64,
+}
+
+impl BlockStats {
+    /// Uncompressed size divided by the compressed one, the higher the better
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn ratio(&self) -> f64 {
+        self.uncompressed_size as f64 / self.compressed_size.max(1) as f64
+    }
+}
+
+/// Progress of a decompression, reported after each block, see `EncoderDecoder::into_blocks`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DecompressProgress {
+    /// Compressed bytes of the blocks decompressed so far, headers included
+    pub bytes_read: u64,
+    pub blocks_done: usize,
+    /// Index following the last outcome decompressed, from which an interrupted decompression can restart
+    pub next_idx: u64,
+}
+
+/// Blocks of a table overlapping a range of indexes, each one only read and decompressed
+/// when the iterator reaches it, so that the first outcomes can be used while the next blocks are not read yet.
+/// Blocks before the range are skipped from their header. The iterator stops at the end of the table,
+/// and after the first error
+#[derive(Debug)]
+pub struct DecompressedBlocks<T> {
+    decoder: EncoderDecoder<T>,
+    end: u64,
+    byte_offset: u64,
+    progress: DecompressProgress,
+}
+
+impl<T> DecompressedBlocks<T> {
+    /// Progress after the last block returned by the iterator
+    #[must_use]
+    pub fn progress(&self) -> DecompressProgress {
+        self.progress
+    }
+}
+
+impl<T: ReadAt> Iterator for DecompressedBlocks<T> {
+    type Item = io::Result<OutcomesRange>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.progress.next_idx < self.end {
+            let block_header = match self.decoder.read_block_header(self.byte_offset) {
+                Ok(block_header) => block_header,
+                // we have reached the end of the table
+                Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(err) => {
+                    self.end = self.progress.next_idx;
+                    return Some(Err(err));
+                }
+            };
+            let byte_offset = self.byte_offset;
+            self.byte_offset += to_u64(block_header.size_including_headers());
+            if block_header.index_to <= self.progress.next_idx {
+                continue;
+            }
+            let end = self.end.min(block_header.index_to);
+            let outcomes_range = self
+                .decoder
+                .read_block(byte_offset)
+                .and_then(|block| block.get_range(None, self.progress.next_idx..end));
+            if outcomes_range.is_ok() {
+                self.progress.bytes_read += to_u64(block_header.size_including_headers());
+                self.progress.blocks_done += 1;
+                self.progress.next_idx = end;
+            } else {
+                self.end = self.progress.next_idx;
+            }
+            return Some(outcomes_range);
+        }
+        None
+    }
+}
+
+/// Why a table file cannot be read. Carried by the `io::Error` of the readers, with `io::ErrorKind::InvalidData`,
+/// so that callers can tell corrupted tables apart and explain what is wrong with them, see `TableFormatError::of`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TableFormatError {
+    /// Fewer bytes than a block header are left at `byte_offset`
+    TruncatedHeader {
+        byte_offset: u64,
+    },
+    /// The block at `byte_offset` ends after the end of the file
+    TruncatedBlock {
+        byte_offset: u64,
+    },
+    /// The block at `byte_offset` covers `range`, which is empty, overlaps the previous block,
+    /// or leaves a gap after it, the previous block ending at `expected_start`
+    NonContiguousBlock {
+        byte_offset: u64,
+        range: Range<u64>,
+        expected_start: u64,
+    },
+    UnknownPreFilter(u8),
+    UnknownCodec(u8),
+    /// The codec or the pre-filter failed to restore a block, eg a zstd error
+    Decompression(String),
+    /// A block was restored into `found` bytes, instead of the `expected` ones given by its header
+    BlockSizeMismatch {
+        expected: usize,
+        found: usize,
+    },
+    /// The blocks cover `len` indexes instead of `expected`
+    WrongLength {
+        len: u64,
+        expected: u64,
+    },
+    /// The blocks cover `len` indexes, fewer than the `expected_min` ones of any complete table of its material,
+    /// see `NaiveIndexer::min_table_len`. The table was most likely cut at a block boundary, eg by a crash
+    TruncatedTable {
+        len: u64,
+        expected_min: u64,
+    },
+    /// Bytes left at `byte_offset`, after the blocks covering the whole table
+    TrailingGarbage {
+        byte_offset: u64,
+    },
+    /// The trailer at `byte_offset` is cut, followed by other bytes, its spans are not
+    /// sorted, disjoint and within the table, or its outcome counts do not cover the table
+    MalformedTrailer {
+        byte_offset: u64,
+    },
+}
+
+impl TableFormatError {
+    /// The `TableFormatError` carried by `err`, if any
+    #[must_use]
+    pub fn of(err: &io::Error) -> Option<&Self> {
+        err.get_ref().and_then(|inner| inner.downcast_ref())
+    }
+}
+
+impl fmt::Display for TableFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TruncatedHeader { byte_offset } => {
+                write!(f, "truncated block header at byte {byte_offset}")
+            }
+            Self::TruncatedBlock { byte_offset } => {
+                write!(f, "block at byte {byte_offset} is truncated")
+            }
+            Self::NonContiguousBlock {
+                byte_offset,
+                range,
+                expected_start,
+            } => write!(
+                f,
+                "block at byte {byte_offset} covers {range:?}, expected to start at {expected_start}"
+            ),
+            Self::UnknownPreFilter(u) => write!(f, "unknown block pre-filter {u}"),
+            Self::UnknownCodec(u) => write!(f, "unknown block codec {u}"),
+            Self::Decompression(reason) => write!(f, "block decompression failed: {reason}"),
+            Self::BlockSizeMismatch { expected, found } => write!(
+                f,
+                "decompressed block of {found} bytes, expected {expected} from its number of elements"
+            ),
+            Self::WrongLength { len, expected } => {
+                write!(f, "table ends at index {len}, expected {expected}")
+            }
+            Self::TruncatedTable { len, expected_min } => write!(
+                f,
+                "truncated table, ends at index {len}, expected at least {expected_min} from its material"
+            ),
+            Self::TrailingGarbage { byte_offset } => {
+                write!(f, "unexpected bytes after the end of the table, at byte {byte_offset}")
+            }
+            Self::MalformedTrailer { byte_offset } => {
+                write!(f, "malformed trailer at byte {byte_offset}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for TableFormatError {}
+
+impl From<TableFormatError> for io::Error {
+    fn from(err: TableFormatError) -> Self {
+        io::Error::new(InvalidData, err)
     }
 }
 
 #[derive(Debug)]
 pub struct EncoderDecoder<T> {
     inner: T,
+    lenient: bool,
 }
 
 impl<T> EncoderDecoder<T> {
     pub fn new(inner: T) -> Self {
-        Self { inner }
+        Self {
+            inner,
+            lenient: false,
+        }
+    }
+
+    /// Stop reading at the first truncated or non-contiguous block instead of failing, as readers did
+    /// before those were detected. Salvages the complete blocks of a table whose writing was interrupted
+    #[cfg_attr(not(feature = "unstable-internals"), allow(dead_code))]
+    #[must_use]
+    pub fn lenient(mut self) -> Self {
+        self.lenient = true;
+        self
     }
+
+    /// Blocks overlapping `range`, decompressed one by one as they are iterated over
+    #[must_use]
+    pub fn into_blocks(self, range: Range<u64>) -> DecompressedBlocks<T> {
+        DecompressedBlocks {
+            decoder: self,
+            end: range.end,
+            byte_offset: 0,
+            progress: DecompressProgress {
+                next_idx: range.start,
+                ..DecompressProgress::default()
+            },
+        }
+    }
+}
+
+#[inline]
+pub(crate) fn to_u64(x: usize) -> u64 {
+    x.try_into().unwrap()
 }
 
+/// Unlike `as usize`, panics instead of truncating indexes or sizes on 32-bit platforms
 #[inline]
-fn to_u64(x: usize) -> u64 {
+pub(crate) fn to_usize(x: u64) -> usize {
     x.try_into().unwrap()
 }
 
+#[cfg_attr(not(feature = "unstable-internals"), allow(dead_code))]
 impl<T: Write> EncoderDecoder<T> {
-    pub fn compress(&mut self, outcomes: &Reports) -> io::Result<()> {
+    pub fn compress(&mut self, outcomes: OutcomesSlice) -> io::Result<()> {
+        self.compress_with_options(outcomes, CompressionOptions::default())
+    }
+
+    pub fn compress_with_pre_filter(
+        &mut self,
+        outcomes: OutcomesSlice,
+        pre_filter: PreFilter,
+    ) -> io::Result<()> {
+        self.compress_with_options(
+            outcomes,
+            CompressionOptions {
+                pre_filter,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// The codec and pre-filter are recorded in each block, and automatically detected when reading
+    pub fn compress_with_options(
+        &mut self,
+        outcomes: OutcomesSlice,
+        options: CompressionOptions,
+    ) -> io::Result<()> {
         for (i, elements) in outcomes.chunks(BLOCK_ELEMENTS).enumerate() {
-            let block = Block::new(elements, BLOCK_ELEMENTS * i)?;
+            let block = Block::new_with_options(elements, BLOCK_ELEMENTS * i, options)?;
             self.inner.write_all(&block.to_bytes().unwrap())?;
         }
         Ok(())
     }
+
+    /// Append the spans of undefined entries after the blocks written, for readers to skip them
+    /// without scanning the table, see `EncoderDecoder::undefined_spans`. Older readers stop before it,
+    /// as at the end of the table.
+    /// Layout: `TRAILER_MAGIC`, then the number of entries indexed, the number of spans,
+    /// and the start (inclusive) and end (exclusive) of each span, all u64 little-endian
+    pub fn write_undefined_spans(&mut self, undefined_spans: &UndefinedSpans) -> io::Result<()> {
+        let mut trailer = Vec::with_capacity(24 + 16 * undefined_spans.spans().len());
+        trailer.extend_from_slice(&TRAILER_MAGIC);
+        trailer.extend_from_slice(&to_u64(undefined_spans.len()).to_le_bytes());
+        trailer.extend_from_slice(&to_u64(undefined_spans.spans().len()).to_le_bytes());
+        for span in undefined_spans.spans() {
+            trailer.extend_from_slice(&to_u64(span.start).to_le_bytes());
+            trailer.extend_from_slice(&to_u64(span.end).to_le_bytes());
+        }
+        self.inner.write_all(&trailer)
+    }
+
+    /// Append the number of entries of each outcome, after the blocks and the spans of undefined entries if any,
+    /// for readers to summarise the table without decompressing it, see `EncoderDecoder::outcome_distrib`.
+    /// Unlike the spans, readers predating it reject the table as malformed.
+    /// Layout: `DISTRIB_TRAILER_MAGIC`, then with white and black to move in turn, the number of outcomes
+    /// followed by each outcome, as its raw `OutcomeU8`, and its number of entries, all u64 little-endian
+    pub fn write_outcome_distrib(&mut self, distrib: &OutcomeDistrib) -> io::Result<()> {
+        let mut trailer =
+            Vec::with_capacity(24 + 16 * distrib.by_turn.iter().map(FxHashMap::len).sum::<usize>());
+        trailer.extend_from_slice(&DISTRIB_TRAILER_MAGIC);
+        for turn in Color::ALL {
+            let by_outcome = distrib.by_turn.get(turn);
+            trailer.extend_from_slice(&to_u64(by_outcome.len()).to_le_bytes());
+            // sorted, so that the same table is always written the same way
+            let mut raw_counts: Vec<(u64, u64)> = by_outcome
+                .iter()
+                .map(|(outcome, nb)| (u64::from(OutcomeU8::from(*outcome).as_raw_u8()), *nb))
+                .collect();
+            raw_counts.sort_unstable();
+            for (raw, nb) in raw_counts {
+                trailer.extend_from_slice(&raw.to_le_bytes());
+                trailer.extend_from_slice(&nb.to_le_bytes());
+            }
+        }
+        self.inner.write_all(&trailer)
+    }
+
+    /// Append the objective of the winner, after all the other trailers, so that it stays with the table
+    /// when copied, see `EncoderDecoder::win_objective`. Readers predating it reject the table as malformed.
+    /// Layout: `OBJECTIVE_TRAILER_MAGIC`, then 0 for `WinObjective::Mate` or 1 for `WinObjective::Draw`,
+    /// as a u64 little-endian
+    pub fn write_win_objective(&mut self, win_objective: WinObjective) -> io::Result<()> {
+        let raw: u64 = match win_objective {
+            WinObjective::Mate => 0,
+            WinObjective::Draw => 1,
+        };
+        self.inner.write_all(&OBJECTIVE_TRAILER_MAGIC)?;
+        self.inner.write_all(&raw.to_le_bytes())
+    }
 }
 
 impl<T: ReadAt> EncoderDecoder<T> {
+    /// `io::ErrorKind::UnexpectedEof` at the end of the table, which callers stop at
     fn read_block_header(&self, byte_offset: u64) -> io::Result<BlockHeader> {
         let mut header_buf: [u8; BlockHeader::BYTE_SIZE] = [0; BlockHeader::BYTE_SIZE];
-        self.inner.read_exact_at(byte_offset, &mut header_buf)?;
-        from_bytes_exact::<BlockHeader>(&header_buf)
+        match self.inner.read_exact_at(byte_offset, &mut header_buf) {
+            // the objective trailer alone is shorter than a block header
+            Err(err)
+                if err.kind() == io::ErrorKind::UnexpectedEof
+                    && self.has_trailer_at(byte_offset, OBJECTIVE_TRAILER_MAGIC)? =>
+            {
+                Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "trailer reached",
+                ))
+            }
+            Err(err)
+                if err.kind() == io::ErrorKind::UnexpectedEof
+                    && self.inner.read_at(byte_offset, &mut [0])? > 0 =>
+            {
+                Err(self.malformed(TableFormatError::TruncatedHeader { byte_offset }))
+            }
+            Err(err) => Err(err),
+            Ok(())
+                if [
+                    TRAILER_MAGIC,
+                    DISTRIB_TRAILER_MAGIC,
+                    OBJECTIVE_TRAILER_MAGIC,
+                ]
+                .iter()
+                .any(|magic| header_buf.starts_with(magic)) =>
+            {
+                Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "trailer reached",
+                ))
+            }
+            Ok(()) => from_bytes_exact::<BlockHeader>(&header_buf),
+        }
+    }
+
+    fn has_trailer_at(&self, byte_offset: u64, magic: [u8; 8]) -> io::Result<bool> {
+        let mut magic_buf = [0; 8];
+        match self.inner.read_exact_at(byte_offset, &mut magic_buf) {
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+            res => res.map(|()| magic_buf == magic),
+        }
+    }
+
+    /// Byte offset where the blocks end, and the trailers start if any. Only the block headers are read
+    fn blocks_end(&self) -> io::Result<u64> {
+        let mut byte_offset = 0;
+        loop {
+            match self.read_block_header(byte_offset) {
+                Ok(block_header) => {
+                    byte_offset += to_u64(block_header.size_including_headers());
+                }
+                Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                    return Ok(byte_offset)
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Spans of undefined entries stored in the trailer of the table, `None` if it has none.
+    /// Only the block headers and the trailer are read
+    #[cfg_attr(not(feature = "unstable-internals"), allow(dead_code))]
+    pub fn undefined_spans(&self) -> io::Result<Option<UndefinedSpans>> {
+        let byte_offset = self.blocks_end()?;
+        if !self.has_trailer_at(byte_offset, TRAILER_MAGIC)? {
+            return Ok(None);
+        }
+        self.read_trailer(byte_offset).map(|(spans, _)| Some(spans))
+    }
+
+    /// Number of entries of each outcome stored in the trailer of the table, `None` if it has none.
+    /// Only the block headers and the trailers are read
+    #[cfg_attr(not(feature = "unstable-internals"), allow(dead_code))]
+    pub fn outcome_distrib(&self) -> io::Result<Option<OutcomeDistrib>> {
+        let mut byte_offset = self.blocks_end()?;
+        if self.has_trailer_at(byte_offset, TRAILER_MAGIC)? {
+            byte_offset = self.read_trailer(byte_offset)?.1;
+        }
+        if !self.has_trailer_at(byte_offset, DISTRIB_TRAILER_MAGIC)? {
+            return Ok(None);
+        }
+        self.read_distrib_trailer(byte_offset)
+            .map(|(distrib, _)| Some(distrib))
+    }
+
+    /// Objective of the winner stored in the last trailer of the table, `None` if it has none,
+    /// eg for tables generated before it was recorded. Only the block headers and the trailers are read
+    #[cfg_attr(not(feature = "unstable-internals"), allow(dead_code))]
+    pub fn win_objective(&self) -> io::Result<Option<WinObjective>> {
+        let mut byte_offset = self.blocks_end()?;
+        if self.has_trailer_at(byte_offset, TRAILER_MAGIC)? {
+            byte_offset = self.read_trailer(byte_offset)?.1;
+        }
+        if self.has_trailer_at(byte_offset, DISTRIB_TRAILER_MAGIC)? {
+            byte_offset = self.read_distrib_trailer(byte_offset)?.1;
+        }
+        if !self.has_trailer_at(byte_offset, OBJECTIVE_TRAILER_MAGIC)? {
+            return Ok(None);
+        }
+        self.read_objective_trailer(byte_offset).map(Some)
+    }
+
+    // whether the bytes at `end` of a trailer are either the end of the table, or one of the trailers
+    // allowed to follow it
+    fn ends_trailer(&self, end: u64, next_magics: &[[u8; 8]]) -> io::Result<bool> {
+        if self.inner.read_at(end, &mut [0])? == 0 {
+            return Ok(true);
+        }
+        for magic in next_magics {
+            if self.has_trailer_at(end, *magic)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    // u64 of the trailer starting at `byte_offset`, which is malformed if cut before it
+    fn read_trailer_u64(&self, byte_offset: u64, offset: u64) -> io::Result<u64> {
+        let mut buf = [0; 8];
+        match self.inner.read_exact_at(offset, &mut buf) {
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                Err(TableFormatError::MalformedTrailer { byte_offset }.into())
+            }
+            res => res.map(|()| u64::from_le_bytes(buf)),
+        }
+    }
+
+    /// Along with the byte offset of its end
+    fn read_trailer(&self, byte_offset: u64) -> io::Result<(UndefinedSpans, u64)> {
+        let malformed = || io::Error::from(TableFormatError::MalformedTrailer { byte_offset });
+        let read_u64 = |offset: u64| self.read_trailer_u64(byte_offset, offset);
+        let len = read_u64(byte_offset + 8)?;
+        let nb_spans = read_u64(byte_offset + 16)?;
+        let spans_offset = byte_offset + 24;
+        let end = nb_spans
+            .checked_mul(16)
+            .and_then(|size| size.checked_add(spans_offset))
+            .ok_or_else(malformed)?;
+        // checked before allocating, as the number of spans of a corrupted trailer can be anything
+        if (end > spans_offset && read_u64(end - 8).is_err())
+            || !self.ends_trailer(end, &[DISTRIB_TRAILER_MAGIC, OBJECTIVE_TRAILER_MAGIC])?
+        {
+            return Err(malformed());
+        }
+        let mut spans_buf = vec![0; to_usize(end - spans_offset)];
+        self.inner.read_exact_at(spans_offset, &mut spans_buf)?;
+        let spans = spans_buf
+            .chunks_exact(16)
+            .map(|span| {
+                let bound =
+                    |bytes: &[u8]| to_usize(u64::from_le_bytes(bytes.try_into().expect("8 bytes")));
+                bound(&span[..8])..bound(&span[8..])
+            })
+            .collect();
+        UndefinedSpans::from_spans(spans, to_usize(len))
+            .map(|spans| (spans, end))
+            .ok_or_else(malformed)
+    }
+
+    /// Along with the byte offset of its end
+    fn read_distrib_trailer(&self, byte_offset: u64) -> io::Result<(OutcomeDistrib, u64)> {
+        let malformed = || io::Error::from(TableFormatError::MalformedTrailer { byte_offset });
+        let read_u64 = |offset: u64| self.read_trailer_u64(byte_offset, offset);
+        let mut offset = byte_offset + 8;
+        let mut distrib = OutcomeDistrib::default();
+        for turn in Color::ALL {
+            let nb_outcomes = read_u64(offset)?;
+            offset += 8;
+            // an outcome is stored at most once, so a corrupted number is caught before reading that many
+            if nb_outcomes > 128 {
+                return Err(malformed());
+            }
+            for _ in 0..nb_outcomes {
+                let outcome = u8::try_from(read_u64(offset)?)
+                    .ok()
+                    .and_then(OutcomeU8::from_raw_u8)
+                    .ok_or_else(malformed)?;
+                let nb = read_u64(offset + 8)?;
+                offset += 16;
+                if distrib
+                    .by_turn
+                    .get_mut(turn)
+                    .insert(outcome.into(), nb)
+                    .is_some()
+                {
+                    return Err(malformed());
+                }
+            }
+        }
+        let nb_indexes_black: u64 = distrib.by_turn.black.values().sum();
+        if !self.ends_trailer(offset, &[OBJECTIVE_TRAILER_MAGIC])?
+            || distrib.nb_indexes() != nb_indexes_black
+        {
+            return Err(malformed());
+        }
+        Ok((distrib, offset))
+    }
+
+    fn read_objective_trailer(&self, byte_offset: u64) -> io::Result<WinObjective> {
+        let win_objective = match self.read_trailer_u64(byte_offset, byte_offset + 8)? {
+            0 => WinObjective::Mate,
+            1 => WinObjective::Draw,
+            _ => return Err(TableFormatError::MalformedTrailer { byte_offset }.into()),
+        };
+        if !self.ends_trailer(byte_offset + 16, &[])? {
+            return Err(TableFormatError::MalformedTrailer { byte_offset }.into());
+        }
+        Ok(win_objective)
+    }
+
+    /// The trailers following the blocks at `byte_offset` must cover the `len` entries of the table,
+    /// and end it
+    fn check_trailers(&self, byte_offset: u64, len: u64) -> io::Result<()> {
+        let mut trailer_offset = byte_offset;
+        if self.has_trailer_at(byte_offset, TRAILER_MAGIC)? {
+            let (spans, end) = self.read_trailer(byte_offset)?;
+            if to_u64(spans.len()) != len {
+                return Err(TableFormatError::MalformedTrailer { byte_offset }.into());
+            }
+            trailer_offset = end;
+        }
+        if self.has_trailer_at(trailer_offset, DISTRIB_TRAILER_MAGIC)? {
+            let (distrib, end) = self.read_distrib_trailer(trailer_offset)?;
+            if distrib.nb_indexes() != len {
+                return Err(TableFormatError::MalformedTrailer {
+                    byte_offset: trailer_offset,
+                }
+                .into());
+            }
+            trailer_offset = end;
+        }
+        if self.has_trailer_at(trailer_offset, OBJECTIVE_TRAILER_MAGIC)? {
+            self.read_objective_trailer(trailer_offset)?;
+        } else if trailer_offset == byte_offset {
+            return Err(TableFormatError::TrailingGarbage { byte_offset }.into());
+        }
+        Ok(())
     }
 
     fn read_block(&self, byte_offset: u64) -> io::Result<Block> {
@@ -103,11 +824,57 @@ impl<T: ReadAt> EncoderDecoder<T> {
             "size_including_headers {:?}",
             block_header.size_including_headers()
         );
+        // checked before allocating, as the size in a corrupted header can be anything
+        self.check_block_is_complete(byte_offset, &block_header)?;
         let mut block_buf: Vec<u8> = vec![0; block_header.size_including_headers()];
         self.inner.read_exact_at(byte_offset, &mut block_buf)?;
         from_bytes_exact::<Block>(&block_buf)
     }
 
+    fn check_block_is_complete(
+        &self,
+        byte_offset: u64,
+        block_header: &BlockHeader,
+    ) -> io::Result<()> {
+        let last_byte = byte_offset + to_u64(block_header.size_including_headers()) - 1;
+        match self.inner.read_exact_at(last_byte, &mut [0]) {
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                Err(self.malformed(TableFormatError::TruncatedBlock { byte_offset }))
+            }
+            res => res,
+        }
+    }
+
+    /// Fails if the block at `byte_offset` does not start at `expected_start`
+    fn check_contiguous(
+        &self,
+        byte_offset: u64,
+        block_header: &BlockHeader,
+        expected_start: u64,
+    ) -> io::Result<()> {
+        if block_header.index_from == expected_start
+            && block_header.index_to > block_header.index_from
+        {
+            Ok(())
+        } else {
+            Err(self.malformed(TableFormatError::NonContiguousBlock {
+                byte_offset,
+                range: block_header.index_from..block_header.index_to,
+                expected_start,
+            }))
+        }
+    }
+
+    /// Where the table is cut or malformed, as the end of the table if `EncoderDecoder::lenient`
+    fn malformed(&self, err: TableFormatError) -> io::Error {
+        if self.lenient {
+            trace!("lenient reading, stopping at {err}");
+            io::Error::new(io::ErrorKind::UnexpectedEof, err)
+        } else {
+            err.into()
+        }
+    }
+
     pub fn outcome_of(&self, idx_with_turn: IndexWithTurn) -> io::Result<Outcome> {
         self.internal_outcome_of(None, idx_with_turn)
     }
@@ -115,15 +882,16 @@ impl<T: ReadAt> EncoderDecoder<T> {
     #[cfg(feature = "cached")]
     pub fn outcome_of_cached(
         &self,
-        mat_win: MaterialWinner,
+        mat_win: &MaterialWinner,
         idx_with_turn: IndexWithTurn,
     ) -> io::Result<Outcome> {
         self.internal_outcome_of(Some(mat_win), idx_with_turn)
     }
 
+    #[cfg_attr(not(feature = "cached"), allow(unused_variables))]
     pub fn internal_outcome_of(
         &self,
-        _mat_win: Option<MaterialWinner>,
+        mat_win: Option<&MaterialWinner>,
         idx_with_turn: IndexWithTurn,
     ) -> io::Result<Outcome> {
         let mut byte_offset = 0;
@@ -132,20 +900,10 @@ impl<T: ReadAt> EncoderDecoder<T> {
                 Ok(block_header) if block_header.idx_is_in_block(idx_with_turn.idx) => {
                     return self
                         .read_block(byte_offset)
-                        .and_then(|block| {
-                            #[cfg(feature = "cached")]
-                            let outcome = block.get_outcome_cached(
-                                _mat_win.expect(
-                                    "internal_outcome_of: mat_win necessary to create cache key",
-                                ),
-                                idx_with_turn.idx,
-                            );
-                            #[cfg(not(feature = "cached"))]
-                            let outcome = block.get_outcome(idx_with_turn.idx);
-                            outcome
-                        })
+                        // without material winner the block is not cached
+                        .and_then(|block| block.internal_get_outcome(mat_win, idx_with_turn.idx))
                         .map(|bc| *bc.get(idx_with_turn.turn))
-                        .map(Outcome::from)
+                        .map(Outcome::from);
                 }
                 Ok(block_header) => {
                     byte_offset += to_u64(block_header.size_including_headers());
@@ -161,15 +919,191 @@ impl<T: ReadAt> EncoderDecoder<T> {
         ))
     }
 
+    /// Outcomes of all indexes in `range`, one `OutcomesRange` by block overlapping it, in order.
+    /// Blocks are decompressed once, instead of once per index with `outcome_of`
+    pub fn outcomes_in_range(&self, range: Range<u64>) -> io::Result<Vec<OutcomesRange>> {
+        self.internal_outcomes_in_range(None, range)
+    }
+
+    #[cfg(feature = "cached")]
+    pub fn outcomes_in_range_cached(
+        &self,
+        mat_win: &MaterialWinner,
+        range: Range<u64>,
+    ) -> io::Result<Vec<OutcomesRange>> {
+        self.internal_outcomes_in_range(Some(mat_win), range)
+    }
+
+    fn internal_outcomes_in_range(
+        &self,
+        mat_win: Option<&MaterialWinner>,
+        range: Range<u64>,
+    ) -> io::Result<Vec<OutcomesRange>> {
+        let mut ranges = Vec::new();
+        let mut byte_offset = 0;
+        let mut next_idx = range.start;
+        while next_idx < range.end {
+            let block_header = match self.read_block_header(byte_offset) {
+                Ok(block_header) => block_header,
+                // we have reached the end of the table
+                Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        "index not found in table",
+                    ))
+                }
+                Err(err) => return Err(err),
+            };
+            if block_header.idx_is_in_block(next_idx) {
+                let end = range.end.min(block_header.index_to);
+                ranges.push(
+                    self.read_block(byte_offset)?
+                        .get_range(mat_win, next_idx..end)?,
+                );
+                next_idx = end;
+            }
+            byte_offset += to_u64(block_header.size_including_headers());
+        }
+        Ok(ranges)
+    }
+
+    /// Quick sanity check of the table structure, without decompressing it:
+    /// blocks must be contiguous, fully present, with a known codec and pre-filter,
+    /// and the last one must end at `expected_len`, with no byte left after it
+    #[cfg_attr(not(feature = "unstable-internals"), allow(dead_code))]
+    pub fn check_structure(&self, expected_len: usize) -> io::Result<()> {
+        let expected = to_u64(expected_len);
+        let len = self
+            .blocks_stats_up_to(Some(expected))?
+            .last()
+            .map_or(0, |stats| stats.range.end);
+        if len == expected {
+            Ok(())
+        } else {
+            Err(TableFormatError::WrongLength { len, expected }.into())
+        }
+    }
+
+    /// Read the layout of the table from its block headers, without decompressing it.
+    /// Fails if the blocks are not contiguous, not fully present, or with an unknown codec or pre-filter
+    pub fn table_format(&self) -> io::Result<TableFormat> {
+        let blocks_stats = self.block_stats()?;
+        Ok(TableFormat {
+            len: blocks_stats.last().map_or(0, |stats| stats.range.end),
+            blocks: blocks_stats
+                .into_iter()
+                .map(|stats| stats.options)
+                .collect(),
+        })
+    }
+
+    /// Index ranges of each block, in order, read from their headers only. Fails like `EncoderDecoder::table_format`
+    pub fn block_ranges(&self) -> io::Result<Vec<Range<u64>>> {
+        Ok(self
+            .block_stats()?
+            .into_iter()
+            .map(|stats| stats.range)
+            .collect())
+    }
+
+    /// Sizes of each block, in order, read from their headers only. Fails like `EncoderDecoder::table_format`
+    pub fn block_stats(&self) -> io::Result<Vec<BlockStats>> {
+        self.blocks_stats_up_to(None)
+    }
+
+    /// Same as `EncoderDecoder::block_stats`, failing with `TableFormatError::TrailingGarbage`
+    /// if bytes are left once the blocks reach `expected_len`
+    fn blocks_stats_up_to(&self, expected_len: Option<u64>) -> io::Result<Vec<BlockStats>> {
+        let mut byte_offset = 0;
+        let mut len = 0;
+        let mut blocks_stats = Vec::new();
+        loop {
+            if Some(len) == expected_len && self.inner.read_at(byte_offset, &mut [0])? > 0 {
+                self.check_trailers(byte_offset, len)?;
+                break;
+            }
+            match self
+                .read_block_header(byte_offset)
+                .and_then(|block_header| {
+                    self.check_contiguous(byte_offset, &block_header, len)?;
+                    self.check_block_is_complete(byte_offset, &block_header)?;
+                    Ok(block_header)
+                }) {
+                Ok(block_header) => {
+                    blocks_stats.push(BlockStats {
+                        range: block_header.index_from..block_header.index_to,
+                        options: CompressionOptions {
+                            pre_filter: block_header.pre_filter()?,
+                            codec: block_header.codec()?,
+                        },
+                        compressed_size: block_header.compressed_size(),
+                        uncompressed_size: 2 * to_u64(block_header.nb_elements()),
+                    });
+                    byte_offset += to_u64(block_header.size_including_headers());
+                    len = block_header.index_to;
+                }
+                // we have reached the end of the table
+                Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(blocks_stats)
+    }
+
     /// Decompress a helpmate file
     pub fn decompress_file(&self) -> io::Result<Outcomes> {
+        self.decompress_file_into(Outcomes::new())
+    }
+
+    /// Outcomes of the indexes in `range`, or up to the end of the table if it is shorter, calling `on_progress`
+    /// after each block. Restarting from `DecompressProgress::next_idx` resumes an interrupted decompression
+    pub fn decompress_file_with_progress(
+        &self,
+        range: Range<u64>,
+        mut on_progress: impl FnMut(DecompressProgress),
+    ) -> io::Result<Outcomes> {
         let mut outcomes = Outcomes::new();
+        let mut blocks = EncoderDecoder {
+            inner: &self.inner,
+            lenient: self.lenient,
+        }
+        .into_blocks(range);
+        while let Some(outcomes_range) = blocks.next() {
+            outcomes.extend_from_slice(&outcomes_range?);
+            on_progress(blocks.progress());
+        }
+        Ok(outcomes)
+    }
+
+    /// Decompress a helpmate file, only keeping Win/Draw/Lose information.
+    /// Blocks are packed one by one, so the whole table is never fully held in RAM as `Outcomes`
+    pub fn decompress_file_wdl(&self) -> io::Result<WdlOutcomes> {
+        self.decompress_file_into(WdlOutcomes::new())
+    }
+
+    /// Decompress a helpmate file, only storing the entries defined for at least one side to move.
+    /// Blocks are compacted one by one, as with `EncoderDecoder::decompress_file_wdl`
+    pub fn decompress_file_dense(&self) -> io::Result<DenseOutcomes> {
+        self.decompress_file_into(DenseOutcomes::new())
+    }
+
+    fn decompress_file_into<O: Extend<ByColor<OutcomeU8>>>(
+        &self,
+        mut outcomes: O,
+    ) -> io::Result<O> {
         let mut byte_offset = 0;
+        let mut len = 0;
         loop {
-            match self.read_block(byte_offset) {
+            match self
+                .read_block_header(byte_offset)
+                .and_then(|block_header| {
+                    self.check_contiguous(byte_offset, &block_header, len)?;
+                    self.read_block(byte_offset)
+                }) {
                 Ok(block) => {
                     byte_offset += to_u64(block.header.size_including_headers());
-                    outcomes.extend(block.decompress_outcomes()?);
+                    len = block.header.index_to;
+                    block.decompress_outcomes_into(&mut outcomes)?;
                 }
                 // we have reached the end of the table
                 Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
@@ -184,140 +1118,318 @@ impl<T: ReadAt> EncoderDecoder<T> {
 struct BlockHeader {
     pub index_from: u64, // inclusive
     pub index_to: u64,   // exclusive
-    pub block_size: u64, // number of bytes the actual size of the block (excluding the headers). Should be close to `BLOCK_SIZE` / 10, except for the last block
+    // number of bytes the actual size of the block (excluding the headers). Should be close to `BLOCK_SIZE` / 10, except for the last block
+    // Its most significant byte is used to store the `PreFilter` (low nibble) and the `Codec` (high nibble),
+    // always 0 (`PreFilter::None` and `Codec::Zstd`) for older tables
+    pub block_size: u64,
 }
 
 impl BlockHeader {
     const BYTE_SIZE: usize = BitSize::of::<BlockHeader>().0 / 8;
+    const PRE_FILTER_SHIFT: u32 = 56;
+    const CODEC_SHIFT: u32 = 60;
+
+    fn new(
+        index_from: u64,
+        index_to: u64,
+        compressed_size: u64,
+        options: CompressionOptions,
+    ) -> Self {
+        assert!(compressed_size < 1 << Self::PRE_FILTER_SHIFT);
+        Self {
+            index_from,
+            index_to,
+            block_size: compressed_size
+                | (options.pre_filter as u64) << Self::PRE_FILTER_SHIFT
+                | (options.codec as u64) << Self::CODEC_SHIFT,
+        }
+    }
+
+    pub const fn compressed_size(&self) -> u64 {
+        self.block_size & ((1 << Self::PRE_FILTER_SHIFT) - 1)
+    }
+
+    pub fn pre_filter(&self) -> io::Result<PreFilter> {
+        PreFilter::from_u8((self.block_size >> Self::PRE_FILTER_SHIFT) as u8 & 0xf)
+    }
+
+    pub fn codec(&self) -> io::Result<Codec> {
+        Codec::from_u8((self.block_size >> Self::CODEC_SHIFT) as u8)
+    }
 
     pub fn size_including_headers(&self) -> usize {
-        Self::BYTE_SIZE + self.block_size as usize
+        Self::BYTE_SIZE + to_usize(self.compressed_size())
     }
 
     pub fn idx_is_in_block(&self, idx: u64) -> bool {
         self.index_from <= idx && idx < self.index_to
     }
 
-    pub const fn nb_elements(&self) -> usize {
-        (self.index_to - self.index_from) as usize
+    pub fn nb_elements(&self) -> usize {
+        to_usize(self.index_to - self.index_from)
     }
 }
 
 #[derive(Debug, PartialEq, DekuRead, DekuWrite, Eq, Hash)]
 struct Block {
     header: BlockHeader,
-    #[deku(count = "header.block_size")]
+    #[deku(count = "header.compressed_size()")]
     pub compressed_outcomes: Vec<u8>, // compressed bytes of `Outcomes`
 }
 
 impl Block {
-    pub fn new(outcomes: ReportsSlice, index_from_usize: usize) -> io::Result<Self> {
+    #[cfg(test)]
+    pub fn new(outcomes: OutcomesSlice, index_from_usize: usize) -> io::Result<Self> {
+        Self::new_with_options(outcomes, index_from_usize, CompressionOptions::default())
+    }
+
+    pub fn new_with_options(
+        outcomes: OutcomesSlice,
+        index_from_usize: usize,
+        options: CompressionOptions,
+    ) -> io::Result<Self> {
         let index_from = to_u64(index_from_usize);
         let index_to = to_u64(index_from_usize + outcomes.len());
 
         trace!("turning outcomes into bytes");
-        let raw_outcomes_bytes: Vec<u8> = outcomes
+        let mut raw_outcomes_bytes: Vec<u8> = outcomes
             .iter()
             .map(RawOutcome::from)
             .flat_map(|raw_outcome| raw_outcome.to_bytes().unwrap())
             .collect();
+        options.pre_filter.apply(&mut raw_outcomes_bytes);
 
         trace!("Compressing block");
-        encode_all(raw_outcomes_bytes.as_slice(), 21).map(|compressed_outcomes| {
-            let block_size = to_u64(compressed_outcomes.len());
-            Self {
-                header: BlockHeader {
-                    index_from,
-                    index_to,
-                    block_size,
-                },
-                compressed_outcomes,
-            }
-        })
-    }
-
-    #[cfg(not(feature = "cached"))]
-    fn get_outcome(&self, idx: u64) -> io::Result<ByColor<OutcomeU8>> {
-        self.internal_get_outcome(None, idx)
+        options
+            .codec
+            .compress(&raw_outcomes_bytes)
+            .map(|compressed_outcomes| {
+                let compressed_size = to_u64(compressed_outcomes.len());
+                Self {
+                    header: BlockHeader::new(index_from, index_to, compressed_size, options),
+                    compressed_outcomes,
+                }
+            })
     }
 
-    #[cfg(feature = "cached")]
-    pub fn get_outcome_cached(
+    fn internal_get_outcome(
         &self,
-        mat_win: MaterialWinner,
+        mat_win: Option<&MaterialWinner>,
         idx: u64,
     ) -> io::Result<ByColor<OutcomeU8>> {
-        self.internal_get_outcome(Some(mat_win), idx)
+        debug_assert!(self.header.idx_is_in_block(idx));
+        self.get_range(mat_win, idx..idx + 1)
+            .map(|outcomes_range| outcomes_range[0])
     }
 
-    fn internal_get_outcome(
+    /// Outcomes of the indexes in `range`, which must be included in the block
+    #[cfg_attr(not(feature = "cached"), allow(unused_variables))]
+    fn get_range(
         &self,
-        _mat_win: Option<MaterialWinner>,
-        idx: u64,
-    ) -> io::Result<ByColor<OutcomeU8>> {
-        debug_assert!(self.header.idx_is_in_block(idx));
-        let block_idx = (idx.checked_sub(self.header.index_from))
-            .ok_or_else(|| {
-                io::Error::new(
-                    io::ErrorKind::InvalidInput,
-                    "Min index of the block superior to index input",
-                )
-            })
-            .map(|idx_u64| idx_u64 as usize)?;
+        mat_win: Option<&MaterialWinner>,
+        range: Range<u64>,
+    ) -> io::Result<OutcomesRange> {
+        let start = to_usize(
+            range
+                .start
+                .checked_sub(self.header.index_from)
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "Min index of the block superior to index input",
+                    )
+                })?,
+        );
+        let end = start + to_usize(range.end.saturating_sub(range.start));
+        if end > self.header.nb_elements() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Index not found in the block",
+            ));
+        }
 
+        // without material winner there is no cache key
         #[cfg(feature = "cached")]
-        let decompressed_outcomes = decompress_outcomes_cached(
-            _mat_win.expect("not material winner to set cache key"),
-            self,
-        );
+        let decompressed_outcomes = match mat_win {
+            Some(mat_win) => decompress_outcomes_cached(mat_win, self),
+            None => self.decompress_outcomes().map(Arc::new),
+        };
         #[cfg(not(feature = "cached"))]
-        let decompressed_outcomes = self.decompress_outcomes();
-        decompressed_outcomes.and_then(|outcomes| {
-            outcomes
-                .get(block_idx)
-                .ok_or_else(|| {
-                    io::Error::new(io::ErrorKind::InvalidInput, "Index not found in the block")
-                })
-                .copied()
+        let decompressed_outcomes = self.decompress_outcomes().map(Arc::new);
+        decompressed_outcomes.map(|outcomes| OutcomesRange {
+            outcomes,
+            range: start..end,
+            index_from: range.start,
         })
     }
 
     pub fn decompress_outcomes(&self) -> io::Result<Outcomes> {
-        trace!("decompressing outcomes");
-        decode_all(self.compressed_outcomes.as_slice()).and_then(|decompressed_outcomes_bytes| {
-            trace!("finished decompressing outcomes");
-            Vec::<RawOutcome>::read(
-                decompressed_outcomes_bytes.view_bits(),
-                Limit::new_count(self.header.nb_elements()),
-            )
-            .map_err(|e| io::Error::new(InvalidData, e))
-            .map(|(inner_rest, raw_outcomes)| {
-                assert!(inner_rest.is_empty());
-                raw_outcomes
-                    .into_iter()
-                    .map(<ByColor<OutcomeU8>>::from)
-                    .collect()
-            })
+        let mut outcomes = Outcomes::with_capacity(self.header.nb_elements());
+        self.decompress_outcomes_into(&mut outcomes)?;
+        Ok(outcomes)
+    }
+
+    /// Append the outcomes of the block to `outcomes`, without allocating anything else
+    /// than the thread decompression buffer, if it is too small
+    fn decompress_outcomes_into<O: Extend<ByColor<OutcomeU8>>>(
+        &self,
+        outcomes: &mut O,
+    ) -> io::Result<()> {
+        self.with_decompressed_bytes(|bytes| {
+            outcomes.extend(bytes.chunks_exact(2).map(|raw| {
+                ByColor::from(RawOutcome {
+                    black: raw[0],
+                    white: raw[1],
+                })
+            }));
         })
     }
+
+    /// Call `f` on the decompressed bytes of the block, with the pre-filter reverted,
+    /// laid out as `RawOutcome`. They are written in a buffer reused by the thread
+    fn with_decompressed_bytes<R>(&self, f: impl FnOnce(&[u8]) -> R) -> io::Result<R> {
+        trace!("decompressing outcomes");
+        let pre_filter = self.header.pre_filter()?;
+        let codec = self.header.codec()?;
+        // taken rather than borrowed, so that a nested decompression uses its own buffer
+        let mut buffer = DECOMPRESSION_BUFFER.take();
+        let res = codec
+            .decompress_into(&self.compressed_outcomes, &mut buffer)
+            .and_then(|()| pre_filter.revert(&mut buffer, self.header.nb_elements()))
+            .and_then(|()| {
+                trace!("finished decompressing outcomes");
+                if buffer.len() != 2 * self.header.nb_elements() {
+                    return Err(TableFormatError::BlockSizeMismatch {
+                        expected: 2 * self.header.nb_elements(),
+                        found: buffer.len(),
+                    }
+                    .into());
+                }
+                Ok(f(&buffer))
+            });
+        if buffer.capacity() <= MAX_KEPT_BUFFER_SIZE {
+            DECOMPRESSION_BUFFER.set(buffer);
+        }
+        res
+    }
+}
+
+/// The lock is not held while decompressing, so a block missing from the cache can be decompressed
+/// by several threads at once, the last one keeping it
+#[cfg(feature = "cached")]
+fn decompress_outcomes_cached(
+    mat_win: &MaterialWinner,
+    block: &Block,
+) -> io::Result<Arc<Outcomes>> {
+    let key = (mat_win.clone(), block.header);
+    {
+        let mut cache = BLOCK_CACHE.lock().expect("block cache poisoned");
+        if cache.is_bypassed() {
+            drop(cache);
+            return block.decompress_outcomes().map(Arc::new);
+        }
+        if let Some(outcomes) = cache.get(&key) {
+            return Ok(outcomes);
+        }
+    }
+    let outcomes = Arc::new(block.decompress_outcomes()?);
+    // blocks are only split at `BLOCK_ELEMENTS`, so a table starting and ending in the same block has no other
+    let whole_table = block.header.index_from == 0 && block.header.nb_elements() < BLOCK_ELEMENTS;
+    BLOCK_CACHE.lock().expect("block cache poisoned").insert(
+        key,
+        Arc::clone(&outcomes),
+        whole_table,
+    );
+    Ok(outcomes)
+}
+
+/// Replace the cache of decompressed blocks by an empty one with the budgets of `config`, eg to pin
+/// more small tables. The cache is shared by all the tables and probers of the process, and its statistics
+/// are reset as well
+#[cfg(feature = "cached")]
+pub fn configure_block_cache(config: BlockCacheConfig) {
+    *BLOCK_CACHE.lock().expect("block cache poisoned") = BlockCache::new(config);
+}
+
+/// Shrink the cache of decompressed blocks, shared by the whole process, to fit in `limits.max_ram_bytes`,
+/// see `BlockCacheConfig::within`. The tables themselves are read lazily when probing.
+/// The budgets set by `configure_block_cache` are only ever lowered. The cache is emptied if they are,
+/// and left untouched if they already fit, or if `limits` sets no RAM limit
+#[cfg_attr(not(feature = "cached"), allow(unused_variables))]
+pub fn limit_block_cache(limits: &ResourceLimits) {
+    #[cfg(feature = "cached")]
+    if let Some(max_ram_bytes) = limits.max_ram_bytes {
+        let mut cache = BLOCK_CACHE.lock().expect("block cache poisoned");
+        let config = cache.config().within(max_ram_bytes);
+        if config != cache.config() {
+            *cache = BlockCache::new(config);
+        }
+    }
 }
 
+/// Hits, misses and usage of each tier of the cache of decompressed blocks, shared by the whole process,
+/// since it was last configured
 #[cfg(feature = "cached")]
-#[cached(result = true,
-    // A block header is unique to a block given a material configuration and a winner
-    type = "cached::SizedCache<(MaterialWinner, BlockHeader), Outcomes>",
-    create = "{ cached::SizedCache::with_size(CACHE_ELEMENTS) }",
-    convert = "{ (_mat_win.clone(), block.header) }")]
-fn decompress_outcomes_cached(_mat_win: MaterialWinner, block: &Block) -> io::Result<Outcomes> {
-    block.decompress_outcomes()
+#[must_use]
+pub fn block_cache_stats() -> BlockCacheStats {
+    BLOCK_CACHE.lock().expect("block cache poisoned").stats()
+}
+
+/// Contiguous indexes of a decompressed block, dereferencing to their outcomes.
+/// Shares the decompressed block with the cache, so it is cheap to create
+#[derive(Debug, Clone)]
+pub struct OutcomesRange {
+    outcomes: Arc<Outcomes>,
+    range: Range<usize>,
+    index_from: u64,
+}
+
+#[cfg_attr(not(feature = "unstable-internals"), allow(dead_code))]
+impl OutcomesRange {
+    /// Index of the first outcome of the range
+    #[must_use]
+    pub fn index_from(&self) -> u64 {
+        self.index_from
+    }
+}
+
+impl Deref for OutcomesRange {
+    type Target = [ByColor<OutcomeU8>];
+
+    fn deref(&self) -> &Self::Target {
+        &self.outcomes[self.range.clone()]
+    }
+}
+
+/// Bytes of a table of `outcomes` split into blocks of `block_len` indexes, rather than the ones
+/// `EncoderDecoder::compress` would write, eg like a table written with another block size
+#[cfg(test)]
+pub(crate) fn table_bytes_with_block_len(outcomes: OutcomesSlice, block_len: usize) -> Vec<u8> {
+    outcomes
+        .chunks(block_len)
+        .enumerate()
+        .flat_map(|(i, chunk)| {
+            Block::new(chunk, i * block_len)
+                .unwrap()
+                .to_bytes()
+                .unwrap()
+        })
+        .collect()
 }
 
 fn from_bytes_exact<'a, T: deku::DekuContainerRead<'a>>(buf: &'a [u8]) -> io::Result<T> {
     let ((byte_not_read, bit_offset), t) =
         T::from_bytes((buf, 0)).map_err(|e| io::Error::new(InvalidData, e))?;
-    assert!(byte_not_read.is_empty()); // since we read the exact number of byte needed to build the struct, there should be no byte left.
-    assert_eq!(bit_offset, 0); // there should never be **bit** offset neither when reader the header or after it.
-    Ok(t)
+    // since we read the exact number of byte needed to build the struct, there should be no byte left,
+    // nor **bit** offset, neither when reading the header or after it
+    if byte_not_read.is_empty() && bit_offset == 0 {
+        Ok(t)
+    } else {
+        Err(io::Error::new(
+            InvalidData,
+            "block not fully read from its bytes",
+        ))
+    }
 }
 
 #[cfg(test)]
@@ -327,34 +1439,22 @@ mod tests {
 
     const DUMMY_NUMBER: usize = 10000;
 
-    fn gen_reports(nb: usize) -> Reports {
-        let mut outcomes = Reports::with_capacity(nb);
+    fn gen_outcomes(nb: usize) -> Outcomes {
+        let mut outcomes = Outcomes::with_capacity(nb);
         let mut j: u8 = 0;
-        let mut x: u16 = 0;
-        for _i in 0..nb {
+        for _ in 0..nb {
             j = j.checked_add(1).unwrap_or(0);
-            x = x.checked_add(1).unwrap_or(0);
-            if x == 0 {
-                // println!("{i}");
-            }
-            let report_u8 = ReportU8::from_raw_u8(j);
+            let outcome_u8 = OutcomeU8::from_raw_u8(j & 127).unwrap();
             outcomes.push(ByColor {
-                black: report_u8,
-                white: report_u8,
-            })
+                black: outcome_u8,
+                white: outcome_u8,
+            });
         }
         outcomes
     }
 
-    fn dummy_reports() -> Reports {
-        gen_reports(DUMMY_NUMBER)
-    }
-
-    fn into_outcomes(reports: Reports) -> Outcomes {
-        reports
-            .into_iter()
-            .map(|bc| bc.map(|x| OutcomeU8::from(Report::from(x).outcome())))
-            .collect()
+    fn dummy_outcomes() -> Outcomes {
+        gen_outcomes(DUMMY_NUMBER)
     }
 
     #[test]
@@ -368,13 +1468,13 @@ mod tests {
         assert_eq!(
             BitSize::of::<BlockHeader>(),
             BitSize(BlockHeader::BYTE_SIZE * 8),
-        )
+        );
     }
 
     #[cfg(not(miri))]
     #[test]
     fn test_block_byte_serialisation() {
-        let block = Block::new(&dummy_reports(), 0).unwrap();
+        let block = Block::new(&dummy_outcomes(), 0).unwrap();
         assert_eq!(
             block.to_bytes().unwrap().len(),
             block.header.size_including_headers()
@@ -387,71 +1487,473 @@ mod tests {
     #[cfg(not(miri))]
     #[test]
     fn test_outcome_decompression() {
-        let reports = dummy_reports();
-        let block = Block::new(&reports, 0).unwrap();
-        assert_eq!(block.decompress_outcomes().unwrap(), into_outcomes(reports));
+        let outcomes = dummy_outcomes();
+        let block = Block::new(&outcomes, 0).unwrap();
+        assert_eq!(block.decompress_outcomes().unwrap(), outcomes);
     }
 
     #[cfg(not(miri))]
-    #[cfg(not(feature = "cached"))]
     #[test]
     fn test_outcome_partial_decompression() {
-        let reports = gen_reports(200);
+        let outcomes = gen_outcomes(200);
         let offset = 100;
-        let block = Block::new(&reports, offset).unwrap();
-        for (i, report) in reports.into_iter().enumerate() {
+        let block = Block::new(&outcomes, offset).unwrap();
+        for (i, outcome) in outcomes.into_iter().enumerate() {
             assert_eq!(
-                block.get_outcome((i + offset) as u64).unwrap(),
-                report.map(|x| OutcomeU8::from(Report::from(x).outcome()))
+                block
+                    .internal_get_outcome(None, (i + offset) as u64)
+                    .unwrap(),
+                outcome
             );
         }
     }
 
+    #[cfg(not(miri))]
+    #[test]
+    fn test_decompressed_blocks() {
+        let outcomes = gen_outcomes(250);
+        let file: Vec<u8> = [0..100, 100..250]
+            .into_iter()
+            .flat_map(|range| {
+                Block::new(&outcomes[range.clone()], range.start)
+                    .unwrap()
+                    .to_bytes()
+                    .unwrap()
+            })
+            .collect();
+        let mut blocks = EncoderDecoder::new(file.as_slice()).into_blocks(50..200);
+        let first = blocks.next().unwrap().unwrap();
+        assert_eq!(first.index_from(), 50);
+        assert_eq!(&*first, &outcomes[50..100]);
+        let progress = blocks.progress();
+        assert_eq!(progress.blocks_done, 1);
+        assert_eq!(progress.next_idx, 100);
+        // restarting where the first block ended
+        let restarted: Vec<_> = EncoderDecoder::new(file.as_slice())
+            .into_blocks(progress.next_idx..200)
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(restarted.len(), 1);
+        assert_eq!(&*restarted[0], &outcomes[100..200]);
+        assert_eq!(&*blocks.next().unwrap().unwrap(), &outcomes[100..200]);
+        assert!(blocks.next().is_none());
+
+        let mut reported = Vec::new();
+        let decompressed = EncoderDecoder::new(file.as_slice())
+            .decompress_file_with_progress(0..u64::MAX, |progress| reported.push(progress))
+            .unwrap();
+        assert_eq!(decompressed, outcomes);
+        assert_eq!(reported.len(), 2);
+        assert_eq!(reported[1].bytes_read, file.len() as u64);
+        assert_eq!(reported[1].next_idx, 250);
+    }
+
+    #[cfg(not(miri))]
+    #[test]
+    fn test_outcome_decompression_pre_filters() {
+        let outcomes = dummy_outcomes();
+        for pre_filter in [PreFilter::None, PreFilter::Delta, PreFilter::Dense] {
+            let options = CompressionOptions {
+                pre_filter,
+                ..Default::default()
+            };
+            let block = Block::new_with_options(&outcomes, 0, options).unwrap();
+            assert_eq!(block.header.pre_filter().unwrap(), pre_filter);
+            assert_eq!(
+                block.header.size_including_headers(),
+                block.to_bytes().unwrap().len()
+            );
+            let block_2 = from_bytes_exact::<Block>(&block.to_bytes().unwrap()).unwrap();
+            assert_eq!(block_2.decompress_outcomes().unwrap(), outcomes);
+        }
+    }
+
+    #[cfg(not(miri))]
+    #[test]
+    fn test_outcome_decompression_codecs() {
+        let outcomes = dummy_outcomes();
+        for codec in [Codec::Zstd, Codec::Lz4] {
+            for pre_filter in [PreFilter::None, PreFilter::Delta, PreFilter::Dense] {
+                let options = CompressionOptions { pre_filter, codec };
+                let mut encoder = EncoderDecoder::new(Vec::<u8>::new());
+                encoder.compress_with_options(&outcomes, options).unwrap();
+                let block = from_bytes_exact::<Block>(&encoder.inner).unwrap();
+                assert_eq!(block.header.codec().unwrap(), codec);
+                assert_eq!(block.header.pre_filter().unwrap(), pre_filter);
+                assert_eq!(
+                    EncoderDecoder::new(encoder.inner)
+                        .decompress_file()
+                        .unwrap(),
+                    outcomes
+                );
+            }
+        }
+    }
+
+    #[cfg(not(miri))]
+    #[test]
+    fn test_decompression_buffer_reuse() {
+        let (big, small) = (gen_outcomes(2000), gen_outcomes(50));
+        let big_block = Block::new(&big, 0).unwrap();
+        let small_block = Block::new_with_options(
+            &small,
+            0,
+            CompressionOptions {
+                codec: Codec::Lz4,
+                pre_filter: PreFilter::Delta,
+            },
+        )
+        .unwrap();
+        for (block, outcomes) in [
+            (&big_block, &big),
+            (&small_block, &small),
+            (&big_block, &big),
+        ] {
+            assert_eq!(block.decompress_outcomes().unwrap(), *outcomes);
+        }
+        let buffer = DECOMPRESSION_BUFFER.take();
+        assert!(buffer.capacity() >= 2 * big.len());
+        DECOMPRESSION_BUFFER.set(buffer);
+    }
+
+    #[cfg(not(miri))]
+    #[test]
+    fn test_check_structure() {
+        let outcomes = gen_outcomes(100);
+        let format_error = |bytes: &[u8], expected_len| {
+            TableFormatError::of(
+                &EncoderDecoder::new(bytes.to_vec())
+                    .check_structure(expected_len)
+                    .unwrap_err(),
+            )
+            .cloned()
+        };
+        // two blocks, as `compress` would write for a table bigger than `BLOCK_ELEMENTS`
+        let mut bytes = Block::new(&outcomes, 0).unwrap().to_bytes().unwrap();
+        let first_block_len = to_u64(bytes.len());
+        bytes.extend(Block::new(&outcomes, 100).unwrap().to_bytes().unwrap());
+        assert!(EncoderDecoder::new(bytes.clone())
+            .check_structure(200)
+            .is_ok());
+        assert_eq!(
+            format_error(&bytes, 201),
+            Some(TableFormatError::WrongLength {
+                len: 200,
+                expected: 201
+            })
+        );
+        for truncated_len in [bytes.len() - 1, bytes.len() - 30] {
+            assert_eq!(
+                format_error(&bytes[..truncated_len], 200),
+                Some(TableFormatError::TruncatedBlock {
+                    byte_offset: first_block_len
+                })
+            );
+        }
+        assert_eq!(
+            format_error(&bytes[..10], 200),
+            Some(TableFormatError::TruncatedHeader { byte_offset: 0 })
+        );
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(
+            format_error(&trailing, 200),
+            Some(TableFormatError::TrailingGarbage {
+                byte_offset: to_u64(bytes.len())
+            })
+        );
+        let mut gap = Block::new(&outcomes, 0).unwrap().to_bytes().unwrap();
+        gap.extend(Block::new(&outcomes, 101).unwrap().to_bytes().unwrap());
+        assert_eq!(
+            format_error(&gap, 201),
+            Some(TableFormatError::NonContiguousBlock {
+                byte_offset: first_block_len,
+                range: 101..201,
+                expected_start: 100
+            })
+        );
+        assert!(EncoderDecoder::new(gap.clone()).decompress_file().is_err());
+        // the first block is kept when reading leniently
+        assert_eq!(
+            EncoderDecoder::new(gap)
+                .lenient()
+                .decompress_file()
+                .unwrap(),
+            outcomes
+        );
+    }
+
+    #[test]
+    fn test_undefined_spans_trailer() {
+        fn format_error<T: fmt::Debug>(res: io::Result<T>) -> Option<TableFormatError> {
+            TableFormatError::of(&res.unwrap_err()).cloned()
+        }
+        let mut outcomes = gen_outcomes(300);
+        outcomes[100..200].fill(crate::UNDEFINED_OUTCOME_BYCOLOR);
+        let undefined_spans = UndefinedSpans::from_outcomes(&outcomes);
+        let mut encoder = EncoderDecoder::new(Vec::new());
+        encoder.compress(&outcomes).unwrap();
+        let table_len = to_u64(encoder.inner.len());
+        assert_eq!(encoder.undefined_spans().unwrap(), None);
+        encoder.write_undefined_spans(&undefined_spans).unwrap();
+        let bytes = encoder.inner;
+        let decoder = EncoderDecoder::new(bytes.clone());
+        assert_eq!(decoder.undefined_spans().unwrap(), Some(undefined_spans));
+        // readers stop at the trailer, as at the end of the table
+        assert_eq!(decoder.decompress_file().unwrap(), outcomes);
+        assert_eq!(decoder.block_stats().unwrap().len(), 1);
+        assert!(decoder.check_structure(300).is_ok());
+        let malformed = Some(TableFormatError::MalformedTrailer {
+            byte_offset: table_len,
+        });
+        for cut in [1, 16] {
+            let truncated = EncoderDecoder::new(bytes[..bytes.len() - cut].to_vec());
+            assert_eq!(format_error(truncated.undefined_spans()), malformed);
+            assert_eq!(format_error(truncated.check_structure(300)), malformed);
+        }
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(
+            format_error(EncoderDecoder::new(trailing).undefined_spans()),
+            malformed
+        );
+        // spans of another table
+        let mut other_len = bytes[..to_usize(table_len)].to_vec();
+        EncoderDecoder::new(&mut other_len)
+            .write_undefined_spans(&UndefinedSpans::from_outcomes(&outcomes[..250]))
+            .unwrap();
+        assert_eq!(
+            format_error(EncoderDecoder::new(other_len).check_structure(300)),
+            malformed
+        );
+    }
+
+    #[test]
+    fn test_outcome_distrib_trailer() {
+        fn format_error<T: fmt::Debug>(res: io::Result<T>) -> Option<TableFormatError> {
+            TableFormatError::of(&res.unwrap_err()).cloned()
+        }
+        let mut outcomes = gen_outcomes(300);
+        outcomes[100..200].fill(crate::UNDEFINED_OUTCOME_BYCOLOR);
+        let distrib = OutcomeDistrib::from_outcomes(&outcomes);
+        assert_eq!(distrib.nb_indexes(), 300);
+        for with_spans in [false, true] {
+            let mut encoder = EncoderDecoder::new(Vec::new());
+            encoder.compress(&outcomes).unwrap();
+            if with_spans {
+                encoder
+                    .write_undefined_spans(&UndefinedSpans::from_outcomes(&outcomes))
+                    .unwrap();
+            }
+            let distrib_offset = to_u64(encoder.inner.len());
+            assert_eq!(encoder.outcome_distrib().unwrap(), None);
+            encoder.write_outcome_distrib(&distrib).unwrap();
+            let bytes = encoder.inner;
+            let decoder = EncoderDecoder::new(bytes.clone());
+            assert_eq!(decoder.outcome_distrib().unwrap(), Some(distrib.clone()));
+            assert_eq!(decoder.undefined_spans().unwrap().is_some(), with_spans);
+            assert_eq!(decoder.decompress_file().unwrap(), outcomes);
+            assert!(decoder.check_structure(300).is_ok());
+            let malformed = Some(TableFormatError::MalformedTrailer {
+                byte_offset: distrib_offset,
+            });
+            for cut in [1, 16] {
+                let truncated = EncoderDecoder::new(bytes[..bytes.len() - cut].to_vec());
+                assert_eq!(format_error(truncated.outcome_distrib()), malformed);
+                assert_eq!(format_error(truncated.check_structure(300)), malformed);
+            }
+            let mut trailing = bytes.clone();
+            trailing.push(0);
+            assert_eq!(
+                format_error(EncoderDecoder::new(trailing).outcome_distrib()),
+                malformed
+            );
+            // counts of another table
+            let mut other_len = bytes[..to_usize(distrib_offset)].to_vec();
+            EncoderDecoder::new(&mut other_len)
+                .write_outcome_distrib(&OutcomeDistrib::from_outcomes(&outcomes[..250]))
+                .unwrap();
+            assert_eq!(
+                format_error(EncoderDecoder::new(other_len).check_structure(300)),
+                malformed
+            );
+        }
+    }
+
+    #[test]
+    fn test_win_objective_trailer() {
+        fn format_error<T: fmt::Debug>(res: io::Result<T>) -> Option<TableFormatError> {
+            TableFormatError::of(&res.unwrap_err()).cloned()
+        }
+        let outcomes = gen_outcomes(300);
+        for (with_trailers, win_objective) in
+            [(false, WinObjective::Mate), (true, WinObjective::Draw)]
+        {
+            let mut encoder = EncoderDecoder::new(Vec::new());
+            encoder.compress(&outcomes).unwrap();
+            if with_trailers {
+                encoder
+                    .write_undefined_spans(&UndefinedSpans::from_outcomes(&outcomes))
+                    .unwrap();
+                encoder
+                    .write_outcome_distrib(&OutcomeDistrib::from_outcomes(&outcomes))
+                    .unwrap();
+            }
+            let objective_offset = to_u64(encoder.inner.len());
+            assert_eq!(encoder.win_objective().unwrap(), None);
+            encoder.write_win_objective(win_objective).unwrap();
+            let bytes = encoder.inner;
+            let decoder = EncoderDecoder::new(bytes.clone());
+            assert_eq!(decoder.win_objective().unwrap(), Some(win_objective));
+            assert_eq!(decoder.undefined_spans().unwrap().is_some(), with_trailers);
+            assert_eq!(decoder.outcome_distrib().unwrap().is_some(), with_trailers);
+            assert_eq!(decoder.decompress_file().unwrap(), outcomes);
+            assert!(decoder.check_structure(300).is_ok());
+            let malformed = Some(TableFormatError::MalformedTrailer {
+                byte_offset: objective_offset,
+            });
+            let truncated = EncoderDecoder::new(bytes[..bytes.len() - 1].to_vec());
+            assert_eq!(format_error(truncated.win_objective()), malformed);
+            assert_eq!(format_error(truncated.check_structure(300)), malformed);
+            let mut trailing = bytes.clone();
+            trailing.push(0);
+            assert_eq!(
+                format_error(EncoderDecoder::new(trailing).check_structure(300)),
+                malformed
+            );
+            let mut unknown = bytes.clone();
+            unknown[to_usize(objective_offset) + 8] = 2;
+            assert_eq!(
+                format_error(EncoderDecoder::new(unknown).win_objective()),
+                malformed
+            );
+        }
+    }
+
+    #[cfg(not(miri))]
+    #[test]
+    fn test_table_format() {
+        let outcomes = gen_outcomes(100);
+        let lz4 = CompressionOptions {
+            codec: Codec::Lz4,
+            pre_filter: PreFilter::Delta,
+        };
+        let mut bytes = Block::new(&outcomes, 0).unwrap().to_bytes().unwrap();
+        bytes.extend(
+            Block::new_with_options(&outcomes, 100, lz4)
+                .unwrap()
+                .to_bytes()
+                .unwrap(),
+        );
+        assert_eq!(
+            EncoderDecoder::new(bytes.clone()).table_format().unwrap(),
+            TableFormat {
+                len: 200,
+                blocks: vec![CompressionOptions::default(), lz4],
+            }
+        );
+        // unknown codec, eg written by a newer version of the crate
+        bytes[BlockHeader::BYTE_SIZE - 1] = 0xf0;
+        assert_eq!(
+            EncoderDecoder::new(bytes)
+                .table_format()
+                .unwrap_err()
+                .kind(),
+            InvalidData
+        );
+    }
+
+    #[test]
+    fn test_block_stats() {
+        let outcomes = gen_outcomes(100);
+        let lz4 = CompressionOptions {
+            codec: Codec::Lz4,
+            pre_filter: PreFilter::Delta,
+        };
+        let mut bytes = Block::new(&outcomes, 0).unwrap().to_bytes().unwrap();
+        bytes.extend(
+            Block::new_with_options(&outcomes, 100, lz4)
+                .unwrap()
+                .to_bytes()
+                .unwrap(),
+        );
+        let blocks_stats = EncoderDecoder::new(bytes.clone()).block_stats().unwrap();
+        assert_eq!(blocks_stats.len(), 2);
+        assert_eq!(blocks_stats[1].range, 100..200);
+        assert_eq!(blocks_stats[1].options, lz4);
+        assert!(blocks_stats
+            .iter()
+            .all(|stats| stats.uncompressed_size == 200));
+        assert_eq!(
+            blocks_stats
+                .iter()
+                .map(|stats| stats.compressed_size + to_u64(BlockHeader::BYTE_SIZE))
+                .sum::<u64>(),
+            to_u64(bytes.len())
+        );
+        assert!(blocks_stats[0].ratio() > 0.);
+    }
+
+    #[cfg(not(miri))]
+    #[test]
+    fn test_outcomes_in_range() {
+        let outcomes = gen_outcomes(200);
+        let mut bytes = Block::new(&outcomes[..100], 0).unwrap().to_bytes().unwrap();
+        bytes.extend(
+            Block::new(&outcomes[100..], 100)
+                .unwrap()
+                .to_bytes()
+                .unwrap(),
+        );
+        let encoder_decoder = EncoderDecoder::new(bytes);
+        let ranges = encoder_decoder.outcomes_in_range(50..150).unwrap();
+        assert_eq!(ranges.len(), 2);
+        assert_eq!(ranges[0].index_from(), 50);
+        assert_eq!(ranges[1].index_from(), 100);
+        assert_eq!(
+            ranges
+                .iter()
+                .flat_map(|r| r.iter().copied())
+                .collect::<Outcomes>(),
+            outcomes[50..150]
+        );
+        assert_eq!(
+            &*encoder_decoder.outcomes_in_range(120..130).unwrap()[0],
+            &outcomes[120..130]
+        );
+        assert!(encoder_decoder.outcomes_in_range(150..201).is_err());
+    }
+
+    #[cfg(not(miri))]
+    #[ignore = "only used to compare the compression ratio of pre-filters, too slow"]
+    #[test]
+    fn test_compression_ratio_existing_tables() {
+        for entry in std::path::Path::new("../table").read_dir().unwrap() {
+            let path = entry.unwrap().path();
+            let outcomes = EncoderDecoder::new(std::fs::File::open(&path).unwrap())
+                .decompress_file()
+                .unwrap();
+            for pre_filter in [PreFilter::None, PreFilter::Delta, PreFilter::Dense] {
+                let mut encoder = EncoderDecoder::new(Vec::<u8>::new());
+                encoder
+                    .compress_with_pre_filter(&outcomes, pre_filter)
+                    .unwrap();
+                println!("{path:?} {pre_filter:?} {}", encoder.inner.len());
+            }
+        }
+    }
+
     #[cfg(not(miri))]
     #[test]
     fn test_block_compression_soundness() {
-        let reports = dummy_reports();
+        let outcomes = dummy_outcomes();
         let mut encoder = EncoderDecoder::new(Vec::<u8>::new());
-        encoder.compress(&reports).expect("compression failed");
+        encoder.compress(&outcomes).expect("compression failed");
         let decompressed = encoder
             .read_block(0)
             .expect("block retrieval failed")
             .decompress_outcomes()
             .expect("decompression failed");
-        assert_eq!(into_outcomes(reports), decompressed)
-    }
-
-    // #[test]
-    // fn test_serilialise_bincode() {
-    //     #[derive(serde::Serialize, serde::Deserialize)]
-    //     struct TestCompression {
-    //         pub a: u16,
-    //         pub b: u8,
-    //         pub c: u8,
-    //     }
-    //     impl TestCompression {
-    //         fn to_bytes_custom<T: Write>(&self, writer: &mut T) {
-    //             writer.write(&self.a.to_ne_bytes()).unwrap();
-    //             writer.write(&self.b.to_ne_bytes()).unwrap();
-    //             writer.write(&self.c.to_ne_bytes()).unwrap();
-    //         }
-    //     }
-    //     let input: Vec<_> = (0..10_0000usize)
-    //         .map(|i| TestCompression {
-    //             a: 10000,
-    //             b: 0,
-    //             c: 0,
-    //         })
-    //         .collect();
-    //     let mut custom = Vec::new();
-    //     for i in input.iter() {
-    //         i.to_bytes_custom(&mut custom)
-    //     }
-    //     let bincode = bincode::serialize(&input).unwrap();
-    //     println!("bincode {:?}", &bincode[0..10]);
-    //     println!("custom {:?}", &custom[0..10]);
-    //     for (i, elem) in custom.into_iter().enumerate() {
-    //         assert_eq!(elem, bincode[i], "at idx {i}, diff");
-    //     }
-    // }
+        assert_eq!(outcomes, decompressed);
+    }
 }
@@ -0,0 +1,196 @@
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use retroboard::shakmaty::Color;
+
+use crate::{
+    get_estimate_nb_pos, EncoderDecoder, Material, MaterialWinner, Metric, TableName, WinCondition,
+};
+
+/// A table found by `Coverage::scan`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoveredTable {
+    pub table_name: TableName,
+    /// File of the table, which can be named after the legacy scheme, see `TableName::path_in`
+    pub path: PathBuf,
+    /// Number of indexes of the table, `None` if its blocks are truncated or not readable by this version of the crate
+    pub len: Option<u64>,
+}
+
+impl CoveredTable {
+    /// Kings included
+    #[must_use]
+    pub fn nb_pieces(&self) -> usize {
+        self.table_name.mat_win.material.count()
+    }
+
+    /// `true` if the table is fully present, and not bigger than what the indexer can produce
+    #[must_use]
+    pub fn is_complete(&self) -> bool {
+        self.len
+            .is_some_and(|len| len <= get_estimate_nb_pos(&self.table_name.mat_win.material) as u64)
+    }
+
+    fn is_helpmate(&self) -> bool {
+        self.table_name == TableName::helpmate(&self.table_name.mat_win)
+    }
+}
+
+/// Tables present in a directory, sorted by number of pieces, then by name.
+/// When a table is present under both its current and legacy name, only the one read is kept
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Coverage {
+    pub tables: Vec<CoveredTable>,
+}
+
+impl Coverage {
+    /// Only block headers are read, the tables are not decompressed.
+    /// Files whose name is not the one of a table are ignored
+    pub fn scan(tablebase_dir: &Path) -> io::Result<Self> {
+        let mut table_names: Vec<TableName> = Vec::new();
+        for entry in tablebase_dir.read_dir()? {
+            let Ok(file_name) = entry?.file_name().into_string() else {
+                continue;
+            };
+            // leftovers of interrupted generations, eg `KQvKR.w.hm.dtm.tmp`, are skipped
+            if let Ok(table_name) = TableName::from_str(&file_name) {
+                if !table_names.contains(&table_name) {
+                    table_names.push(table_name);
+                }
+            }
+        }
+        let mut tables: Vec<CoveredTable> = table_names
+            .into_iter()
+            .map(|table_name| {
+                let path = table_name.path_in(tablebase_dir);
+                let len = File::open(&path)
+                    .and_then(|file| EncoderDecoder::new(file).table_format())
+                    .map(|table_format| table_format.len)
+                    .ok();
+                CoveredTable {
+                    table_name,
+                    path,
+                    len,
+                }
+            })
+            .collect();
+        tables.sort_by_cached_key(|table| (table.nb_pieces(), table.table_name.to_string()));
+        Ok(Self { tables })
+    }
+
+    #[must_use]
+    pub fn get(&self, table_name: &TableName) -> Option<&CoveredTable> {
+        self.tables
+            .iter()
+            .find(|table| table.table_name == *table_name)
+    }
+
+    /// Material and winner of all regular helpmate tables, see `TableName::helpmate`
+    #[must_use]
+    pub fn mat_winners(&self) -> Vec<MaterialWinner> {
+        self.tables
+            .iter()
+            .filter(|table| table.is_helpmate())
+            .map(|table| table.table_name.mat_win.clone())
+            .collect()
+    }
+
+    /// Helpmate tables needed to probe positions of `material` with `TablebaseProber::new`,
+    /// ie the ones of `material` and all its descendants, that are missing or incomplete
+    #[must_use]
+    pub fn missing_for(&self, material: &Material) -> Vec<MaterialWinner> {
+        let mut materials = material.descendants_recursive(false);
+        materials.push(material.clone());
+        materials
+            .iter()
+            .flat_map(|mat| Color::ALL.map(|winner| MaterialWinner::new(mat, winner)))
+            .filter(|mat_win| {
+                !self
+                    .get(&TableName::helpmate(mat_win))
+                    .is_some_and(CoveredTable::is_complete)
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for Coverage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut nb_pieces = None;
+        for table in &self.tables {
+            if nb_pieces != Some(table.nb_pieces()) {
+                nb_pieces = Some(table.nb_pieces());
+                writeln!(f, "{} pieces:", table.nb_pieces())?;
+            }
+            write!(f, "  {}", table.table_name)?;
+            let file_name = table.path.file_name().and_then(|name| name.to_str());
+            if file_name != Some(table.table_name.to_string().as_str()) {
+                write!(f, " (stored as {})", file_name.unwrap_or("?"))?;
+            }
+            let TableName {
+                objective,
+                win_condition,
+                metric,
+                ..
+            } = table.table_name;
+            write!(f, ", {objective:?}")?;
+            if win_condition != WinCondition::AnyMate {
+                write!(f, " {win_condition:?}")?;
+            }
+            match metric {
+                Metric::Dtm => write!(f, ", distance to mate")?,
+                Metric::Wdl => write!(f, ", win/draw/lose")?,
+            }
+            match table.len {
+                Some(len) if table.is_complete() => writeln!(f, ", {len} indexes")?,
+                Some(len) => writeln!(f, ", {len} indexes, INCOMPLETE")?,
+                None => writeln!(f, ", INCOMPLETE")?,
+            }
+        }
+        let incomplete = self.tables.iter().filter(|table| !table.is_complete());
+        write!(
+            f,
+            "{} tables, {} incomplete",
+            self.tables.len(),
+            incomplete.count()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tb_test_dir() -> PathBuf {
+        ["..", "table"].iter().collect()
+    }
+
+    #[test]
+    fn test_scan() {
+        let coverage = Coverage::scan(&tb_test_dir()).unwrap();
+        assert_eq!(coverage.tables.len(), 8);
+        assert!(coverage.tables.iter().all(CoveredTable::is_complete));
+        assert_eq!(coverage.tables[0].table_name.to_string(), "KQvK.b.hm.dtm");
+        assert!(coverage
+            .tables
+            .windows(2)
+            .all(|w| w[0].nb_pieces() <= w[1].nb_pieces()));
+        let kqvk = MaterialWinner::from_str("KQvKw").unwrap();
+        let table = coverage.get(&TableName::helpmate(&kqvk)).unwrap();
+        assert_eq!(table.path, tb_test_dir().join("KQvKw"));
+        assert_eq!(table.len, Some(29560));
+        assert_eq!(coverage.mat_winners().len(), 8);
+        assert!(coverage
+            .missing_for(&Material::from_str("KQvKR").unwrap())
+            .is_empty());
+        // only the table of the material itself is missing, not the one of its descendant `KRvK`
+        let krrvk = Material::from_str("KRRvK").unwrap();
+        assert_eq!(
+            coverage.missing_for(&krrvk),
+            Color::ALL.map(|winner| MaterialWinner::new(&krrvk, winner))
+        );
+        assert!(coverage.to_string().ends_with("8 tables, 0 incomplete"));
+    }
+}
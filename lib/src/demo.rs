@@ -0,0 +1,140 @@
+//! Small tables built in memory, to demonstrate probing in docs, examples and downstream tests
+//! without shipping any table file
+
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+use indicatif::{MultiProgress, ProgressDrawTarget};
+use retroboard::shakmaty::{ByColor, Chess, Color, Position};
+
+use crate::{
+    BuildOptions, DefaultIndexer, FileHandler, Indexer, Material, MaterialWinner, Outcome,
+    ProbeResult, RawAccessOutcome, RetrieveOutcome, Route, SideToMove, TableBaseBuilder,
+};
+
+/// Tables of a material for both winners, built in memory, see `InMemoryTable::build`.
+/// Positions are looked up like with `TablebaseProber`, through `RetrieveOutcome`
+#[derive(Debug)]
+pub struct InMemoryTable {
+    material: Material,
+    tables: ByColor<FileHandler>,
+}
+
+impl InMemoryTable {
+    /// Descendants tables are read from `tablebase_dir`, so only materials whose descendants
+    /// are all drawn, like `KQvK`, can be built without any file. No progress bar is displayed.
+    /// Fails if a descendant table is missing, see `TableBaseBuilder::check_descendants`
+    pub fn build(material: &Material, tablebase_dir: &Path) -> io::Result<Self> {
+        let options = BuildOptions {
+            multi_progress: MultiProgress::with_draw_target(ProgressDrawTarget::hidden()),
+            ..Default::default()
+        };
+        let build = |winner| -> io::Result<FileHandler> {
+            let (common, _) = TableBaseBuilder::build_with_options(
+                MaterialWinner::new(material, winner),
+                tablebase_dir,
+                &options,
+            )?;
+            Ok(FileHandler {
+                indexer: DefaultIndexer::from(material.clone()),
+                outcomes: common.all_pos,
+                path: None,
+            })
+        };
+        Ok(Self {
+            material: material.clone(),
+            tables: ByColor {
+                white: build(Color::White)?,
+                black: build(Color::Black)?,
+            },
+        })
+    }
+
+    #[must_use]
+    pub fn material(&self) -> &Material {
+        &self.material
+    }
+
+    /// Same as `TablebaseProber::probe_result`, castling rights being ignored
+    pub fn probe_result(&self, pos: &Chess, winner: Color) -> io::Result<ProbeResult> {
+        Ok(ProbeResult {
+            outcome: self.retrieve_outcome(pos, winner)?,
+            winner,
+            turn: pos.turn(),
+        })
+    }
+}
+
+impl RawAccessOutcome for InMemoryTable {
+    /// Fails with `io::ErrorKind::InvalidInput` for positions of another material
+    fn raw_access_outcome(&self, route: &Route, pos: &impl SideToMove) -> io::Result<Outcome> {
+        if route.mat_win.material != self.material {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{:?} positions are not part of the {:?} table",
+                    route.mat_win.material, self.material
+                ),
+            ));
+        }
+        let table = self.tables.get(route.mat_win.winner);
+        table.outcome_at(table.indexer.encode(&(pos.board(), route.turn)))
+    }
+}
+
+/// `KQvK` tables, built in memory in a fraction of a second
+#[must_use]
+pub fn kqk_table() -> InMemoryTable {
+    InMemoryTable::build(
+        &Material::from_str("KQvK").expect("valid material"),
+        Path::new(""),
+    )
+    .expect("KQvK has no descendant table")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use retroboard::shakmaty::{fen::Fen, CastlingMode};
+
+    use crate::TablebaseProber;
+
+    fn chess(fen: &str) -> Chess {
+        Fen::from_str(fen)
+            .unwrap()
+            .into_position(CastlingMode::Standard)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_kqk_table() {
+        let table = kqk_table();
+        let tb_dir = ["..", "table"].iter().collect::<std::path::PathBuf>();
+        let prober: TablebaseProber = TablebaseProber::new(table.material(), &tb_dir);
+        for fen in [
+            "8/8/8/8/8/2k5/8/KQ6 w - - 0 1",
+            "8/8/8/8/8/2k5/8/KQ6 b - - 0 1",
+            // colors swapped
+            "8/8/8/8/8/2K5/8/kq6 w - - 0 1",
+        ] {
+            let pos = chess(fen);
+            for winner in Color::ALL {
+                assert_eq!(
+                    table.probe_result(&pos, winner).unwrap(),
+                    prober.probe_result(&pos, winner).unwrap(),
+                    "{fen} {winner}"
+                );
+            }
+        }
+        let result = table
+            .probe_result(&chess("k7/8/1K6/8/8/8/7Q/8 w - - 0 1"), Color::White)
+            .unwrap();
+        assert_eq!(result.outcome, Outcome::Win(1));
+        let err = table
+            .probe_result(&chess("8/8/8/8/8/2k5/8/KR6 w - - 0 1"), Color::White)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
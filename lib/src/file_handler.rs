@@ -1,34 +1,363 @@
+use std::collections::hash_map::RandomState;
 use std::fmt;
-use std::path::Path;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::{Arc, Mutex, OnceLock};
 
-use log::trace;
+use itertools::process_results;
+use log::{debug, trace};
 use positioned_io::RandomAccessFile;
-use retroboard::shakmaty::{Chess, Color, Position};
+use retroboard::shakmaty::{Bitboard, Chess, Color, Move, MoveList, Position};
 use rustc_hash::FxHashMap;
 
 use crate::{
-    indexer::Indexer, is_black_stronger, DefaultIndexer, EncoderDecoder, Material, Outcome,
-    Outcomes, SideToMoveGetter, KB_K, KN_K,
+    compression::TableFormatError,
+    indexer::{DeIndexer, Indexer},
+    limits::table_ram_bytes,
+    DecompressProgress, DefaultIndexer, DenseOutcomes, EncoderDecoder, IndexWithTurn, Material,
+    Outcome, Outcomes, OutcomesRange, ResourceLimits, Route, Router, SideToMove, SideToMoveGetter,
+    WdlOutcomes,
 };
 
+/// `O` is the in-memory representation of the outcomes, either `Outcomes`, `WdlOutcomes` or `DenseOutcomes`
 #[derive(Debug)]
-pub struct FileHandler<T = DefaultIndexer> {
+pub struct FileHandler<T = DefaultIndexer, O = Outcomes> {
     pub indexer: T, // needed in case we want to re-extract the position from the index if reversible
-    pub outcomes: Outcomes,
+    pub outcomes: O,
+    /// File the outcomes were read from, `None` for tables built in memory. Only used in errors
+    pub path: Option<PathBuf>,
 }
 
+fn open_table(
+    table_name: &TableName,
+    tablebase_dir: &Path,
+) -> (EncoderDecoder<RandomAccessFile>, PathBuf) {
+    let table_path = table_name.path_in(tablebase_dir);
+    trace!("Creating new FileHandler for {table_path:?}");
+    let raf = RandomAccessFile::open(&table_path).unwrap_or_else(|e| {
+        panic!(
+            "{e}, Most probably {} not found, use `--recursive` option to regenerate descendants",
+            table_path.display()
+        )
+    });
+    let encoder_decoder = EncoderDecoder::new(raf);
+    encoder_decoder
+        .table_format()
+        .and_then(|format| check_table_len(table_name, format.len))
+        .unwrap_or_else(|e| panic!("{}: {e}", table_path.display()));
+    (encoder_decoder, table_path)
+}
+
+/// Fails with `TableFormatError::TruncatedTable` if the `len` indexes covered by the blocks of the table stored
+/// as `table_name` are fewer than its material requires, instead of probes later going out of the table.
+/// Tables are always generated with `DefaultIndexer`, whatever the indexer reading them
+pub(crate) fn check_table_len(table_name: &TableName, len: u64) -> io::Result<()> {
+    let material = &table_name.mat_win.material;
+    let expected_min = DefaultIndexer::from(material.clone()).min_table_len(material);
+    if len < expected_min {
+        return Err(TableFormatError::TruncatedTable { len, expected_min }.into());
+    }
+    Ok(())
+}
+
+/// Error for an index beyond the `len` entries of a table, usually meaning the table
+/// was generated with another indexer than the one reading it
+pub(crate) fn index_out_of_table(
+    idx: u64,
+    len: u64,
+    mat_win: Option<&MaterialWinner>,
+    path: Option<&Path>,
+) -> io::Error {
+    let table = mat_win.map(|m| format!(" {m:?}")).unwrap_or_default();
+    let max_index = len.checked_sub(1).map_or_else(
+        || "which is empty".to_string(),
+        |max_index| format!("max index {max_index}"),
+    );
+    let read_from = path
+        .map(|p| format!(", read from {}", p.display()))
+        .unwrap_or_default();
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("index {idx} out of the{table} table, {max_index}{read_from}"),
+    )
+}
+
+#[cfg_attr(not(feature = "unstable-internals"), allow(dead_code))]
 impl<T: From<Material>> FileHandler<T> {
     #[must_use]
     pub fn new(mat: &MaterialWinner, tablebase_dir: &Path) -> Self {
-        let table_path = tablebase_dir.join(format!("{mat:?}"));
-        trace!("Creating new FileHandler for {table_path:?}");
-        let raf = RandomAccessFile::open(&table_path).unwrap_or_else(|e| panic!("{e}, Most probably {table_path:?} not found, use `--recursive` option to regenerate descendants"));
-        let outcomes = EncoderDecoder::new(raf)
-            .decompress_file()
+        Self::new_with_win_condition(mat, tablebase_dir, WinCondition::default())
+    }
+
+    /// Open the table generated with `win_condition`, see `WinCondition::table_name`
+    #[must_use]
+    pub fn new_with_win_condition(
+        mat: &MaterialWinner,
+        tablebase_dir: &Path,
+        win_condition: WinCondition,
+    ) -> Self {
+        Self::new_with_table_name(
+            &TableName::new(mat, Objective::Helpmate, win_condition),
+            tablebase_dir,
+        )
+    }
+
+    /// Open the table stored as `table_name`, eg `KQvKR.w.sm.dtm`
+    #[must_use]
+    pub fn new_with_table_name(table_name: &TableName, tablebase_dir: &Path) -> Self {
+        let (encoder_decoder, path) = open_table(table_name, tablebase_dir);
+        let outcomes = encoder_decoder
+            .decompress_file_with_progress(0..u64::MAX, |progress| {
+                debug!(
+                    "{}: {} blocks decompressed, {} bytes read",
+                    path.display(),
+                    progress.blocks_done,
+                    progress.bytes_read
+                );
+            })
+            .expect("decompression failed");
+        let indexer = T::from(table_name.mat_win.material.clone());
+        Self {
+            indexer,
+            outcomes,
+            path: Some(path),
+        }
+    }
+
+    /// Same as `FileHandler::new_with_table_name`, but fails instead of panicking, and `on_block` is called
+    /// with each block as soon as it is decompressed, along with the progress so far.
+    /// Loading big tables takes minutes, this lets callers report it, or start working on the first blocks
+    pub fn try_new_streaming(
+        table_name: &TableName,
+        tablebase_dir: &Path,
+        mut on_block: impl FnMut(&OutcomesRange, DecompressProgress),
+    ) -> io::Result<Self> {
+        let path = table_name.path_in(tablebase_dir);
+        let encoder_decoder = EncoderDecoder::new(RandomAccessFile::open(&path)?);
+        check_table_len(table_name, encoder_decoder.table_format()?.len)?;
+        let mut blocks = encoder_decoder.into_blocks(0..u64::MAX);
+        let mut outcomes = Outcomes::new();
+        while let Some(outcomes_range) = blocks.next() {
+            let outcomes_range = outcomes_range?;
+            outcomes.extend_from_slice(&outcomes_range);
+            on_block(&outcomes_range, blocks.progress());
+        }
+        let indexer = T::from(table_name.mat_win.material.clone());
+        Ok(Self {
+            indexer,
+            outcomes,
+            path: Some(path),
+        })
+    }
+
+    /// Same as `FileHandler::new_with_table_name`, but fails instead of panicking, in particular with
+    /// a `ResourceLimitExceeded` if the decompressed table would not fit in `limits.max_ram_bytes`.
+    /// Only block headers are read to check it
+    pub fn try_new_with_limits(
+        table_name: &TableName,
+        tablebase_dir: &Path,
+        limits: &ResourceLimits,
+    ) -> io::Result<Self> {
+        let path = table_name.path_in(tablebase_dir);
+        let encoder_decoder = EncoderDecoder::new(RandomAccessFile::open(&path)?);
+        let len = encoder_decoder.table_format()?.len;
+        check_table_len(table_name, len)?;
+        limits.check_ram(table_ram_bytes(len), table_name)?;
+        let outcomes = encoder_decoder.decompress_file()?;
+        let indexer = T::from(table_name.mat_win.material.clone());
+        Ok(Self {
+            indexer,
+            outcomes,
+            path: Some(path),
+        })
+    }
+}
+
+impl<T> FileHandler<T> {
+    /// Outcome stored at `idx`, for tools already working with indexes rather than positions.
+    /// Fails with `io::ErrorKind::InvalidInput` if `idx` is out of the table
+    pub fn outcome_at(&self, idx: IndexWithTurn) -> io::Result<Outcome> {
+        self.outcome_of_table_at(None, idx)
+    }
+
+    /// Same as `FileHandler::outcome_at`, `mat_win` only being used in the error
+    fn outcome_of_table_at(
+        &self,
+        mat_win: Option<&MaterialWinner>,
+        idx: IndexWithTurn,
+    ) -> io::Result<Outcome> {
+        self.outcomes
+            .get(idx.usize())
+            .map(|by_color| by_color.get_by_color(idx.turn))
+            .ok_or_else(|| {
+                index_out_of_table(
+                    idx.idx,
+                    self.outcomes.len() as u64,
+                    mat_win,
+                    self.path.as_deref(),
+                )
+            })
+    }
+
+    /// Index ranges of the blocks the table is stored in, in order, read from the block headers of the
+    /// file it was loaded from. Each block can be decompressed on its own with `LazyFileHandler::outcomes_in_range`,
+    /// eg to split a table between workers. Fails with `io::ErrorKind::NotFound` for tables built in memory
+    #[cfg_attr(not(feature = "unstable-internals"), allow(dead_code))]
+    pub fn block_ranges(&self) -> io::Result<Vec<Range<u64>>> {
+        let path = self.path.as_deref().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "table not loaded from a file")
+        })?;
+        EncoderDecoder::new(RandomAccessFile::open(path)?).block_ranges()
+    }
+}
+
+#[cfg_attr(not(feature = "unstable-internals"), allow(dead_code))]
+impl<T: From<Material> + DeIndexer> FileHandler<T> {
+    /// Same as `FileHandler::new`, followed by `FileHandler::selftest`, but fails instead of panicking
+    /// if the table is missing or malformed
+    pub fn open_with_selftest(
+        mat: &MaterialWinner,
+        tablebase_dir: &Path,
+        nb_samples: usize,
+    ) -> io::Result<Self> {
+        let file_handler = Self::try_new_with_limits(
+            &TableName::helpmate(mat),
+            tablebase_dir,
+            &ResourceLimits::default(),
+        )?;
+        file_handler.selftest(mat, nb_samples)?;
+        Ok(file_handler)
+    }
+}
+
+#[cfg_attr(not(feature = "unstable-internals"), allow(dead_code))]
+impl<T: DeIndexer> FileHandler<T> {
+    /// Restore the positions of `nb_samples` random defined entries of the helpmate table of `mat`,
+    /// and check that `Win(0)`/`Lose(0)` entries are checkmates of the right side, and `Draw` ones are not checkmates.
+    /// Cheap enough to be run on load, to catch corrupted tables or tables generated with another indexer
+    pub fn selftest(&self, mat: &MaterialWinner, nb_samples: usize) -> io::Result<()> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+        let mut seed = RandomState::new().build_hasher().finish();
+        let mut sampled = 0;
+        // give up on tables with too few defined entries, rather than looping forever
+        for _ in 0..nb_samples * 100 {
+            if sampled == nb_samples || self.outcomes.is_empty() {
+                break;
+            }
+            let random = splitmix64(&mut seed);
+            let idx = IndexWithTurn {
+                idx: random % self.outcomes.len() as u64,
+                turn: if random >> 63 == 0 {
+                    Color::White
+                } else {
+                    Color::Black
+                },
+            };
+            let outcome = self.outcomes[idx.usize()].get_by_color(idx.turn);
+            let is_mated = |side: Color| {
+                let chess: Chess = self.indexer.restore(&mat.material, idx).into();
+                chess.turn() == side && chess.is_checkmate()
+            };
+            let consistent = match outcome {
+                Outcome::Undefined => continue,
+                Outcome::Win(0) => is_mated(!mat.winner),
+                Outcome::Lose(0) => is_mated(mat.winner),
+                Outcome::Draw => !is_mated(idx.turn),
+                _ => true,
+            };
+            if !consistent {
+                return Err(invalid(format!(
+                    "{mat:?} self-test failed, entry {idx:?} is {outcome:?} but its position {:?} disagrees",
+                    self.indexer.restore(&mat.material, idx)
+                )));
+            }
+            sampled += 1;
+        }
+        trace!("{mat:?} self-test passed on {sampled} entries");
+        Ok(())
+    }
+}
+
+// splitmix64, to spread samples over the table without a dependency on a random crate
+#[cfg_attr(not(feature = "unstable-internals"), allow(dead_code))]
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+#[cfg_attr(not(feature = "unstable-internals"), allow(dead_code))]
+impl<T: From<Material>> FileHandler<T, WdlOutcomes> {
+    /// Only keep Win/Draw/Lose information in memory, using half the RAM of `FileHandler::new`
+    #[must_use]
+    pub fn new_wdl_only(mat: &MaterialWinner, tablebase_dir: &Path) -> Self {
+        let (encoder_decoder, path) = open_table(&TableName::helpmate(mat), tablebase_dir);
+        let outcomes = encoder_decoder
+            .decompress_file_wdl()
             .expect("decompression failed");
         let indexer = T::from(mat.material.clone());
-        Self { indexer, outcomes }
+        Self {
+            indexer,
+            outcomes,
+            path: Some(path),
+        }
+    }
+
+    /// Same as `FileHandler::new_wdl_only`, but fails instead of panicking if the table is missing or malformed
+    pub fn try_new_wdl_only(mat: &MaterialWinner, tablebase_dir: &Path) -> io::Result<Self> {
+        let table_name = TableName::helpmate(mat);
+        let path = table_name.path_in(tablebase_dir);
+        let encoder_decoder = EncoderDecoder::new(RandomAccessFile::open(&path)?);
+        check_table_len(&table_name, encoder_decoder.table_format()?.len)?;
+        let outcomes = encoder_decoder.decompress_file_wdl()?;
+        let indexer = T::from(mat.material.clone());
+        Ok(Self {
+            indexer,
+            outcomes,
+            path: Some(path),
+        })
+    }
+}
+
+#[cfg_attr(not(feature = "unstable-internals"), allow(dead_code))]
+impl<T: From<Material>> FileHandler<T, DenseOutcomes> {
+    /// Only keep the entries defined for at least one side to move in memory, at the cost of translating
+    /// each index with `DenseOutcomes::rank`. Same outcomes as `FileHandler::new`
+    #[must_use]
+    pub fn new_dense(mat: &MaterialWinner, tablebase_dir: &Path) -> Self {
+        let (encoder_decoder, path) = open_table(&TableName::helpmate(mat), tablebase_dir);
+        let outcomes = encoder_decoder
+            .decompress_file_dense()
+            .expect("decompression failed");
+        let indexer = T::from(mat.material.clone());
+        Self {
+            indexer,
+            outcomes,
+            path: Some(path),
+        }
+    }
+}
+
+#[cfg_attr(not(feature = "unstable-internals"), allow(dead_code))]
+impl<T> FileHandler<T, DenseOutcomes> {
+    /// Same as `FileHandler::outcome_at`
+    pub fn outcome_at(&self, idx: IndexWithTurn) -> io::Result<Outcome> {
+        self.outcomes
+            .get(idx.usize())
+            .map(|by_color| by_color.get_by_color(idx.turn))
+            .ok_or_else(|| {
+                index_out_of_table(
+                    idx.idx,
+                    self.outcomes.len() as u64,
+                    None,
+                    self.path.as_deref(),
+                )
+            })
     }
 }
 
@@ -72,100 +401,701 @@ impl fmt::Debug for MaterialWinner {
     }
 }
 
+/// Additional condition a helpmate must fulfil for the winner to be considered winning.
+/// Tables generated with a condition other than `WinCondition::AnyMate` are stored under another name,
+/// see `TableName`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum WinCondition {
+    #[default]
+    AnyMate,
+    /// The winner must mate without any of its pieces being captured along the line (no sacrifice).
+    /// Promotions are allowed
+    KeepAllPieces,
+    /// The winner must mate the loser's king on one of these squares, eg a corner.
+    /// Other mates are draws, like stalemates
+    MatedKingOn(KingSquares),
+}
+
+/// Squares of `WinCondition::MatedKingOn`. Tables only store one of the positions symmetric to each other,
+/// and black-stronger positions are read with colors swapped and the board mirrored, so the squares must be
+/// unchanged by mirroring the board vertically, horizontally and along the a1-h8 diagonal
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KingSquares(Bitboard);
+
+impl KingSquares {
+    pub const CORNERS: Self = Self(Bitboard(0x8100_0000_0000_0081));
+
+    /// `None` if `squares` is empty, or changed by one of the board symmetries
+    #[must_use]
+    pub fn new(squares: Bitboard) -> Option<Self> {
+        let symmetric = squares.flip_vertical() == squares
+            && squares.flip_horizontal() == squares
+            && squares.flip_diagonal() == squares;
+        (symmetric && squares.any()).then_some(Self(squares))
+    }
+
+    #[must_use]
+    pub fn bitboard(self) -> Bitboard {
+        self.0
+    }
+
+    /// `true` if the king of `loser` is mated on one of the squares
+    #[must_use]
+    pub fn is_mated_on(self, pos: &Chess, loser: Color) -> bool {
+        pos.turn() == loser
+            && pos.is_checkmate()
+            && pos
+                .board()
+                .king_of(loser)
+                .is_some_and(|king| self.0.contains(king))
+    }
+}
+
+/// What the winner is trying to achieve. Tables of each objective are stored under their own name,
+/// see `TableName`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Objective {
+    /// Both sides cooperate for the winner to mate
+    #[default]
+    Helpmate,
+    /// The winner forces the loser to mate it, whatever the loser plays.
+    /// Positions where it cannot are `Outcome::Draw`, `Outcome::Lose` is never used
+    Selfmate,
+}
+
+/// Data stored in a table file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Metric {
+    /// Distance to mate, in plies
+    #[default]
+    Dtm,
+    /// Win/Draw/Lose only, for sidecar tables smaller to probe. Same format as `Dtm` tables,
+    /// but outcomes are only read as `Wdl`, so they can be reduced to `Win(0)`, `Draw` and `Lose(0)`
+    /// to compress better, see `TablebaseProber::wdl`
+    Wdl,
+}
+
+/// Name of a table file, `<material>.<winner>.<objective>[.keep|.on<squares>].<metric>`,
+/// eg `KQvKR.w.hm.dtm`, `KQvKR.w.hm.keep.dtm` for `WinCondition::KeepAllPieces`,
+/// `KQvKR.w.hm.on8100000000000081.dtm` for `WinCondition::MatedKingOn` the corners, or `KQvKR.w.sm.dtm` for selfmates.
+/// Tables are always written under this name, but the ones named after the legacy scheme
+/// (`KQvKRw`, `KQvKRw-keep` and `KQvKRw-self`) are still read, see `TableName::path_in`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TableName {
+    pub mat_win: MaterialWinner,
+    pub objective: Objective,
+    pub win_condition: WinCondition,
+    pub metric: Metric,
+}
+
+impl TableName {
+    #[must_use]
+    pub fn new(
+        mat_win: &MaterialWinner,
+        objective: Objective,
+        win_condition: WinCondition,
+    ) -> Self {
+        Self {
+            mat_win: mat_win.clone(),
+            objective,
+            win_condition,
+            metric: Metric::Dtm,
+        }
+    }
+
+    /// Name of the regular helpmate table of `mat_win`
+    #[must_use]
+    pub fn helpmate(mat_win: &MaterialWinner) -> Self {
+        Self::new(mat_win, Objective::Helpmate, WinCondition::AnyMate)
+    }
+
+    /// Name the table had before extension-based naming, if it could be generated back then
+    #[must_use]
+    pub fn legacy(&self) -> Option<String> {
+        let mat_win = &self.mat_win;
+        match (self.metric, self.objective, self.win_condition) {
+            (Metric::Dtm, Objective::Helpmate, WinCondition::AnyMate) => {
+                Some(format!("{mat_win:?}"))
+            }
+            (Metric::Dtm, Objective::Helpmate, WinCondition::KeepAllPieces) => {
+                Some(format!("{mat_win:?}-keep"))
+            }
+            (Metric::Dtm, Objective::Selfmate, WinCondition::AnyMate) => {
+                Some(format!("{mat_win:?}-self"))
+            }
+            _ => None,
+        }
+    }
+
+    /// Path of the table in `tablebase_dir`, see `TablePath::table`
+    #[must_use]
+    pub fn path_in(&self, tablebase_dir: &Path) -> PathBuf {
+        TablePath::new(tablebase_dir).table(self)
+    }
+
+    /// `true` if the table is in `tablebase_dir`, under its current or legacy name
+    #[must_use]
+    pub fn exists_in(&self, tablebase_dir: &Path) -> bool {
+        self.path_in(tablebase_dir).exists()
+    }
+}
+
+/// Files of a directory written by the generation or read by the prober. Paths are only built
+/// by joining file names to the directory, never by formatting them with a separator,
+/// so that they are valid on every platform
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TablePath {
+    dir: PathBuf,
+}
+
+impl TablePath {
+    #[must_use]
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    #[must_use]
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Path of the table to read. The legacy name is only used if the table
+    /// is not found under its current one, and is found under the legacy one
+    #[must_use]
+    pub fn table(&self, table_name: &TableName) -> PathBuf {
+        let path = self.table_to_write(table_name);
+        if !path.exists() {
+            if let Some(legacy_path) = table_name.legacy().map(|name| self.dir.join(name)) {
+                if legacy_path.exists() {
+                    return legacy_path;
+                }
+            }
+        }
+        path
+    }
+
+    /// Path of the table to write, always under its current name
+    #[must_use]
+    pub fn table_to_write(&self, table_name: &TableName) -> PathBuf {
+        self.dir.join(table_name.to_string())
+    }
+
+    /// `<mat_win>.win<max_plies>`, see `WinWithinTable`
+    #[must_use]
+    pub fn win_within(&self, mat_win: &MaterialWinner, max_plies: u8) -> PathBuf {
+        self.dir.join(format!("{mat_win:?}.win{max_plies}"))
+    }
+
+    /// `<mat_win>.fortress`, see `FortressTable`
+    #[must_use]
+    pub fn fortress(&self, mat_win: &MaterialWinner) -> PathBuf {
+        self.dir.join(format!("{mat_win:?}.fortress"))
+    }
+
+    /// `<table>.json`, the metadata of a table built
+    #[must_use]
+    pub fn metadata(&self, table_name: &TableName) -> PathBuf {
+        self.dir.join(format!("{table_name}.json"))
+    }
+
+    /// `<table>.interrupted`, marking a table whose build was stopped before it was written
+    #[must_use]
+    pub fn interrupted(&self, table_name: &TableName) -> PathBuf {
+        self.dir.join(format!("{table_name}.interrupted"))
+    }
+
+    /// `<table>.checkpoint`, the tagging state of a table whose build was stopped, see `BuildOptions::checkpoint_dir`
+    #[must_use]
+    pub fn checkpoint(&self, table_name: &TableName) -> PathBuf {
+        self.dir.join(format!("{table_name}.checkpoint"))
+    }
+
+    /// `<mat_win>.json`, the inconsistencies recorded while building a table
+    #[must_use]
+    pub fn quarantine_report(&self, mat_win: &MaterialWinner) -> PathBuf {
+        self.dir.join(format!("{mat_win:?}.json"))
+    }
+
+    /// `anomalies.jsonl`, see `AnomalySink`
+    #[must_use]
+    pub fn anomalies(&self) -> PathBuf {
+        self.dir.join("anomalies.jsonl")
+    }
+}
+
+impl fmt::Display for TableName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?}.{}",
+            self.mat_win.material,
+            self.mat_win.winner.char()
+        )?;
+        match self.objective {
+            Objective::Helpmate => write!(f, ".hm")?,
+            Objective::Selfmate => write!(f, ".sm")?,
+        }
+        match self.win_condition {
+            WinCondition::AnyMate => (),
+            WinCondition::KeepAllPieces => write!(f, ".keep")?,
+            WinCondition::MatedKingOn(squares) => write!(f, ".on{:016x}", squares.bitboard().0)?,
+        }
+        match self.metric {
+            Metric::Dtm => write!(f, ".dtm"),
+            Metric::Wdl => write!(f, ".wdl"),
+        }
+    }
+}
+
+/// Accepts both current and legacy names
+impl FromStr for TableName {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split('.').collect();
+        if let [mat_win_str] = parts[..] {
+            let (mat_win_str, objective, win_condition) =
+                if let Some(mat_win_str) = mat_win_str.strip_suffix("-keep") {
+                    (
+                        mat_win_str,
+                        Objective::Helpmate,
+                        WinCondition::KeepAllPieces,
+                    )
+                } else if let Some(mat_win_str) = mat_win_str.strip_suffix("-self") {
+                    (mat_win_str, Objective::Selfmate, WinCondition::AnyMate)
+                } else {
+                    (mat_win_str, Objective::Helpmate, WinCondition::AnyMate)
+                };
+            let (mat_str, winner_str) = mat_win_str
+                .split_at_checked(mat_win_str.len().saturating_sub(1))
+                .ok_or("material should only contain ascii cases")?;
+            return Ok(Self::new(
+                &MaterialWinner::new(&Material::from_str(mat_str)?, parse_winner(winner_str)?),
+                objective,
+                win_condition,
+            ));
+        }
+        let (mat_str, winner_str, objective_str, win_condition, metric_str) = match parts[..] {
+            [mat, winner, objective, metric] => {
+                (mat, winner, objective, WinCondition::AnyMate, metric)
+            }
+            [mat, winner, objective, "keep", metric] => {
+                (mat, winner, objective, WinCondition::KeepAllPieces, metric)
+            }
+            [mat, winner, objective, condition, metric] if condition.starts_with("on") => {
+                let squares = u64::from_str_radix(&condition[2..], 16)
+                    .ok()
+                    .and_then(|squares| KingSquares::new(Bitboard(squares)))
+                    .ok_or("squares should be a symmetric bitboard, in hexadecimal after `on`")?;
+                (
+                    mat,
+                    winner,
+                    objective,
+                    WinCondition::MatedKingOn(squares),
+                    metric,
+                )
+            }
+            _ => {
+                return Err(
+                    "expected `<material>.<winner>.<objective>[.keep|.on<squares>].<metric>`",
+                )
+            }
+        };
+        let objective = match objective_str {
+            "hm" => Objective::Helpmate,
+            "sm" => Objective::Selfmate,
+            _ => return Err("objective should be 'hm' for helpmate or 'sm' for selfmate"),
+        };
+        let metric = match metric_str {
+            "dtm" => Metric::Dtm,
+            "wdl" => Metric::Wdl,
+            _ => return Err("metric should be 'dtm' or 'wdl'"),
+        };
+        Ok(Self {
+            mat_win: MaterialWinner::new(&Material::from_str(mat_str)?, parse_winner(winner_str)?),
+            objective,
+            win_condition,
+            metric,
+        })
+    }
+}
+
+fn parse_winner(s: &str) -> Result<Color, &'static str> {
+    char::from_str(s)
+        .ok()
+        .and_then(Color::from_char)
+        .ok_or("winner must be 'b' for black or 'w' for white")
+}
+
+/// Decompressed tables shared across successive builds, so that descendants common to several
+/// material configurations (eg `KQvK`) are only decompressed once. Clones share the same cache.
+/// Tables are kept until released by `TableCache::release_except`
 #[derive(Debug)]
-pub struct Descendants<T = DefaultIndexer>(FxHashMap<MaterialWinner, FileHandler<T>>);
+pub struct TableCache<T = DefaultIndexer>(Arc<Mutex<FxHashMap<PathBuf, TableCacheEntry<T>>>>);
+
+// each table is decompressed under its own lock, so that loading one does not block access to the others
+type TableCacheEntry<T> = Arc<OnceLock<Arc<FileHandler<T>>>>;
+
+impl<T> Default for TableCache<T> {
+    fn default() -> Self {
+        Self(Arc::default())
+    }
+}
+
+impl<T> Clone for TableCache<T> {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+
+impl<T> TableCache<T> {
+    /// Release the tables of `tablebase_dir` not in `needed`, those of other directories are kept.
+    /// Tables still used by a `Descendants` are only freed once it is dropped
+    pub fn release_except<'a>(
+        &self,
+        needed: impl IntoIterator<Item = &'a TableName>,
+        tablebase_dir: &Path,
+    ) {
+        let needed: Vec<PathBuf> = needed
+            .into_iter()
+            .map(|table_name| table_name.path_in(tablebase_dir))
+            .collect();
+        self.0
+            .lock()
+            .expect("table cache poisoned")
+            .retain(|path, _| !path.starts_with(tablebase_dir) || needed.contains(path));
+    }
+
+    /// Number of tables currently held
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.0.lock().expect("table cache poisoned").len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T: From<Material>> TableCache<T> {
+    fn get_or_load(&self, table_name: &TableName, tablebase_dir: &Path) -> Arc<FileHandler<T>> {
+        let entry = Arc::clone(
+            self.0
+                .lock()
+                .expect("table cache poisoned")
+                .entry(table_name.path_in(tablebase_dir))
+                .or_default(),
+        );
+        // concurrent loads of the same table wait for the first one instead of decompressing it again
+        Arc::clone(
+            entry.get_or_init(|| {
+                Arc::new(FileHandler::new_with_table_name(table_name, tablebase_dir))
+            }),
+        )
+    }
+}
+
+/// Tables keyed by material and winner, probed through `RetrieveOutcome`
+#[derive(Debug)]
+struct Tables<'a, T>(&'a FxHashMap<MaterialWinner, Arc<FileHandler<T>>>);
+
+#[derive(Debug, Clone)]
+pub struct Descendants<T = DefaultIndexer> {
+    // tables generated with `objective`
+    tables: FxHashMap<MaterialWinner, Arc<FileHandler<T>>>,
+    // tables generated with `win_condition`, empty for `WinCondition::AnyMate`
+    tables_with_condition: FxHashMap<MaterialWinner, Arc<FileHandler<T>>>,
+    win_condition: WinCondition,
+}
 
 impl<T: Indexer + From<Material>> Descendants<T> {
     #[must_use]
     pub fn new(mat: &MaterialWinner, tablebase_dir: &Path) -> Self {
+        Self::new_with_win_condition(mat, tablebase_dir, WinCondition::default(), None)
+    }
+
+    /// With a `win_condition` other than `WinCondition::AnyMate`, the descendants tables generated with it
+    /// are loaded as well when present, alongside the regular ones.
+    /// With a `table_cache`, tables already in it are reused and the others are added to it
+    #[must_use]
+    pub fn new_with_win_condition(
+        mat: &MaterialWinner,
+        tablebase_dir: &Path,
+        win_condition: WinCondition,
+        table_cache: Option<&TableCache<T>>,
+    ) -> Self {
+        Self::new_internal(
+            mat,
+            tablebase_dir,
+            win_condition,
+            Objective::Helpmate,
+            table_cache,
+        )
+    }
+
+    /// Only load the descendants tables generated with `objective`
+    #[must_use]
+    pub fn new_with_objective(
+        mat: &MaterialWinner,
+        tablebase_dir: &Path,
+        objective: Objective,
+        table_cache: Option<&TableCache<T>>,
+    ) -> Self {
+        Self::new_internal(
+            mat,
+            tablebase_dir,
+            WinCondition::AnyMate,
+            objective,
+            table_cache,
+        )
+    }
+
+    fn new_internal(
+        mat: &MaterialWinner,
+        tablebase_dir: &Path,
+        win_condition: WinCondition,
+        objective: Objective,
+        table_cache: Option<&TableCache<T>>,
+    ) -> Self {
+        let load = |table_name: &TableName| match table_cache {
+            Some(table_cache) => table_cache.get_or_load(table_name, tablebase_dir),
+            None => Arc::new(FileHandler::new_with_table_name(table_name, tablebase_dir)),
+        };
+        let mut tables: FxHashMap<MaterialWinner, Arc<FileHandler<T>>> = FxHashMap::default();
+        let mut tables_with_condition: FxHashMap<MaterialWinner, Arc<FileHandler<T>>> =
+            FxHashMap::default();
+        for table_name in Self::table_names(mat, win_condition, objective) {
+            if table_name.win_condition == WinCondition::AnyMate {
+                tables.insert(table_name.mat_win.clone(), load(&table_name));
+            } else {
+                tables_with_condition.insert(table_name.mat_win.clone(), load(&table_name));
+            }
+        }
+
+        trace!("All descendants decompressed and loaded");
+        Self {
+            tables,
+            tables_with_condition,
+            win_condition,
+        }
+    }
+
+    /// Tables loaded by `Descendants::new_internal`, those generated with `win_condition` included
+    pub(crate) fn table_names(
+        mat: &MaterialWinner,
+        win_condition: WinCondition,
+        objective: Objective,
+    ) -> Vec<TableName> {
         let MaterialWinner { material, winner } = mat;
         let winners: Vec<Color> = if material.can_need_opposite_winner() {
             Color::ALL.into()
         } else {
             vec![*winner]
         };
-        let mut hash_map: FxHashMap<MaterialWinner, FileHandler<T>> = FxHashMap::default();
+        let mut table_names = Vec::new();
+        let descendants_with_condition = match win_condition {
+            WinCondition::AnyMate => Vec::new(),
+            // a descendant only reached by capturing a piece of the winner does not need it
+            WinCondition::KeepAllPieces => material.descendants_not_draw_keeping(*winner),
+            // nor one where the winner cannot mate anymore
+            WinCondition::MatedKingOn(_) => material.descendants_winnable(*winner),
+        };
+        for (m, w) in descendants_with_condition {
+            table_names.push(TableName::new(
+                &MaterialWinner::new(&m, w),
+                Objective::Helpmate,
+                win_condition,
+            ));
+        }
         for m in material.descendants_not_draw() {
-            for w in winners.iter() {
+            for w in &winners {
                 let mat_win = MaterialWinner::new(&m, *w);
-                hash_map.insert(mat_win.clone(), FileHandler::new(&mat_win, tablebase_dir));
+                table_names.push(TableName::new(&mat_win, objective, WinCondition::AnyMate));
             }
         }
-
-        trace!("All descendants decompressed and loaded");
-        Self(hash_map)
+        table_names
     }
 
-    // For test purpose
+    #[cfg(test)]
     #[must_use]
     pub fn empty() -> Self {
-        Self(FxHashMap::default())
+        Self {
+            tables: FxHashMap::default(),
+            tables_with_condition: FxHashMap::default(),
+            win_condition: WinCondition::default(),
+        }
+    }
+
+    #[must_use]
+    pub fn win_condition(&self) -> WinCondition {
+        self.win_condition
     }
 
     /// For the given position, compute all moves that are either captures and/or promotion,
-    /// and return the best result
+    /// and return the best result, see `Outcome::best_of`
     /// Example:
     /// "`KPvRK`" where the pawn can take and promote then mate in 4, or just promote and mate in 2, will return `Outcome::Win(2)`
     /// Also return a boolean whose value is `true` if and only if all legal moves are promotion/captures
-    #[must_use]
+    /// and none of them leads to an `Outcome::Unknown` position, ie the result is exact.
+    /// `None` if no capture nor promotion has a known outcome.
+    /// Fails if a position after a move is out of its descendant table, see `FileHandler::outcome_at`
     pub fn outcome_from_captures_promotion(
         &self,
         pos: &Chess,
         winner: Color,
-    ) -> Option<(Outcome, bool)> {
+    ) -> io::Result<Option<(Outcome, bool)>> {
         let mut moves = pos.legal_moves();
         let all_moves_nb = moves.len();
         moves.retain(|m| m.is_capture() || m.is_promotion());
         let are_all_moves_captures = all_moves_nb == moves.len();
-        moves
-            .iter()
-            .map(|chess_move| {
-                let mut pos_after_move = pos.clone();
-                pos_after_move.play_unchecked(chess_move);
-                self.retrieve_outcome(&pos_after_move, winner)
-                    .expect("No IO operation involved here")
-            })
-            .max()
-            .map(|o| (o + 1, are_all_moves_captures)) // we are one move further from the max
+        let outcomes: Vec<Outcome> = process_results(
+            moves.iter().map(|m| match self.win_condition {
+                WinCondition::AnyMate => {
+                    let mut pos_after_move = pos.clone();
+                    pos_after_move.play_unchecked(m);
+                    self.retrieve_outcome(&pos_after_move, winner)
+                }
+                WinCondition::KeepAllPieces => {
+                    self.outcome_after_move_keep_all_pieces(pos, m, winner)
+                }
+                WinCondition::MatedKingOn(_) => {
+                    self.outcome_after_move_mated_king_on(pos, m, winner)
+                }
+            }),
+            |iter| iter.collect(),
+        )?;
+        let is_exact = are_all_moves_captures && !outcomes.contains(&Outcome::Unknown);
+        Ok(Outcome::best_of(outcomes)
+            .filter(|o| *o != Outcome::Unknown)
+            .map(|o| (o + 1, is_exact))) // we are one move further from the max
     }
-}
 
-pub trait RetrieveOutcome {
-    fn raw_access_outcome(
+    fn outcome_after_move_keep_all_pieces(
         &self,
-        mat: Material,
         pos: &Chess,
+        m: &Move,
         winner: Color,
-        flip: bool,
-    ) -> std::io::Result<Outcome>;
+    ) -> io::Result<Outcome> {
+        let mut pos_after_move = pos.clone();
+        pos_after_move.play_unchecked(m);
+        let winner_pieces = |p: &Chess| p.board().by_color(winner).count();
+        if winner_pieces(&pos_after_move) < winner_pieces(pos) {
+            // the winner cannot win anymore, but can still be mated
+            Ok(std::cmp::min(
+                self.retrieve_outcome(&pos_after_move, winner)?,
+                Outcome::Draw,
+            ))
+        } else {
+            Tables(&self.tables_with_condition).retrieve_outcome(&pos_after_move, winner)
+        }
+    }
 
-    /// Returns the distance to helpmate in the descendant table, or panics
-    fn retrieve_outcome(&self, pos: &Chess, winner: Color) -> std::io::Result<Outcome> {
-        let flip = is_black_stronger(pos.board());
-        let mat = Material::from_board(pos.board());
-        // special case for material config known to be draw in every position
-        if mat.count() == 2 || mat == KB_K || mat == KN_K {
-            return Ok(Outcome::Draw);
+    fn outcome_after_move_mated_king_on(
+        &self,
+        pos: &Chess,
+        m: &Move,
+        winner: Color,
+    ) -> io::Result<Outcome> {
+        let mut pos_after_move = pos.clone();
+        pos_after_move.play_unchecked(m);
+        match Router::route_pos(&pos_after_move, winner) {
+            Some(route) if route.mat_win.material.can_mate(route.mat_win.winner) => {
+                Tables(&self.tables_with_condition).raw_access_outcome(&route, &pos_after_move)
+            }
+            // the winner cannot win anymore, but can still be mated
+            _ => Ok(std::cmp::min(
+                self.retrieve_outcome(&pos_after_move, winner)?,
+                Outcome::Draw,
+            )),
         }
-        self.raw_access_outcome(mat, pos, winner, flip)
     }
 }
 
-impl<T: Indexer> RetrieveOutcome for Descendants<T> {
-    fn raw_access_outcome(
+/// Lookup of a position in the tables, the only method implementors of `RetrieveOutcome` provide.
+/// Not part of the public API, which keeps `RetrieveOutcome` sealed
+pub trait RawAccessOutcome {
+    /// Outcome of `pos` in the table given by `route`, see `Router`
+    fn raw_access_outcome(&self, route: &Route, pos: &impl SideToMove) -> std::io::Result<Outcome>;
+}
+
+impl<T: RawAccessOutcome> RetrieveOutcome for T {}
+
+pub trait RetrieveOutcome: RawAccessOutcome {
+    /// Returns the distance to helpmate in the descendant table.
+    /// Fails if the index of `pos` is out of the table, see `FileHandler::outcome_at`
+    /// Accepts any `SideToMove`, so for example a raw `(Board, Color)` can be probed without building a `Chess`.
+    /// In that case no legality check is performed, the position is assumed to be legal
+    /// (eg the side not to move is not in check), and castling rights and en passant square are ignored,
+    /// as they are not part of the tables.
+    fn retrieve_outcome(&self, pos: &impl SideToMove, winner: Color) -> std::io::Result<Outcome> {
+        // no route for material config known to be draw in every position
+        Router::route_pos(pos, winner).map_or(Ok(Outcome::Draw), |route| {
+            self.raw_access_outcome(&route, pos)
+        })
+    }
+
+    /// Among `moves`, return the one leading to the best outcome, see `Outcome::best_of`, along with the outcome
+    /// of the position **after** the move has been played.
+    /// `None` if `moves` is empty
+    fn best_move_among(
+        &self,
+        pos: &Chess,
+        moves: MoveList,
+        winner: Color,
+    ) -> std::io::Result<Option<(Move, Outcome)>> {
+        process_results(
+            moves.into_iter().map(|chess_move| {
+                let mut pos_after_move = pos.clone();
+                pos_after_move.play_unchecked(&chess_move);
+                self.retrieve_outcome(&pos_after_move, winner)
+                    .map(|outcome| (chess_move, outcome))
+            }),
+            |iter| {
+                let moves_and_outcomes: Vec<_> = iter.collect();
+                let best =
+                    Outcome::best_of(moves_and_outcomes.iter().map(|(_, outcome)| *outcome))?;
+                // the last of the best ones, as `Iterator::max_by_key` does
+                moves_and_outcomes
+                    .into_iter()
+                    .rfind(|(_, outcome)| *outcome == best)
+            },
+        )
+    }
+
+    /// For the given position, compute all moves that are either captures and/or promotion,
+    /// and return the best one along with the outcome of `pos` if it is played.
+    /// `None` if there is no capture nor promotion available
+    fn best_capture_promotion(
         &self,
-        mat: Material,
         pos: &Chess,
         winner: Color,
-        flip: bool,
-    ) -> std::io::Result<Outcome> {
-        let mat_win = MaterialWinner::new(&mat, winner ^ flip);
+    ) -> std::io::Result<Option<(Move, Outcome)>> {
+        let mut moves = pos.legal_moves();
+        moves.retain(|m| m.is_capture() || m.is_promotion());
+        self.best_move_among(pos, moves, winner)
+            // we are one move further from the max, unless the outcome is not known
+            .map(|best| best.map(|(m, o)| (m, if o == Outcome::Unknown { o } else { o + 1 })))
+    }
+}
+
+impl<T: Indexer> RawAccessOutcome for Descendants<T> {
+    fn raw_access_outcome(&self, route: &Route, pos: &impl SideToMove) -> std::io::Result<Outcome> {
+        Tables(&self.tables).raw_access_outcome(route, pos)
+    }
+}
+
+impl<T: Indexer> RawAccessOutcome for Tables<'_, T> {
+    fn raw_access_outcome(&self, route: &Route, pos: &impl SideToMove) -> std::io::Result<Outcome> {
         let table_file = self
             .0
-            .get(&mat_win)
-            .expect("Position to be among descendants");
-        let idx = table_file.indexer.encode(pos).usize();
-        Ok(table_file.outcomes[idx].get_by_color(pos.turn() ^ flip))
+            .get(&route.mat_win)
+            .expect("Position to be among descendants, generated with the win condition if any");
+        let idx = IndexWithTurn {
+            idx: table_file.indexer.encode(pos).idx,
+            turn: route.turn,
+        };
+        table_file.outcome_of_table_at(Some(&route.mat_win), idx)
     }
 }
 
@@ -174,6 +1104,7 @@ mod tests {
     use paste::paste;
 
     use super::*;
+    use crate::{compression::to_usize, probe::LazyFileHandler, OutcomeU8, TablebaseProber, Wdl};
     use retroboard::shakmaty::{
         fen::Fen,
         CastlingMode::Standard,
@@ -189,7 +1120,7 @@ mod tests {
             ((Material::from_str("KBvKN").unwrap(), Black), "KBvKNb"),
         ] {
             let mat_winner = MaterialWinner::new(&m, c);
-            assert_eq!(format!("{mat_winner:?}"), expected_file_name)
+            assert_eq!(format!("{mat_winner:?}"), expected_file_name);
         }
     }
 
@@ -197,6 +1128,423 @@ mod tests {
         ["..", "table"].iter().collect()
     }
 
+    #[test]
+    fn test_outcome_at() {
+        let mat_win = MaterialWinner::from_str("KQvKw").unwrap();
+        let file_handler: FileHandler = FileHandler::new(&mat_win, &tb_test_dir());
+        let lazy_file_handler: LazyFileHandler = LazyFileHandler::new(&mat_win, &tb_test_dir());
+        let len = file_handler.outcomes.len() as u64;
+        for idx in [0, 1000, len - 1] {
+            for turn in Color::ALL {
+                let idx_with_turn = IndexWithTurn { idx, turn };
+                let outcome = file_handler.outcome_at(idx_with_turn).unwrap();
+                assert_eq!(
+                    outcome,
+                    file_handler.outcomes[to_usize(idx)].get_by_color(turn)
+                );
+                assert_eq!(
+                    lazy_file_handler.outcome_at(idx_with_turn).unwrap(),
+                    outcome
+                );
+            }
+        }
+        let out_of_table = IndexWithTurn {
+            idx: len,
+            turn: White,
+        };
+        let path = TableName::helpmate(&mat_win).path_in(&tb_test_dir());
+        let expected = format!(
+            "index {len} out of the table, max index {}, read from {}",
+            len - 1,
+            path.display()
+        );
+        for err in [
+            file_handler.outcome_at(out_of_table).unwrap_err(),
+            lazy_file_handler.outcome_at(out_of_table).unwrap_err(),
+        ] {
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            assert_eq!(err.to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn test_king_squares() {
+        use retroboard::shakmaty::Square;
+        assert_eq!(KingSquares::new(Bitboard::EMPTY), None);
+        assert_eq!(KingSquares::new(Bitboard::from_square(Square::A1)), None);
+        assert_eq!(
+            KingSquares::new(Bitboard(0x8100_0000_0000_0081)),
+            Some(KingSquares::CORNERS)
+        );
+        let mat_win = MaterialWinner::from_str("KQvKRw").unwrap();
+        let table_name = TableName::new(
+            &mat_win,
+            Objective::Helpmate,
+            WinCondition::MatedKingOn(KingSquares::CORNERS),
+        );
+        assert_eq!(table_name.to_string(), "KQvKR.w.hm.on8100000000000081.dtm");
+        assert_eq!(table_name.legacy(), None);
+        assert_eq!(
+            TableName::from_str("KQvKR.w.hm.on8100000000000081.dtm"),
+            Ok(table_name)
+        );
+        assert!(TableName::from_str("KQvKR.w.hm.on0000000000000001.dtm").is_err());
+        let corner_mate: Chess = Fen::from_ascii("k7/1Q6/1K6/8/8/8/8/8 b - - 0 1".as_bytes())
+            .unwrap()
+            .into_position(Standard)
+            .unwrap();
+        assert!(KingSquares::CORNERS.is_mated_on(&corner_mate, Black));
+        let edge_mate: Chess = Fen::from_ascii("1k6/1Q6/1K6/8/8/8/8/8 b - - 0 1".as_bytes())
+            .unwrap()
+            .into_position(Standard)
+            .unwrap();
+        assert!(edge_mate.is_checkmate());
+        assert!(!KingSquares::CORNERS.is_mated_on(&edge_mate, Black));
+    }
+
+    #[test]
+    fn test_descendant_index_out_of_table() {
+        let mat_win = MaterialWinner::from_str("KQvKw").unwrap();
+        let mut file_handler: FileHandler = FileHandler::new(&mat_win, &tb_test_dir());
+        // as if the table had been generated with an indexer using fewer indexes
+        file_handler.outcomes.truncate(10);
+        let mut descendants: Descendants = Descendants::empty();
+        descendants
+            .tables
+            .insert(mat_win.clone(), Arc::new(file_handler));
+        let chess: Chess = Fen::from_ascii("8/8/8/8/8/2k5/8/KQ6 w - - 0 1".as_bytes())
+            .unwrap()
+            .into_position(Standard)
+            .unwrap();
+        let err = descendants.retrieve_outcome(&chess, White).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let msg = err.to_string();
+        assert!(msg.contains("out of the KQvKw table, max index 9"), "{msg}");
+    }
+
+    #[test]
+    fn test_selftest() {
+        let mat_win = MaterialWinner::from_str("KQvKw").unwrap();
+        let mut file_handler: FileHandler =
+            FileHandler::open_with_selftest(&mat_win, &tb_test_dir(), 1000).unwrap();
+        // corrupted table, where most entries claim a checkmate
+        for by_color in &mut file_handler.outcomes {
+            for turn in Color::ALL {
+                if by_color.get_by_color(turn) != Outcome::Undefined {
+                    *by_color.get_mut(turn) = OutcomeU8::from(Outcome::Win(0));
+                }
+            }
+        }
+        assert!(file_handler.selftest(&mat_win, 1000).is_err());
+        let missing = MaterialWinner::from_str("KRRvKw").unwrap();
+        let err = FileHandler::<DefaultIndexer>::open_with_selftest(&missing, &tb_test_dir(), 1000)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_block_ranges() {
+        let mat_win = MaterialWinner::from_str("KQvKw").unwrap();
+        let file_handler: FileHandler = FileHandler::new(&mat_win, &tb_test_dir());
+        let lazy_file_handler: LazyFileHandler = LazyFileHandler::new(&mat_win, &tb_test_dir());
+        let block_ranges = file_handler.block_ranges().unwrap();
+        assert_eq!(block_ranges, vec![0..file_handler.outcomes.len() as u64]);
+        assert_eq!(lazy_file_handler.block_ranges().unwrap(), block_ranges);
+        let mut outcomes = Outcomes::new();
+        for range in block_ranges {
+            for outcomes_range in lazy_file_handler.outcomes_in_range(range).unwrap() {
+                outcomes.extend_from_slice(&outcomes_range);
+            }
+        }
+        assert_eq!(outcomes, file_handler.outcomes);
+        // a sub-range of a block
+        let outcomes_ranges = lazy_file_handler.outcomes_in_range(100..200).unwrap();
+        assert_eq!(*outcomes_ranges[0], file_handler.outcomes[100..200]);
+        // blocks smaller than the ones written by `EncoderDecoder::compress`
+        let tmp = tempfile::tempdir().unwrap();
+        std::fs::write(
+            tmp.path().join(TableName::helpmate(&mat_win).to_string()),
+            crate::compression::table_bytes_with_block_len(&outcomes, 10_000),
+        )
+        .unwrap();
+        let small_blocks: FileHandler = FileHandler::new(&mat_win, tmp.path());
+        assert_eq!(
+            small_blocks.block_ranges().unwrap(),
+            vec![0..10_000, 10_000..20_000, 20_000..29_560]
+        );
+        let in_memory: FileHandler = FileHandler {
+            path: None,
+            ..small_blocks
+        };
+        assert_eq!(
+            in_memory.block_ranges().unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+    }
+
+    #[test]
+    fn test_table_name() {
+        let mat_win = MaterialWinner::from_str("KQvKRw").unwrap();
+        for (objective, win_condition, expected, legacy) in [
+            (
+                Objective::Helpmate,
+                WinCondition::AnyMate,
+                "KQvKR.w.hm.dtm",
+                "KQvKRw",
+            ),
+            (
+                Objective::Helpmate,
+                WinCondition::KeepAllPieces,
+                "KQvKR.w.hm.keep.dtm",
+                "KQvKRw-keep",
+            ),
+            (
+                Objective::Selfmate,
+                WinCondition::AnyMate,
+                "KQvKR.w.sm.dtm",
+                "KQvKRw-self",
+            ),
+        ] {
+            let table_name = TableName::new(&mat_win, objective, win_condition);
+            assert_eq!(table_name.to_string(), expected);
+            assert_eq!(table_name.legacy().as_deref(), Some(legacy));
+            assert_eq!(TableName::from_str(expected), Ok(table_name.clone()));
+            assert_eq!(TableName::from_str(legacy), Ok(table_name));
+        }
+        let wdl = TableName::from_str("KQvKR.b.hm.wdl").unwrap();
+        assert_eq!(wdl.metric, Metric::Wdl);
+        assert_eq!(wdl.legacy(), None);
+        for invalid in [
+            "KQvKRw.tmp",
+            "KQvKR.w.hm.dtm.tmp",
+            "KQvKR.w.xx.dtm",
+            "KQvKRw.json",
+            "",
+        ] {
+            assert!(TableName::from_str(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_table_name_path_in() {
+        let mat_win = MaterialWinner::from_str("KQvKw").unwrap();
+        let table_name = TableName::helpmate(&mat_win);
+        // test tables use the legacy names
+        assert_eq!(
+            table_name.path_in(&tb_test_dir()),
+            tb_test_dir().join("KQvKw")
+        );
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        std::fs::copy(tb_test_dir().join("KQvKw"), dir.join("KQvKw")).unwrap();
+        std::fs::copy(tb_test_dir().join("KQvKw"), dir.join("KQvK.w.hm.dtm")).unwrap();
+        assert_eq!(table_name.path_in(dir), dir.join("KQvK.w.hm.dtm"));
+        let file_handler: FileHandler = FileHandler::new(&mat_win, dir);
+        assert_eq!(
+            file_handler.outcomes,
+            FileHandler::<DefaultIndexer>::new(&mat_win, &tb_test_dir()).outcomes
+        );
+        assert!(
+            !TableName::new(&mat_win, Objective::Selfmate, WinCondition::AnyMate).exists_in(dir)
+        );
+    }
+
+    #[test]
+    fn test_truncated_table() {
+        let mat_win = MaterialWinner::from_str("KQvKw").unwrap();
+        let table_name = TableName::helpmate(&mat_win);
+        let material = &mat_win.material;
+        let expected_min = DefaultIndexer::from(material.clone()).min_table_len(material);
+        let outcomes = FileHandler::<DefaultIndexer>::new(&mat_win, &tb_test_dir()).outcomes;
+        assert!(
+            (expected_min..=DefaultIndexer::default().index_space(material))
+                .contains(&(outcomes.len() as u64))
+        );
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        // as if the last blocks had never been written
+        EncoderDecoder::new(std::fs::File::create(dir.join(table_name.to_string())).unwrap())
+            .compress(&outcomes[..20_000])
+            .unwrap();
+        let truncated = TableFormatError::TruncatedTable {
+            len: 20_000,
+            expected_min,
+        };
+        let err = FileHandler::<DefaultIndexer>::try_new_with_limits(
+            &table_name,
+            dir,
+            &ResourceLimits::default(),
+        )
+        .unwrap_err();
+        assert_eq!(TableFormatError::of(&err), Some(&truncated));
+        let err = FileHandler::<DefaultIndexer>::try_new_streaming(&table_name, dir, |_, _| ())
+            .unwrap_err();
+        assert_eq!(TableFormatError::of(&err), Some(&truncated));
+        let err = LazyFileHandler::<DefaultIndexer>::try_new(&mat_win, dir).unwrap_err();
+        assert_eq!(TableFormatError::of(&err), Some(&truncated));
+        let err = FileHandler::<DefaultIndexer>::open_with_selftest(&mat_win, dir, 10).unwrap_err();
+        assert_eq!(TableFormatError::of(&err), Some(&truncated));
+        let err = TablebaseProber::<DefaultIndexer>::try_new(material, dir).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("truncated table"), "{err}");
+    }
+
+    #[test]
+    fn test_table_path() {
+        let mat_win = MaterialWinner::from_str("KQvKw").unwrap();
+        let table_name = TableName::helpmate(&mat_win);
+        let table_path = TablePath::new(tb_test_dir());
+        // read under the legacy name, but always written under the current one
+        assert_eq!(table_path.table(&table_name), tb_test_dir().join("KQvKw"));
+        assert_eq!(
+            table_path.table_to_write(&table_name),
+            tb_test_dir().join("KQvK.w.hm.dtm")
+        );
+        let dir = Path::new("tables").join("sub dir");
+        let table_path = TablePath::new(&dir);
+        assert_eq!(table_path.dir(), dir);
+        for (path, file_name) in [
+            (table_path.win_within(&mat_win, 5), "KQvKw.win5"),
+            (table_path.fortress(&mat_win), "KQvKw.fortress"),
+            (table_path.metadata(&table_name), "KQvK.w.hm.dtm.json"),
+            (
+                table_path.interrupted(&table_name),
+                "KQvK.w.hm.dtm.interrupted",
+            ),
+            (
+                table_path.checkpoint(&table_name),
+                "KQvK.w.hm.dtm.checkpoint",
+            ),
+            (table_path.quarantine_report(&mat_win), "KQvKw.json"),
+            (table_path.anomalies(), "anomalies.jsonl"),
+        ] {
+            assert_eq!(path.parent(), Some(dir.as_path()));
+            assert_eq!(
+                path.file_name().and_then(|name| name.to_str()),
+                Some(file_name)
+            );
+        }
+    }
+
+    #[test]
+    fn test_table_cache_shared_across_descendants() {
+        let table_cache: TableCache = TableCache::default();
+        let kqvkr = MaterialWinner::from_str("KQvKRw").unwrap();
+        let first: Descendants = Descendants::new_with_win_condition(
+            &kqvkr,
+            &tb_test_dir(),
+            WinCondition::AnyMate,
+            Some(&table_cache),
+        );
+        let loaded = table_cache.len();
+        assert!(loaded > 0);
+        let second: Descendants = Descendants::new_with_win_condition(
+            &kqvkr,
+            &tb_test_dir(),
+            WinCondition::AnyMate,
+            Some(&table_cache),
+        );
+        assert_eq!(table_cache.len(), loaded);
+        let kqvk = MaterialWinner::from_str("KQvKw").unwrap();
+        assert!(Arc::ptr_eq(&first.tables[&kqvk], &second.tables[&kqvk]));
+        let kqvk_name = TableName::new(&kqvk, Objective::Helpmate, WinCondition::AnyMate);
+        table_cache.release_except([&kqvk_name], &tb_test_dir());
+        assert_eq!(table_cache.len(), 1);
+        table_cache.release_except([], &tb_test_dir());
+        assert!(table_cache.is_empty());
+    }
+
+    #[test]
+    fn test_new_wdl_only() {
+        let mat_win = MaterialWinner::new(&Material::from_str("KQvK").unwrap(), White);
+        let file_handler: FileHandler = FileHandler::new(&mat_win, &tb_test_dir());
+        let wdl_file_handler: FileHandler<DefaultIndexer, WdlOutcomes> =
+            FileHandler::new_wdl_only(&mat_win, &tb_test_dir());
+        assert_eq!(file_handler.outcomes.len(), wdl_file_handler.outcomes.len());
+        for (outcome, wdl) in file_handler
+            .outcomes
+            .iter()
+            .zip(wdl_file_handler.outcomes.iter())
+        {
+            for color in Color::ALL {
+                assert_eq!(
+                    Wdl::from(Outcome::from(outcome.get(color))),
+                    *wdl.get(color)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_new_dense() {
+        let mat_win = MaterialWinner::from_str("KQvKRw").unwrap();
+        let file_handler: FileHandler = FileHandler::new(&mat_win, &tb_test_dir());
+        let dense_file_handler: FileHandler<DefaultIndexer, DenseOutcomes> =
+            FileHandler::new_dense(&mat_win, &tb_test_dir());
+        assert_eq!(
+            dense_file_handler.outcomes.iter().collect::<Outcomes>(),
+            file_handler.outcomes
+        );
+        // indexes of pieces sharing a square, among others, are not stored
+        assert!(dense_file_handler.outcomes.nb_defined() < file_handler.outcomes.len() * 9 / 10);
+        let idx = IndexWithTurn {
+            idx: dense_file_handler.outcomes.select(1000).unwrap() as u64,
+            turn: White,
+        };
+        assert_eq!(
+            dense_file_handler.outcome_at(idx).unwrap(),
+            file_handler.outcome_at(idx).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_try_new_with_limits() {
+        let table_name = TableName::helpmate(&MaterialWinner::from_str("KQvKw").unwrap());
+        let limits = |max_ram_bytes| ResourceLimits {
+            max_ram_bytes: Some(max_ram_bytes),
+            ..Default::default()
+        };
+        // 29560 indexes of 2 bytes
+        let file_handler: FileHandler =
+            FileHandler::try_new_with_limits(&table_name, &tb_test_dir(), &limits(59120)).unwrap();
+        assert_eq!(file_handler.outcomes.len(), 29560);
+        let err = FileHandler::<DefaultIndexer>::try_new_with_limits(
+            &table_name,
+            &tb_test_dir(),
+            &limits(59119),
+        )
+        .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::OutOfMemory);
+        let missing = TableName::helpmate(&MaterialWinner::from_str("KRRvKw").unwrap());
+        assert!(FileHandler::<DefaultIndexer>::try_new_with_limits(
+            &missing,
+            &tb_test_dir(),
+            &ResourceLimits::default()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_try_new_streaming() {
+        let table_name = TableName::helpmate(&MaterialWinner::from_str("KQvKw").unwrap());
+        let mut streamed = Outcomes::new();
+        let mut last_progress = DecompressProgress::default();
+        let file_handler: FileHandler =
+            FileHandler::try_new_streaming(&table_name, &tb_test_dir(), |block, progress| {
+                assert_eq!(block.index_from(), streamed.len() as u64);
+                streamed.extend_from_slice(block);
+                last_progress = progress;
+            })
+            .unwrap();
+        assert_eq!(streamed, file_handler.outcomes);
+        assert_eq!(last_progress.next_idx, 29560);
+        assert_eq!(last_progress.blocks_done, 1);
+        assert_eq!(
+            file_handler.outcomes,
+            FileHandler::<DefaultIndexer>::new(&table_name.mat_win, &tb_test_dir()).outcomes
+        );
+    }
+
     fn check_pos(fen: &str, outcome: Outcome, desired_are_all_moves_capture: bool, winner: Color) {
         let chess: Chess = Fen::from_ascii(fen.as_bytes())
             .unwrap()
@@ -206,6 +1554,7 @@ mod tests {
         let descendants: Descendants = Descendants::new(&mat_win, &tb_test_dir());
         let (fetched_outcome, are_all_moves_capture) = descendants
             .outcome_from_captures_promotion(&chess, winner)
+            .unwrap()
             .unwrap();
         assert_eq!(fetched_outcome, outcome);
         assert_eq!(desired_are_all_moves_capture, are_all_moves_capture);
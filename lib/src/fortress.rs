@@ -0,0 +1,251 @@
+use std::io::{self, Read, Write};
+
+use retroboard::shakmaty::{ByColor, Color};
+
+use crate::{Common, DeIndexer, IndexWithTurn, Indexer, Outcome, SideToMoveGetter};
+
+/// Why a position is drawn, once both sides are also allowed to "pass" (null move) when not in check,
+/// passing being equivalent to playing on the same board with the other side to move
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DrawKind {
+    /// Still drawn with passes, the defender does not rely on zugzwang to avoid cooperation
+    Fortress = 1,
+    /// A mate can be reached with passes, the draw depends on who has to move
+    ZugzwangDependent = 2,
+    /// Still drawn with passes, but the side to move cannot pass right away because it is in check
+    NoPass = 3,
+}
+
+impl DrawKind {
+    fn from_u2(u: u8) -> Option<Self> {
+        match u {
+            1 => Some(Self::Fortress),
+            2 => Some(Self::ZugzwangDependent),
+            3 => Some(Self::NoPass),
+            _ => None,
+        }
+    }
+}
+
+/// Sidecar table classifying drawn positions, see `DrawKind`.
+/// One byte by index, 2 bits by side to move, white to move in the low bits
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FortressTable(Vec<u8>);
+
+impl FortressTable {
+    /// Pass-move analysis on a tagged table, see `pass_reachable`
+    #[must_use]
+    pub fn from_common(common: &Common) -> Self {
+        Self::new(&common.all_pos, &pass_reachable(common))
+    }
+
+    fn new<T>(outcomes: &[ByColor<T>], pass_reachable: &[u8]) -> Self
+    where
+        ByColor<T>: SideToMoveGetter,
+    {
+        Self(
+            outcomes
+                .iter()
+                .zip(pass_reachable)
+                .map(|(by_color, reachable)| {
+                    let kind = |turn: Color| {
+                        if by_color.get_outcome_by_color(turn) != Outcome::Draw {
+                            return 0;
+                        }
+                        (if reachable & turn_bit(turn) != 0 {
+                            DrawKind::ZugzwangDependent
+                        } else if by_color.get_outcome_by_color(!turn) == Outcome::Undefined {
+                            DrawKind::NoPass
+                        } else {
+                            DrawKind::Fortress
+                        }) as u8
+                    };
+                    kind(Color::White) | kind(Color::Black) << 2
+                })
+                .collect(),
+        )
+    }
+
+    /// `None` if the position is not drawn
+    #[must_use]
+    pub fn draw_kind(&self, idx: IndexWithTurn) -> Option<DrawKind> {
+        let shift = if idx.turn.is_white() { 0 } else { 2 };
+        DrawKind::from_u2((self.0[idx.usize()] >> shift) & 0b11)
+    }
+
+    /// Number of drawn positions of each kind, `[Fortress, ZugzwangDependent, NoPass]`
+    #[must_use]
+    pub fn counts(&self) -> [usize; 3] {
+        let mut counts = [0; 3];
+        for byte in &self.0 {
+            for shift in [0, 2] {
+                if let Some(kind) = DrawKind::from_u2((byte >> shift) & 0b11) {
+                    counts[kind as usize - 1] += 1;
+                }
+            }
+        }
+        counts
+    }
+
+    pub fn write(&self, writer: impl Write) -> io::Result<()> {
+        zstd::stream::copy_encode(self.0.as_slice(), writer, 21)
+    }
+
+    pub fn read(reader: impl Read) -> io::Result<Self> {
+        zstd::stream::decode_all(reader).map(Self)
+    }
+}
+
+fn turn_bit(turn: Color) -> u8 {
+    if turn.is_white() {
+        0b01
+    } else {
+        0b10
+    }
+}
+
+/// Positions from which a mate can be reached once passing is allowed, one bit by side to move.
+/// Same retrograde analysis as the tagger, starting from the positions won or lost, with an additional
+/// "unpass" to the same board with the other side to move. As the board is left untouched the side
+/// passing is never in check, the position being legal. Positions of the descendants keep their
+/// outcome, passes are only considered within the table
+fn pass_reachable(common: &Common) -> Vec<u8> {
+    let mut reachable = vec![0; common.all_pos.len()];
+    let mut stack = Vec::new();
+    for (idx, by_color) in common.all_pos.iter().enumerate() {
+        for turn in Color::ALL {
+            if matches!(
+                by_color.get_outcome_by_color(turn),
+                Outcome::Win(_) | Outcome::Lose(_)
+            ) {
+                reachable[idx] |= turn_bit(turn);
+                stack.push(IndexWithTurn {
+                    idx: idx as u64,
+                    turn,
+                });
+            }
+        }
+    }
+    while let Some(idx_with_turn) = stack.pop() {
+        let rboard = common.indexer.restore(common.material(), idx_with_turn);
+        let unpass = IndexWithTurn {
+            idx: idx_with_turn.idx,
+            turn: !idx_with_turn.turn,
+        };
+        let unmoves = rboard.legal_unmoves().into_iter().map(|m| {
+            let mut rboard_after_unmove = rboard.clone();
+            rboard_after_unmove.push(&m);
+            common.indexer.encode(&rboard_after_unmove)
+        });
+        for pred in std::iter::once(unpass).chain(unmoves) {
+            let bit = turn_bit(pred.turn);
+            if reachable[pred.usize()] & bit == 0
+                && common.all_pos[pred.usize()].get_outcome_by_color(pred.turn) == Outcome::Draw
+            {
+                reachable[pred.usize()] |= bit;
+                stack.push(pred);
+            }
+        }
+    }
+    reachable
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MaterialWinner, OutcomeU8, TableBaseBuilder};
+    use retroboard::shakmaty::{fen::Fen, CastlingMode, Chess};
+    use std::{path::PathBuf, str::FromStr};
+
+    fn idx_of(common: &Common, fen: &str) -> IndexWithTurn {
+        let chess: Chess = Fen::from_ascii(fen.as_bytes())
+            .unwrap()
+            .into_position(CastlingMode::Standard)
+            .unwrap();
+        common.indexer.encode(&chess)
+    }
+
+    #[test]
+    fn test_fortress_table() {
+        let by_color = |white: Outcome, black: Outcome| ByColor {
+            white: OutcomeU8::from(white),
+            black: OutcomeU8::from(black),
+        };
+        let outcomes = vec![
+            by_color(Outcome::Draw, Outcome::Draw),
+            by_color(Outcome::Draw, Outcome::Win(3)),
+            by_color(Outcome::Undefined, Outcome::Draw),
+            by_color(Outcome::Lose(2), Outcome::Win(1)),
+        ];
+        // the white draw of index 1 is one pass away from a win
+        let table = FortressTable::new(&outcomes, &[0b00, 0b01, 0b00, 0b11]);
+        let kind = |idx: u64, turn: Color| table.draw_kind(IndexWithTurn { idx, turn });
+        assert_eq!(kind(0, Color::White), Some(DrawKind::Fortress));
+        assert_eq!(kind(0, Color::Black), Some(DrawKind::Fortress));
+        assert_eq!(kind(1, Color::White), Some(DrawKind::ZugzwangDependent));
+        assert_eq!(kind(1, Color::Black), None);
+        assert_eq!(kind(2, Color::White), None);
+        assert_eq!(kind(2, Color::Black), Some(DrawKind::NoPass));
+        assert_eq!(kind(3, Color::White), None);
+        assert_eq!(table.counts(), [2, 1, 1]);
+
+        let mut buf = Vec::new();
+        table.write(&mut buf).unwrap();
+        assert_eq!(FortressTable::read(buf.as_slice()).unwrap(), table);
+    }
+
+    #[test]
+    fn test_from_common() {
+        let tb_dir: PathBuf = ["..", "table"].iter().collect();
+        let (common, _) =
+            TableBaseBuilder::build(MaterialWinner::from_str("KRvKw").unwrap(), &tb_dir).unwrap();
+        let table = FortressTable::from_common(&common);
+        // Kxb7 is forced, but after passing white can move the rook away
+        let kind = |fen| table.draw_kind(idx_of(&common, fen));
+        assert_eq!(
+            kind("k7/1R6/8/8/8/8/8/1K6 b - - 0 1"),
+            Some(DrawKind::ZugzwangDependent)
+        );
+        // in check, Kxa2 is forced
+        assert_eq!(
+            kind("8/8/8/8/8/8/R7/k1K5 b - - 0 1"),
+            Some(DrawKind::NoPass)
+        );
+        assert_eq!(kind("k6R/8/1K6/8/8/8/8/8 b - - 0 1"), None);
+        assert_eq!(table.counts(), [0, 49, 14]);
+    }
+
+    #[test]
+    fn test_pass_reachable() {
+        let tb_dir: PathBuf = ["..", "table"].iter().collect();
+        let (mut common, _) =
+            TableBaseBuilder::build(MaterialWinner::from_str("KRvKw").unwrap(), &tb_dir).unwrap();
+        // only keep one mate, every other legal position being a draw
+        let mate = idx_of(&common, "k6R/8/1K6/8/8/8/8/8 b - - 0 1");
+        for by_color in &mut common.all_pos {
+            for turn in Color::ALL {
+                if by_color.get_outcome_by_color(turn) != Outcome::Undefined {
+                    *by_color.get_mut(turn) = OutcomeU8::from(Outcome::Draw);
+                }
+            }
+        }
+        *common.all_pos[mate.usize()].get_mut(mate.turn) = OutcomeU8::from(Outcome::Win(0));
+        let table = FortressTable::from_common(&common);
+        let kind = |fen| table.draw_kind(idx_of(&common, fen));
+        // Rh8# is one move away
+        assert_eq!(
+            kind("k7/7R/1K6/8/8/8/8/8 w - - 0 1"),
+            Some(DrawKind::ZugzwangDependent)
+        );
+        // black has to pass first, while the same board with white to move is a draw as well
+        assert_eq!(
+            kind("k7/7R/1K6/8/8/8/8/8 b - - 0 1"),
+            Some(DrawKind::ZugzwangDependent)
+        );
+        // many moves and passes away
+        assert_eq!(
+            kind("8/8/8/8/4k3/8/8/K1R5 w - - 0 1"),
+            Some(DrawKind::ZugzwangDependent)
+        );
+    }
+}
@@ -1,20 +1,31 @@
 use crate::{
-    indexer::{DeIndexer, Indexer, A1_D1_D4},
-    Common, DefaultReversibleIndexer, Descendants, Material, MaterialWinner, Outcome, OutcomeU8,
-    Report, ReportU8, A1_H8_DIAG, UNDEFINED_OUTCOME_BYCOLOR,
+    checkpoint::Checkpoint,
+    common::{get_estimate_nb_pos, AnomalySink, WinObjective},
+    compression::to_usize,
+    file_handler::{Objective, RetrieveOutcome, TableCache, TableName, TablePath, WinCondition},
+    indexer::{DeIndexer, Indexer, A1_D1_D4, A_FILE_TO_D_FILE},
+    invariants::debug_check_reports,
+    limits::table_ram_bytes,
+    outcome::iter_defined,
+    Common, DefaultIndexer, DefaultReversibleIndexer, Descendants, EncoderDecoder, Material,
+    MaterialWinner, Outcome, OutcomeU8, OutcomesSlice, Pieces, ProcessedBits, Report,
+    ResourceLimits, UndefinedSpans, A1_H8_DIAG, UNDEFINED_OUTCOME_BYCOLOR,
 };
-use log::{debug, warn};
+use itertools::process_results;
+use log::{debug, info, warn};
 use retroboard::shakmaty::{
-    Bitboard, Board, ByColor, CastlingMode,
-    CastlingMode::Standard,
-    Chess,
+    Bitboard, Board, ByColor, CastlingMode, Chess,
     Color::{self, White},
-    FromSetup, Outcome as ChessOutcome, Piece, Position, PositionError, Setup, Square,
+    FromSetup, Outcome as ChessOutcome, Piece, Position, PositionError, Role, Setup, Square,
 };
 use retroboard::RetroBoard;
-use std::path::Path;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
-use indicatif::ProgressBar;
+use indicatif::{MultiProgress, ProgressBar};
 
 pub trait WithBoard {
     fn board(&self) -> &Board;
@@ -26,7 +37,7 @@ impl WithBoard for Board {
     }
 }
 
-impl<'a> WithBoard for &'a Board {
+impl WithBoard for &Board {
     fn board(&self) -> &Board {
         self
     }
@@ -50,7 +61,7 @@ impl WithBoard for (Board, Color) {
     }
 }
 
-impl<'a> WithBoard for (&'a Board, Color) {
+impl WithBoard for (&Board, Color) {
     fn board(&self) -> &Board {
         self.0
     }
@@ -80,7 +91,7 @@ impl SideToMove for (Board, Color) {
     }
 }
 
-impl<'a> SideToMove for (&'a Board, Color) {
+impl SideToMove for (&Board, Color) {
     fn side_to_move(&self) -> Color {
         self.1
     }
@@ -97,21 +108,6 @@ pub trait SideToMoveGetter {
     fn set_to(&mut self, pos: &impl SideToMove, t: Self::T);
 }
 
-impl SideToMoveGetter for ByColor<ReportU8> {
-    type T = Report;
-    fn get_by_color(&self, color: Color) -> Self::T {
-        self.get(color).into()
-    }
-
-    fn get_outcome_by_color(&self, color: Color) -> Outcome {
-        self.get_by_color(color).outcome()
-    }
-    fn set_to(&mut self, pos: &impl SideToMove, t: Self::T) {
-        let x_mut = self.get_mut(pos.side_to_move());
-        *x_mut = t.into();
-    }
-}
-
 impl SideToMoveGetter for ByColor<OutcomeU8> {
     type T = Outcome;
     fn get_by_color(&self, color: Color) -> Self::T {
@@ -128,6 +124,8 @@ impl SideToMoveGetter for ByColor<OutcomeU8> {
     }
 }
 
+/// Entry of a table: the index of a position, shared by both sides to move, and the side to move.
+/// Indexes are only meaningful for the table they come from
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct IndexWithTurn {
     pub idx: u64,
@@ -157,11 +155,65 @@ pub trait PosHandler<I> {
         idx: IndexWithTurn,
         all_pos_idx: usize,
     );
+
+    /// Report `handle_position` sets for `chess`, computed without any side effect,
+    /// see `Generator::reconcile_duplicate`. `None` for handlers not setting reports
+    fn initial_report(
+        &self,
+        _common: &Common<I>,
+        _tablebase: &Descendants,
+        _chess: &Chess,
+    ) -> Option<Report> {
+        None
+    }
+}
+
+/// Does nothing, see `TableBaseBuilder::build_with_observer`
+impl<I> PosHandler<I> for () {
+    fn handle_position(
+        &mut self,
+        _: &mut Common<I>,
+        _: &Descendants,
+        _: &Chess,
+        _: IndexWithTurn,
+        _: usize,
+    ) {
+    }
+}
+
+/// Both handlers are called on each position, the first one before the second,
+/// eg `(DefaultGeneratorHandler, my_handler)` to run custom analysis alongside the generation
+impl<I, A: PosHandler<I>, B: PosHandler<I>> PosHandler<I> for (A, B) {
+    fn handle_position(
+        &mut self,
+        common: &mut Common<I>,
+        tablebase: &Descendants,
+        chess: &Chess,
+        idx: IndexWithTurn,
+        all_pos_idx: usize,
+    ) {
+        self.0
+            .handle_position(common, tablebase, chess, idx, all_pos_idx);
+        self.1
+            .handle_position(common, tablebase, chess, idx, all_pos_idx);
+    }
+
+    /// The report of the second one, if any, since it is set last
+    fn initial_report(
+        &self,
+        common: &Common<I>,
+        tablebase: &Descendants,
+        chess: &Chess,
+    ) -> Option<Report> {
+        self.1
+            .initial_report(common, tablebase, chess)
+            .or_else(|| self.0.initial_report(common, tablebase, chess))
+    }
 }
 
 /// handler used when generating the helpmate tablebase
 /// another handler can be found in `syzygy_check.rs`
-struct DefaultGeneratorHandler;
+pub struct DefaultGeneratorHandler;
 
 impl<I> PosHandler<I> for DefaultGeneratorHandler {
     fn handle_position(
@@ -172,44 +224,187 @@ impl<I> PosHandler<I> for DefaultGeneratorHandler {
         _: IndexWithTurn,
         all_pos_idx: usize,
     ) {
-        match chess.outcome() {
+        let (report, is_capture_draw) =
+            Self::report(common, tablebase, chess).unwrap_or_else(|e| {
+                common.record_inconsistency(all_pos_idx as u64, chess, e.to_string());
+                (Report::Unprocessed(Outcome::Unknown), false)
+            });
+        if is_capture_draw {
+            common.capture_draws += 1;
+        }
+        common.set_report(all_pos_idx, chess.turn(), report);
+    }
+
+    fn initial_report(
+        &self,
+        common: &Common<I>,
+        tablebase: &Descendants,
+        chess: &Chess,
+    ) -> Option<Report> {
+        Some(
+            Self::report(common, tablebase, chess)
+                .map_or(Report::Unprocessed(Outcome::Unknown), |(report, _)| report),
+        )
+    }
+}
+
+impl DefaultGeneratorHandler {
+    /// Initial report of `chess`, and whether it is a draw because all its legal moves
+    /// are captures/promotions, see `Common::capture_draws`.
+    /// Fails if the outcome of its captures/promotions could not be retrieved
+    fn report<I>(
+        common: &Common<I>,
+        tablebase: &Descendants,
+        chess: &Chess,
+    ) -> io::Result<(Report, bool)> {
+        Ok(match chess.outcome() {
             Some(ChessOutcome::Decisive { winner }) => {
                 // we know the result is exact, since the game is over
-                let outcome = Report::Unprocessed(if winner == common.winner() {
+                let report = if winner != common.winner() {
+                    Report::Unprocessed(Outcome::Lose(0))
+                } else if let WinCondition::MatedKingOn(squares) = tablebase.win_condition() {
                     assert!(common.can_mate());
-                    Outcome::Win(0)
+                    if squares.is_mated_on(chess, !winner) {
+                        Report::Unprocessed(Outcome::Win(0))
+                    } else {
+                        // like a stalemate, no position is won through it
+                        Report::Processed(Outcome::Draw)
+                    }
                 } else {
-                    Outcome::Lose(0)
-                });
-                common.all_pos[all_pos_idx].set_to(chess, outcome);
+                    assert!(common.can_mate());
+                    Report::Unprocessed(Outcome::Win(0))
+                };
+                (report, false)
             }
 
             Some(ChessOutcome::Draw) => {
-                let can_mate = common.can_mate();
-                common.all_pos[all_pos_idx].set_to(
-                    chess,
-                    if !can_mate {
-                        // need to be unprocessed because when we cannot mate
-                        // we work backward from stalemate/captures that lead to insufficient material
-                        Report::Unprocessed(Outcome::Draw)
-                    } else {
-                        Report::Processed(Outcome::Draw)
-                    },
-                );
+                let report = if common.can_mate() {
+                    Report::Processed(Outcome::Draw)
+                } else {
+                    // need to be unprocessed because when we cannot mate
+                    // we work backward from stalemate/captures that lead to insufficient material
+                    Report::Unprocessed(Outcome::Draw)
+                };
+                (report, false)
             }
             None => {
-                let (fetched_outcome, _) = tablebase
-                    .outcome_from_captures_promotion(chess, common.winner())
+                let (fetched_outcome, is_exact) = tablebase
+                    .outcome_from_captures_promotion(chess, common.winner())?
                     .unwrap_or((Outcome::Unknown, false));
-                // fetched outcome should always be unprocessed
-                // in case of **drawn** unreachable positions (and only when we try to win),
-                // those will be left unprocessed
-                common.all_pos[all_pos_idx].set_to(chess, Report::Unprocessed(fetched_outcome));
+                if fetched_outcome == Outcome::Draw && is_exact {
+                    // the draw is exact since there is no other move, nor unknown capture. Like stalemates, it only needs
+                    // to be processed when we cannot mate, otherwise no position is won through it
+                    let report = if common.can_mate() {
+                        Report::Processed(Outcome::Draw)
+                    } else {
+                        Report::Unprocessed(Outcome::Draw)
+                    };
+                    (report, true)
+                } else {
+                    // other draws are a lower bound, and are left unprocessed when we try to win,
+                    // see `Tagger::process_positions`
+                    (Report::Unprocessed(fetched_outcome), false)
+                }
+            }
+        })
+    }
+}
+
+/// handler used when generating selfmate tablebases, see `Objective::Selfmate`
+pub struct SelfmateGeneratorHandler;
+
+impl<I> PosHandler<I> for SelfmateGeneratorHandler {
+    fn handle_position(
+        &mut self,
+        common: &mut Common<I>,
+        tablebase: &Descendants,
+        chess: &Chess,
+        _: IndexWithTurn,
+        all_pos_idx: usize,
+    ) {
+        let report = Self::report(common, tablebase, chess).unwrap_or_else(|e| {
+            common.record_inconsistency(all_pos_idx as u64, chess, e.to_string());
+            Report::Unprocessed(Outcome::Unknown)
+        });
+        common.set_report(all_pos_idx, chess.turn(), report);
+    }
+
+    fn initial_report(
+        &self,
+        common: &Common<I>,
+        tablebase: &Descendants,
+        chess: &Chess,
+    ) -> Option<Report> {
+        Some(
+            Self::report(common, tablebase, chess).unwrap_or(Report::Unprocessed(Outcome::Unknown)),
+        )
+    }
+}
+
+impl SelfmateGeneratorHandler {
+    /// Fails if the outcome of the captures/promotions of `chess` could not be retrieved
+    fn report<I>(common: &Common<I>, tablebase: &Descendants, chess: &Chess) -> io::Result<Report> {
+        match chess.outcome() {
+            // the winner has been mated, as it wanted
+            Some(ChessOutcome::Decisive { winner }) if winner != common.winner() => {
+                Ok(Report::Unprocessed(Outcome::Win(0)))
             }
+            // the winner mated the loser, or stalemate
+            Some(_) => Ok(Report::Processed(Outcome::Draw)),
+            None => selfmate_report_from_captures_promotion(tablebase, chess, common.winner()),
         }
     }
 }
 
+/// Initial report of a selfmate position, from its captures/promotions.
+/// When the winner is to move, the best of them is a lower bound, as for helpmates.
+/// When the loser is to move, a single one not leading to a selfmate is enough to escape,
+/// otherwise the other moves are checked when tagging, see `Tagger::all_moves_win`.
+/// An `Outcome::Unknown` capture is never an escape, nor does it let the others decide alone
+fn selfmate_report_from_captures_promotion(
+    tablebase: &Descendants,
+    chess: &Chess,
+    winner: Color,
+) -> io::Result<Report> {
+    let mut moves = chess.legal_moves();
+    let all_moves_nb = moves.len();
+    moves.retain(|m| m.is_capture() || m.is_promotion());
+    let are_all_moves_captures = all_moves_nb == moves.len();
+    let outcomes: Vec<Outcome> = process_results(
+        moves.iter().map(|m| {
+            let mut pos_after_move = chess.clone();
+            pos_after_move.play_unchecked(m);
+            tablebase.retrieve_outcome(&pos_after_move, winner)
+        }),
+        |iter| iter.collect(),
+    )?;
+    // an unknown capture could be anything, so the others only decide if they are enough on their own
+    let is_exact = are_all_moves_captures && !outcomes.contains(&Outcome::Unknown);
+    let winner_to_move = chess.turn() == winner;
+    let outcome = if winner_to_move {
+        Outcome::best_of(outcomes)
+    } else {
+        outcomes
+            .into_iter()
+            .filter(|outcome| !matches!(outcome, Outcome::Unknown | Outcome::Undefined))
+            .min()
+    }
+    .filter(|outcome| *outcome != Outcome::Unknown)
+    // we are one move further
+    .map(|outcome| outcome + 1);
+    Ok(match outcome {
+        Some(outcome @ Outcome::Win(_)) if winner_to_move || is_exact => {
+            Report::Unprocessed(outcome)
+        }
+        // the loser escapes, or the winner has no other move
+        Some(outcome) if !matches!(outcome, Outcome::Win(_)) && (!winner_to_move || is_exact) => {
+            Report::Processed(Outcome::Draw)
+        }
+        // the other moves decide
+        _ => Report::Unprocessed(Outcome::Unknown),
+    })
+}
+
 /// Struct that only handle the generation phase of the tablebase building process
 /// See `Tagger` for the backward algorithm part.
 pub struct Generator<T, I> {
@@ -217,8 +412,37 @@ pub struct Generator<T, I> {
     tablebase: Descendants, // access to the DTM of descendants (different material config, following a capture/promotion)
     pb: ProgressBar,
     pos_handler: T,
+    enumeration_order: EnumerationOrder,
 }
 
+/// Order in which pieces are put on the board when enumerating positions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EnumerationOrder {
+    /// Kings, then white pieces, then black pieces
+    #[default]
+    Material,
+    /// Pieces encoded in the most significant digits of the index first, so that writes are more sequential
+    IndexSignificance,
+}
+
+impl EnumerationOrder {
+    fn pieces_without_white_king(self, material: &Material) -> Pieces {
+        let mut pieces = material.pieces_without_white_king();
+        if self == Self::IndexSignificance {
+            // same order as `NaiveIndexer::encode_board_unchecked`, stable sort keeps the black king first
+            pieces.sort_by_key(|piece| {
+                (
+                    piece.role != Role::King,
+                    piece.role,
+                    piece.color == Color::Black,
+                )
+            });
+        }
+        pieces
+    }
+}
+
+#[cfg_attr(not(feature = "unstable-internals"), allow(dead_code))]
 impl<I: Indexer + From<Material>> Generator<DefaultGeneratorHandler, I> {
     #[must_use]
     pub fn new(mat_win: MaterialWinner, tablebase_path: &Path) -> Self {
@@ -227,80 +451,50 @@ impl<I: Indexer + From<Material>> Generator<DefaultGeneratorHandler, I> {
 }
 
 impl<T: PosHandler<I>, I: Indexer + From<Material>> Generator<T, I> {
+    #[cfg_attr(not(feature = "unstable-internals"), allow(dead_code))]
     pub fn new_with_pos_handler(
         pos_handler: T,
         mat_win: MaterialWinner,
         tablebase_dir: &Path,
     ) -> Self {
-        // it's important to initialise `Descendants` before `Common`
-        // because decompressing a table with zstd takes quite some RAM, which we will not have much left
-        // after `Common` being created
         let tablebase = Descendants::new(&mat_win, tablebase_dir);
+        Self::new_with_pos_handler_and_descendants(pos_handler, mat_win, tablebase)
+    }
+
+    /// It's important to initialise `Descendants` before `Common`
+    /// because decompressing a table with zstd takes quite some RAM, which we will not have much left
+    /// after `Common` being created
+    pub fn new_with_pos_handler_and_descendants(
+        pos_handler: T,
+        mat_win: MaterialWinner,
+        tablebase: Descendants,
+    ) -> Self {
         let common: Common<I> = Common::new(mat_win);
         let pb = common.get_progress_bar().with_message("Gen pos");
         Self {
-            pb,
-            tablebase,
             common,
+            tablebase,
+            pb,
             pos_handler,
+            enumeration_order: EnumerationOrder::default(),
         }
     }
 
-    pub fn get_result(self) -> (Common<I>, T) {
-        (self.common, self.pos_handler)
+    #[must_use]
+    pub fn with_enumeration_order(mut self, enumeration_order: EnumerationOrder) -> Self {
+        self.enumeration_order = enumeration_order;
+        self
     }
 
-    fn generate_positions_internal(
-        &mut self,
-        piece_vec: &[Piece],
-        setup: &Setup,
-        last_piece_and_square: (Piece, Square),
-    ) {
-        match piece_vec {
-            [piece, tail @ ..] => {
-                let squares = self.valid_squares(
-                    &setup.board,
-                    *piece,
-                    last_piece_and_square.0,
-                    last_piece_and_square.1,
-                );
-                for sq in squares {
-                    if setup.board.piece_at(sq).is_none() {
-                        let mut new_setup = setup.clone();
-                        new_setup.board.set_piece_at(sq, *piece);
-                        self.generate_positions_internal(tail, &new_setup, (*piece, sq));
-                    }
-                }
-            }
-            [] => self.check_setup(setup),
-        }
+    /// Display the progress bar as part of `multi_progress`, alongside other bars
+    #[must_use]
+    pub fn with_multi_progress(mut self, multi_progress: &MultiProgress) -> Self {
+        self.pb = multi_progress.add(self.pb);
+        self
     }
 
-    #[inline]
-    fn valid_squares(
-        &self,
-        board: &Board,
-        piece: Piece,
-        last_piece: Piece,
-        last_square: Square,
-    ) -> Bitboard {
-        if last_piece == piece {
-            // by convention the former piece put on the board
-            // should have a "higher" square than the later to avoid
-            // generating the same position but with identical pieces swapped
-            (0..last_square.into())
-                .map(unsafe { |sq| Square::new_unchecked(sq) })
-                .collect()
-        }
-        // Do not restrict duplicate pieces as they already have other constraints
-        // and combining with this one resulting in the generating function not to be surjective anymore
-        else if (self.common.material().by_piece(piece) == 1)
-            && A1_H8_DIAG.is_superset(board.occupied())
-        {
-            A1_H1_H8
-        } else {
-            Bitboard::FULL
-        }
+    pub fn get_result(self) -> (Common<I>, T) {
+        (self.common, self.pos_handler)
     }
 
     fn check_setup(&mut self, setup: &Setup) {
@@ -309,31 +503,17 @@ impl<T: PosHandler<I>, I: Indexer + From<Material>> Generator<T, I> {
             let mut valid_setup = setup.clone();
             valid_setup.turn = color;
             self.common.counter += 1;
-            if self.common.counter % 100_000 == 0 {
+            if self.common.counter.is_multiple_of(100_000) {
                 self.pb.set_position(self.common.counter);
             }
-            if let Ok(chess) = to_chess_with_illegal_checks(valid_setup.clone()) {
-                let rboard = RetroBoard::from_setup(valid_setup, Standard)
-                    .expect("if chess is valid then rboard should be too");
-                let idx = self.common.indexer().encode(&rboard); // The position by construction is unfortunately not always canonical, so best to re-check when encoding
-                let all_pos_idx = self.common.indexer().encode(&chess).usize();
-                // if format!("{}", rboard.board().board_fen(Bitboard::EMPTY))
-                //     == "7k/2R5/8/8/3K4/8/8/1R6"
-                // {
-                //     println!("TEST {rboard:?}")
-                // };
-                // if all_pos_idx == 942601 {
-                //     println!("TEST {rboard:?}")
-                // };
+            if let Ok(chess) = to_chess_with_illegal_checks(valid_setup) {
+                // The position by construction is unfortunately not always canonical, so best to re-check when encoding.
+                // A single encoding is needed, since the index of `all_pos` is the one of the indexer
+                let idx = self.common.indexer.encode(&chess);
+                let all_pos_idx = idx.usize();
                 // Check that position is generated for the first time/index schema is injective
-                // We consider the syzygy indexer trusty enough for pawnless positions to allow for
-                // duplicates
-                if Outcome::Undefined
-                    == self.common.all_pos[all_pos_idx]
-                        .get_by_pos(&chess)
-                        .outcome()
-                {
-                    // only handle the position if it's not a duplicate
+                let stored = self.common.report(all_pos_idx, chess.turn());
+                if Outcome::Undefined == stored.outcome() {
                     self.pos_handler.handle_position(
                         &mut self.common,
                         &self.tablebase,
@@ -341,29 +521,67 @@ impl<T: PosHandler<I>, I: Indexer + From<Material>> Generator<T, I> {
                         idx,
                         all_pos_idx,
                     );
-                } else {
-                    assert!(
-                        // In positions without pawns with duplicate pieces, duplicate indexes are tolerated
-                        // because could not find a way to generate positions without those
-                        !self.common.material().has_pawns()
-                            && self.common.material().min_like_man() > 1,
-                        "Index {all_pos_idx} already generated, board: {rboard:?}"
+                } else if self.common.material().has_pawns()
+                    || self.common.material().min_like_man() <= 1
+                {
+                    self.common.record_inconsistency(
+                        all_pos_idx as u64,
+                        &chess,
+                        format!(
+                            "Index {all_pos_idx} already generated, board: {:?}",
+                            RetroBoard::from(chess.clone())
+                        ),
                     );
+                } else {
+                    self.reconcile_duplicate(&chess, all_pos_idx, stored);
                 }
             }
         }
     }
 
+    /// In positions without pawns with like pieces, duplicate indexes are tolerated
+    /// because could not find a way to generate positions without those.
+    /// The duplicate is not handled again, but its initial report must be the one already stored,
+    /// unless one of them is `Outcome::Unknown`, in which case the other, better-defined, is kept
+    fn reconcile_duplicate(&mut self, chess: &Chess, all_pos_idx: usize, stored: Report) {
+        self.common.duplicates += 1;
+        let Some(duplicate) = self
+            .pos_handler
+            .initial_report(&self.common, &self.tablebase, chess)
+        else {
+            return;
+        };
+        if duplicate == stored {
+            return;
+        }
+        let kept = match (stored.outcome(), duplicate.outcome()) {
+            (Outcome::Unknown, _) => duplicate,
+            (_, Outcome::Unknown) => stored,
+            _ => {
+                self.common.record_inconsistency(
+                    all_pos_idx as u64,
+                    chess,
+                    format!(
+                        "Index {all_pos_idx} already generated as {stored:?}, but duplicate is {duplicate:?}, board: {:?}",
+                        RetroBoard::from(chess.clone())
+                    ),
+                );
+                stored
+            }
+        };
+        self.common.reconciled_duplicates += 1;
+        self.common.set_report(all_pos_idx, chess.turn(), kept);
+    }
+
     pub fn generate_positions(&mut self) {
-        let piece_vec = self.common.material().pieces_without_white_king();
+        let material = self.common.material().clone();
         self.common.counter = 0;
         let all_pos_vec_capacity_before_gen = self.common.all_pos.capacity();
         debug!("all_pos_vec capacity before generating: {all_pos_vec_capacity_before_gen}");
-        for white_king_sq in A1_D1_D4 {
-            let mut new_setup = Setup::empty();
-            new_setup.board.set_piece_at(white_king_sq, White.king());
-            self.generate_positions_internal(&piece_vec, &new_setup, (White.king(), white_king_sq));
-        }
+        let enumeration_order = self.enumeration_order;
+        for_each_setup(&material, enumeration_order, &mut |setup| {
+            self.check_setup(setup);
+        });
         self.pb.finish_and_clear();
         let all_pos_vec_capacity_after_gen = self.common.all_pos.capacity();
         debug!("all_pos_vec capacity after generating: {all_pos_vec_capacity_after_gen}");
@@ -376,6 +594,7 @@ impl<T: PosHandler<I>, I: Indexer + From<Material>> Generator<T, I> {
         }
 
         self.common.all_pos.shrink_to_fit();
+        self.common.processed.truncate(self.common.all_pos.len());
         debug!(
             "all_pos_vec capacity: {} after shrinking",
             self.common.all_pos.capacity()
@@ -383,6 +602,113 @@ impl<T: PosHandler<I>, I: Indexer + From<Material>> Generator<T, I> {
     }
 }
 
+/// Call `f` on every setup of `material` up to symmetry, with the side to move not set yet.
+/// Setups can be illegal
+fn for_each_setup(
+    material: &Material,
+    enumeration_order: EnumerationOrder,
+    f: &mut impl FnMut(&Setup),
+) {
+    let piece_vec = enumeration_order.pieces_without_white_king(material);
+    let white_king_squares = if material.has_pawns() {
+        A_FILE_TO_D_FILE
+    } else {
+        A1_D1_D4
+    };
+    for white_king_sq in white_king_squares {
+        let mut new_setup = Setup::empty();
+        new_setup.board.set_piece_at(white_king_sq, White.king());
+        for_each_setup_internal(
+            material,
+            &piece_vec,
+            &new_setup,
+            (White.king(), white_king_sq),
+            f,
+        );
+    }
+}
+
+fn for_each_setup_internal(
+    material: &Material,
+    piece_vec: &[Piece],
+    setup: &Setup,
+    last_piece_and_square: (Piece, Square),
+    f: &mut impl FnMut(&Setup),
+) {
+    match piece_vec {
+        [piece, tail @ ..] => {
+            let squares = valid_squares(
+                material,
+                &setup.board,
+                *piece,
+                last_piece_and_square.0,
+                last_piece_and_square.1,
+            );
+            for sq in squares {
+                if setup.board.piece_at(sq).is_none() {
+                    let mut new_setup = setup.clone();
+                    new_setup.board.set_piece_at(sq, *piece);
+                    for_each_setup_internal(material, tail, &new_setup, (*piece, sq), f);
+                }
+            }
+        }
+        [] => f(setup),
+    }
+}
+
+#[inline]
+fn valid_squares(
+    material: &Material,
+    board: &Board,
+    piece: Piece,
+    last_piece: Piece,
+    last_square: Square,
+) -> Bitboard {
+    let squares = if last_piece == piece {
+        // by convention the former piece put on the board
+        // should have a "higher" square than the later to avoid
+        // generating the same position but with identical pieces swapped
+        (0..last_square.into())
+            .map(unsafe { |sq| Square::new_unchecked(sq) })
+            .collect()
+    }
+    // Do not restrict duplicate pieces as they already have other constraints
+    // and combining with this one resulting in the generating function not to be surjective anymore
+    // With pawns, there is no diagonal symmetry
+    else if !material.has_pawns()
+        && (material.by_piece(piece) == 1)
+        && A1_H8_DIAG.is_superset(board.occupied())
+    {
+        A1_H1_H8
+    } else {
+        Bitboard::FULL
+    };
+    // pawns are never on the first nor last rank, such setups would be rejected when checked anyway
+    if piece.role == Role::Pawn {
+        squares.without(Bitboard::BACKRANKS)
+    } else {
+        squares
+    }
+}
+
+/// Visit every legal position of `material`, up to symmetry, along with its index, using the same
+/// enumeration as `Generator` but without allocating the table nor loading descendants.
+/// Positions where the side not to move is in check are skipped, as in the tables.
+/// For materials with duplicate pieces, a few positions can be visited more than once, see `Generator::check_setup`
+#[cfg_attr(not(feature = "unstable-internals"), allow(dead_code))]
+pub fn for_each_legal_position(material: &Material, mut f: impl FnMut(&Chess, IndexWithTurn)) {
+    let indexer = DefaultIndexer::from(material.clone());
+    for_each_setup(material, EnumerationOrder::default(), &mut |setup| {
+        for color in Color::ALL {
+            let mut valid_setup = setup.clone();
+            valid_setup.turn = color;
+            if let Ok(chess) = to_chess_with_illegal_checks(valid_setup) {
+                f(&chess, indexer.encode(&chess));
+            }
+        }
+    });
+}
+
 /// When all legal positions have already been generated, start backward algo from all mates positions
 /// and tag them (ie associates an Outcome)
 #[derive(Debug)]
@@ -390,6 +716,43 @@ struct Tagger<T = DefaultReversibleIndexer> {
     common: Common,
     pb: ProgressBar,
     reversible_indexer: T,
+    // number of plies processed, across both queues
+    tagging_passes: usize,
+    // only for selfmates, to check the captures/promotions of the loser, see `Tagger::all_moves_win`
+    selfmate_descendants: Option<Descendants>,
+    unknown_policy: UnknownPolicy,
+    // entries left undefined by the generation, never updated while tagging, so skipped by every scan
+    undefined_spans: UndefinedSpans,
+    // see `BuildOptions::stop`, checked before each ply
+    stop: Option<Arc<AtomicBool>>,
+    // ply at which tagging stopped because `stop` was set, the reports are then incomplete
+    stopped_at: Option<Outcome>,
+    // ply at which tagging stopped in a previous build, the plies before being already processed
+    resume_from: Option<Outcome>,
+}
+
+/// What to do with the positions whose outcome is still unknown once tagging is over,
+/// ie the ones from which no mate was reached
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnknownPolicy {
+    /// Considered draws, like the ones found while tagging
+    #[default]
+    Draw,
+    /// Stored as `Outcome::Unknown`, to keep them distinct from genuine draws
+    Unreachable,
+    /// Treated as an inconsistency, see `Common::record_inconsistency`
+    Error,
+}
+
+/// Double-check of the outcome of terminal positions once tagging is over, see `BuildOptions::paranoid`.
+/// Positions are restored from their index and checked with shakmaty, mismatches being inconsistencies,
+/// see `Common::record_inconsistency`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParanoidCheck {
+    /// Every defined position, so that non-terminal positions tagged `Win(0)` or `Lose(0)` are caught too
+    All,
+    /// At most this number of positions tagged `Win(0)`, `Lose(0)` or `Draw`, spread across the table
+    Sample(usize),
 }
 
 impl<T: From<Material>> Tagger<T> {
@@ -397,8 +760,32 @@ impl<T: From<Material>> Tagger<T> {
         let pb = common.get_progress_bar().with_message("Tagging pos");
         Self {
             reversible_indexer: T::from(common.material().clone()),
+            undefined_spans: UndefinedSpans::from_outcomes(&common.all_pos),
             common,
             pb,
+            tagging_passes: 0,
+            selfmate_descendants: None,
+            unknown_policy: UnknownPolicy::default(),
+            stop: None,
+            stopped_at: None,
+            resume_from: None,
+        }
+    }
+
+    /// Resume tagging from the ply a previous build stopped before, see `Checkpoint`
+    fn resuming(self, stopped_at: Outcome, tagging_passes: usize) -> Self {
+        Self {
+            tagging_passes,
+            resume_from: Some(stopped_at),
+            ..self
+        }
+    }
+
+    /// Tag selfmate positions, generated with `SelfmateGeneratorHandler`
+    pub fn new_selfmate(common: Common, descendants: Descendants) -> Self {
+        Self {
+            selfmate_descendants: Some(descendants),
+            ..Self::new(common)
         }
     }
 }
@@ -406,82 +793,186 @@ impl<T: From<Material>> Tagger<T> {
 impl<T: Indexer + DeIndexer> Tagger<T> {
     pub fn process_positions(&mut self) {
         // need to process FIRST winning positions, then losing ones.
-        self.process_one_queue(true);
-        self.process_one_queue(false);
+        // When resuming from a losing ply, the winning ones are all processed
+        if !matches!(self.resume_from, Some(Outcome::Lose(_))) {
+            self.process_one_queue(true);
+        }
+        // there is no losing position in selfmates
+        if self.selfmate_descendants.is_none() && self.stopped_at.is_none() {
+            self.process_one_queue(false);
+        }
+        if self.stopped_at.is_none() {
+            self.mark_remaining_draws();
+        }
+    }
 
-        for report_bc in &mut self.common.all_pos.iter_mut() {
+    /// `true` if every move of the loser to move in `rboard` leads to a selfmate
+    /// in at most the ply of `desired_outcome`, currently processed.
+    /// A descendant outcome that cannot be retrieved is recorded as an inconsistency, and counts as not winning
+    fn all_moves_win(&mut self, rboard: &RetroBoard, desired_outcome: Outcome) -> bool {
+        let descendants = self
+            .selfmate_descendants
+            .as_ref()
+            .expect("Only used for selfmates");
+        let chess: Chess = rboard.clone().into();
+        let all_win = chess
+            .legal_moves()
+            .iter()
+            .map(|m| -> io::Result<bool> {
+                let mut pos_after_move = chess.clone();
+                pos_after_move.play_unchecked(m);
+                let outcome = if m.is_capture() || m.is_promotion() {
+                    descendants.retrieve_outcome(&pos_after_move, self.common.winner())?
+                } else {
+                    let idx = self.common.indexer.encode(&pos_after_move);
+                    self.common.all_pos[idx.usize()].get_outcome_by_color(idx.turn)
+                };
+                // outcomes lower than the one processed can still be improved
+                Ok(matches!(outcome, Outcome::Win(_)) && outcome >= desired_outcome)
+            })
+            .find(|win| !matches!(win, Ok(true)))
+            .unwrap_or(Ok(true));
+        all_win.unwrap_or_else(|e| {
+            let idx = self.common.indexer.encode(rboard).idx;
+            self.common.record_inconsistency(
+                idx,
+                rboard,
+                format!("descendant outcome not retrieved: {e}"),
+            );
+            false
+        })
+    }
+
+    /// After tagging, the only positions left unprocessed must be the ones never reached,
+    /// and when we try to win, the ones drawn through captures/promotions. Both are draws,
+    /// unless `Tagger::unknown_policy` says otherwise for the former
+    fn mark_remaining_draws(&mut self) {
+        let can_mate = self.common.can_mate();
+        let defined_ranges = self
+            .undefined_spans
+            .defined_ranges(0..self.common.all_pos.len());
+        for idx in defined_ranges.into_iter().flatten() {
             for turn in Color::ALL {
-                let report = report_bc.get_mut(turn);
-                if let Report::Unprocessed(Outcome::Unknown) = Report::from(*report) {
-                    *report = ReportU8::from(Report::Processed(Outcome::Draw));
+                let idx_with_turn = IndexWithTurn {
+                    idx: idx as u64,
+                    turn,
+                };
+                match self.common.report(idx, turn) {
+                    Report::Unprocessed(Outcome::Unknown) => match self.unknown_policy {
+                        UnknownPolicy::Draw => (),
+                        UnknownPolicy::Unreachable => {
+                            self.common.processed.set(idx, turn, true);
+                            continue;
+                        }
+                        UnknownPolicy::Error => {
+                            self.record_left_unprocessed(idx_with_turn, Outcome::Unknown);
+                            continue;
+                        }
+                    },
+                    Report::Unprocessed(Outcome::Draw) if can_mate => (),
+                    Report::Unprocessed(outcome) => {
+                        self.record_left_unprocessed(idx_with_turn, outcome);
+                        continue;
+                    }
+                    Report::Processed(_) => continue,
                 }
+                self.common
+                    .set_report(idx, turn, Report::Processed(Outcome::Draw));
             }
         }
     }
 
+    fn record_left_unprocessed(&mut self, idx_with_turn: IndexWithTurn, outcome: Outcome) {
+        let rboard = self
+            .reversible_indexer
+            .restore(self.common.material(), idx_with_turn);
+        self.common.record_inconsistency(
+            idx_with_turn.idx,
+            &rboard,
+            format!("{outcome:?} left unprocessed after tagging"),
+        );
+    }
+
     // if `desired_outcome_to_process` is set to `true`, we go from `Win(0)` to `Win(1)` ...
     // if `false`, go from `Lose(0)` to Lose(1) Lose(2) ...
     pub fn process_one_queue(&mut self, desired_outcome_to_process: bool) {
         self.common.counter = 0;
         let mut at_least_one_pos_processed = true;
-        let mut desired_outcome = if desired_outcome_to_process {
-            if self.common.can_mate() {
-                Outcome::Win(0)
-            } else {
-                Outcome::Draw
+        let resume_from = self
+            .resume_from
+            .filter(|outcome| matches!(outcome, Outcome::Lose(_)) != desired_outcome_to_process);
+        let mut desired_outcome = if let Some(outcome) = resume_from {
+            outcome
+        } else if desired_outcome_to_process {
+            match self.common.win_objective() {
+                WinObjective::Draw if self.selfmate_descendants.is_none() => Outcome::Draw,
+                _ => Outcome::Win(0),
             }
         } else {
             Outcome::Lose(0)
         };
-        while at_least_one_pos_processed {
+        // smallest ply still to be processed in each chunk of `DIRTY_CHUNK` indexes, `None` if there is none
+        // so that each ply only scans the chunks containing positions to process
+        let mut pending_plies: Vec<Option<u8>> = (0..self.common.all_pos.len())
+            .step_by(DIRTY_CHUNK)
+            .map(|chunk_start| {
+                min_pending_ply(
+                    &self.common,
+                    &self.undefined_spans,
+                    chunk_start,
+                    desired_outcome,
+                )
+            })
+            .collect();
+        // positions reached through captures/promotions can have a longer outcome than the one processed
+        // even when no position has been processed at this ply, eg with pawns there can be no mate
+        // before promoting
+        let mut longer_outcome_pending = true;
+        while at_least_one_pos_processed || longer_outcome_pending {
+            if is_stop_requested(self.stop.as_ref()) {
+                self.stopped_at = Some(desired_outcome);
+                break;
+            }
+            self.tagging_passes += 1;
             at_least_one_pos_processed = false;
-            let desired_report_u8: ReportU8 = Report::Unprocessed(desired_outcome).into();
-            for idx in 0..self.common.all_pos.len() {
-                let report_u8_bc = self.common.all_pos[idx];
-                for turn in Color::ALL {
-                    if &desired_report_u8 == report_u8_bc.get(turn) {
+            let desired_ply = pending_ply(desired_outcome, desired_outcome);
+            let desired_outcome_u8 = OutcomeU8::from(desired_outcome);
+            for chunk in 0..pending_plies.len() {
+                // chunks can be dirtied by the processing of previous ones
+                if pending_plies[chunk] != desired_ply {
+                    continue;
+                }
+                let chunk_start = chunk * DIRTY_CHUNK;
+                let chunk_end = (chunk_start + DIRTY_CHUNK).min(self.common.all_pos.len());
+                let defined_ranges = self.undefined_spans.defined_ranges(chunk_start..chunk_end);
+                for idx in defined_ranges.into_iter().flatten() {
+                    for turn in Color::ALL {
+                        if &desired_outcome_u8 != self.common.all_pos[idx].get(turn)
+                            || self.common.processed.get(idx, turn)
+                        {
+                            continue;
+                        }
                         at_least_one_pos_processed = true;
                         let idx_with_turn = IndexWithTurn {
                             idx: idx as u64,
                             turn,
                         };
-                        *self.common.all_pos[idx].get_mut(turn) =
-                            Report::Processed(desired_outcome).into();
+                        self.common.processed.set(idx, turn, true);
                         self.common.counter += 1;
-                        if self.common.counter % 100_000 == 0 {
+                        if self.common.counter.is_multiple_of(100_000) {
                             self.pb.set_position(self.common.counter);
                         }
-                        let rboard = self
-                            .reversible_indexer
-                            .restore(&self.common.material(), idx_with_turn);
-                        for m in rboard.legal_unmoves() {
-                            let mut rboard_after_unmove = rboard.clone();
-                            rboard_after_unmove.push(&m);
-                            let idx_all_pos_after_unmove =
-                                self.common.indexer().encode(&rboard_after_unmove);
-                            match self.common.all_pos[idx_all_pos_after_unmove.usize()]
-                                .get_by_pos(&rboard_after_unmove)
-                            {
-                                Report::Processed(Outcome::Undefined) => {
-                                    panic!("pos before: {rboard:?}, and after {m:?} pos not found, illegal? {rboard_after_unmove:?}, idx: {idx_all_pos_after_unmove:?}")
-                                }
-                                Report::Unprocessed(fetched_outcome) => {
-                                    // we know the position is unprocessed
-                                    assert!(fetched_outcome <= desired_outcome);
-                                    *self.common.all_pos[idx_all_pos_after_unmove.usize()]
-                                        .get_mut(idx_all_pos_after_unmove.turn) =
-                                        Report::Unprocessed(std::cmp::max(
-                                            fetched_outcome,
-                                            desired_outcome + 1,
-                                        ))
-                                        .into();
-                                }
-                                Report::Processed(_) => (),
-                            }
-                        }
+                        self.tag_predecessors(idx_with_turn, desired_outcome, &mut pending_plies);
                     }
                 }
+                pending_plies[chunk] = min_pending_ply(
+                    &self.common,
+                    &self.undefined_spans,
+                    chunk_start,
+                    desired_outcome,
+                );
             }
+            longer_outcome_pending = pending_plies.iter().any(|ply| *ply > desired_ply);
 
             if desired_outcome == Outcome::Win(0) {
                 debug!(
@@ -501,6 +992,93 @@ impl<T: Indexer + DeIndexer> Tagger<T> {
         }
         self.pb.finish_and_clear();
     }
+
+    /// Update the positions one unmove away from the processed `idx_with_turn`,
+    /// marking the chunks they belong to in `pending_plies`
+    fn tag_predecessors(
+        &mut self,
+        idx_with_turn: IndexWithTurn,
+        desired_outcome: Outcome,
+        pending_plies: &mut [Option<u8>],
+    ) {
+        let rboard = self
+            .reversible_indexer
+            .restore(self.common.material(), idx_with_turn);
+        for m in rboard.legal_unmoves() {
+            let mut rboard_after_unmove = rboard.clone();
+            rboard_after_unmove.push(&m);
+            let idx_all_pos_after_unmove = self.common.indexer.encode(&rboard_after_unmove);
+            match self.common.report(
+                idx_all_pos_after_unmove.usize(),
+                idx_all_pos_after_unmove.turn,
+            ) {
+                Report::Processed(Outcome::Undefined) => {
+                    self.common.record_inconsistency(idx_all_pos_after_unmove.idx, &rboard_after_unmove, format!("pos before: {rboard:?}, and after {m:?} pos not found, illegal? {rboard_after_unmove:?}, idx: {idx_all_pos_after_unmove:?}"));
+                }
+                Report::Unprocessed(fetched_outcome) if fetched_outcome > desired_outcome => {
+                    self.common.record_inconsistency(
+                        idx_all_pos_after_unmove.idx,
+                        &rboard_after_unmove,
+                        format!("unprocessed {fetched_outcome:?} better than {desired_outcome:?} currently processed"),
+                    );
+                }
+                // in selfmates the loser is only lost once all its moves are, and then only once
+                Report::Unprocessed(fetched_outcome)
+                    if self.selfmate_descendants.is_some()
+                        && rboard_after_unmove.side_to_move() != self.common.winner()
+                        && (matches!(fetched_outcome, Outcome::Win(_))
+                            || !self.all_moves_win(&rboard_after_unmove, desired_outcome)) => {}
+                Report::Unprocessed(fetched_outcome) => {
+                    let outcome = std::cmp::max(fetched_outcome, desired_outcome + 1);
+                    // we know the position is unprocessed
+                    *self.common.all_pos[idx_all_pos_after_unmove.usize()]
+                        .get_mut(idx_all_pos_after_unmove.turn) = outcome.into();
+                    let dirty = &mut pending_plies[idx_all_pos_after_unmove.usize() / DIRTY_CHUNK];
+                    *dirty = min_option(*dirty, pending_ply(outcome, desired_outcome));
+                }
+                Report::Processed(_) => (),
+            }
+        }
+    }
+}
+
+// number of indexes by chunk tracked by the `Tagger` to skip chunks without positions to process
+const DIRTY_CHUNK: usize = 1 << 16;
+
+/// Ply of `outcome` if it is of the same kind as the `desired_outcome` currently processed, `Draw` being ply 0
+fn pending_ply(outcome: Outcome, desired_outcome: Outcome) -> Option<u8> {
+    match (outcome, desired_outcome) {
+        (Outcome::Win(x), Outcome::Win(_)) | (Outcome::Lose(x), Outcome::Lose(_)) => Some(x),
+        (Outcome::Draw, Outcome::Draw) => Some(0),
+        _ => None,
+    }
+}
+
+/// Over the chunk of `DIRTY_CHUNK` indexes starting at `chunk_start`
+fn min_pending_ply<I>(
+    common: &Common<I>,
+    undefined_spans: &UndefinedSpans,
+    chunk_start: usize,
+    desired_outcome: Outcome,
+) -> Option<u8> {
+    let chunk_end = (chunk_start + DIRTY_CHUNK).min(common.all_pos.len());
+    undefined_spans
+        .defined_ranges(chunk_start..chunk_end)
+        .into_iter()
+        .flatten()
+        .flat_map(|idx| Color::ALL.map(|turn| common.report(idx, turn)))
+        .filter_map(|report| match report {
+            Report::Unprocessed(outcome) => pending_ply(outcome, desired_outcome),
+            Report::Processed(_) => None,
+        })
+        .min()
+}
+
+fn min_option(a: Option<u8>, b: Option<u8>) -> Option<u8> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        _ => a.or(b),
+    }
 }
 
 impl<T> From<Tagger<T>> for Common {
@@ -511,21 +1089,565 @@ impl<T> From<Tagger<T>> for Common {
 
 pub struct TableBaseBuilder;
 
+/// Summary of a table built by `TableBaseBuilder`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BuildReport {
+    /// Number of plies processed by the tagger, for both the winning and losing positions
+    pub tagging_passes: usize,
+    /// Number of positions by ply, `win_counts[x]` being the number of `Win(x)` positions
+    pub win_counts: Vec<u64>,
+    /// Number of positions by ply, `lose_counts[x]` being the number of `Lose(x)` positions
+    pub lose_counts: Vec<u64>,
+    pub draws: u64,
+    /// Drawn positions whose legal moves are all captures/promotions, see `Common::capture_draws`
+    pub capture_draws: u64,
+    /// Indexes generated more than once, see `Generator::reconcile_duplicate`
+    pub duplicates: u64,
+    /// Among `duplicates`, those whose initial reports differed and were reconciled
+    pub reconciled_duplicates: u64,
+    /// Positions with the longest `Win`, empty if there is none
+    pub win_records: Vec<IndexWithTurn>,
+    /// Positions with the longest `Lose`, empty if there is none
+    pub lose_records: Vec<IndexWithTurn>,
+    /// Number of indexes of the table
+    pub max_index: u64,
+    /// Number of entries corresponding to a legal position, ie not `Outcome::Undefined`,
+    /// each side to move being counted separately
+    pub defined: u64,
+}
+
+impl BuildReport {
+    fn new(common: &Common, tagging_passes: usize) -> Self {
+        let all_pos = &common.all_pos;
+        let mut report = Self {
+            tagging_passes,
+            capture_draws: common.capture_draws,
+            duplicates: common.duplicates,
+            reconciled_duplicates: common.reconciled_duplicates,
+            max_index: all_pos.len() as u64,
+            ..Default::default()
+        };
+        for (idx_with_turn, outcome) in common.iter_defined() {
+            report.defined += 1;
+            let (counts, records, ply) = match outcome {
+                Outcome::Win(x) => (&mut report.win_counts, &mut report.win_records, x),
+                Outcome::Lose(x) => (&mut report.lose_counts, &mut report.lose_records, x),
+                Outcome::Draw => {
+                    report.draws += 1;
+                    continue;
+                }
+                Outcome::Unknown | Outcome::Undefined => continue,
+            };
+            let ply = usize::from(ply);
+            if ply >= counts.len() {
+                counts.resize(ply + 1, 0);
+                records.clear();
+            }
+            counts[ply] += 1;
+            if ply + 1 == counts.len() {
+                records.push(idx_with_turn);
+            }
+        }
+        report
+    }
+
+    /// Share of the entries of the table, for both sides to move, corresponding to a legal position.
+    /// Between 0 and 1, the lower the more space the indexer wastes on illegal positions
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn density(&self) -> f64 {
+        self.defined as f64 / (2 * self.max_index).max(1) as f64
+    }
+}
+
+/// Phase of a build, see `BuildOptions::on_phase`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildPhase {
+    /// Enumeration of all the positions, with their initial report, see `Generator`
+    Generation,
+    /// Backward propagation of the outcomes, see `Tagger`
+    Tagging,
+}
+
+#[derive(Debug, Default)]
+pub struct BuildOptions {
+    /// The progress bars of each phase are displayed as part of it,
+    /// allowing the caller to show global progress alongside
+    pub multi_progress: MultiProgress,
+    /// Instead of panicking, record inconsistencies found in `Common::quarantine` and go on
+    pub quarantine: bool,
+    pub enumeration_order: EnumerationOrder,
+    /// Descendants tables generated with the same condition must be present in the tablebase directory,
+    /// alongside the regular ones
+    pub win_condition: WinCondition,
+    /// Share decompressed descendants tables across builds using the same options
+    pub table_cache: Option<TableCache>,
+    /// Descendants tables generated with the same objective must be present in the tablebase directory.
+    /// Only helpmates support a `win_condition`
+    pub objective: Objective,
+    pub unknown_policy: UnknownPolicy,
+    /// Checked before loading anything, see `TableBaseBuilder::check_resources`
+    pub resource_limits: ResourceLimits,
+    /// Inconsistencies are also written there, see `Common::record_inconsistency`
+    pub anomaly_sink: Option<AnomalySink>,
+    /// Called right before each phase starts, eg to take heap snapshots attributing memory usage to phases.
+    /// The last phase ends when the build returns
+    pub on_phase: Option<fn(&MaterialWinner, BuildPhase)>,
+    /// Double-check the outcome of terminal positions at the end of the build
+    pub paranoid: Option<ParanoidCheck>,
+    /// Once set, eg from a signal handler, the build stops at the next safe boundary: the end of the generation,
+    /// or of the tagging ply being processed. It then fails with `io::ErrorKind::Interrupted`, nothing being kept
+    /// unless `checkpoint_dir` is set
+    pub stop: Option<Arc<AtomicBool>>,
+    /// When tagging is stopped, its state is saved there, see `TablePath::checkpoint`. A build finding
+    /// the checkpoint of its table skips the generation and resumes tagging from it, then removes it.
+    /// `observer`s of `TableBaseBuilder::build_with_observer` then see no position
+    pub checkpoint_dir: Option<PathBuf>,
+}
+
+impl BuildOptions {
+    fn start_phase(&self, mat_win: &MaterialWinner, phase: BuildPhase) {
+        if let Some(on_phase) = self.on_phase {
+            on_phase(mat_win, phase);
+        }
+    }
+
+    /// See `BuildOptions::checkpoint_dir`
+    fn checkpoint_path(&self, mat_win: &MaterialWinner) -> Option<PathBuf> {
+        self.checkpoint_dir.as_ref().map(|dir| {
+            TablePath::new(dir).checkpoint(&TableName::new(
+                mat_win,
+                self.objective,
+                self.win_condition,
+            ))
+        })
+    }
+
+    /// Read if found, see `BuildOptions::checkpoint_dir`
+    fn find_checkpoint(&self, mat_win: &MaterialWinner) -> io::Result<Option<Checkpoint>> {
+        match self.checkpoint_path(mat_win) {
+            Some(path) if path.exists() => {
+                info!("Resuming {mat_win:?} from {}", path.display());
+                Checkpoint::read(&path, mat_win.clone()).map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn check_stop(&self, mat_win: &MaterialWinner, phase: BuildPhase) -> io::Result<()> {
+        if is_stop_requested(self.stop.as_ref()) {
+            return Err(io::Error::new(
+                io::ErrorKind::Interrupted,
+                format!("build of {mat_win:?} stopped before {phase:?}"),
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn is_stop_requested(stop: Option<&Arc<AtomicBool>>) -> bool {
+    stop.is_some_and(|stop| stop.load(Ordering::Relaxed))
+}
+
 impl TableBaseBuilder {
+    pub fn build(
+        mat_win: MaterialWinner,
+        tablebase_dir: &Path,
+    ) -> io::Result<(Common, BuildReport)> {
+        Self::build_with_options(mat_win, tablebase_dir, &BuildOptions::default())
+    }
+
+    pub fn build_with_options(
+        mat_win: MaterialWinner,
+        tablebase_dir: &Path,
+        options: &BuildOptions,
+    ) -> io::Result<(Common, BuildReport)> {
+        let (common, build_report, ()) =
+            Self::build_observed(mat_win, tablebase_dir, options, ())?;
+        Ok((common, build_report))
+    }
+
+    /// RAM needed to build the table of `mat_win`, in bytes: its outcomes, their processed flags and all
+    /// the descendants tables loaded, including the ones already in `options.table_cache`. Only block headers are read.
+    /// Fails with a `ResourceLimitExceeded` if it exceeds `options.resource_limits`
+    pub fn check_resources(
+        mat_win: &MaterialWinner,
+        tablebase_dir: &Path,
+        options: &BuildOptions,
+    ) -> io::Result<u64> {
+        let nb_pos = get_estimate_nb_pos(&mat_win.material);
+        let mut bytes = table_ram_bytes(nb_pos as u64) + nb_pos.div_ceil(4) as u64;
+        for table_name in Self::descendants_table_names(mat_win, options) {
+            let file = File::open(table_name.path_in(tablebase_dir))
+                .map_err(|e| missing_descendant(&e, &table_name, tablebase_dir))?;
+            bytes += table_ram_bytes(EncoderDecoder::new(file).table_format()?.len);
+        }
+        options
+            .resource_limits
+            .check_ram(bytes, format_args!("Building {mat_win:?}"))?;
+        Ok(bytes)
+    }
+
+    /// Fails with `io::ErrorKind::NotFound`, naming the first one missing, unless all the descendants
+    /// tables needed to build `mat_win` are in `tablebase_dir`
+    pub fn check_descendants(
+        mat_win: &MaterialWinner,
+        tablebase_dir: &Path,
+        options: &BuildOptions,
+    ) -> io::Result<()> {
+        for table_name in Self::descendants_table_names(mat_win, options) {
+            if !table_name.exists_in(tablebase_dir) {
+                return Err(missing_descendant(
+                    &io::Error::from(io::ErrorKind::NotFound),
+                    &table_name,
+                    tablebase_dir,
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Descendants tables loaded when building `mat_win` with `options`
     #[must_use]
-    pub fn build(mat_win: MaterialWinner, tablebase_dir: &Path) -> Common {
-        let mut generator = Generator::new(mat_win, tablebase_dir);
+    pub fn descendants_table_names(
+        mat_win: &MaterialWinner,
+        options: &BuildOptions,
+    ) -> Vec<TableName> {
+        let win_condition = match options.objective {
+            Objective::Helpmate => options.win_condition,
+            Objective::Selfmate => WinCondition::AnyMate,
+        };
+        <Descendants>::table_names(mat_win, win_condition, options.objective)
+    }
+
+    /// Same as `TableBaseBuilder::build_with_options`, but `observer` is also called on each position
+    /// enumerated, right after the handler of the objective has set its initial report.
+    /// Allows custom analysis of the positions without enumerating them again, `observer` is returned
+    /// once the table is built. It must not modify the reports.
+    /// Fails if `options.resource_limits` would be exceeded, see `TableBaseBuilder::check_resources`,
+    /// or if a descendant table is missing, see `TableBaseBuilder::check_descendants`
+    #[cfg(feature = "unstable-internals")]
+    pub fn build_with_observer<H: PosHandler<DefaultIndexer>>(
+        mat_win: MaterialWinner,
+        tablebase_dir: &Path,
+        options: &BuildOptions,
+        observer: H,
+    ) -> io::Result<(Common, BuildReport, H)> {
+        Self::build_observed(mat_win, tablebase_dir, options, observer)
+    }
+
+    /// See `TableBaseBuilder::build_with_observer`, only public with the `unstable-internals` feature
+    /// since `PosHandler` is not part of `api`
+    fn build_observed<H: PosHandler<DefaultIndexer>>(
+        mat_win: MaterialWinner,
+        tablebase_dir: &Path,
+        options: &BuildOptions,
+        observer: H,
+    ) -> io::Result<(Common, BuildReport, H)> {
+        Self::check_before_loading(&mat_win, tablebase_dir, options)?;
+        let checkpoint = options.find_checkpoint(&mat_win)?;
+        if checkpoint.is_none() {
+            options.start_phase(&mat_win, BuildPhase::Generation);
+        }
+        match options.objective {
+            Objective::Helpmate => {
+                let (tagger, observer) = if let Some(checkpoint) = checkpoint {
+                    let tagger: Tagger = Tagger::new(checkpoint.common);
+                    (
+                        tagger.resuming(checkpoint.stopped_at, checkpoint.tagging_passes),
+                        observer,
+                    )
+                } else {
+                    let descendants = Descendants::new_with_win_condition(
+                        &mat_win,
+                        tablebase_dir,
+                        options.win_condition,
+                        options.table_cache.as_ref(),
+                    );
+                    let (common, (_, observer)) = Self::generate(
+                        (DefaultGeneratorHandler, observer),
+                        mat_win,
+                        descendants,
+                        options,
+                    );
+                    options.check_stop(common.material_winner(), BuildPhase::Tagging)?;
+                    (Tagger::new(common), observer)
+                };
+                options.start_phase(tagger.common.material_winner(), BuildPhase::Tagging);
+                let (common, build_report) = Self::tag_with(tagger, options)?;
+                Ok((common, build_report, observer))
+            }
+            Objective::Selfmate => {
+                assert_eq!(
+                    options.win_condition,
+                    WinCondition::AnyMate,
+                    "Win conditions are only supported for helpmates"
+                );
+                let descendants = Descendants::new_with_objective(
+                    &mat_win,
+                    tablebase_dir,
+                    options.objective,
+                    options.table_cache.as_ref(),
+                );
+                let (tagger, observer) = if let Some(checkpoint) = checkpoint {
+                    let tagger: Tagger = Tagger::new_selfmate(checkpoint.common, descendants);
+                    (
+                        tagger.resuming(checkpoint.stopped_at, checkpoint.tagging_passes),
+                        observer,
+                    )
+                } else {
+                    let (common, (_, observer)) = Self::generate(
+                        (SelfmateGeneratorHandler, observer),
+                        mat_win,
+                        descendants.clone(),
+                        options,
+                    );
+                    options.check_stop(common.material_winner(), BuildPhase::Tagging)?;
+                    (Tagger::new_selfmate(common, descendants), observer)
+                };
+                options.start_phase(tagger.common.material_winner(), BuildPhase::Tagging);
+                let (common, build_report) = Self::tag_with(tagger, options)?;
+                Ok((common, build_report, observer))
+            }
+        }
+    }
+
+    fn generate<H: PosHandler<DefaultIndexer>>(
+        pos_handler: H,
+        mat_win: MaterialWinner,
+        descendants: Descendants,
+        options: &BuildOptions,
+    ) -> (Common, H) {
+        let mut generator =
+            Generator::new_with_pos_handler_and_descendants(pos_handler, mat_win, descendants)
+                .with_multi_progress(&options.multi_progress)
+                .with_enumeration_order(options.enumeration_order);
+        if options.quarantine {
+            generator.common.quarantine = Some(Vec::new());
+        }
+        generator
+            .common
+            .anomaly_sink
+            .clone_from(&options.anomaly_sink);
         generator.generate_positions();
-        let (common, _): (Common, DefaultGeneratorHandler) = generator.get_result();
+        let (common, pos_handler) = generator.get_result();
         debug!("nb pos {:?}", common.all_pos.len());
         debug!("counter {:?}", common.counter);
-        // Should be the same indexer than for `Queue`
-        let mut tagger: Tagger = Tagger::new(common);
+        (common, pos_handler)
+    }
+
+    /// Re-run the tagging on the `outcomes` of an existing table, see `EncoderDecoder::decompress_file`,
+    /// without enumerating the positions again. Only defined positions are kept, and their outcome
+    /// is reset from the terminal ones: mates, stalemates and captures/promotions into descendants.
+    /// Fails in the same cases as `TableBaseBuilder::build_with_observer`
+    pub fn retag(
+        mat_win: MaterialWinner,
+        outcomes: OutcomesSlice,
+        tablebase_dir: &Path,
+        options: &BuildOptions,
+    ) -> io::Result<(Common, BuildReport)> {
+        Self::check_before_loading(&mat_win, tablebase_dir, options)?;
+        let mut common: Common = Common::new(mat_win);
+        common.all_pos = vec![UNDEFINED_OUTCOME_BYCOLOR; outcomes.len()];
+        common.processed = ProcessedBits::new_processed(outcomes.len());
+        if options.quarantine {
+            common.quarantine = Some(Vec::new());
+        }
+        common.anomaly_sink.clone_from(&options.anomaly_sink);
+        let tagger = match options.objective {
+            Objective::Helpmate => {
+                let descendants = Descendants::new_with_win_condition(
+                    common.material_winner(),
+                    tablebase_dir,
+                    options.win_condition,
+                    options.table_cache.as_ref(),
+                );
+                Self::reset_reports(DefaultGeneratorHandler, &mut common, &descendants, outcomes);
+                Tagger::new(common)
+            }
+            Objective::Selfmate => {
+                assert_eq!(
+                    options.win_condition,
+                    WinCondition::AnyMate,
+                    "Win conditions are only supported for helpmates"
+                );
+                let descendants = Descendants::new_with_objective(
+                    common.material_winner(),
+                    tablebase_dir,
+                    options.objective,
+                    options.table_cache.as_ref(),
+                );
+                Self::reset_reports(
+                    SelfmateGeneratorHandler,
+                    &mut common,
+                    &descendants,
+                    outcomes,
+                );
+                Tagger::new_selfmate(common, descendants)
+            }
+        };
+        Self::tag_with(tagger, options)
+    }
+
+    /// Initial report of each position defined in `outcomes`, as set by `pos_handler` when generating
+    fn reset_reports(
+        mut pos_handler: impl PosHandler<DefaultIndexer>,
+        common: &mut Common,
+        descendants: &Descendants,
+        outcomes: OutcomesSlice,
+    ) {
+        let reversible_indexer = DefaultReversibleIndexer::from(common.material().clone());
+        for (idx_with_turn, _) in iter_defined(outcomes, 0) {
+            let chess: Chess = reversible_indexer
+                .restore(common.material(), idx_with_turn)
+                .into();
+            pos_handler.handle_position(
+                common,
+                descendants,
+                &chess,
+                idx_with_turn,
+                to_usize(idx_with_turn.idx),
+            );
+        }
+    }
+
+    /// Missing descendants tables are reported up front, rather than when they are loaded
+    fn check_before_loading(
+        mat_win: &MaterialWinner,
+        tablebase_dir: &Path,
+        options: &BuildOptions,
+    ) -> io::Result<()> {
+        Self::check_descendants(mat_win, tablebase_dir, options)?;
+        if options.resource_limits.max_ram_bytes.is_some() {
+            Self::check_resources(mat_win, tablebase_dir, options)?;
+        }
+        Ok(())
+    }
+
+    /// Fails if tagging was stopped, see `BuildOptions::stop`, after saving a checkpoint if
+    /// `options.checkpoint_dir` is set. The checkpoint resumed, if any, is removed once tagging completes
+    fn tag_with(mut tagger: Tagger, options: &BuildOptions) -> io::Result<(Common, BuildReport)> {
+        tagger.pb = options.multi_progress.add(tagger.pb);
+        tagger.unknown_policy = options.unknown_policy;
+        tagger.stop.clone_from(&options.stop);
         tagger.process_positions();
-        tagger.into()
+        let checkpoint_path = options.checkpoint_path(tagger.common.material_winner());
+        if let Some(outcome) = tagger.stopped_at {
+            if let Some(path) = checkpoint_path {
+                Checkpoint::write(&path, &tagger.common, outcome, tagger.tagging_passes)?;
+                info!("Tagging progress saved in {}", path.display());
+            }
+            return Err(io::Error::new(
+                io::ErrorKind::Interrupted,
+                format!(
+                    "tagging of {:?} stopped before processing {outcome:?}",
+                    tagger.common.material_winner()
+                ),
+            ));
+        }
+        if tagger.resume_from.is_some() {
+            if let Some(path) = checkpoint_path {
+                fs::remove_file(path)?;
+            }
+        }
+        tagger.common.drop_processed_flags();
+        debug_check_reports(
+            &tagger.common.all_pos,
+            tagger.common.winner(),
+            options.objective,
+        );
+        if let Some(check) = options.paranoid {
+            check_terminal_positions(&mut tagger.common, options, check);
+        }
+        let build_report = BuildReport::new(&tagger.common, tagger.tagging_passes);
+        Ok((tagger.into(), build_report))
+    }
+}
+
+/// Outcome a position must be tagged with if the game is over, `None` otherwise.
+/// Mirrors the terminal reports of `DefaultGeneratorHandler` and `SelfmateGeneratorHandler`
+fn terminal_outcome(chess: &Chess, winner: Color, options: &BuildOptions) -> Option<Outcome> {
+    let outcome = match (chess.outcome()?, options.objective) {
+        (ChessOutcome::Decisive { winner: mating }, Objective::Helpmate) if mating != winner => {
+            Outcome::Lose(0)
+        }
+        (ChessOutcome::Decisive { .. }, Objective::Helpmate) => match options.win_condition {
+            WinCondition::MatedKingOn(squares) if !squares.is_mated_on(chess, !winner) => {
+                Outcome::Draw
+            }
+            _ => Outcome::Win(0),
+        },
+        (ChessOutcome::Decisive { winner: mating }, Objective::Selfmate) if mating != winner => {
+            Outcome::Win(0)
+        }
+        // the winner mated the loser in a selfmate, or stalemate
+        (ChessOutcome::Decisive { .. } | ChessOutcome::Draw, _) => Outcome::Draw,
+    };
+    Some(outcome)
+}
+
+/// See `ParanoidCheck`
+fn check_terminal_positions(common: &mut Common, options: &BuildOptions, check: ParanoidCheck) {
+    let is_candidate = |outcome: Outcome| {
+        check == ParanoidCheck::All
+            || matches!(outcome, Outcome::Win(0) | Outcome::Lose(0) | Outcome::Draw)
+    };
+    let (step, limit) = match check {
+        ParanoidCheck::All => (1, usize::MAX),
+        ParanoidCheck::Sample(nb) => {
+            let nb_candidates = common
+                .iter_defined()
+                .filter(|(_, outcome)| is_candidate(*outcome))
+                .count();
+            ((nb_candidates / nb.max(1)).max(1), nb)
+        }
+    };
+    let reversible_indexer = DefaultReversibleIndexer::from(common.material().clone());
+    let winner = common.winner();
+    let mut nb_checked = 0;
+    // the table cannot be modified while iterating over it, and mismatches should be few
+    let mismatches: Vec<(u64, Chess, String)> = common
+        .iter_defined()
+        .filter(|(_, outcome)| is_candidate(*outcome))
+        .step_by(step)
+        .take(limit)
+        .filter_map(|(idx_with_turn, tagged)| {
+            nb_checked += 1;
+            let chess: Chess = reversible_indexer
+                .restore(common.material(), idx_with_turn)
+                .into();
+            let reason = match terminal_outcome(&chess, winner, options) {
+                Some(expected) if expected != tagged => {
+                    format!("terminal position tagged {tagged:?} instead of {expected:?}")
+                }
+                None if matches!(tagged, Outcome::Win(0) | Outcome::Lose(0)) => {
+                    format!("position not over tagged {tagged:?}")
+                }
+                _ => return None,
+            };
+            Some((idx_with_turn.idx, chess, reason))
+        })
+        .collect();
+    debug!(
+        "{:?}: {nb_checked} positions double-checked, {} mismatches",
+        common.material_winner(),
+        mismatches.len()
+    );
+    for (idx, chess, reason) in mismatches {
+        common.record_inconsistency(idx, &chess, reason);
     }
 }
 
+fn missing_descendant(err: &io::Error, table_name: &TableName, tablebase_dir: &Path) -> io::Error {
+    io::Error::new(
+        err.kind(),
+        format!(
+            "descendant table {table_name} not readable in {}, it must be generated first: {err}",
+            tablebase_dir.display()
+        ),
+    )
+}
+
 #[allow(clippy::result_large_err)]
 pub fn to_chess_with_illegal_checks(setup: Setup) -> Result<Chess, PositionError<Chess>> {
     Chess::from_setup(setup, CastlingMode::Standard).or_else(PositionError::ignore_impossible_check)
@@ -533,11 +1655,837 @@ pub fn to_chess_with_illegal_checks(setup: Setup) -> Result<Chess, PositionError
 #[cfg(test)]
 mod tests {
     use super::*;
-    use retroboard::shakmaty::fen::Fen;
+    use crate::KingSquares;
+    use retroboard::shakmaty::{fen::Fen, Move};
+    use std::{collections::HashSet, path::PathBuf, str::FromStr};
 
     #[test]
     fn test_a1_h8_bb() {
-        assert_eq!(A1_H1_H8, Bitboard(9_277_662_557_957_324_543))
+        assert_eq!(A1_H1_H8, Bitboard(9_277_662_557_957_324_543));
+    }
+
+    #[test]
+    fn test_min_pending_ply() {
+        let reports = [
+            (
+                Report::Processed(Outcome::Win(0)),
+                Report::Unprocessed(Outcome::Win(4)),
+            ),
+            (
+                Report::Unprocessed(Outcome::Lose(1)),
+                Report::Unprocessed(Outcome::Win(2)),
+            ),
+            (
+                Report::Unprocessed(Outcome::Unknown),
+                Report::Unprocessed(Outcome::Draw),
+            ),
+        ];
+        let common_with = |reports: &[(Report, Report)]| {
+            let mut common: Common = Common::new(MaterialWinner::from_str("KQvKw").unwrap());
+            common.all_pos = vec![UNDEFINED_OUTCOME_BYCOLOR; reports.len()];
+            common.processed = ProcessedBits::new_processed(reports.len());
+            for (idx, (white, black)) in reports.iter().enumerate() {
+                common.set_report(idx, White, *white);
+                common.set_report(idx, Color::Black, *black);
+            }
+            common
+        };
+        let no_spans = UndefinedSpans::new();
+        let common = common_with(&reports);
+        assert_eq!(
+            min_pending_ply(&common, &no_spans, 0, Outcome::Win(1)),
+            Some(2)
+        );
+        assert_eq!(
+            min_pending_ply(&common, &no_spans, 0, Outcome::Lose(0)),
+            Some(1)
+        );
+        assert_eq!(
+            min_pending_ply(&common, &no_spans, 0, Outcome::Draw),
+            Some(0)
+        );
+        assert_eq!(
+            min_pending_ply(&common_with(&reports[..1]), &no_spans, 0, Outcome::Lose(0)),
+            None
+        );
+        // the same reports after a span of undefined entries
+        let undefined = (
+            Report::Processed(Outcome::Undefined),
+            Report::Processed(Outcome::Undefined),
+        );
+        let mut spanned = vec![undefined; UndefinedSpans::MIN_SPAN];
+        spanned.extend_from_slice(&reports);
+        let common = common_with(&spanned);
+        let undefined_spans = UndefinedSpans::from_outcomes(&common.all_pos);
+        assert_eq!(undefined_spans.nb_undefined(), UndefinedSpans::MIN_SPAN);
+        assert_eq!(
+            min_pending_ply(&common, &undefined_spans, 0, Outcome::Win(1)),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn test_for_each_legal_position() {
+        let material = Material::from_str("KQvK").unwrap();
+        let mut nb_pos = 0;
+        let mut nb_checkmates = 0;
+        for_each_legal_position(&material, |chess, _| {
+            nb_pos += 1;
+            if chess.is_checkmate() {
+                nb_checkmates += 1;
+            }
+        });
+        let (common, build_report) =
+            TableBaseBuilder::build(MaterialWinner::new(&material, White), &PathBuf::new())
+                .unwrap();
+        let nb_defined = common
+            .all_pos
+            .iter()
+            .flat_map(|bc| Color::ALL.map(|c| bc.get_outcome_by_color(c)))
+            .filter(|o| *o != Outcome::Undefined)
+            .count();
+        assert_eq!(nb_pos, nb_defined);
+        assert!(nb_checkmates > 0);
+
+        assert_eq!(build_report.win_counts[0], nb_checkmates);
+        assert_eq!(build_report.win_counts.len(), 15);
+        assert_eq!(build_report.win_records.len(), 4);
+        assert!(build_report.lose_counts.is_empty());
+        assert_eq!(
+            build_report.win_counts.iter().sum::<u64>() + build_report.draws,
+            nb_defined as u64
+        );
+        assert!(build_report.tagging_passes > 15);
+    }
+
+    #[test]
+    fn test_pawn_setups() {
+        let material = Material::from_str("KPPvK").unwrap();
+        for enumeration_order in [
+            EnumerationOrder::Material,
+            EnumerationOrder::IndexSignificance,
+        ] {
+            let mut boards = HashSet::new();
+            for_each_setup(&material, enumeration_order, &mut |setup| {
+                let pawns = setup.board.pawns();
+                assert!((pawns & Bitboard::BACKRANKS).is_empty());
+                assert_eq!(pawns.count(), 2);
+                // identical pawns are only put once in each order, doubled ones included
+                assert!(boards.insert(setup.board.clone()));
+            });
+            // pairs of pawns among the 48 squares of ranks 2 to 7 not taken by a king,
+            // with the white king on the 8 back rank squares of the a-d files, or the 24 others
+            let pawn_pairs = |free: u64| free * (free - 1) / 2;
+            assert_eq!(
+                boards.len() as u64,
+                8 * (15 * pawn_pairs(48) + 48 * pawn_pairs(47))
+                    + 24 * (16 * pawn_pairs(47) + 47 * pawn_pairs(46))
+            );
+        }
+    }
+
+    #[test]
+    fn test_pawn_promotion() {
+        let tb_dir: PathBuf = ["..", "table"].iter().collect();
+        let options = BuildOptions {
+            quarantine: true,
+            ..Default::default()
+        };
+        let (common, build_report) = TableBaseBuilder::build_with_options(
+            MaterialWinner::from_str("KPvKw").unwrap(),
+            &tb_dir,
+            &options,
+        )
+        .unwrap();
+        assert!(!common.is_dirty());
+        let mut nb_pos = 0;
+        for_each_legal_position(common.material(), |_, _| nb_pos += 1);
+        assert_eq!(build_report.defined, nb_pos);
+        // like pawnless ones, the table ends among the indexes of the last kings configuration
+        let material = common.material();
+        assert!(
+            build_report.max_index
+                >= DefaultIndexer::from(material.clone()).min_table_len(material)
+        );
+        // c8=Q is mate, resolved through the `KQvK` table
+        let chess: Chess = Fen::from_ascii(b"k7/2P5/1K6/8/8/8/8/8 w - - 0 1")
+            .unwrap()
+            .into_position(CastlingMode::Standard)
+            .unwrap();
+        let idx = common.indexer.encode(&chess);
+        assert_eq!(
+            common.all_pos[idx.usize()].get_outcome_by_color(idx.turn),
+            Outcome::Win(1)
+        );
+    }
+
+    #[test]
+    fn test_retag() {
+        let mat_win = MaterialWinner::from_str("KQvKw").unwrap();
+        let tb_dir: PathBuf = ["..", "table"].iter().collect();
+        let outcomes = crate::EncoderDecoder::new(
+            std::fs::File::open(tb_dir.join(format!("{mat_win:?}"))).unwrap(),
+        )
+        .decompress_file()
+        .unwrap();
+        let (common, build_report) =
+            TableBaseBuilder::retag(mat_win, &outcomes, &tb_dir, &BuildOptions::default()).unwrap();
+        assert_eq!(common.all_pos, outcomes);
+        for (retagged, original) in common.all_pos.iter().zip(outcomes.iter()) {
+            for turn in Color::ALL {
+                assert_eq!(
+                    retagged.get_outcome_by_color(turn),
+                    original.get_outcome_by_color(turn)
+                );
+            }
+        }
+        assert!(build_report.tagging_passes > 12);
+    }
+
+    #[test]
+    fn test_build_with_observer() {
+        #[derive(Default)]
+        struct Visited(HashSet<IndexWithTurn>);
+
+        impl<I> PosHandler<I> for Visited {
+            fn handle_position(
+                &mut self,
+                common: &mut Common<I>,
+                _: &Descendants,
+                chess: &Chess,
+                idx: IndexWithTurn,
+                all_pos_idx: usize,
+            ) {
+                // the default handler has already been called
+                assert_ne!(
+                    common.all_pos[all_pos_idx].get_outcome_by_color(chess.turn()),
+                    Outcome::Undefined
+                );
+                self.0.insert(idx);
+            }
+        }
+
+        let mat_win = MaterialWinner::from_str("KQvKw").unwrap();
+        let tb_dir: PathBuf = ["..", "table"].iter().collect();
+        let (common, build_report, visited) = TableBaseBuilder::build_observed(
+            mat_win.clone(),
+            &tb_dir,
+            &BuildOptions::default(),
+            Visited::default(),
+        )
+        .unwrap();
+        assert_eq!(visited.0.len() as u64, build_report.defined);
+        let (common_without_observer, _) = TableBaseBuilder::build(mat_win, &tb_dir).unwrap();
+        assert_eq!(common.all_pos, common_without_observer.all_pos);
+    }
+
+    #[test]
+    fn test_like_pieces_duplicates() {
+        #[derive(Default)]
+        struct Calls(u64);
+
+        impl<I> PosHandler<I> for Calls {
+            fn handle_position(
+                &mut self,
+                _: &mut Common<I>,
+                _: &Descendants,
+                _: &Chess,
+                _: IndexWithTurn,
+                _: usize,
+            ) {
+                self.0 += 1;
+            }
+        }
+
+        let mat_win = MaterialWinner::from_str("KRRvKw").unwrap();
+        let tb_dir = tempfile::tempdir().unwrap();
+        let options = BuildOptions::default();
+        write_descendants(&mat_win, tb_dir.path(), &options);
+        let (common, build_report, calls) = TableBaseBuilder::build_observed(
+            mat_win,
+            tb_dir.path(),
+            &options,
+            Calls::default(),
+        )
+        .unwrap();
+        assert!(common.duplicates > 0);
+        assert_eq!(common.reconciled_duplicates, 0);
+        // duplicates are not handled a second time
+        assert_eq!(calls.0, build_report.defined);
+    }
+
+    #[test]
+    fn test_capture_draws() {
+        let mat_win = MaterialWinner::from_str("KQvKw").unwrap();
+        let tb_dir: PathBuf = ["..", "table"].iter().collect();
+        let (common, build_report) = TableBaseBuilder::build(mat_win, &tb_dir).unwrap();
+        assert!(build_report.capture_draws > 0);
+        assert_eq!(build_report.capture_draws, common.capture_draws);
+        assert_eq!(build_report.duplicates, 0);
+        assert_eq!(build_report.max_index, common.all_pos.len() as u64);
+        let defined = common
+            .all_pos
+            .iter()
+            .flat_map(|report_bc| Color::ALL.map(|turn| report_bc.get_outcome_by_color(turn)))
+            .filter(|outcome| *outcome != Outcome::Undefined)
+            .count();
+        assert_eq!(build_report.defined, defined as u64);
+        assert!(build_report.density() > 0.5 && build_report.density() < 1.);
+        // the only legal move is to take the queen
+        let chess: Chess = Fen::from_ascii(b"k7/1Q6/8/8/8/8/8/7K b - - 0 1")
+            .unwrap()
+            .into_position(CastlingMode::Standard)
+            .unwrap();
+        let idx = common.indexer.encode(&chess);
+        assert_eq!(
+            common.all_pos[idx.usize()].get_outcome_by_color(idx.turn),
+            Outcome::Draw
+        );
+        // the processed flags are dropped once tagging is over
+        assert_eq!(common.processed, ProcessedBits::default());
+    }
+
+    #[test]
+    fn test_unknown_policy() {
+        let tb_dir: PathBuf = ["..", "table"].iter().collect();
+        let mat_win = MaterialWinner::from_str("KRvKw").unwrap();
+        let build = |unknown_policy, quarantine| {
+            let options = BuildOptions {
+                objective: Objective::Selfmate,
+                unknown_policy,
+                quarantine,
+                ..Default::default()
+            };
+            TableBaseBuilder::build_with_options(mat_win.clone(), &tb_dir, &options).unwrap()
+        };
+        let (_, draw_report) = build(UnknownPolicy::Draw, false);
+        let (common, report) = build(UnknownPolicy::Unreachable, false);
+        let unreachable = common
+            .all_pos
+            .iter()
+            .flat_map(|by_color| Color::ALL.map(|turn| by_color.get_outcome_by_color(turn)))
+            .filter(|outcome| *outcome == Outcome::Unknown)
+            .count() as u64;
+        assert!(unreachable > 0);
+        // not read back as draws from a WDL table either
+        let tmp = tempfile::tempdir().unwrap();
+        let wdl_path = tmp.path().join("KRvKw.sm.wdl");
+        EncoderDecoder::new(File::create(&wdl_path).unwrap())
+            .compress(&common.all_pos)
+            .unwrap();
+        let wdl_outcomes = EncoderDecoder::new(File::open(&wdl_path).unwrap())
+            .decompress_file_wdl()
+            .unwrap();
+        let wdl_unknown = wdl_outcomes
+            .iter()
+            .flat_map(|by_color| Color::ALL.map(|turn| *by_color.get(turn)))
+            .filter(|wdl| *wdl == crate::Wdl::Unknown)
+            .count() as u64;
+        assert_eq!(wdl_unknown, unreachable);
+        assert_eq!(report.draws + unreachable, draw_report.draws);
+        assert_eq!(report.win_counts, draw_report.win_counts);
+        let (common, _) = build(UnknownPolicy::Error, true);
+        assert_eq!(common.quarantine.unwrap().len() as u64, unreachable);
+    }
+
+    #[test]
+    fn test_check_resources() {
+        let tb_dir: PathBuf = ["..", "table"].iter().collect();
+        let mat_win = MaterialWinner::from_str("KQvKRw").unwrap();
+        let bytes =
+            TableBaseBuilder::check_resources(&mat_win, &tb_dir, &BuildOptions::default()).unwrap();
+        // the table itself, and at least the `KQvK` and `KRvK` descendants of both winners
+        assert!(bytes > get_estimate_nb_pos(&mat_win.material) as u64 * 2 + 4 * 29560);
+        let limited = |max_ram_bytes| BuildOptions {
+            resource_limits: ResourceLimits {
+                max_ram_bytes: Some(max_ram_bytes),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(
+            TableBaseBuilder::check_resources(&mat_win, &tb_dir, &limited(bytes)).unwrap(),
+            bytes
+        );
+        let err =
+            TableBaseBuilder::check_resources(&mat_win, &tb_dir, &limited(bytes - 1)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::OutOfMemory);
+        assert_eq!(
+            crate::ResourceLimitExceeded::of(&err).map(|e| e.bytes),
+            Some(bytes)
+        );
+    }
+
+    #[test]
+    fn test_check_descendants() {
+        let tb_dir: PathBuf = ["..", "table"].iter().collect();
+        let mat_win = MaterialWinner::from_str("KQvKRw").unwrap();
+        TableBaseBuilder::check_descendants(&mat_win, &tb_dir, &BuildOptions::default()).unwrap();
+        let keep = BuildOptions {
+            win_condition: WinCondition::KeepAllPieces,
+            ..Default::default()
+        };
+        let err = TableBaseBuilder::check_descendants(&mat_win, &tb_dir, &keep).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(err.to_string().contains("KQvK"), "{err}");
+        // reported by the build itself, before loading anything
+        let err = TableBaseBuilder::build_with_options(mat_win, &tb_dir, &keep).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_on_phase() {
+        static PHASES: std::sync::Mutex<Vec<(String, BuildPhase)>> =
+            std::sync::Mutex::new(Vec::new());
+        fn record(mat_win: &MaterialWinner, phase: BuildPhase) {
+            PHASES.lock().unwrap().push((format!("{mat_win:?}"), phase));
+        }
+        let tb_dir: PathBuf = ["..", "table"].iter().collect();
+        let options = BuildOptions {
+            on_phase: Some(record),
+            ..Default::default()
+        };
+        TableBaseBuilder::build_with_options(
+            MaterialWinner::from_str("KQvKw").unwrap(),
+            &tb_dir,
+            &options,
+        )
+        .unwrap();
+        assert_eq!(
+            *PHASES.lock().unwrap(),
+            vec![
+                ("KQvKw".to_string(), BuildPhase::Generation),
+                ("KQvKw".to_string(), BuildPhase::Tagging)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mated_king_on_corners() {
+        let tb_dir: PathBuf = ["..", "table"].iter().collect();
+        let corners = BuildOptions {
+            win_condition: WinCondition::MatedKingOn(KingSquares::CORNERS),
+            ..Default::default()
+        };
+        // the queen cannot mate anymore once taken, so only `KQvK` generated with the condition is needed
+        assert_eq!(
+            TableBaseBuilder::descendants_table_names(
+                &MaterialWinner::from_str("KQvKRw").unwrap(),
+                &corners
+            )
+            .iter()
+            .filter(|table_name| table_name.win_condition != WinCondition::AnyMate)
+            .map(ToString::to_string)
+            .collect::<Vec<_>>(),
+            vec!["KQvK.w.hm.on8100000000000081.dtm"]
+        );
+        let mat_win = MaterialWinner::from_str("KQvKw").unwrap();
+        let (_, report) = TableBaseBuilder::build(mat_win.clone(), &tb_dir).unwrap();
+        let (_, corners_report) =
+            TableBaseBuilder::build_with_options(mat_win, &tb_dir, &corners).unwrap();
+        assert!(0 < corners_report.win_counts[0]);
+        assert!(corners_report.win_counts[0] < report.win_counts[0]);
+        assert!(corners_report.win_counts.len() >= report.win_counts.len());
+    }
+
+    #[test]
+    fn test_selfmate_lone_king() {
+        let tb_dir: PathBuf = ["..", "table"].iter().collect();
+        let selfmate = BuildOptions {
+            objective: Objective::Selfmate,
+            ..Default::default()
+        };
+        let (_, helpmate_report) =
+            TableBaseBuilder::build(MaterialWinner::from_str("KRvKw").unwrap(), &tb_dir).unwrap();
+        // the rook side can always avoid mating, so the lone king is only selfmated once mated
+        let (_, report) = TableBaseBuilder::build_with_options(
+            MaterialWinner::from_str("KRvKb").unwrap(),
+            &tb_dir,
+            &selfmate,
+        )
+        .unwrap();
+        assert_eq!(report.win_counts, vec![helpmate_report.win_counts[0]]);
+        assert!(report.lose_counts.is_empty());
+        // and the lone king cannot mate
+        let (_, report) = TableBaseBuilder::build_with_options(
+            MaterialWinner::from_str("KRvKw").unwrap(),
+            &tb_dir,
+            &selfmate,
+        )
+        .unwrap();
+        assert!(report.win_counts.is_empty());
+        assert!(report.lose_counts.is_empty());
+    }
+
+    #[test]
+    fn test_all_moves_win() {
+        let tb_dir: PathBuf = ["..", "table"].iter().collect();
+        let mat_win = MaterialWinner::from_str("KRvKb").unwrap();
+        let selfmate = BuildOptions {
+            objective: Objective::Selfmate,
+            ..Default::default()
+        };
+        let (common, _) =
+            TableBaseBuilder::build_with_options(mat_win.clone(), &tb_dir, &selfmate).unwrap();
+        let descendants =
+            Descendants::new_with_objective(&mat_win, &tb_dir, Objective::Selfmate, None);
+        let mut tagger: Tagger = Tagger::new_selfmate(common, descendants);
+        // white, the loser, has no capture so every move stays in the table
+        let rboard = RetroBoard::new_no_pockets("k7/8/8/8/8/8/8/KR6 w - - 0 1").unwrap();
+        let chess: Chess = rboard.clone().into();
+        let set_after = |tagger: &mut Tagger, nb_moves: usize, outcome: Outcome| {
+            for m in chess.legal_moves().iter().take(nb_moves) {
+                let mut after = chess.clone();
+                after.play_unchecked(m);
+                let idx = tagger.common.indexer.encode(&after);
+                *tagger.common.all_pos[idx.usize()].get_mut(idx.turn) = outcome.into();
+            }
+        };
+        // the rook side can always avoid mating
+        assert!(!tagger.all_moves_win(&rboard, Outcome::Win(0)));
+        set_after(&mut tagger, usize::MAX, Outcome::Win(0));
+        assert!(tagger.all_moves_win(&rboard, Outcome::Win(0)));
+        set_after(&mut tagger, 1, Outcome::Draw);
+        assert!(!tagger.all_moves_win(&rboard, Outcome::Win(0)));
+    }
+
+    /// Also builds the missing descendants tables of `mat_win`, writing them in `tb_dir`
+    fn build_with_descendants(
+        mat_win: &MaterialWinner,
+        tb_dir: &Path,
+        options: &BuildOptions,
+    ) -> Common {
+        write_descendants(mat_win, tb_dir, options);
+        let (common, _) =
+            TableBaseBuilder::build_with_options(mat_win.clone(), tb_dir, options).unwrap();
+        common
+    }
+
+    /// Builds the descendants tables of `mat_win` missing from `tb_dir`, and writes them there
+    fn write_descendants(mat_win: &MaterialWinner, tb_dir: &Path, options: &BuildOptions) {
+        for table_name in TableBaseBuilder::descendants_table_names(mat_win, options) {
+            if !table_name.exists_in(tb_dir) {
+                let common = build_with_descendants(&table_name.mat_win, tb_dir, options);
+                EncoderDecoder::new(File::create(table_name.path_in(tb_dir)).unwrap())
+                    .compress(&common.all_pos)
+                    .unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_selfmate_kqvk() {
+        let tmp = tempfile::tempdir().unwrap();
+        let tb_dir = tmp.path();
+        let selfmate = BuildOptions {
+            objective: Objective::Selfmate,
+            ..Default::default()
+        };
+        let mated: Chess = Fen::from_ascii(b"8/8/8/8/8/8/8/kQK5 b - - 0 1")
+            .unwrap()
+            .into_position(CastlingMode::Standard)
+            .unwrap();
+        let not_mated: Chess = Fen::from_ascii(b"k7/8/8/8/8/8/8/KQ6 w - - 0 1")
+            .unwrap()
+            .into_position(CastlingMode::Standard)
+            .unwrap();
+        // black is mated, as it wanted. White mating black is not a selfmate, only a draw
+        for (mat_win, mate) in [("KQvKb", Outcome::Win(0)), ("KQvKw", Outcome::Draw)] {
+            let common = build_with_descendants(
+                &MaterialWinner::from_str(mat_win).unwrap(),
+                tb_dir,
+                &selfmate,
+            );
+            let outcome_of = |chess: &Chess| {
+                let idx = common.indexer.encode(chess);
+                common.all_pos[idx.usize()].get_outcome_by_color(idx.turn)
+            };
+            assert_eq!(outcome_of(&mated), mate, "{mat_win}");
+            assert_eq!(outcome_of(&not_mated), Outcome::Draw, "{mat_win}");
+            // the queen side can always avoid mating, and the lone king can never mate
+            for by_color in &common.all_pos {
+                for turn in Color::ALL {
+                    let outcome = by_color.get_outcome_by_color(turn);
+                    assert!(
+                        !matches!(outcome, Outcome::Win(1..) | Outcome::Lose(1..)),
+                        "{mat_win} {outcome:?}"
+                    );
+                }
+            }
+            let (retagged, _) = TableBaseBuilder::retag(
+                common.material_winner().clone(),
+                &common.all_pos,
+                tb_dir,
+                &selfmate,
+            )
+            .unwrap();
+            assert_eq!(retagged.all_pos, common.all_pos, "{mat_win}");
+        }
+    }
+
+    #[ignore = "too slow to be enabled by default"]
+    #[test]
+    fn test_selfmate_in_one() {
+        let tmp = tempfile::tempdir().unwrap();
+        let tb_dir = tmp.path();
+        let selfmate = BuildOptions {
+            objective: Objective::Selfmate,
+            ..Default::default()
+        };
+        let common = build_with_descendants(
+            &MaterialWinner::from_str("KRBvKRb").unwrap(),
+            tb_dir,
+            &selfmate,
+        );
+        // 1...Rc2+ 2.Kxc2# is forced, the capture going through the `KRBvK` descendant table
+        let chess: Chess = Fen::from_ascii(b"8/8/8/8/2B5/8/r7/k1KR4 b - - 0 1")
+            .unwrap()
+            .into_position(CastlingMode::Standard)
+            .unwrap();
+        let idx = common.indexer.encode(&chess);
+        assert_eq!(
+            common.all_pos[idx.usize()].get_outcome_by_color(idx.turn),
+            Outcome::Win(2)
+        );
+        // white, in check, can only take the rook
+        let mut after = chess.clone();
+        after.play_unchecked(&Move::Normal {
+            role: Role::Rook,
+            from: Square::A2,
+            capture: None,
+            to: Square::C2,
+            promotion: None,
+        });
+        assert_eq!(after.legal_moves().len(), 1);
+    }
+
+    /// Materials with less than 5 pieces have no selfmate besides the mates themselves, see `test_selfmate_in_one`.
+    /// So the winner aims at another goal, driving the loser's king into a corner, seeded like mates and
+    /// tagged the same way, and every position is checked against the outcomes of its moves
+    #[test]
+    fn test_selfmate_propagation() {
+        struct CornerGoal;
+
+        impl<I> PosHandler<I> for CornerGoal {
+            fn handle_position(
+                &mut self,
+                common: &mut Common<I>,
+                _: &Descendants,
+                chess: &Chess,
+                _: IndexWithTurn,
+                all_pos_idx: usize,
+            ) {
+                if is_corner_goal(chess, common.winner()) {
+                    common.set_report(
+                        all_pos_idx,
+                        chess.turn(),
+                        Report::Unprocessed(Outcome::Win(0)),
+                    );
+                }
+            }
+        }
+
+        fn is_corner_goal(chess: &Chess, winner: Color) -> bool {
+            chess.turn() == winner
+                && Position::board(chess)
+                    .king_of(!winner)
+                    .is_some_and(|sq| Bitboard::CORNERS.contains(sq))
+        }
+
+        let mat_win = MaterialWinner::from_str("KQvKw").unwrap();
+        let tb_dir = tempfile::tempdir().unwrap();
+        let selfmate = BuildOptions {
+            objective: Objective::Selfmate,
+            ..Default::default()
+        };
+        write_descendants(&mat_win, tb_dir.path(), &selfmate);
+        let descendants =
+            || Descendants::new_with_objective(&mat_win, tb_dir.path(), Objective::Selfmate, None);
+        let mut generator: Generator<_, DefaultIndexer> =
+            Generator::new_with_pos_handler_and_descendants(
+                (SelfmateGeneratorHandler, CornerGoal),
+                mat_win.clone(),
+                descendants(),
+            );
+        generator.generate_positions();
+        let (common, _) = generator.get_result();
+        let mut tagger: Tagger = Tagger::new_selfmate(common, descendants());
+        tagger.process_positions();
+        let common = Common::from(tagger);
+        let descendants = descendants();
+        let mut longest_win = 0;
+        for_each_legal_position(&mat_win.material, |chess, idx| {
+            let outcome = common.all_pos[idx.usize()].get_outcome_by_color(idx.turn);
+            let outcomes_after_move = chess.legal_moves().into_iter().map(|m| {
+                let mut after = chess.clone();
+                after.play_unchecked(&m);
+                if m.is_capture() || m.is_promotion() {
+                    descendants.retrieve_outcome(&after, White).unwrap()
+                } else {
+                    let idx = common.indexer.encode(&after);
+                    common.all_pos[idx.usize()].get_outcome_by_color(idx.turn)
+                }
+            });
+            // the winner picks the shortest selfmate, the loser the longest, or escapes
+            let expected = if is_corner_goal(chess, White) {
+                Some(Outcome::Win(0))
+            } else if chess.turn() == White {
+                outcomes_after_move.max()
+            } else {
+                outcomes_after_move.min()
+            }
+            .filter(|outcome| matches!(outcome, Outcome::Win(_)))
+            .map_or(Outcome::Draw, |outcome| {
+                if is_corner_goal(chess, White) {
+                    outcome
+                } else {
+                    outcome + 1
+                }
+            });
+            assert_eq!(outcome, expected, "{chess:?}");
+            if let Outcome::Win(ply) = outcome {
+                longest_win = longest_win.max(ply);
+            }
+        });
+        // the loser is driven into the corner from afar
+        assert!(longest_win > 5, "{longest_win}");
+    }
+
+    #[test]
+    fn test_paranoid_check() {
+        let tb_dir: PathBuf = ["..", "table"].iter().collect();
+        for (mat_win, objective, win_condition) in [
+            ("KQvKw", Objective::Helpmate, WinCondition::AnyMate),
+            ("KQvKb", Objective::Helpmate, WinCondition::AnyMate),
+            (
+                "KQvKw",
+                Objective::Helpmate,
+                WinCondition::MatedKingOn(KingSquares::CORNERS),
+            ),
+            ("KRvKb", Objective::Selfmate, WinCondition::AnyMate),
+        ] {
+            for check in [ParanoidCheck::All, ParanoidCheck::Sample(100)] {
+                let options = BuildOptions {
+                    paranoid: Some(check),
+                    quarantine: true,
+                    objective,
+                    win_condition,
+                    ..Default::default()
+                };
+                let (common, _) = TableBaseBuilder::build_with_options(
+                    MaterialWinner::from_str(mat_win).unwrap(),
+                    &tb_dir,
+                    &options,
+                )
+                .unwrap();
+                assert_eq!(
+                    common.quarantine().map(<[_]>::len),
+                    Some(0),
+                    "{mat_win} {check:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_stop() {
+        let tb_dir: PathBuf = ["..", "table"].iter().collect();
+        let mat_win = MaterialWinner::from_str("KQvKw").unwrap();
+        let (common, _) = TableBaseBuilder::build(mat_win.clone(), &tb_dir).unwrap();
+        let options = BuildOptions {
+            stop: Some(Arc::new(AtomicBool::new(true))),
+            ..Default::default()
+        };
+        // stopped once the positions are enumerated
+        let err =
+            TableBaseBuilder::build_with_options(mat_win.clone(), &tb_dir, &options).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Interrupted);
+        assert!(err.to_string().contains("before Tagging"), "{err}");
+        // stopped before the first ply
+        let err = TableBaseBuilder::retag(mat_win, &common.all_pos, &tb_dir, &options).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Interrupted);
+        assert!(
+            err.to_string().contains("before processing Win(0)"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_resume_from_checkpoint() {
+        let tb_dir: PathBuf = ["..", "table"].iter().collect();
+        let checkpoint_dir = tempfile::tempdir().unwrap();
+        let mat_win = MaterialWinner::from_str("KQvKw").unwrap();
+        let (expected, expected_report) =
+            TableBaseBuilder::build(mat_win.clone(), &tb_dir).unwrap();
+        let options = BuildOptions {
+            checkpoint_dir: Some(checkpoint_dir.path().to_path_buf()),
+            ..Default::default()
+        };
+        let checkpoint_path = options.checkpoint_path(&mat_win).unwrap();
+        let stopped = BuildOptions {
+            checkpoint_dir: Some(checkpoint_dir.path().to_path_buf()),
+            stop: Some(Arc::new(AtomicBool::new(true))),
+            ..Default::default()
+        };
+        // stopped before the first ply
+        let err = TableBaseBuilder::retag(mat_win.clone(), &expected.all_pos, &tb_dir, &stopped)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Interrupted);
+        assert!(checkpoint_path.exists());
+        let (common, build_report) =
+            TableBaseBuilder::build_with_options(mat_win.clone(), &tb_dir, &options).unwrap();
+        assert_eq!(common.all_pos, expected.all_pos);
+        assert_eq!(build_report, expected_report);
+        assert!(!checkpoint_path.exists());
+        // stopped before the first losing ply, once the winning ones are all processed
+        let descendants = Descendants::new(&mat_win, &tb_dir);
+        let (common, _) = TableBaseBuilder::generate(
+            DefaultGeneratorHandler,
+            mat_win.clone(),
+            descendants,
+            &options,
+        );
+        let mut tagger: Tagger = Tagger::new(common);
+        tagger.process_one_queue(true);
+        tagger.stop.clone_from(&stopped.stop);
+        tagger.process_one_queue(false);
+        assert_eq!(tagger.stopped_at, Some(Outcome::Lose(0)));
+        Checkpoint::write(
+            &checkpoint_path,
+            &tagger.common,
+            Outcome::Lose(0),
+            tagger.tagging_passes,
+        )
+        .unwrap();
+        let (common, build_report) =
+            TableBaseBuilder::build_with_options(mat_win, &tb_dir, &options).unwrap();
+        assert_eq!(common.all_pos, expected.all_pos);
+        assert_eq!(build_report, expected_report);
+        assert!(!checkpoint_path.exists());
+    }
+
+    #[test]
+    fn test_paranoid_check_mismatch() {
+        let tb_dir: PathBuf = ["..", "table"].iter().collect();
+        let options = BuildOptions {
+            quarantine: true,
+            ..Default::default()
+        };
+        let (mut common, _) = TableBaseBuilder::build_with_options(
+            MaterialWinner::from_str("KQvKw").unwrap(),
+            &tb_dir,
+            &options,
+        )
+        .unwrap();
+        let chess: Chess = Fen::from_ascii(b"k7/8/1K6/8/8/8/7Q/8 w - - 0 1")
+            .unwrap()
+            .into_position(CastlingMode::Standard)
+            .unwrap();
+        assert_eq!(terminal_outcome(&chess, White, &options), None);
+        let idx = common.indexer.encode(&chess);
+        *common.all_pos[idx.usize()].get_mut(idx.turn) = Outcome::Win(0).into();
+        check_terminal_positions(&mut common, &options, ParanoidCheck::All);
+        assert_eq!(common.quarantine().map(<[_]>::len), Some(1));
     }
 
     #[test]
@@ -546,7 +2494,7 @@ mod tests {
         let rboard = RetroBoard::new_no_pockets(fen).unwrap();
         let chess: Chess = Fen::from_ascii(fen.as_bytes())
             .unwrap()
-            .into_position(Standard)
+            .into_position(CastlingMode::Standard)
             .unwrap();
         assert_eq!(rboard.side_to_move(), White);
         assert_eq!(chess.side_to_move(), White);
@@ -3,19 +3,29 @@
 /// can have.
 /// It has the benefit of being fast and easily reversible
 use retroboard::shakmaty::{
-    Bitboard, Board, ByColor, CastlingMode, Color, Color::Black, Color::White, FromSetup, Piece,
-    Role, Setup, Square,
+    Bitboard, Board, ByColor, CastlingMode, Color, Color::Black, Color::White, File, FromSetup,
+    Move, Piece, Rank, Role, Setup, Square,
 };
 
 use crate::{
     generation::{IndexWithTurn, WithBoard},
-    indexer_syzygy::{INV_TRIANGLE, KK_IDX, TRIANGLE, Z0},
-    is_black_stronger, Material, SideToMove, A1_H8_DIAG,
+    indexer_syzygy::{triangle_idx, KK_IDX},
+    invariants::debug_check_board,
+    is_black_stronger,
+    kings::{decode_kings, KK_NB},
+    Material, SideToMove, A1_H8_DIAG,
 };
 use retroboard::RetroBoard;
+use std::fmt;
 
 pub const A1_D1_D4: Bitboard = Bitboard(135_007_759);
 
+/// Files a to d, where the white king is mapped to when there are pawns
+pub const A_FILE_TO_D_FILE: Bitboard = Bitboard(0x0f0f_0f0f_0f0f_0f0f);
+
+// number of squares the white king can be on when there are pawns, times 64 for the black king
+const KK_PAWNS_NB: u64 = 32 * 64;
+
 pub const PIECES_ORDER: [Piece; 12] = [
     // kings first
     White.king(),
@@ -34,45 +44,6 @@ pub const PIECES_ORDER: [Piece; 12] = [
     Black.queen(),
 ];
 
-// impossible king square setup because by construction the white king
-// should be in the A1_D1_D4 triangle
-const IMPOSSIBLE_KING_SQ: ByColor<Square> = ByColor {
-    white: Square::H8,
-    black: Square::H8,
-};
-
-const fn invert_kk_idx(kk_idx: &[[u64; 64]; 10]) -> [ByColor<Square>; 462] {
-    let mut res: [ByColor<Square>; 462] = [IMPOSSIBLE_KING_SQ; 462];
-    let mut white_king_sq: usize = 0;
-    loop {
-        // for loops not available in const context
-        let mut black_king_sq: usize = 0;
-        loop {
-            let idx = kk_idx[white_king_sq][black_king_sq];
-            if idx != Z0 {
-                res[idx as usize] = ByColor {
-                    white: Square::new(INV_TRIANGLE[white_king_sq] as u32),
-                    black: Square::new(black_king_sq as u32),
-                }
-            }
-
-            // simulating for 0..64
-            black_king_sq += 1;
-            if black_king_sq == 64 {
-                break;
-            }
-        }
-        // simulating for 0..10
-        white_king_sq += 1;
-        if white_king_sq == 10 {
-            break;
-        }
-    }
-    res
-}
-
-const INV_KK_IDX: [ByColor<Square>; 462] = invert_kk_idx(&KK_IDX);
-
 #[rustfmt::skip]
 const WHITE_KING_SQUARES_TO_TRANSFO: [u64; 64] = [
     0, 0, 0, 0, 2, 2, 2, 2,
@@ -113,88 +84,452 @@ pub trait DeIndexer {
     }
 }
 
+/// `Default` is only valid for pawnless materials, use `NaiveIndexer::from(material)`
+/// when the material can have pawns, otherwise the pawn symmetries will be wrong.
 #[allow(clippy::module_name_repetitions)]
 #[derive(Debug, Clone, Copy, Default)]
-pub struct NaiveIndexer;
+pub struct NaiveIndexer {
+    has_pawns: bool,
+}
 
 impl From<Material> for NaiveIndexer {
-    fn from(_: Material) -> Self {
-        Self
+    fn from(mat: Material) -> Self {
+        Self {
+            has_pawns: mat.has_pawns(),
+        }
+    }
+}
+
+impl NaiveIndexer {
+    /// Square of the white king of the position at `index`, without restoring the whole board.
+    /// In the `A1_D1_D4` triangle, or on the a-d files with pawns
+    #[must_use]
+    pub fn white_king_square(self, material: &Material, index: u64) -> Square {
+        let kk_idx = index >> (6 * (material.count() - 2));
+        if self.has_pawns {
+            let white_king_idx = u32::try_from(kk_idx / 64).expect("Corrupted index");
+            Square::from_coords(File::new(white_king_idx % 4), Rank::new(white_king_idx / 4))
+        } else {
+            decode_kings(u16::try_from(kk_idx).expect("Corrupted index")).0
+        }
+    }
+}
+
+/// Transformation of the board bringing the white king in the `A1_D1_D4` triangle,
+/// or on the a-d files with pawns
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Transformation {
+    #[default]
+    Identity,
+    FlipDiagonal,
+    FlipHorizontal,
+    Rotate90,
+    Rotate270,
+    FlipVertical,
+    Rotate180,
+    FlipAntiDiagonal,
+}
+
+impl Transformation {
+    /// `Identity` first
+    pub const ALL: [Self; 8] = [
+        Self::Identity,
+        Self::FlipDiagonal,
+        Self::FlipHorizontal,
+        Self::Rotate90,
+        Self::Rotate270,
+        Self::FlipVertical,
+        Self::Rotate180,
+        Self::FlipAntiDiagonal,
+    ];
+
+    /// Only `FlipHorizontal` keeps the outcome of positions with pawns, all of them otherwise
+    pub fn apply(self, board: &mut Board) {
+        match self {
+            Self::Identity => (),
+            Self::FlipDiagonal => board.flip_diagonal(),
+            Self::FlipHorizontal => board.flip_horizontal(),
+            Self::Rotate90 => board.rotate_90(),
+            Self::Rotate270 => board.rotate_270(),
+            Self::FlipVertical => board.flip_vertical(),
+            Self::Rotate180 => board.rotate_180(),
+            Self::FlipAntiDiagonal => board.flip_anti_diagonal(),
+        }
+    }
+
+    /// Where the piece on `sq` ends up once the board is transformed, see `Transformation::apply`
+    #[must_use]
+    pub fn apply_to_square(self, sq: Square) -> Square {
+        match self {
+            Self::Identity => sq,
+            Self::FlipDiagonal => sq.flip_diagonal(),
+            Self::FlipHorizontal => sq.flip_horizontal(),
+            Self::Rotate90 => sq.rotate_90(),
+            Self::Rotate270 => sq.rotate_270(),
+            Self::FlipVertical => sq.flip_vertical(),
+            Self::Rotate180 => sq.rotate_180(),
+            Self::FlipAntiDiagonal => sq.flip_anti_diagonal(),
+        }
+    }
+
+    /// Transformation undoing `self`
+    #[must_use]
+    pub fn inverse(self) -> Self {
+        match self {
+            Self::Rotate90 => Self::Rotate270,
+            Self::Rotate270 => Self::Rotate90,
+            other => other,
+        }
+    }
+}
+
+/// Transformations applied, in that order, to get the canonical version of a board. See `canonical_board`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Symmetry {
+    /// Colors swapped, black being stronger in the original board
+    pub swap_colors: bool,
+    pub transformation: Transformation,
+    /// Additional flip along the a1-h8 diagonal, when the white king is on it
+    pub flip_diagonal: bool,
+}
+
+impl Symmetry {
+    /// Where the piece on `sq` of the original board is on the canonical one
+    #[must_use]
+    pub fn apply_to_square(self, sq: Square) -> Square {
+        let sq = if self.swap_colors {
+            sq.flip_vertical()
+        } else {
+            sq
+        };
+        let sq = self.transformation.apply_to_square(sq);
+        if self.flip_diagonal {
+            sq.flip_diagonal()
+        } else {
+            sq
+        }
+    }
+
+    /// Where the piece on `sq` of the canonical board is on the original one,
+    /// inverse of `Symmetry::apply_to_square`
+    #[must_use]
+    pub fn revert_square(self, sq: Square) -> Square {
+        let sq = if self.flip_diagonal {
+            sq.flip_diagonal()
+        } else {
+            sq
+        };
+        let sq = self.transformation.inverse().apply_to_square(sq);
+        if self.swap_colors {
+            sq.flip_vertical()
+        } else {
+            sq
+        }
+    }
+
+    /// `m`, played on the original board, as played on the canonical one
+    #[must_use]
+    pub fn apply_to_move(self, m: &Move) -> Move {
+        map_move_squares(m, |sq| self.apply_to_square(sq))
+    }
+
+    /// `m`, played on the canonical board, as played on the original one.
+    /// The moves returned by `TablebaseProber` are always played on the original board
+    #[must_use]
+    pub fn revert_move(self, m: &Move) -> Move {
+        map_move_squares(m, |sq| self.revert_square(sq))
+    }
+
+    /// Original board of the canonical `board`, inverse of `canonical_board`
+    #[must_use]
+    pub fn revert_board(self, board: &Board) -> Board {
+        let mut board = board.clone();
+        if self.flip_diagonal {
+            board.flip_diagonal();
+        }
+        self.transformation.inverse().apply(&mut board);
+        if self.swap_colors {
+            board = swap_color_board(board);
+        }
+        board
+    }
+}
+
+fn map_move_squares(m: &Move, f: impl Fn(Square) -> Square) -> Move {
+    match *m {
+        Move::Normal {
+            role,
+            from,
+            capture,
+            to,
+            promotion,
+        } => Move::Normal {
+            role,
+            from: f(from),
+            capture,
+            to: f(to),
+            promotion,
+        },
+        Move::EnPassant { from, to } => Move::EnPassant {
+            from: f(from),
+            to: f(to),
+        },
+        Move::Castle { king, rook } => Move::Castle {
+            king: f(king),
+            rook: f(rook),
+        },
+        Move::Put { role, to } => Move::Put { role, to: f(to) },
     }
 }
 
 // should take any board and return the canonical version of it, along with a boolean
 // whose truthness is equal to the fact that black were stronger in the original board
+// When `material` has pawns, only horizontal flip is allowed, so the white king ends up on the a-d files
+#[must_use]
+pub fn handle_symetry(b: &Board, material: &Material) -> (Board, bool) {
+    let (board, symmetry) = canonical_board(b, material.has_pawns());
+    (board, symmetry.swap_colors)
+}
+
+/// Canonical version of `b`, as indexed by `NaiveIndexer`, with the transformations applied to get it
 #[must_use]
-pub fn handle_symetry(b: &Board) -> (Board, bool) {
+pub fn canonical_board(b: &Board, has_pawns: bool) -> (Board, Symmetry) {
     let mut board_check = b.clone();
-    let is_black_stronger = is_black_stronger(b.board());
-    if is_black_stronger {
+    let mut symmetry = Symmetry {
+        swap_colors: is_black_stronger(b.board()),
+        ..Default::default()
+    };
+    if symmetry.swap_colors {
         board_check = swap_color_board(board_check);
     }
     let white_king_sq = board_check.king_of(White).expect("white king");
-    let board_transfo_needed = WHITE_KING_SQUARES_TO_TRANSFO[white_king_sq as usize];
-
-    match board_transfo_needed {
-        0 => (),
-        1 => board_check.flip_diagonal(),
-        2 => board_check.flip_horizontal(),
-        3 => board_check.rotate_90(),
-        4 => board_check.rotate_270(),
-        5 => board_check.flip_vertical(),
-        6 => board_check.rotate_180(),
-        7 => board_check.flip_anti_diagonal(),
+    if has_pawns {
+        if !A_FILE_TO_D_FILE.contains(white_king_sq) {
+            symmetry.transformation = Transformation::FlipHorizontal;
+            board_check.flip_horizontal();
+        }
+        return (board_check, symmetry);
+    }
+    symmetry.transformation = match WHITE_KING_SQUARES_TO_TRANSFO[white_king_sq as usize] {
+        0 => Transformation::Identity,
+        1 => Transformation::FlipDiagonal,
+        2 => Transformation::FlipHorizontal,
+        3 => Transformation::Rotate90,
+        4 => Transformation::Rotate270,
+        5 => Transformation::FlipVertical,
+        6 => Transformation::Rotate180,
+        7 => Transformation::FlipAntiDiagonal,
         _ => unreachable!("Only 7 transformations expected"),
     };
+    symmetry.transformation.apply(&mut board_check);
 
     for piece in PIECES_ORDER {
         // we check if flipping would result in a "lower" bitboard
         // dictionary order for all their square.
         // This is a better way to check if there is a symetry on the A1_H8 diagonal
         if board_check.by_piece(piece).flip_diagonal() < board_check.by_piece(piece) {
+            symmetry.flip_diagonal = true;
             board_check.flip_diagonal();
             break;
         } else if !A1_H8_DIAG.is_superset(board_check.by_piece(piece)) {
             break;
         }
     }
-    (board_check, is_black_stronger)
+    (board_check, symmetry)
 }
 
 impl Indexer for NaiveIndexer {
     fn encode_board(&self, b: &Board) -> u64 {
-        let (board_check, _) = handle_symetry(b);
+        let (board_check, _) = canonical_board(b, self.has_pawns);
         self.encode_board_unchecked(&board_check)
     }
 
     /// ASSUME the white king is in the a1-d1-d4 corner already
     /// If the white king is on the `A1_H8` diagonal, the black king MUST BE in the `A1_H1_H8` triangle
+    /// With pawns, ASSUME the white king is on the a-d files instead
     /// Do not take the turn into account the turn
     fn encode_board_unchecked(&self, b: &Board) -> u64 {
-        let mut idx = KK_IDX
-            [TRIANGLE[b.board().king_of(White).expect("white king") as usize] as usize]
-            [b.board().king_of(Black).expect("black king") as usize];
-        debug_assert!(
-            idx < 462,
-            "Corrupted KK index, board: {:?}, idx: {}",
-            b.board(),
-            idx
-        );
-        for role in [
-            Role::Pawn,
-            Role::Knight,
-            Role::Bishop,
-            Role::Rook,
-            Role::Queen,
-        ] {
-            for color in Color::ALL {
-                for sq in b.board().by_piece(Piece { color, role }) {
-                    idx *= 64;
-                    idx += sq as u64;
-                }
+        let mut idx = self.kings_index(b);
+        for_each_indexed_piece(b, |_, sq| {
+            idx *= 64;
+            idx += sq as u64;
+        });
+        idx
+    }
+}
+
+impl NaiveIndexer {
+    /// Number of indexes of `material`, all of them being below it.
+    /// Indexes of illegal positions, eg with pieces on the same square, are included
+    #[must_use]
+    pub fn index_space(self, material: &Material) -> u64 {
+        self.kings_nb()
+            * 64_u64.pow(u32::try_from(material.count() - 2).expect("at most 32 pieces"))
+    }
+
+    /// Smallest number of entries of a complete table of `material`. Trailing entries undefined for both
+    /// sides to move are not written, but the last kings configuration has legal positions, so the table
+    /// ends among its indexes, the last `64^(n-2)` ones of `NaiveIndexer::index_space`
+    #[must_use]
+    pub fn min_table_len(self, material: &Material) -> u64 {
+        let last_kings_indexes =
+            64_u64.pow(u32::try_from(material.count() - 2).expect("at most 32 pieces"));
+        self.index_space(material) - last_kings_indexes + 1
+    }
+
+    #[cfg(feature = "unstable-internals")]
+    #[inline]
+    pub(crate) fn has_pawns(self) -> bool {
+        self.has_pawns
+    }
+
+    /// Number of values of `NaiveIndexer::kings_index`
+    #[inline]
+    pub(crate) fn kings_nb(self) -> u64 {
+        if self.has_pawns {
+            KK_PAWNS_NB
+        } else {
+            u64::from(KK_NB)
+        }
+    }
+
+    /// Squares of both kings from their index, see `NaiveIndexer::kings_index`
+    #[inline]
+    pub(crate) fn kings_squares(self, kings_index: u64) -> ByColor<Square> {
+        if self.has_pawns {
+            let white_king_idx = u32::try_from(kings_index / 64).expect("Corrupted index");
+            ByColor {
+                white: Square::from_coords(
+                    File::new(white_king_idx % 4),
+                    Rank::new(white_king_idx / 4),
+                ),
+                black: unsafe { Square::new_unchecked((kings_index % 64) as u32) },
             }
+        } else {
+            let (white, black) = decode_kings(u16::try_from(kings_index).expect("Corrupted index"));
+            ByColor { white, black }
         }
-        idx
+    }
+
+    /// Index of the squares of both kings, the most significant digit of the index.
+    /// Same assumptions as `encode_board_unchecked`
+    #[inline]
+    pub(crate) fn kings_index(self, b: &Board) -> u64 {
+        let white_king_sq = b.board().king_of(White).expect("white king");
+        let black_king_sq = b.board().king_of(Black).expect("black king");
+        debug_check_board(b.board(), self.has_pawns);
+        if self.has_pawns {
+            (u64::from(white_king_sq.rank()) * 4 + u64::from(white_king_sq.file())) * 64
+                + black_king_sq as u64
+        } else {
+            KK_IDX[triangle_idx(white_king_sq)][black_king_sq as usize]
+        }
+    }
+
+    /// Every step of `encode_board`, to debug index mismatches
+    #[must_use]
+    pub fn explain(self, b: &Board) -> IndexExplanation {
+        let (canonical_board, symmetry) = canonical_board(b, self.has_pawns);
+        let kings_index = self.kings_index(&canonical_board);
+        let mut digits = Vec::new();
+        for_each_indexed_piece(&canonical_board, |piece, sq| digits.push((piece, sq)));
+        IndexExplanation {
+            symmetry,
+            index: self.encode_board_unchecked(&canonical_board),
+            canonical_board,
+            kings_index,
+            digits,
+        }
+    }
+}
+
+// pieces other than kings, in the order they are indexed, from the most significant digit
+#[inline]
+fn for_each_indexed_piece(b: &Board, mut f: impl FnMut(Piece, Square)) {
+    for role in [
+        Role::Pawn,
+        Role::Knight,
+        Role::Bishop,
+        Role::Rook,
+        Role::Queen,
+    ] {
+        for color in Color::ALL {
+            let piece = Piece { color, role };
+            for sq in b.board().by_piece(piece) {
+                f(piece, sq);
+            }
+        }
+    }
+}
+
+/// Steps of `NaiveIndexer::encode_board` for a board, see `NaiveIndexer::explain`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexExplanation {
+    /// Transformations applied to get the canonical board
+    pub symmetry: Symmetry,
+    pub canonical_board: Board,
+    /// Index of the squares of both kings of the canonical board, the most significant digit of the index.
+    /// Below 462, or `32 * 64` with pawns
+    pub kings_index: u64,
+    /// Other pieces of the canonical board in indexing order, the square of each one being a base 64 digit of the index
+    pub digits: Vec<(Piece, Square)>,
+    pub index: u64,
+}
+
+impl IndexExplanation {
+    /// Part of the index coming from each digit, kings first. They sum up to the index
+    #[must_use]
+    pub fn contributions(&self) -> Vec<u64> {
+        let nb_digits =
+            u32::try_from(self.digits.len()).expect("at most 30 pieces besides the kings");
+        std::iter::once(self.kings_index * 64_u64.pow(nb_digits))
+            .chain(
+                self.digits
+                    .iter()
+                    .zip((0..nb_digits).rev())
+                    .map(|((_, sq), exp)| *sq as u64 * 64_u64.pow(exp)),
+            )
+            .collect()
+    }
+}
+
+impl fmt::Display for IndexExplanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let yes_no = |b: bool| if b { "yes" } else { "no" };
+        writeln!(
+            f,
+            "colors swapped (black stronger): {}",
+            yes_no(self.symmetry.swap_colors)
+        )?;
+        writeln!(f, "king transformation: {:?}", self.symmetry.transformation)?;
+        writeln!(
+            f,
+            "a1-h8 diagonal flip: {}",
+            yes_no(self.symmetry.flip_diagonal)
+        )?;
+        writeln!(f, "canonical board: {}", self.canonical_board)?;
+        let contributions = self.contributions();
+        let nb_digits = self.digits.len();
+        writeln!(
+            f,
+            "kings (K{}, k{}): {} * 64^{nb_digits} = {}",
+            self.canonical_board.king_of(White).expect("white king"),
+            self.canonical_board.king_of(Black).expect("black king"),
+            self.kings_index,
+            contributions[0]
+        )?;
+        for (i, ((piece, sq), contribution)) in
+            self.digits.iter().zip(&contributions[1..]).enumerate()
+        {
+            writeln!(
+                f,
+                "{}{sq}: {} * 64^{} = {contribution}",
+                piece.char(),
+                *sq as u64,
+                nb_digits - 1 - i
+            )?;
+        }
+        write!(f, "index: {}", self.index)
     }
 }
 
@@ -217,8 +552,12 @@ impl DeIndexer for NaiveIndexer {
                 }
             }
         }
-        debug_assert!(idx < 462, "Corrupted index: {index}");
-        let kings_sq = INV_KK_IDX[idx as usize];
+        // from `material`, since `Default` ignores pawns
+        let kings_indexer = NaiveIndexer {
+            has_pawns: material.has_pawns(),
+        };
+        debug_assert!(idx < kings_indexer.kings_nb(), "Corrupted index: {index}");
+        let kings_sq = kings_indexer.kings_squares(idx);
         board.set_piece_at(kings_sq.black, Black.king());
         board.set_piece_at(kings_sq.white, White.king());
         board
@@ -226,7 +565,8 @@ impl DeIndexer for NaiveIndexer {
 }
 
 /// flip color of pieces and their positions vertically
-fn swap_color_board(b: Board) -> Board {
+#[must_use]
+pub fn swap_color_board(b: Board) -> Board {
     let (by_roles, by_color) = b.into_bitboards();
     let by_roles_inverted_180 = by_roles.map(Bitboard::flip_vertical);
     Board::from_bitboards(
@@ -244,8 +584,8 @@ mod tests {
 
     #[test]
     fn test_inv_king_idx() {
-        for bc in INV_KK_IDX {
-            assert!(A1_D1_D4.contains(bc.white))
+        for bc in crate::kings::INV_KK_IDX {
+            assert!(A1_D1_D4.contains(bc.white));
         }
     }
 
@@ -253,6 +593,52 @@ mod tests {
         Material::from_str(fen).expect("valid fen config to init Material")
     }
 
+    #[test]
+    fn test_white_king_square() {
+        for (material, fen) in [
+            ("KQvKR", "8/8/8/2k5/8/1r6/2KQ4/8 w - - 0 1"),
+            ("KPvK", "8/8/8/2k5/8/8/1P6/3K4 w - - 0 1"),
+        ] {
+            let material = mat(material);
+            let indexer = NaiveIndexer::from(material.clone());
+            let board =
+                Board::from_ascii_board_fen(fen.split(' ').next().unwrap().as_bytes()).unwrap();
+            let idx = indexer.encode_board(&board);
+            assert_eq!(
+                indexer.white_king_square(&material, idx),
+                indexer
+                    .restore_board(&material, idx)
+                    .king_of(White)
+                    .unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_explain() {
+        for (material, fen, swap_colors) in [
+            ("KQvKR", "8/8/8/2k5/8/1r6/2KQ4/8", false),
+            ("KQvKR", "8/8/8/2k5/8/1R6/2Kq4/8", true),
+            ("KPvK", "8/8/8/2k5/8/8/1P6/3K4", false),
+        ] {
+            let material = mat(material);
+            let indexer = NaiveIndexer::from(material.clone());
+            let board = Board::from_ascii_board_fen(fen.as_bytes()).unwrap();
+            let explanation = indexer.explain(&board);
+            assert_eq!(explanation.index, indexer.encode_board(&board));
+            assert_eq!(
+                explanation.contributions().iter().sum::<u64>(),
+                explanation.index
+            );
+            assert_eq!(
+                explanation.canonical_board,
+                indexer.restore_board(&material, explanation.index)
+            );
+            assert_eq!(explanation.digits.len(), material.count() - 2);
+            assert_eq!(explanation.symmetry.swap_colors, swap_colors);
+        }
+    }
+
     #[test]
     fn test_swap_color_board() {
         let b = Board::from_ascii_board_fen(b"8/8/2p2P2/3nN3/3Bb3/2R2r2/1Q4q1/K6k").unwrap();
@@ -261,21 +647,58 @@ mod tests {
         assert_eq!(swap_color_board(b), swapped_b);
     }
 
+    #[test]
+    fn test_transformation_apply_to_square() {
+        for (transformation, sq) in itertools::iproduct!(Transformation::ALL, Square::ALL) {
+            let mut board = Board::empty();
+            board.set_piece_at(sq, White.king());
+            transformation.apply(&mut board);
+            let transformed_sq = transformation.apply_to_square(sq);
+            assert_eq!(board.king_of(White), Some(transformed_sq));
+            assert_eq!(transformation.inverse().apply_to_square(transformed_sq), sq);
+        }
+    }
+
+    #[test]
+    fn test_symmetry_revert() {
+        for fen in [
+            "8/8/8/8/8/1QK5/8/k7",
+            "4K3/3q4/8/8/8/8/8/3k4",
+            "5R1k/7R/8/8/8/8/8/K7",
+            "3k4/K7/1p6/8/8/8/8/8",
+            "k7/8/1K6/8/8/8/7Q/8",
+        ] {
+            let board = Board::from_ascii_board_fen(fen.as_bytes()).unwrap();
+            for has_pawns in [false, true] {
+                let (canonical, symmetry) = canonical_board(&board, has_pawns);
+                assert_eq!(symmetry.revert_board(&canonical), board);
+                for sq in board.occupied() {
+                    let canonical_sq = symmetry.apply_to_square(sq);
+                    assert_eq!(symmetry.revert_square(canonical_sq), sq);
+                    let piece = board.piece_at(sq).unwrap();
+                    let canonical_piece = canonical.piece_at(canonical_sq).unwrap();
+                    assert_eq!(canonical_piece.role, piece.role);
+                    assert_eq!(canonical_piece.color, piece.color ^ symmetry.swap_colors);
+                }
+            }
+        }
+    }
+
     #[test]
     fn test_index_unchecked_high_value_index() {
         let high_value_board = RetroBoard::new_no_pockets("3BNQQk/8/8/8/3K4/8/8/8 b - -").unwrap();
-        let idx = NaiveIndexer.encode_unchecked(&high_value_board);
+        let idx = NaiveIndexer::default().encode_unchecked(&high_value_board);
         let config = mat("KBNQQvK");
-        let high_value_from_idx = NaiveIndexer.restore_board(&config, idx.idx);
+        let high_value_from_idx = NaiveIndexer::default().restore_board(&config, idx.idx);
         assert_eq!(high_value_board.board(), &high_value_from_idx);
     }
 
     #[test]
     fn test_index_unchecked_then_de_index() {
         let two_kings = RetroBoard::new_no_pockets("8/7k/8/8/3K4/8/8/8 b").unwrap();
-        let idx = NaiveIndexer.encode_unchecked(&two_kings);
+        let idx = NaiveIndexer::default().encode_unchecked(&two_kings);
         let config = mat("KvK");
-        let two_kings_from_idx = NaiveIndexer.restore_board(&config, idx.idx);
+        let two_kings_from_idx = NaiveIndexer::default().restore_board(&config, idx.idx);
         assert_eq!(two_kings.board(), &two_kings_from_idx);
     }
 
@@ -283,7 +706,7 @@ mod tests {
     fn test_check_a1_h8_diagonal_symetry() {
         for fen in ["8/8/8/8/8/1QK5/8/k7", "8/8/8/8/8/2K5/2Q5/k7"] {
             let r = Board::from_ascii_board_fen(fen.as_bytes()).unwrap();
-            let idx = NaiveIndexer.encode_board(&r);
+            let idx = NaiveIndexer::default().encode_board(&r);
             assert_eq!(idx, 28938);
         }
     }
@@ -292,7 +715,7 @@ mod tests {
     fn test_check_a1_h8_diagonal_symetry2() {
         for fen in ["8/8/8/8/8/2K5/k1Q5/8", "8/8/8/8/8/1QK5/8/1k6"] {
             let r = Board::from_ascii_board_fen(fen.as_bytes()).unwrap();
-            let idx = NaiveIndexer.encode_board(&r);
+            let idx = NaiveIndexer::default().encode_board(&r);
             assert_eq!(idx, 25041);
         }
     }
@@ -301,7 +724,7 @@ mod tests {
     fn test_check_a1_h8_diagonal_symetry3() {
         for fen in ["5R1k/7R/8/8/8/8/8/K7", "6Rk/8/7R/8/8/8/8/K7"] {
             let r = Board::from_ascii_board_fen(fen.as_bytes()).unwrap();
-            let idx = NaiveIndexer.encode_board(&r);
+            let idx = NaiveIndexer::default().encode_board(&r);
             assert_eq!(idx, 1_830_397);
         }
     }
@@ -310,7 +733,7 @@ mod tests {
     fn test_check_a1_h8_diagonal_symetry4() {
         for fen in ["5R1k/7R/8/8/8/8/8/K7", "6Rk/8/7R/8/8/8/8/K7"] {
             let r = Board::from_ascii_board_fen(fen.as_bytes()).unwrap();
-            let idx = NaiveIndexer.encode_board(&r);
+            let idx = NaiveIndexer::default().encode_board(&r);
             assert_eq!(idx, 1_830_397);
         }
     }
@@ -319,22 +742,57 @@ mod tests {
     fn test_check_a1_h8_diagonal_symetry5() {
         for fen in ["5RRk/7R/8/8/8/8/8/K7", "6Rk/7R/7R/8/8/8/8/K7"] {
             let r = Board::from_ascii_board_fen(fen.as_bytes()).unwrap();
-            let idx = NaiveIndexer.encode_board(&r);
+            let idx = NaiveIndexer::default().encode_board(&r);
             assert_eq!(idx, 117_112_318);
         }
     }
 
+    #[test]
+    fn test_pawns_only_horizontal_symetry() {
+        let config = mat("KPvK");
+        let indexer = NaiveIndexer::from(config.clone());
+        // mirrored horizontally
+        let idx =
+            indexer.encode_board(&Board::from_ascii_board_fen(b"8/8/8/8/8/1P6/k7/3K4").unwrap());
+        assert_eq!(
+            idx,
+            indexer.encode_board(&Board::from_ascii_board_fen(b"8/8/8/8/8/6P1/7k/4K3").unwrap())
+        );
+        // flipped diagonally, different position since pawns do not move the same way
+        assert_ne!(
+            idx,
+            indexer.encode_board(&Board::from_ascii_board_fen(b"8/8/8/8/8/8/1k3P2/3K4").unwrap())
+        );
+        // black stronger, colors are swapped and the board flipped vertically
+        assert_eq!(
+            idx,
+            indexer.encode_board(&Board::from_ascii_board_fen(b"3k4/K7/1p6/8/8/8/8/8").unwrap())
+        );
+    }
+
+    #[test]
+    fn test_pawns_index_then_de_index() {
+        let config = mat("KPvKP");
+        let indexer = NaiveIndexer::from(config.clone());
+        for fen in ["8/1p6/8/8/8/6P1/k7/3K4", "k7/8/8/8/8/2p5/1P6/K7"] {
+            let board = Board::from_ascii_board_fen(fen.as_bytes()).unwrap();
+            let idx = indexer.encode_board(&board);
+            assert_eq!(indexer.restore_board(&config, idx), board);
+        }
+    }
+
     #[test]
     fn test_index_unchecked_then_de_index_no_swapping_color() {
         // check if the color of the pieces are not swapped.
         let knights = RetroBoard::new_no_pockets("8/8/8/8/8/1N6/8/KBkn4 b").unwrap();
         let knights_color_swapped = RetroBoard::new_no_pockets("8/8/8/8/8/1n6/8/KBkN4 b").unwrap();
-        let idx = NaiveIndexer.encode_unchecked(&knights);
-        let idx_swapped = NaiveIndexer.encode_unchecked(&knights_color_swapped);
+        let idx = NaiveIndexer::default().encode_unchecked(&knights);
+        let idx_swapped = NaiveIndexer::default().encode_unchecked(&knights_color_swapped);
         assert_ne!(idx, idx_swapped);
         let config = mat("KBNvKN");
-        let knights_from_idx = NaiveIndexer.restore_board(&config, idx.idx);
-        let knights_swapped_from_idx = NaiveIndexer.restore_board(&config, idx_swapped.idx);
+        let knights_from_idx = NaiveIndexer::default().restore_board(&config, idx.idx);
+        let knights_swapped_from_idx =
+            NaiveIndexer::default().restore_board(&config, idx_swapped.idx);
         assert_eq!(knights.board(), &knights_from_idx);
         assert_eq!(knights_color_swapped.board(), &knights_swapped_from_idx);
     }
@@ -359,9 +817,9 @@ mod tests {
             let rboard =
                 RetroBoard::from_setup(setup, CastlingMode::Standard).expect("Valid setup");
             println!("{rboard:?}");
-            let idx = NaiveIndexer.encode(&rboard);
+            let idx = NaiveIndexer::default().encode(&rboard);
             let config = mat("KvK");
-            let rboard_restored = NaiveIndexer.restore_board(&config, idx.idx);
+            let rboard_restored = NaiveIndexer::default().restore_board(&config, idx.idx);
             let white_king_bb = Bitboard::EMPTY
                 | Square::A1
                 | Square::B1
@@ -0,0 +1,241 @@
+/// Compact alternative to `NaiveIndexer`, sharing its canonical board and kings index.
+/// Instead of a base 64 digit per piece, each group of like pieces is a single digit: the combination
+/// of their squares among the ones still free, in the combinatorial number system, as syzygy does.
+/// It is still reversible, but slower than `NaiveIndexer`
+use retroboard::shakmaty::{Bitboard, Board, Color, Piece, Square};
+
+use crate::{
+    compression::{to_u64, to_usize},
+    indexer::{canonical_board, DeIndexer, Indexer, NaiveIndexer},
+    Material,
+};
+
+/// Pieces other than kings, in the order they are indexed, from the most significant digit
+const INDEXED_PIECES: [Piece; 10] = [
+    Color::White.pawn(),
+    Color::Black.pawn(),
+    Color::White.knight(),
+    Color::Black.knight(),
+    Color::White.bishop(),
+    Color::Black.bishop(),
+    Color::White.rook(),
+    Color::Black.rook(),
+    Color::White.queen(),
+    Color::Black.queen(),
+];
+
+/// `Default` is only valid for pawnless materials, use `CompactIndexer::from(material)`
+/// when the material can have pawns, as for `NaiveIndexer`
+#[allow(clippy::module_name_repetitions)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CompactIndexer {
+    naive: NaiveIndexer,
+}
+
+impl From<Material> for CompactIndexer {
+    fn from(mat: Material) -> Self {
+        Self {
+            naive: NaiveIndexer::from(mat),
+        }
+    }
+}
+
+impl CompactIndexer {
+    /// Number of indexes of `material`, all of them being below it, see `NaiveIndexer::index_space`
+    #[must_use]
+    pub fn index_space(self, material: &Material) -> u64 {
+        let mut nb_free = 62;
+        INDEXED_PIECES
+            .iter()
+            .fold(self.naive.kings_nb(), |space, piece| {
+                let nb = u64::from(material.by_piece(*piece));
+                let radix = binomial(nb_free, nb);
+                nb_free -= nb;
+                space * radix
+            })
+    }
+}
+
+impl Indexer for CompactIndexer {
+    fn encode_board(&self, b: &Board) -> u64 {
+        let (board_check, _) = canonical_board(b, self.naive.has_pawns());
+        self.encode_board_unchecked(&board_check)
+    }
+
+    /// Same assumptions as `NaiveIndexer::encode_board_unchecked`
+    fn encode_board_unchecked(&self, b: &Board) -> u64 {
+        let mut idx = self.naive.kings_index(b);
+        let mut occupied = b.kings();
+        for piece in INDEXED_PIECES {
+            let squares = b.by_piece(piece);
+            idx = idx * binomial(64 - to_u64(occupied.count()), to_u64(squares.count()))
+                + combination_index(squares, occupied);
+            occupied |= squares;
+        }
+        idx
+    }
+}
+
+impl DeIndexer for CompactIndexer {
+    fn restore_board(&self, material: &Material, index: u64) -> Board {
+        let mut digits = [0; INDEXED_PIECES.len()];
+        let mut nb_free = 62;
+        let radixes = INDEXED_PIECES.map(|piece| {
+            let nb = u64::from(material.by_piece(piece));
+            let radix = binomial(nb_free, nb);
+            nb_free -= nb;
+            radix
+        });
+        let mut idx = index;
+        for (digit, radix) in digits.iter_mut().zip(radixes).rev() {
+            *digit = idx % radix;
+            idx /= radix;
+        }
+        debug_assert!(idx < self.naive.kings_nb(), "Corrupted index: {index}");
+        let kings_sq = self.naive.kings_squares(idx);
+        let mut board = Board::empty();
+        board.set_piece_at(kings_sq.black, Color::Black.king());
+        board.set_piece_at(kings_sq.white, Color::White.king());
+        for (piece, digit) in INDEXED_PIECES.into_iter().zip(digits) {
+            let squares =
+                restore_combination(digit, u64::from(material.by_piece(piece)), board.occupied());
+            for sq in squares {
+                board.set_piece_at(sq, piece);
+            }
+        }
+        board
+    }
+}
+
+#[inline]
+const fn binomial(n: u64, k: u64) -> u64 {
+    if k > n {
+        return 0;
+    }
+    let mut res = 1;
+    let mut i = 0;
+    while i < k {
+        res = res * (n - i) / (i + 1);
+        i += 1;
+    }
+    res
+}
+
+// position of `sq` among the squares not in `occupied`
+#[inline]
+fn free_rank(sq: Square, occupied: Bitboard) -> u64 {
+    u64::from((!occupied.0 & ((1 << sq as u32) - 1)).count_ones())
+}
+
+// index of `squares` among all the combinations of as many squares not in `occupied`
+#[inline]
+fn combination_index(squares: Bitboard, occupied: Bitboard) -> u64 {
+    (1..)
+        .zip(squares)
+        .map(|(i, sq)| binomial(free_rank(sq, occupied), i))
+        .sum()
+}
+
+// inverse of `combination_index`, for `nb` squares
+fn restore_combination(mut digit: u64, nb: u64, occupied: Bitboard) -> Bitboard {
+    let free: Vec<Square> = (!occupied).into_iter().collect();
+    let mut rank = to_u64(free.len());
+    let mut squares = Bitboard::EMPTY;
+    for i in (1..=nb).rev() {
+        rank -= 1;
+        while binomial(rank, i) > digit {
+            rank -= 1;
+        }
+        digit -= binomial(rank, i);
+        squares.add(free[to_usize(rank)]);
+    }
+    squares
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::for_each_legal_position;
+    use retroboard::shakmaty::Position;
+    use std::str::FromStr;
+
+    fn mat(fen: &str) -> Material {
+        Material::from_str(fen).expect("valid fen config to init Material")
+    }
+
+    #[test]
+    fn test_binomial() {
+        assert_eq!(binomial(62, 0), 1);
+        assert_eq!(binomial(62, 1), 62);
+        assert_eq!(binomial(62, 2), 1891);
+        assert_eq!(binomial(2, 3), 0);
+    }
+
+    #[test]
+    fn test_combination_roundtrip() {
+        let occupied = Bitboard::from(Square::A1) | Bitboard::from(Square::E4);
+        let mut indexes = Vec::new();
+        for a in !occupied {
+            for b in (!occupied).into_iter().filter(|b| *b > a) {
+                let squares = Bitboard::from(a) | Bitboard::from(b);
+                let idx = combination_index(squares, occupied);
+                assert_eq!(restore_combination(idx, 2, occupied), squares);
+                indexes.push(idx);
+            }
+        }
+        indexes.sort_unstable();
+        assert_eq!(indexes, (0..binomial(62, 2)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_index_then_de_index() {
+        for config in ["KQvK", "KPvK", "KNvKN"] {
+            let material = mat(config);
+            let indexer = CompactIndexer::from(material.clone());
+            let index_space = indexer.index_space(&material);
+            for_each_legal_position(&material, |chess, _| {
+                let (canonical, _) = canonical_board(chess.board(), material.has_pawns());
+                let idx = indexer.encode_board(chess.board());
+                assert!(idx < index_space, "{config}, {canonical:?}");
+                assert_eq!(indexer.restore_board(&material, idx), canonical);
+            });
+        }
+    }
+
+    #[test]
+    fn test_pawns_index_then_de_index() {
+        for fen in [
+            "8/1p6/8/8/8/6P1/k7/3K4",
+            "k7/8/8/8/8/2p5/1P6/K7",
+            "8/8/8/3r4/8/1PP5/8/K1k5",
+        ] {
+            let board = Board::from_ascii_board_fen(fen.as_bytes()).unwrap();
+            let config = Material::from_board(&board);
+            let indexer = CompactIndexer::from(config.clone());
+            let idx = indexer.encode_board(&board);
+            assert_eq!(indexer.restore_board(&config, idx), board);
+        }
+    }
+
+    #[test]
+    fn test_index_space_smaller_than_naive() {
+        for config in [
+            "KQvKR", "KRvKB", "KQQvK", "KRRvK", "KBNvK", "KNNvK", "KPvKP", "KPPvK", "KQvKP",
+        ] {
+            let material = mat(config);
+            let naive = NaiveIndexer::from(material.clone()).index_space(&material);
+            let compact = CompactIndexer::from(material.clone()).index_space(&material);
+            assert!(compact < naive, "{config}: {compact} >= {naive}");
+        }
+        // with two like pieces, the combinations are half of the 2-permutations
+        let material = mat("KRRvK");
+        assert_eq!(
+            CompactIndexer::default().index_space(&material),
+            462 * binomial(62, 2)
+        );
+        assert_eq!(
+            NaiveIndexer::default().index_space(&material),
+            462 * 64 * 64
+        );
+    }
+}
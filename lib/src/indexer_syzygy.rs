@@ -3,6 +3,7 @@ use itertools::Itertools as _;
 use retroboard::shakmaty::{Bitboard, File, Piece, Rank, Role, Square};
 
 use crate::{
+    compression::to_usize,
     get_info_table,
     indexer::{handle_symetry, Indexer},
     is_black_stronger, Material, SideToMove,
@@ -45,6 +46,12 @@ pub type Pieces = ArrayVec<Piece, MAX_PIECES>;
 /// Inverse of `TRIANGLE`.
 pub const INV_TRIANGLE: [usize; 10] = [1, 2, 3, 10, 11, 19, 0, 9, 18, 27];
 
+/// `TRIANGLE` of `sq`, to index the tables built on it, eg `KK_IDX`
+#[inline]
+pub(crate) fn triangle_idx(sq: Square) -> usize {
+    to_usize(TRIANGLE[usize::from(sq)])
+}
+
 /// Maps the b1-h1-h7 triangle to `0..=27`.
 #[rustfmt::skip]
 const LOWER: [u64; 64] = [
@@ -73,7 +80,7 @@ const MULT_TWIST: [u64; 64] = [
 
 /// Unused entry. Initialized to `-1`, so that most uses will cause noticable
 /// overflow in debug mode.
-pub const Z0: u64 = u64::max_value();
+pub const Z0: u64 = u64::MAX;
 
 /// Encoding of all 462 configurations of two not-connected kings.
 #[rustfmt::skip]
@@ -345,6 +352,7 @@ impl Consts {
 /// A Syzygy table.
 #[derive(Debug, Clone)]
 pub struct Table {
+    material: Material,
     num_unique_pieces: u8,
     min_like_man: u8,
     files: ArrayVec<ArrayVec<GroupData, 2>, 4>,
@@ -474,6 +482,7 @@ impl From<Material> for Table {
             num_unique_pieces: material.unique_pieces(),
             min_like_man: material.min_like_man(),
             files,
+            material,
         }
     }
 }
@@ -489,7 +498,7 @@ impl Indexer for Table {
 
     // copied from `naive indexer`
     fn encode(&self, pos: &impl SideToMove) -> crate::IndexWithTurn {
-        let (board_check, is_black_stronger) = handle_symetry(pos.board());
+        let (board_check, is_black_stronger) = handle_symetry(pos.board(), &self.material);
         self.encode_unchecked(&(board_check, pos.side_to_move() ^ is_black_stronger))
     }
 
@@ -509,12 +518,12 @@ impl Indexer for Table {
 
 impl Table {
     /// Given a position, determine the unique (modulo symmetries) index into
-    /// the corresponding subtable.
+    /// the corresponding subtable. `pos` must have the material of the table.
     #[allow(clippy::similar_names)] // changing names would make comparison with upstream more difficult
     #[allow(clippy::too_many_lines)] // same for refactoring
     #[allow(clippy::bool_to_int_with_if)] // same for refactoring
     pub fn encode_checked(&self, pos: &impl SideToMove) -> Option<u64> {
-        let material = Material::from_board(pos.board());
+        let material = &self.material;
 
         let symmetric_btm = material.is_symmetric() && pos.side_to_move().is_black();
         let black_stronger = is_black_stronger(pos.board());
@@ -666,7 +675,7 @@ impl Table {
                             + (squares[1].rank() as u64 - adjust)
                     }
                 } else {
-                    KK_IDX[TRIANGLE[usize::from(squares[0])] as usize][usize::from(squares[1])]
+                    KK_IDX[triangle_idx(squares[0])][usize::from(squares[1])]
                 }
             } else if self.min_like_man == 2 {
                 if TRIANGLE[usize::from(squares[0])] > TRIANGLE[usize::from(squares[1])] {
@@ -704,7 +713,7 @@ impl Table {
                     }
                 }
 
-                PP_IDX[TRIANGLE[usize::from(squares[0])] as usize][usize::from(squares[1])]
+                PP_IDX[triangle_idx(squares[0])][usize::from(squares[1])]
             } else {
                 for i in 1..side.lens[0] {
                     if TRIANGLE[usize::from(squares[0])] > TRIANGLE[usize::from(squares[i])] {
@@ -739,8 +748,7 @@ impl Table {
                     }
                 }
 
-                let mut idx =
-                    CONSTS.mult_idx[side.lens[0] - 1][TRIANGLE[usize::from(squares[0])] as usize];
+                let mut idx = CONSTS.mult_idx[side.lens[0] - 1][triangle_idx(squares[0])];
                 for i in 1..side.lens[0] {
                     idx += binomial(MULT_TWIST[usize::from(squares[i])], i as u64);
                 }
@@ -753,9 +761,8 @@ impl Table {
         // Encode remaining pawns.
         let mut remaining_pawns =
             material.by_color.white.has_pawns() && material.by_color.black.has_pawns();
-        let mut next = 1;
         let mut group_sq = side.lens[0];
-        for lens in side.lens.iter().copied().skip(1) {
+        for (next, lens) in (1..).zip(side.lens.iter().copied().skip(1)) {
             let (prev_squares, group_squares) = squares.split_at_mut(group_sq);
             let group_squares = &mut group_squares[..lens];
             group_squares.sort_unstable();
@@ -776,7 +783,6 @@ impl Table {
             remaining_pawns = false;
             idx += n * side.factors[next];
             group_sq += side.lens[next];
-            next += 1;
         }
         Some(idx)
     }
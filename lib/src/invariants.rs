@@ -0,0 +1,216 @@
+//! Invariants the indexers and the tables rely on, checked in a single place. The `check_*` functions
+//! return the first one broken, and are called by `helpmate_tb_cli verify`. The `debug_check_*` ones panic
+//! on it, and are called at key boundaries in debug builds only: once a board is canonical, and once a table is tagged
+use std::fmt;
+
+use retroboard::shakmaty::{Board, Color, Square};
+
+use crate::{
+    file_handler::Objective,
+    indexer::{A1_D1_D4, A_FILE_TO_D_FILE},
+    is_black_stronger,
+    kings::{encode_kings, KK_NB},
+    IndexWithTurn, Outcome, OutcomesSlice,
+};
+
+/// Invariant broken, see `check_board` and `check_reports`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InvariantViolation {
+    /// A canonical board has both kings
+    MissingKing(Color),
+    /// The white king of a canonical board is in the `A1_D1_D4` triangle, or on files a to d with pawns
+    WhiteKingNotCanonical(Square),
+    /// Once the white king is in the `A1_D1_D4` triangle, the kings are one of the `KK_NB` configurations:
+    /// not adjacent, and the black king not above the a1-h8 diagonal while the white king is on it
+    KingsNotIndexable { white: Square, black: Square },
+    /// Black is never stronger than white in a canonical board, see `is_black_stronger`
+    MaterialNotNormalized,
+    /// `Win(n)` and `Lose(n)` count plies, so whose turn it is follows from the parity of `n`
+    WrongParity(IndexWithTurn, Outcome),
+}
+
+impl fmt::Display for InvariantViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingKing(color) => write!(f, "no {color} king"),
+            Self::WhiteKingNotCanonical(sq) => {
+                write!(f, "white king on {sq}, outside of the canonical squares")
+            }
+            Self::KingsNotIndexable { white, black } => write!(
+                f,
+                "kings on {white} and {black} are not among the {KK_NB} indexed configurations"
+            ),
+            Self::MaterialNotNormalized => write!(f, "black is stronger than white"),
+            Self::WrongParity(idx, outcome) => {
+                write!(f, "{outcome:?} at {idx:?}, with the wrong side to move")
+            }
+        }
+    }
+}
+
+impl std::error::Error for InvariantViolation {}
+
+/// Invariants of a canonical board, as returned by `canonical_board`
+pub fn check_board(board: &Board, has_pawns: bool) -> Result<(), InvariantViolation> {
+    let king = |color| {
+        board
+            .king_of(color)
+            .ok_or(InvariantViolation::MissingKing(color))
+    };
+    let (white, black) = (king(Color::White)?, king(Color::Black)?);
+    if is_black_stronger(board) {
+        return Err(InvariantViolation::MaterialNotNormalized);
+    }
+    if has_pawns {
+        return if A_FILE_TO_D_FILE.contains(white) {
+            Ok(())
+        } else {
+            Err(InvariantViolation::WhiteKingNotCanonical(white))
+        };
+    }
+    if !A1_D1_D4.contains(white) {
+        return Err(InvariantViolation::WhiteKingNotCanonical(white));
+    }
+    encode_kings(white, black)
+        .map(|_| ())
+        .ok_or(InvariantViolation::KingsNotIndexable { white, black })
+}
+
+/// Invariants of the outcomes of a tagged table of `winner`, the first one being at `index_from`
+pub fn check_reports(
+    outcomes: OutcomesSlice<'_>,
+    index_from: u64,
+    winner: Color,
+    objective: Objective,
+) -> Result<(), InvariantViolation> {
+    for (idx, by_color) in (index_from..).zip(outcomes) {
+        for turn in Color::ALL {
+            let idx_with_turn = IndexWithTurn { idx, turn };
+            let outcome = Outcome::from(by_color.get(turn));
+            if let Some((mated, plies)) = mated_side(outcome, winner, objective) {
+                // the mated side is to move after an even number of plies
+                if (turn == mated) != (plies % 2 == 0) {
+                    return Err(InvariantViolation::WrongParity(idx_with_turn, outcome));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// side mated at the end of the line, if any, and the number of plies until then
+fn mated_side(outcome: Outcome, winner: Color, objective: Objective) -> Option<(Color, u8)> {
+    match (outcome, objective) {
+        (Outcome::Win(plies), Objective::Helpmate)
+        | (Outcome::Lose(plies), Objective::Selfmate) => Some((!winner, plies)),
+        (Outcome::Lose(plies), Objective::Helpmate)
+        | (Outcome::Win(plies), Objective::Selfmate) => Some((winner, plies)),
+        _ => None,
+    }
+}
+
+/// Panics if `check_board` fails, in debug builds only
+#[inline]
+#[cfg_attr(not(debug_assertions), allow(unused_variables))]
+pub(crate) fn debug_check_board(board: &Board, has_pawns: bool) {
+    #[cfg(debug_assertions)]
+    if let Err(violation) = check_board(board, has_pawns) {
+        panic!("{violation}, board: {board:?}");
+    }
+}
+
+/// Panics if `check_reports` fails, in debug builds only
+#[cfg_attr(not(debug_assertions), allow(unused_variables))]
+pub(crate) fn debug_check_reports(
+    outcomes: OutcomesSlice<'_>,
+    winner: Color,
+    objective: Objective,
+) {
+    #[cfg(debug_assertions)]
+    if let Err(violation) = check_reports(outcomes, 0, winner, objective) {
+        panic!("{violation}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{OutcomeU8, UNDEFINED_OUTCOME_BYCOLOR};
+    use retroboard::shakmaty::ByColor;
+
+    fn board(fen: &str) -> Board {
+        Board::from_ascii_board_fen(fen.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_check_board() {
+        assert_eq!(check_board(&board("8/8/8/8/8/8/8/KQ1k4"), false), Ok(()));
+        assert_eq!(
+            check_board(&board("8/8/8/8/8/8/8/Q2k3K"), false),
+            Err(InvariantViolation::WhiteKingNotCanonical(Square::H1))
+        );
+        assert_eq!(
+            check_board(&board("8/8/8/8/8/8/8/KkQ5"), false),
+            Err(InvariantViolation::KingsNotIndexable {
+                white: Square::A1,
+                black: Square::B1
+            })
+        );
+        assert_eq!(
+            check_board(&board("8/8/8/8/8/8/8/K2kq3"), false),
+            Err(InvariantViolation::MaterialNotNormalized)
+        );
+        assert_eq!(
+            check_board(&board("8/8/8/8/8/8/8/Q2k4"), false),
+            Err(InvariantViolation::MissingKing(Color::White))
+        );
+        // only the files matter with pawns
+        assert_eq!(check_board(&board("8/8/8/8/8/8/P7/3K3k"), true), Ok(()));
+        assert_eq!(
+            check_board(&board("8/8/8/8/8/8/P7/4K2k"), true),
+            Err(InvariantViolation::WhiteKingNotCanonical(Square::E1))
+        );
+    }
+
+    #[test]
+    fn test_check_reports() {
+        let outcomes = |white: Outcome| {
+            vec![
+                UNDEFINED_OUTCOME_BYCOLOR,
+                ByColor {
+                    black: OutcomeU8::from(Outcome::Win(0)),
+                    white: OutcomeU8::from(white),
+                },
+            ]
+        };
+        let check = |white: Outcome, objective| {
+            check_reports(&outcomes(white), 10, Color::White, objective)
+        };
+        // black is mated, and white mates in one
+        assert_eq!(check(Outcome::Win(1), Objective::Helpmate), Ok(()));
+        assert_eq!(check(Outcome::Draw, Objective::Helpmate), Ok(()));
+        assert_eq!(check(Outcome::Lose(0), Objective::Helpmate), Ok(()));
+        let white_to_move = IndexWithTurn {
+            idx: 11,
+            turn: Color::White,
+        };
+        assert_eq!(
+            check(Outcome::Win(2), Objective::Helpmate),
+            Err(InvariantViolation::WrongParity(
+                white_to_move,
+                Outcome::Win(2)
+            ))
+        );
+        // in a selfmate, the winner is the one mated
+        assert_eq!(
+            check(Outcome::Draw, Objective::Selfmate),
+            Err(InvariantViolation::WrongParity(
+                IndexWithTurn {
+                    idx: 11,
+                    turn: Color::Black
+                },
+                Outcome::Win(0)
+            ))
+        );
+    }
+}
@@ -0,0 +1,113 @@
+//! Index of the 462 configurations of two kings, once the white king is mapped to the `A1_D1_D4`
+//! triangle and, when on the a1-h8 diagonal, the black king to the lower half of the board.
+//! It is the most significant digit of the indexes of pawnless tables
+
+use retroboard::shakmaty::{ByColor, Square};
+
+use crate::{
+    indexer::A1_D1_D4,
+    indexer_syzygy::{triangle_idx, INV_TRIANGLE, KK_IDX, Z0},
+};
+
+/// Number of configurations of two kings, see `encode_kings`
+pub const KK_NB: u16 = 462;
+
+// impossible king square setup because by construction the white king
+// should be in the A1_D1_D4 triangle
+const IMPOSSIBLE_KING_SQ: ByColor<Square> = ByColor {
+    white: Square::H8,
+    black: Square::H8,
+};
+
+// `try_from` is not const, and all values are below `KK_NB`
+#[allow(clippy::cast_possible_truncation)]
+const fn invert_kk_idx(kk_idx: &[[u64; 64]; 10]) -> [ByColor<Square>; KK_NB as usize] {
+    let mut res: [ByColor<Square>; KK_NB as usize] = [IMPOSSIBLE_KING_SQ; KK_NB as usize];
+    let mut white_king_sq: usize = 0;
+    loop {
+        // for loops not available in const context
+        let mut black_king_sq: usize = 0;
+        loop {
+            let idx = kk_idx[white_king_sq][black_king_sq];
+            if idx != Z0 {
+                res[idx as usize] = ByColor {
+                    white: Square::new(INV_TRIANGLE[white_king_sq] as u32),
+                    black: Square::new(black_king_sq as u32),
+                }
+            }
+
+            // simulating for 0..64
+            black_king_sq += 1;
+            if black_king_sq == 64 {
+                break;
+            }
+        }
+        // simulating for 0..10
+        white_king_sq += 1;
+        if white_king_sq == 10 {
+            break;
+        }
+    }
+    res
+}
+
+pub(crate) const INV_KK_IDX: [ByColor<Square>; KK_NB as usize] = invert_kk_idx(&KK_IDX);
+
+/// Index of the kings, in `0..KK_NB`. `None` if the white king is not in the `A1_D1_D4` triangle,
+/// if the kings are adjacent, or if the black king is above the a1-h8 diagonal while the white king is on it
+#[must_use]
+pub fn encode_kings(white_sq: Square, black_sq: Square) -> Option<u16> {
+    if !A1_D1_D4.contains(white_sq) {
+        return None;
+    }
+    let idx = KK_IDX[triangle_idx(white_sq)][usize::from(black_sq)];
+    (idx != Z0).then(|| u16::try_from(idx).expect("below KK_NB"))
+}
+
+/// Squares of the white and black kings, inverse of `encode_kings`.
+/// Panics if `idx` is not lower than `KK_NB`
+#[must_use]
+pub fn decode_kings(idx: u16) -> (Square, Square) {
+    let kings = INV_KK_IDX[usize::from(idx)];
+    (kings.white, kings.black)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::A1_H8_DIAG;
+
+    #[test]
+    fn test_encode_decode_kings() {
+        let mut nb_valid = 0;
+        for white_sq in Square::ALL {
+            for black_sq in Square::ALL {
+                let expected_valid = A1_D1_D4.contains(white_sq)
+                    && white_sq.distance(black_sq) > 1
+                    && !(A1_H8_DIAG.contains(white_sq)
+                        && u32::from(black_sq.rank()) > u32::from(black_sq.file()));
+                match encode_kings(white_sq, black_sq) {
+                    Some(idx) => {
+                        assert!(expected_valid, "{white_sq} {black_sq}");
+                        assert!(idx < KK_NB);
+                        assert_eq!(decode_kings(idx), (white_sq, black_sq));
+                        nb_valid += 1;
+                    }
+                    None => assert!(!expected_valid, "{white_sq} {black_sq}"),
+                }
+            }
+        }
+        assert_eq!(nb_valid, KK_NB);
+        for idx in 0..KK_NB {
+            let (white_sq, black_sq) = decode_kings(idx);
+            assert_eq!(encode_kings(white_sq, black_sq), Some(idx));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "index out of bounds")]
+    fn test_decode_kings_out_of_range() {
+        let _ = decode_kings(KK_NB);
+    }
+}
@@ -1,39 +1,84 @@
 #![warn(clippy::pedantic)]
-#![allow(
-    clippy::missing_panics_doc,
-    clippy::missing_errors_doc,
-    clippy::cast_possible_truncation
-)]
+#![allow(clippy::missing_panics_doc, clippy::missing_errors_doc)]
 
+mod analysis;
+pub mod api;
+#[cfg(feature = "cached")]
+mod block_cache;
+mod checkpoint;
 mod common;
 mod compression;
+mod coverage;
+mod demo;
+// only used by the Syzygy indexer
+#[cfg_attr(not(feature = "unstable-internals"), allow(dead_code))]
 mod encoding;
 mod file_handler;
+mod fortress;
 mod generation;
 mod indexer;
+// optional alternative to `NaiveIndexer`, compared with it by `helpmate_tb_cli check-indexer --indexer compact`
+#[cfg(feature = "unstable-internals")]
+mod indexer_compact;
+// kept for comparison with `NaiveIndexer`, eg by `helpmate_tb_cli check-indexer`
+#[cfg_attr(not(feature = "unstable-internals"), allow(dead_code))]
 mod indexer_syzygy;
+// checked in debug builds, and by `helpmate_tb_cli verify`
+#[cfg_attr(not(feature = "unstable-internals"), allow(dead_code))]
+mod invariants;
+mod kings;
+mod limits;
 mod material;
 mod outcome;
 mod probe;
+mod router;
+mod win_within;
 
-pub use crate::common::Common;
-pub use crate::file_handler::{Descendants, FileHandler, MaterialWinner, RetrieveOutcome};
-pub use crate::indexer::{DeIndexer, Indexer};
-pub use crate::outcome::{
-    Outcome, OutcomeU8, Outcomes, OutcomesSlice, Report, ReportU8, Reports, ReportsSlice,
-    UNDEFINED_OUTCOME_BYCOLOR,
-};
-pub use crate::probe::TablebaseProber;
-pub use compression::EncoderDecoder;
-pub use encoding::get_info_table;
-pub use generation::{
-    to_chess_with_illegal_checks, Generator, IndexWithTurn, PosHandler, SideToMove,
-    SideToMoveGetter, TableBaseBuilder,
-};
-pub use indexer::{handle_symetry, NaiveIndexer};
-pub use indexer_syzygy::{Pieces, Table, A1_H8_DIAG, A8_H1_DIAG};
-pub use material::{is_black_stronger, Material, KB_K, KN_K};
+pub use api::*;
 
-pub type DefaultIndexer = NaiveIndexer;
+#[cfg(feature = "unstable-internals")]
+pub use internals::*;
+#[cfg(not(feature = "unstable-internals"))]
+#[allow(clippy::wildcard_imports)]
+pub(crate) use internals::*;
 
-pub type DefaultReversibleIndexer = NaiveIndexer;
+/// Items not part of `api`, re-exported at the root of the crate only with the `unstable-internals` feature
+mod internals {
+    pub use crate::common::get_estimate_nb_pos;
+    pub use crate::compression::{DecompressProgress, EncoderDecoder, OutcomesRange};
+    pub use crate::encoding::get_info_table;
+    pub use crate::file_handler::{Descendants, FileHandler, RawAccessOutcome};
+    pub use crate::generation::SideToMoveGetter;
+    pub use crate::indexer::{DeIndexer, Indexer, NaiveIndexer};
+    pub use crate::indexer_syzygy::{Pieces, A1_H8_DIAG};
+    pub use crate::material::{KB_K, KN_K};
+    pub use crate::outcome::{
+        DenseOutcomes, OutcomeU8, Outcomes, OutcomesSlice, ProcessedBits, Report, UndefinedSpans,
+        WdlOutcomes, UNDEFINED_OUTCOME_BYCOLOR,
+    };
+
+    // not used by the crate itself
+    #[cfg(feature = "unstable-internals")]
+    pub use crate::compression::DecompressedBlocks;
+    #[cfg(feature = "unstable-internals")]
+    pub use crate::generation::{
+        for_each_legal_position, to_chess_with_illegal_checks, DefaultGeneratorHandler, Generator,
+        PosHandler, SelfmateGeneratorHandler,
+    };
+    #[cfg(feature = "unstable-internals")]
+    pub use crate::indexer::{canonical_board, handle_symetry, swap_color_board, IndexExplanation};
+    #[cfg(feature = "unstable-internals")]
+    pub use crate::indexer_compact::CompactIndexer;
+    #[cfg(feature = "unstable-internals")]
+    pub use crate::indexer_syzygy::{Table, A8_H1_DIAG};
+    #[cfg(feature = "unstable-internals")]
+    pub use crate::invariants::{check_board, check_reports, InvariantViolation};
+    #[cfg(feature = "unstable-internals")]
+    pub use crate::outcome::iter_defined;
+    #[cfg(feature = "unstable-internals")]
+    pub use crate::probe::LazyFileHandler;
+
+    pub type DefaultIndexer = NaiveIndexer;
+
+    pub type DefaultReversibleIndexer = NaiveIndexer;
+}
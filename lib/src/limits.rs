@@ -0,0 +1,166 @@
+use std::fmt;
+use std::io;
+use std::mem::size_of;
+use std::path::{Path, PathBuf};
+
+use retroboard::shakmaty::ByColor;
+
+use crate::OutcomeU8;
+
+/// Resources the crate is allowed to use, so that it fails early with a clear error
+/// rather than being killed once the machine is out of memory. The default sets no limit
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResourceLimits {
+    /// RAM allowed for tables, in bytes. Tables are refused rather than decompressed above it,
+    /// and the block cache used when probing is shrunk to fit in it
+    pub max_ram_bytes: Option<u64>,
+    /// Number of threads the caller runs builds on, see `ResourceLimits::per_thread`
+    pub max_threads: Option<usize>,
+    /// Directory for temporary files, instead of next to the files they become, see `ResourceLimits::tmp_path`
+    pub scratch_dir: Option<PathBuf>,
+}
+
+impl ResourceLimits {
+    /// Fails with a `ResourceLimitExceeded` if `bytes` exceed `max_ram_bytes`,
+    /// `what` being what needs them, eg a table name
+    pub fn check_ram(&self, bytes: u64, what: impl fmt::Display) -> io::Result<()> {
+        match self.max_ram_bytes {
+            Some(max_ram_bytes) if bytes > max_ram_bytes => Err(ResourceLimitExceeded {
+                what: what.to_string(),
+                bytes,
+                max_ram_bytes,
+            }
+            .into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// `wanted` threads, at most `max_threads` and at least one
+    #[must_use]
+    pub fn threads(&self, wanted: usize) -> usize {
+        wanted.min(self.max_threads.unwrap_or(usize::MAX)).max(1)
+    }
+
+    /// Limits of each of the `max_threads` threads, `max_ram_bytes` being split evenly among them
+    #[must_use]
+    pub fn per_thread(&self) -> Self {
+        let threads = self.max_threads.unwrap_or(1).max(1);
+        Self {
+            max_ram_bytes: self.max_ram_bytes.map(|ram| ram / threads as u64),
+            max_threads: Some(1),
+            scratch_dir: self.scratch_dir.clone(),
+        }
+    }
+
+    /// Where to write `path` before moving it in place, `<path>.tmp` without `scratch_dir`.
+    /// Moving a file out of `scratch_dir` may require copying it, if it is on another filesystem
+    #[must_use]
+    pub fn tmp_path(&self, path: &Path) -> PathBuf {
+        let mut file_name = path.file_name().unwrap_or_default().to_owned();
+        file_name.push(".tmp");
+        match &self.scratch_dir {
+            Some(scratch_dir) => scratch_dir.join(file_name),
+            None => path.with_file_name(file_name),
+        }
+    }
+}
+
+/// `ResourceLimits::max_ram_bytes` would be exceeded. Carried by an `io::Error` of kind
+/// `io::ErrorKind::OutOfMemory`, see `ResourceLimitExceeded::of`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResourceLimitExceeded {
+    /// What needs the RAM, eg a table name
+    pub what: String,
+    pub bytes: u64,
+    pub max_ram_bytes: u64,
+}
+
+impl ResourceLimitExceeded {
+    /// The `ResourceLimitExceeded` carried by `err`, if any
+    #[must_use]
+    pub fn of(err: &io::Error) -> Option<&Self> {
+        err.get_ref().and_then(|inner| inner.downcast_ref())
+    }
+}
+
+impl fmt::Display for ResourceLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} needs about {} bytes of RAM, more than the {} allowed",
+            self.what, self.bytes, self.max_ram_bytes
+        )
+    }
+}
+
+impl std::error::Error for ResourceLimitExceeded {}
+
+impl From<ResourceLimitExceeded> for io::Error {
+    fn from(err: ResourceLimitExceeded) -> Self {
+        io::Error::new(io::ErrorKind::OutOfMemory, err)
+    }
+}
+
+/// RAM taken by a decompressed table of `len` indexes
+pub(crate) fn table_ram_bytes(len: u64) -> u64 {
+    len * size_of::<ByColor<OutcomeU8>>() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_ram() {
+        assert!(ResourceLimits::default()
+            .check_ram(u64::MAX, "KQvK")
+            .is_ok());
+        let limits = ResourceLimits {
+            max_ram_bytes: Some(1000),
+            ..Default::default()
+        };
+        assert!(limits.check_ram(1000, "KQvK").is_ok());
+        let err = limits.check_ram(1001, "KQvK").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::OutOfMemory);
+        assert!(err.to_string().starts_with("KQvK needs about 1001 bytes"));
+        assert_eq!(
+            ResourceLimitExceeded::of(&err),
+            Some(&ResourceLimitExceeded {
+                what: "KQvK".to_string(),
+                bytes: 1001,
+                max_ram_bytes: 1000,
+            })
+        );
+    }
+
+    #[test]
+    fn test_per_thread() {
+        let limits = ResourceLimits {
+            max_ram_bytes: Some(1000),
+            max_threads: Some(4),
+            scratch_dir: None,
+        };
+        assert_eq!(limits.threads(8), 4);
+        assert_eq!(limits.threads(0), 1);
+        assert_eq!(limits.per_thread().max_ram_bytes, Some(250));
+        assert_eq!(limits.per_thread().threads(8), 1);
+        assert_eq!(ResourceLimits::default().per_thread().max_ram_bytes, None);
+    }
+
+    #[test]
+    fn test_tmp_path() {
+        let path: PathBuf = ["table", "KQvK.w.hm.dtm"].iter().collect();
+        assert_eq!(
+            ResourceLimits::default().tmp_path(&path),
+            path.with_file_name("KQvK.w.hm.dtm.tmp")
+        );
+        let limits = ResourceLimits {
+            scratch_dir: Some(PathBuf::from("scratch")),
+            ..Default::default()
+        };
+        assert_eq!(
+            limits.tmp_path(&path),
+            Path::new("scratch").join("KQvK.w.hm.dtm.tmp")
+        );
+    }
+}
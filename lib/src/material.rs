@@ -91,32 +91,44 @@ impl MaterialSide {
 
     /// All `MaterialSide` configuration than can be possible from this setup using legal moves
     pub fn descendants(&self) -> Vec<Self> {
-        let mut descendants: Vec<Self> = Vec::with_capacity(6); // arbitrary
-        if self.has_pawns() {
-            // a pawn can be promoted
-            for role in [Role::Bishop, Role::Knight, Role::Rook, Role::Queen] {
+        let mut descendants = self.promotions();
+        descendants.extend(self.captures(true));
+        descendants
+    }
+
+    /// A pawn can be promoted
+    fn promotions(&self) -> Vec<Self> {
+        if !self.has_pawns() {
+            return Vec::new();
+        }
+        [Role::Bishop, Role::Knight, Role::Rook, Role::Queen]
+            .into_iter()
+            .map(|role| {
                 let mut descendant = self.clone();
                 descendant.by_role.pawn -= 1;
                 *descendant.by_role.get_mut(role) += 1;
-                descendants.push(descendant);
-            }
-        }
-        // all pieces but king can be taken
-        for role in [
+                descendant
+            })
+            .collect()
+    }
+
+    /// All pieces but king can be taken, pawns only if `with_pawns` is `true`
+    fn captures(&self, with_pawns: bool) -> Vec<Self> {
+        [
             Role::Pawn,
             Role::Bishop,
             Role::Knight,
             Role::Rook,
             Role::Queen,
-        ] {
-            if *self.by_role.get(role) > 0 {
-                let mut descendant = self.clone();
-                *descendant.by_role.get_mut(role) -= 1;
-                descendants.push(descendant);
-            }
-        }
-
-        descendants
+        ]
+        .into_iter()
+        .filter(|role| (with_pawns || *role != Role::Pawn) && *self.by_role.get(*role) > 0)
+        .map(|role| {
+            let mut descendant = self.clone();
+            *descendant.by_role.get_mut(role) -= 1;
+            descendant
+        })
+        .collect()
     }
 
     /// Can this side mate the other one with this material config?
@@ -136,10 +148,64 @@ impl MaterialSide {
     }
 }
 
+const SIDE_SEPARATORS: [char; 5] = ['v', 'V', '-', '_', ' '];
+
+/// Bring the ways material is commonly written to the `KQvK` form, as accepted by `Material::from_str`:
+/// - case-insensitive, with `v`, `-`, `_` or spaces between the sides, eg "kqvk" or "KQ - K"
+/// - FEN piece-list, white pieces uppercase and black ones lowercase, eg `KQk`
+/// - without separator nor case distinction, black pieces starting at the second king, eg "kqk"
+///
+/// Pieces are not checked
+pub fn normalize_material(s: &str) -> Result<String, &'static str> {
+    let s = s.trim();
+    if !s.is_ascii() {
+        return Err("material should only contain ascii characters");
+    }
+    let (white, black): (String, String) =
+        if let Some((white, black)) = s.split_once(SIDE_SEPARATORS) {
+            (
+                white.into(),
+                black.trim_start_matches(SIDE_SEPARATORS).into(),
+            )
+        } else if s.contains(|c: char| c.is_ascii_uppercase())
+            && s.contains(|c: char| c.is_ascii_lowercase())
+        {
+            (
+                s.chars().filter(char::is_ascii_uppercase).collect(),
+                s.chars().filter(char::is_ascii_lowercase).collect(),
+            )
+        } else {
+            let second_king = s
+                .match_indices(['K', 'k'])
+                .nth(1)
+                .ok_or("should contain 'v' to separate white pieces from black ones, eg \"KQvK\"")?
+                .0;
+            let (white, black) = s.split_at(second_king);
+            (white.into(), black.into())
+        };
+    Ok(format!(
+        "{}v{}",
+        white.trim_end().to_ascii_uppercase(),
+        black.to_ascii_uppercase()
+    ))
+}
+
 #[must_use]
 pub fn is_black_stronger(board: &Board) -> bool {
-    MaterialSide::from(board.material_side(Color::Black))
-        > MaterialSide::from(board.material_side(Color::White))
+    is_black_stronger_raw(&ByColor::new_with(|color| board.material_side(color)))
+}
+
+/// Same as `is_black_stronger`, from the material of each side
+pub(crate) fn is_black_stronger_raw(raw_material: &ByColor<ByRole<u8>>) -> bool {
+    MaterialSide::from(raw_material.black) > MaterialSide::from(raw_material.white)
+}
+
+/// Raw material where `color` has `side`, and the other color `other`
+fn raw_with(color: Color, side: &MaterialSide, other: &MaterialSide) -> ByColor<ByRole<u8>> {
+    let mut raw = ByColor::new_with(|_| ByRole::default());
+    *raw.get_mut(color) = side.by_role;
+    *raw.get_mut(!color) = other.by_role;
+    raw
 }
 
 impl Deref for MaterialSide {
@@ -288,11 +354,14 @@ impl Material {
     /// Get the material configuration for a [`Board`].
     #[must_use]
     pub fn from_board(board: &Board) -> Self {
+        Self::from_raw(&ByColor::new_with(|color| board.material_side(color)))
+    }
+
+    /// Normalised material from the material of each side, the stronger side ending up white
+    #[must_use]
+    pub fn from_raw(raw_material: &ByColor<ByRole<u8>>) -> Self {
         Self {
-            by_color: ByColor::new_with(|color| MaterialSide {
-                by_role: board.material_side(color),
-            })
-            .into(),
+            by_color: raw_material.map(MaterialSide::from).into(),
         }
     }
 
@@ -375,23 +444,75 @@ impl Material {
         }
     }
 
-    /// For any color
+    /// Depth 1 descendants reached by a move of `color`: capturing a piece of the other side,
+    /// promoting a pawn, or both at once. Those reached by a move of the other side, eg `color` losing a piece,
+    /// are the ones of `Material::descendants_for(!color)`. Descendants are normalised, so `color`
+    /// ends up black in those where the other side becomes stronger
+    #[must_use]
+    pub fn descendants_for(&self, color: Color) -> Vec<Self> {
+        self.raw_descendants_for(color)
+            .map(|raw| Self::from_raw(&raw))
+            .unique()
+            .collect()
+    }
+
+    /// Same as `Material::descendants_for`, before normalising, so that each side keeps its color
+    fn raw_descendants_for(&self, color: Color) -> impl Iterator<Item = ByColor<ByRole<u8>>> {
+        let (side, other) = (self.by_color.get(color), self.by_color.get(!color));
+        // pawns are never on the last rank, so cannot be taken by a promotion
+        let transitions = [
+            (vec![side.clone()], other.captures(true)),
+            (side.promotions(), vec![other.clone()]),
+            (side.promotions(), other.captures(false)),
+        ];
+        transitions
+            .into_iter()
+            .flat_map(|(sides, others)| sides.into_iter().cartesian_product(others))
+            .map(move |(side, other)| raw_with(color, &side, &other))
+    }
+
+    /// Descendants reached by a move of either color, see `Material::descendants_for`
     fn descendants(&self) -> impl Iterator<Item = Self> + '_ {
-        self.by_color
-            .iter()
-            .circular_tuple_windows()
-            .flat_map(|(mat_1, mat_2)| {
-                mat_1
-                    .descendants()
-                    .into_iter()
-                    .map(|mat_1_descendant| Self {
-                        by_color: ByColor {
-                            white: mat_1_descendant,
-                            black: mat_2.clone(),
-                        }
-                        .into(),
-                    })
+        Color::ALL
+            .into_iter()
+            .flat_map(|color| self.descendants_for(color))
+            .unique()
+    }
+
+    /// Depth 1 descendants not trivially drawn, reached without `color` losing a piece, each with the
+    /// color `color` has in it, swapped if the descendant is normalized by swapping colors
+    pub(crate) fn descendants_not_draw_keeping(&self, color: Color) -> Vec<(Self, Color)> {
+        let side = self.by_color.get(color);
+        let others_promoting = self
+            .by_color
+            .get(!color)
+            .promotions()
+            .into_iter()
+            .map(|other| raw_with(color, side, &other));
+        self.raw_descendants_for(color)
+            .chain(others_promoting)
+            .map(|raw| {
+                let flip = is_black_stronger_raw(&raw);
+                (Self::from_raw(&raw), if flip { !color } else { color })
             })
+            .filter(|(mat, _)| mat.is_mate_possible())
+            .unique()
+            .collect()
+    }
+
+    /// Depth 1 descendants reached by a move of either color where `color` can still mate, each with the
+    /// color `color` has in it, swapped if the descendant is normalized by swapping colors
+    pub(crate) fn descendants_winnable(&self, color: Color) -> Vec<(Self, Color)> {
+        Color::ALL
+            .into_iter()
+            .flat_map(|mover| self.raw_descendants_for(mover))
+            .map(|raw| {
+                let flip = is_black_stronger_raw(&raw);
+                (Self::from_raw(&raw), if flip { !color } else { color })
+            })
+            .filter(|(mat, color)| mat.can_mate(*color))
+            .unique()
+            .collect()
     }
 
     /// For any color, depth 1 descendants not trivially drawn
@@ -416,10 +537,8 @@ impl Material {
         self.descendants()
             .filter(|mat| include_drawn_materials || mat.is_mate_possible())
             .flat_map(|x| {
-                iter::once(x.clone()).chain(
-                    x.descendants_recursive_internal(include_drawn_materials)
-                        .into_iter(),
-                )
+                iter::once(x.clone())
+                    .chain(x.descendants_recursive_internal(include_drawn_materials))
             })
             .collect()
     }
@@ -471,9 +590,10 @@ impl FromStr for Material {
             return Err("string too long to be proper material");
         }
 
-        let (white, black) = s
+        let normalized = normalize_material(s)?;
+        let (white, black) = normalized
             .split_once('v')
-            .ok_or("should contain 'v' to separate white pieces from black ones, eg \"KQvK\"")?;
+            .expect("normalized material has a 'v' separator");
         Ok(Self {
             by_color: ByColor {
                 white: MaterialSide::from_str_part(white).ok_or("invalid white pieces")?,
@@ -492,7 +612,7 @@ impl fmt::Debug for Material {
 
 struct MaterialVisitor;
 
-impl<'de> de::Visitor<'de> for MaterialVisitor {
+impl de::Visitor<'_> for MaterialVisitor {
     type Value = Material;
 
     fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
@@ -519,9 +639,41 @@ impl<'de> Deserialize<'de> for Material {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
     use retroboard::shakmaty::Color::{Black, White};
     use std::collections::HashSet;
 
+    #[test]
+    fn test_normalize_material() {
+        for (input, expected) in [
+            ("KQvK", "KQvK"),
+            ("kqvk", "KQvK"),
+            ("KQVK", "KQvK"),
+            ("KQ-K", "KQvK"),
+            ("kq_kr", "KQvKR"),
+            (" KQ - K ", "KQvK"),
+            ("KQk", "KQvK"),
+            ("KkQr", "KQvKR"),
+            ("kqk", "KQvK"),
+            ("KRRKB", "KRRvKB"),
+        ] {
+            assert_eq!(
+                normalize_material(input).as_deref(),
+                Ok(expected),
+                "{input}"
+            );
+            assert_eq!(
+                Material::from_str(input),
+                Material::from_str(expected),
+                "{input}"
+            );
+        }
+        for invalid in ["KQ", "", "KQ√K"] {
+            assert!(normalize_material(invalid).is_err(), "{invalid}");
+        }
+        assert!(Material::from_str("KQ-KX").is_err());
+    }
+
     #[test]
     fn test_pieces_without_white_king_from_material() {
         let mat = Material::from_str("KRQvKBN").unwrap();
@@ -534,7 +686,7 @@ mod tests {
         ] as &[_])
             .try_into()
             .unwrap();
-        assert_eq!(mat.pieces_without_white_king(), pieces)
+        assert_eq!(mat.pieces_without_white_king(), pieces);
     }
 
     #[test]
@@ -549,13 +701,12 @@ mod tests {
         ] {
             let mat = MaterialSide::from_str_part(test_config.0).unwrap();
             assert_eq!(
-                HashSet::from_iter(mat.descendants().into_iter()),
-                HashSet::<MaterialSide>::from_iter(
-                    test_config
-                        .1
-                        .iter()
-                        .map(|s| MaterialSide::from_str_part(s).unwrap())
-                )
+                mat.descendants().into_iter().collect::<HashSet<_>>(),
+                test_config
+                    .1
+                    .iter()
+                    .map(|s| MaterialSide::from_str_part(s).unwrap())
+                    .collect::<HashSet<_>>()
             );
         }
     }
@@ -632,13 +783,24 @@ mod tests {
             ("KBNvK", vec!["KBvK", "KNvK"]),
             ("KRRvK", vec!["KRvK"]),
             ("KPvK", vec!["KBvK", "KNvK", "KRvK", "KQvK", "KvK"]),
+            (
+                "KPvKR",
+                vec![
+                    "KBvKR", "KNvKR", "KRvKR", "KQvKR", "KvKR", "KPvK", "KBvK", "KNvK", "KRvK",
+                    "KQvK",
+                ],
+            ),
+            // a pawn cannot be taken by a promotion
+            ("KPvKP", vec!["KBvKP", "KNvKP", "KRvKP", "KQvKP", "KvKP"]),
         ] {
             let mat = Material::from_str(test_config.0).unwrap();
             assert_eq!(
-                HashSet::from_iter(mat.descendants()),
-                HashSet::<Material>::from_iter(
-                    test_config.1.iter().map(|s| Material::from_str(s).unwrap())
-                )
+                mat.descendants().collect::<HashSet<_>>(),
+                test_config
+                    .1
+                    .iter()
+                    .map(|s| Material::from_str(s).unwrap())
+                    .collect::<HashSet<_>>()
             );
         }
     }
@@ -659,14 +821,121 @@ mod tests {
         ] {
             let mat = Material::from_str(test_config.0).unwrap();
             assert_eq!(
-                HashSet::from_iter(mat.descendants_not_draw()),
-                HashSet::<Material>::from_iter(
-                    test_config.1.iter().map(|s| Material::from_str(s).unwrap())
-                )
+                mat.descendants_not_draw().collect::<HashSet<_>>(),
+                test_config
+                    .1
+                    .iter()
+                    .map(|s| Material::from_str(s).unwrap())
+                    .collect::<HashSet<_>>()
             );
         }
     }
 
+    #[test]
+    fn test_material_descendants_not_draw_keeping() {
+        let mat = Material::from_str("KQvKR").unwrap();
+        assert_eq!(
+            mat.descendants_not_draw_keeping(White),
+            vec![(Material::from_str("KQvK").unwrap(), White)]
+        );
+        assert_eq!(
+            mat.descendants_not_draw_keeping(Black),
+            vec![(Material::from_str("KRvK").unwrap(), White)]
+        );
+    }
+
+    #[test]
+    fn test_material_descendants_for() {
+        for (mat, color, expected) in [
+            ("KQvKR", White, vec!["KQvK"]),
+            ("KQvKR", Black, vec!["KRvK"]),
+            // black is the one capturing the pawn, and promoting
+            ("KRvKP", White, vec!["KRvK"]),
+            (
+                "KRvKP",
+                Black,
+                vec![
+                    "KQvKR", "KRvKR", "KRvKB", "KRvKN", "KPvK", "KQvK", "KRvK", "KBvK", "KNvK",
+                ],
+            ),
+        ] {
+            let mat = Material::from_str(mat).unwrap();
+            assert_eq!(
+                mat.descendants_for(color)
+                    .into_iter()
+                    .collect::<HashSet<_>>(),
+                expected
+                    .iter()
+                    .map(|s| Material::from_str(s).unwrap())
+                    .collect::<HashSet<_>>(),
+                "{mat:?} {color}"
+            );
+        }
+    }
+
+    fn raw_material() -> impl Strategy<Value = ByColor<ByRole<u8>>> {
+        prop::array::uniform10(0..=2_u8).prop_map(|counts| {
+            ByColor::new_with(|color| {
+                let c = &counts[if color.is_white() { 0..5 } else { 5..10 }];
+                ByRole {
+                    pawn: c[0],
+                    knight: c[1],
+                    bishop: c[2],
+                    rook: c[3],
+                    queen: c[4],
+                    king: 1,
+                }
+            })
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn test_descendants_for_counts(raw in raw_material()) {
+            let mat = Material::from_raw(&raw);
+            for color in Color::ALL {
+                let (side, other) = (mat.by_color.get(color), mat.by_color.get(!color));
+                let nb_promotions = if side.has_pawns() { 4 } else { 0 };
+                let nb_captured = |with_pawns: bool| {
+                    [Role::Pawn, Role::Knight, Role::Bishop, Role::Rook, Role::Queen]
+                        .into_iter()
+                        .filter(|role| {
+                            (with_pawns || *role != Role::Pawn) && *other.by_role.get(*role) > 0
+                        })
+                        .count()
+                };
+                prop_assert_eq!(
+                    mat.raw_descendants_for(color).count(),
+                    nb_captured(true) + nb_promotions * (1 + nb_captured(false))
+                );
+                let nb_pawns =
+                    |m: &Material| m.by_color.iter().map(|side| side.by_role.pawn).sum::<u8>();
+                for descendant in mat.descendants_for(color) {
+                    let count = descendant.count();
+                    prop_assert!(count == mat.count() || count + 1 == mat.count());
+                    prop_assert!(nb_pawns(&descendant) <= nb_pawns(&mat));
+                }
+            }
+        }
+
+        #[test]
+        fn test_descendants_normalisation_stability(raw in raw_material()) {
+            let mat = Material::from_raw(&raw);
+            let swapped = Material::from_raw(&ByColor { white: raw.black, black: raw.white });
+            prop_assert_eq!(&mat, &swapped);
+            let by_color: HashSet<Material> = Color::ALL
+                .into_iter()
+                .flat_map(|color| mat.descendants_for(color))
+                .collect();
+            prop_assert_eq!(&by_color, &mat.descendants().collect::<HashSet<_>>());
+            for descendant in by_color {
+                let raw_descendant = ByColor::new_with(|color| descendant.by_color.get(color).by_role);
+                prop_assert_eq!(&Material::from_raw(&raw_descendant), &descendant);
+                prop_assert_eq!(&Material::from_str(&format!("{descendant:?}")).unwrap(), &descendant);
+            }
+        }
+    }
+
     #[test]
     fn test_material_descendants_not_draw_recursive() {
         for test_config in [
@@ -683,10 +952,14 @@ mod tests {
             ("KRBNvK", vec!["KRvK", "KBNvK", "KRNvK", "KRBvK"]),
         ] {
             let mat = Material::from_str(test_config.0).unwrap();
-            println!("{mat:?}",);
+            println!("{mat:?}");
             assert_eq!(
                 mat.descendants_recursive(false),
-                Vec::from_iter(test_config.1.iter().map(|s| Material::from_str(s).unwrap()))
+                test_config
+                    .1
+                    .iter()
+                    .map(|s| Material::from_str(s).unwrap())
+                    .collect::<Vec<_>>()
             );
         }
     }
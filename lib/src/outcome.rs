@@ -1,9 +1,12 @@
-use retroboard::shakmaty::ByColor;
+use retroboard::shakmaty::{ByColor, Color};
 use std::cmp::Ordering;
 use std::ops::Add;
 use std::ops::Not;
+use std::ops::Range;
 use std::str::FromStr;
 
+use crate::{IndexWithTurn, SideToMoveGetter};
+
 #[allow(clippy::module_name_repetitions)]
 #[derive(Debug, PartialEq, Eq, Hash)]
 pub struct OutcomeOutOfBound;
@@ -11,25 +14,74 @@ pub struct OutcomeOutOfBound;
 pub type Outcomes = Vec<ByColor<OutcomeU8>>;
 pub type OutcomesSlice<'a> = &'a [ByColor<OutcomeU8>];
 
-pub type Reports = Vec<ByColor<ReportU8>>;
-pub type ReportsSlice<'a> = &'a [ByColor<ReportU8>];
-
 /// Wrapper around `Outcome` to track if it has already been processed (ie retro moves generated) or not
 /// When a position is generated it's `Unprocessed` by default.
+/// Stored as an `OutcomeU8` in `Common::all_pos` and a bit in `Common::processed`, see `Common::report`
 #[derive(Debug, Clone, Eq, PartialEq, Copy, Hash)]
 pub enum Report {
     Unprocessed(Outcome),
     Processed(Outcome),
 }
 
-#[repr(transparent)]
-#[derive(Debug, Clone, Eq, PartialEq, Copy, Hash)]
-pub struct ReportU8(u8);
+/// Bit-packed processed flags of a table, 2 bits per element (1 per color),
+/// so that the outcomes themselves can be kept as `Outcomes` while building
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct ProcessedBits {
+    packed: Vec<u8>,
+}
+
+impl ProcessedBits {
+    /// `len` elements, all processed, like the `Undefined` positions not generated yet
+    #[must_use]
+    pub fn new_processed(len: usize) -> Self {
+        Self {
+            packed: vec![u8::MAX; Self::packed_len(len)],
+        }
+    }
+
+    /// Number of bytes holding the flags of `len` elements
+    #[must_use]
+    pub(crate) fn packed_len(len: usize) -> usize {
+        len.div_ceil(4)
+    }
+
+    /// See `ProcessedBits::packed`
+    pub(crate) fn from_packed(packed: Vec<u8>) -> Self {
+        Self { packed }
+    }
 
-impl ReportU8 {
+    /// The flags of 4 elements per byte, in index order
     #[must_use]
-    pub fn from_raw_u8(u: u8) -> Self {
-        Self(u)
+    pub(crate) fn packed(&self) -> &[u8] {
+        &self.packed
+    }
+
+    #[inline]
+    fn bit(idx: usize, turn: Color) -> (usize, u8) {
+        (idx / 4, 1 << (2 * (idx % 4) + usize::from(turn.is_white())))
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn get(&self, idx: usize, turn: Color) -> bool {
+        let (byte, mask) = Self::bit(idx, turn);
+        self.packed[byte] & mask != 0
+    }
+
+    #[inline]
+    pub fn set(&mut self, idx: usize, turn: Color, processed: bool) {
+        let (byte, mask) = Self::bit(idx, turn);
+        if processed {
+            self.packed[byte] |= mask;
+        } else {
+            self.packed[byte] &= !mask;
+        }
+    }
+
+    /// Only keep the flags of the `len` first elements
+    pub fn truncate(&mut self, len: usize) {
+        self.packed.truncate(Self::packed_len(len));
+        self.packed.shrink_to_fit();
     }
 }
 
@@ -49,7 +101,7 @@ impl OutcomeU8 {
     }
 
     #[must_use]
-    pub fn as_raw_u8(&self) -> u8 {
+    pub fn as_raw_u8(self) -> u8 {
         self.0
     }
 }
@@ -57,39 +109,30 @@ impl OutcomeU8 {
 impl Report {
     #[inline]
     #[must_use]
-    pub fn outcome(&self) -> Outcome {
+    pub fn outcome(self) -> Outcome {
         match self {
-            Self::Unprocessed(outcome) | Self::Processed(outcome) => *outcome,
+            Self::Unprocessed(outcome) | Self::Processed(outcome) => outcome,
         }
     }
 }
 
-impl From<Report> for ReportU8 {
-    fn from(r: Report) -> Self {
-        match r {
-            Report::Unprocessed(outcome) => ReportU8(OutcomeU8::from(outcome).as_raw_u8()),
-            Report::Processed(outcome) => ReportU8(OutcomeU8::from(outcome).as_raw_u8() + 128),
-        }
-    }
-}
-
-impl From<ReportU8> for Report {
-    fn from(r: ReportU8) -> Self {
-        if r.0 > 127 {
-            Self::Processed(OutcomeU8(r.0 - 128).into())
-        } else {
-            Self::Unprocessed(OutcomeU8(r.0).into())
-        }
-    }
-}
-
-impl From<&ReportU8> for Report {
-    fn from(u: &ReportU8) -> Self {
-        (*u).into()
-    }
+/// Entries of `outcomes` defined for their side to move, ie not `Outcome::Undefined`, in index order.
+/// `index_from` is the index of `outcomes[0]`, so that chunks of a table, eg processed in parallel,
+/// yield their own indexes
+pub fn iter_defined(
+    outcomes: OutcomesSlice<'_>,
+    index_from: u64,
+) -> impl Iterator<Item = (IndexWithTurn, Outcome)> + '_ {
+    (index_from..).zip(outcomes).flat_map(|(idx, by_color)| {
+        Color::ALL.into_iter().filter_map(move |turn| {
+            let outcome = Outcome::from(*by_color.get(turn));
+            (outcome != Outcome::Undefined).then_some((IndexWithTurn { idx, turn }, outcome))
+        })
+    })
 }
 
 /// According to winnner set in `Generator`. This struct need to fit in a u7
+/// Ordered from the winner point of view, see `Ord for Outcome`
 #[allow(clippy::module_name_repetitions)]
 #[derive(Debug, Clone, Eq, PartialEq, Copy, Hash)]
 pub enum Outcome {
@@ -102,10 +145,10 @@ pub enum Outcome {
     Undefined, // Used for illegal positions. Should we use Option<Outcome> without that variant instead?
 }
 
-pub const UNDEFINED_OUTCOME_BYCOLOR: ByColor<ReportU8> = ByColor {
-    // Report::Processed(Outcome::Undefined).into()
-    black: ReportU8(255),
-    white: ReportU8(255),
+pub const UNDEFINED_OUTCOME_BYCOLOR: ByColor<OutcomeU8> = ByColor {
+    // Outcome::Undefined.into()
+    black: OutcomeU8(127),
+    white: OutcomeU8(127),
 };
 
 /// Accept win18 for Win(18)
@@ -157,12 +200,31 @@ impl From<&OutcomeU8> for Outcome {
     }
 }
 
+impl Outcome {
+    /// Best of `outcomes` for the winner, the one to use when resolving the moves of a position.
+    /// `Undefined` ones, which no legal move leads to, are skipped instead of panicking.
+    /// `Unknown` ones lose to every known outcome, so the result is only a lower bound when some are present,
+    /// and it is `Unknown` if no outcome is known. `None` if there is no outcome left
+    #[must_use]
+    pub fn best_of(outcomes: impl IntoIterator<Item = Self>) -> Option<Self> {
+        outcomes
+            .into_iter()
+            .filter(|outcome| *outcome != Self::Undefined)
+            .max()
+    }
+}
+
+/// From the winner point of view, worst first:
+/// `Unknown` < `Lose(0)` < `Lose(1)` < ... < `Draw` < ... < `Win(1)` < `Win(0)`.
+/// `Unknown` is lower than every known outcome, so that the best of several ones is only known
+/// if none of them is, see `Outcome::best_of`. `Undefined` cannot be compared, as no legal position has it.
+/// Panics if any side is `Undefined`
 impl Ord for Outcome {
     #[allow(clippy::match_same_arms)] // clearer to follow the flow even if there is some duplicates here
     fn cmp(&self, other: &Self) -> Ordering {
         match (self, other) {
             (Self::Undefined, _) | (_, Self::Undefined) => {
-                panic!("No Undefined/Unknown in comparison")
+                panic!("No Undefined in comparison")
             }
             (Self::Win(x), Self::Win(y)) => x.cmp(y).reverse(), // short win is better,
             (Self::Win(_), Self::Draw | Self::Lose(_)) => Ordering::Greater, // if other is not a Win, we're greater
@@ -171,6 +233,7 @@ impl Ord for Outcome {
             (Self::Draw, Self::Lose(_)) => Ordering::Greater,
             (Self::Lose(x), Self::Lose(y)) => x.cmp(y), // losing in many moves is better,
             (Self::Lose(_), Self::Win(_) | Self::Draw) => Ordering::Less,
+            (Self::Unknown, Self::Unknown) => Ordering::Equal,
             (Self::Unknown, _) => Ordering::Less,
             (_, Self::Unknown) => Ordering::Greater,
         }
@@ -183,6 +246,8 @@ impl PartialOrd for Outcome {
     }
 }
 
+// Every one of the 128 values of the u7 is used:
+// `Draw`, `Unknown`, `Undefined`, 62 `Lose` and 63 `Win`
 fn try_into_util(o: Outcome) -> Result<OutcomeU8, OutcomeOutOfBound> {
     match o {
         Outcome::Draw => Ok(0),
@@ -201,6 +266,330 @@ impl From<Outcome> for OutcomeU8 {
     }
 }
 
+/// Win/Draw/Lose only version of `Outcome`, fitting in 3 bits
+#[derive(Debug, Clone, Eq, PartialEq, Copy, Hash)]
+pub enum Wdl {
+    Draw = 0,
+    Win = 1,
+    Lose = 2,
+    Undefined = 3,
+    /// Kept apart from `Wdl::Draw`, see `UnknownPolicy::Unreachable`
+    Unknown = 4,
+}
+
+#[cfg_attr(not(feature = "unstable-internals"), allow(dead_code))]
+impl Wdl {
+    fn from_bits(u: u8) -> Self {
+        match u & 0b111 {
+            0 => Self::Draw,
+            1 => Self::Win,
+            2 => Self::Lose,
+            4 => Self::Unknown,
+            _ => Self::Undefined,
+        }
+    }
+}
+
+impl From<Outcome> for Wdl {
+    fn from(o: Outcome) -> Self {
+        match o {
+            Outcome::Win(_) => Self::Win,
+            Outcome::Lose(_) => Self::Lose,
+            Outcome::Draw => Self::Draw,
+            Outcome::Unknown => Self::Unknown,
+            Outcome::Undefined => Self::Undefined,
+        }
+    }
+}
+
+/// Bit-packed equivalent of `Outcomes` only keeping `Wdl` information,
+/// 8 bits per element (4 per color) instead of 16
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct WdlOutcomes {
+    packed: Vec<u8>,
+    len: usize,
+}
+
+#[cfg_attr(not(feature = "unstable-internals"), allow(dead_code))]
+impl WdlOutcomes {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            packed: Vec::with_capacity(capacity),
+            len: 0,
+        }
+    }
+
+    pub fn push(&mut self, outcome: ByColor<OutcomeU8>) {
+        self.packed.push(
+            (Wdl::from(Outcome::from(outcome.black)) as u8)
+                | ((Wdl::from(Outcome::from(outcome.white)) as u8) << 4),
+        );
+        self.len += 1;
+    }
+
+    #[must_use]
+    pub fn get(&self, idx: usize) -> Option<ByColor<Wdl>> {
+        (idx < self.len).then(|| ByColor {
+            black: Wdl::from_bits(self.packed[idx]),
+            white: Wdl::from_bits(self.packed[idx] >> 4),
+        })
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = ByColor<Wdl>> + '_ {
+        (0..self.len).map(|idx| self.get(idx).expect("idx is lower than len"))
+    }
+}
+
+impl Extend<ByColor<OutcomeU8>> for WdlOutcomes {
+    fn extend<I: IntoIterator<Item = ByColor<OutcomeU8>>>(&mut self, iter: I) {
+        for outcome in iter {
+            self.push(outcome);
+        }
+    }
+}
+
+// `Outcome::Undefined` for both sides to move
+const UNDEFINED_OUTCOMEU8_BYCOLOR: ByColor<OutcomeU8> = ByColor {
+    black: OutcomeU8(127),
+    white: OutcomeU8(127),
+};
+
+/// Equivalent of `Outcomes` only storing the entries defined for at least one side to move,
+/// the indexer leaving large gaps of undefined ones. A bitmap of the defined entries,
+/// along with the number of them before each of its words, translates an index
+/// into its position among the stored outcomes (rank) and back (select)
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct DenseOutcomes {
+    defined: Vec<u64>,
+    // number of defined entries before each word of `defined`
+    ranks: Vec<usize>,
+    outcomes: Outcomes,
+    len: usize,
+}
+
+#[cfg_attr(not(feature = "unstable-internals"), allow(dead_code))]
+impl DenseOutcomes {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, outcome: ByColor<OutcomeU8>) {
+        if self.len.is_multiple_of(64) {
+            self.defined.push(0);
+            self.ranks.push(self.outcomes.len());
+        }
+        if outcome != UNDEFINED_OUTCOMEU8_BYCOLOR {
+            *self.defined.last_mut().expect("pushed above") |= 1 << (self.len % 64);
+            self.outcomes.push(outcome);
+        }
+        self.len += 1;
+    }
+
+    /// `Outcome::Undefined` for both sides to move if the entry is not stored
+    #[must_use]
+    pub fn get(&self, idx: usize) -> Option<ByColor<OutcomeU8>> {
+        (idx < self.len).then(|| {
+            if self.defined[idx / 64] >> (idx % 64) & 1 == 1 {
+                self.outcomes[self.rank(idx)]
+            } else {
+                UNDEFINED_OUTCOMEU8_BYCOLOR
+            }
+        })
+    }
+
+    /// Number of defined entries before `idx`
+    #[must_use]
+    pub fn rank(&self, idx: usize) -> usize {
+        let word = idx / 64;
+        let mask = (1 << (idx % 64)) - 1;
+        self.ranks[word] + (self.defined[word] & mask).count_ones() as usize
+    }
+
+    /// Index of the `n`-th defined entry, starting from 0. `None` if there are not that many
+    #[must_use]
+    pub fn select(&self, n: usize) -> Option<usize> {
+        (n < self.outcomes.len()).then(|| {
+            let word = self.ranks.partition_point(|rank| *rank <= n) - 1;
+            let mut bits = self.defined[word];
+            for _ in self.ranks[word]..n {
+                // clear the lowest defined entry of the word
+                bits &= bits - 1;
+            }
+            word * 64 + bits.trailing_zeros() as usize
+        })
+    }
+
+    /// Number of entries, defined or not, as the `Outcomes` it replaces
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of entries actually stored
+    #[must_use]
+    pub fn nb_defined(&self) -> usize {
+        self.outcomes.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = ByColor<OutcomeU8>> + '_ {
+        (0..self.len).map(|idx| self.get(idx).expect("idx is lower than len"))
+    }
+}
+
+impl Extend<ByColor<OutcomeU8>> for DenseOutcomes {
+    fn extend<I: IntoIterator<Item = ByColor<OutcomeU8>>>(&mut self, iter: I) {
+        for outcome in iter {
+            self.push(outcome);
+        }
+    }
+}
+
+/// Run-length index of the spans of entries undefined for both sides to move, sorted and disjoint.
+/// Only the runs of at least `UndefinedSpans::MIN_SPAN` entries are kept, so that scans can skip them
+/// entirely at the cost of a lookup, see `UndefinedSpans::defined_ranges`
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct UndefinedSpans {
+    spans: Vec<Range<usize>>,
+    len: usize,
+    // undefined entries ending at `len`, kept to merge the run with the next entries pushed
+    trailing_run: usize,
+}
+
+#[cfg_attr(not(feature = "unstable-internals"), allow(dead_code))]
+impl UndefinedSpans {
+    pub const MIN_SPAN: usize = 64;
+
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn from_outcomes<T>(outcomes: &[ByColor<T>]) -> Self
+    where
+        ByColor<T>: SideToMoveGetter,
+    {
+        let mut undefined_spans = Self::new();
+        undefined_spans.extend_from(outcomes);
+        undefined_spans
+    }
+
+    /// Spans as read from a table trailer, covering `len` entries.
+    /// `None` unless they are sorted, disjoint, long enough and below `len`
+    #[must_use]
+    pub fn from_spans(spans: Vec<Range<usize>>, len: usize) -> Option<Self> {
+        let sorted = spans.windows(2).all(|w| w[0].end < w[1].start);
+        let valid = spans
+            .iter()
+            .all(|span| span.len() >= Self::MIN_SPAN && span.end <= len);
+        (sorted && valid).then(|| Self {
+            trailing_run: spans
+                .last()
+                .filter(|span| span.end == len)
+                .map_or(0, ExactSizeIterator::len),
+            spans,
+            len,
+        })
+    }
+
+    /// Index the entries of `outcomes`, following the ones already indexed
+    pub fn extend_from<T>(&mut self, outcomes: &[ByColor<T>])
+    where
+        ByColor<T>: SideToMoveGetter,
+    {
+        for by_color in outcomes {
+            if Color::ALL
+                .iter()
+                .all(|turn| by_color.get_outcome_by_color(*turn) == Outcome::Undefined)
+            {
+                self.trailing_run += 1;
+            } else {
+                self.record_trailing_run();
+                self.trailing_run = 0;
+            }
+            self.len += 1;
+        }
+        self.record_trailing_run();
+    }
+
+    fn record_trailing_run(&mut self) {
+        if self.trailing_run < Self::MIN_SPAN {
+            return;
+        }
+        let start = self.len - self.trailing_run;
+        match self.spans.last_mut() {
+            // the run was already recorded up to the end of the previous entries
+            Some(last) if last.start == start => last.end = self.len,
+            _ => self.spans.push(start..self.len),
+        }
+    }
+
+    #[must_use]
+    pub fn spans(&self) -> &[Range<usize>] {
+        &self.spans
+    }
+
+    /// Number of entries indexed, defined or not
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of entries in the spans
+    #[must_use]
+    pub fn nb_undefined(&self) -> usize {
+        self.spans.iter().map(ExactSizeIterator::len).sum()
+    }
+
+    /// Sub-ranges of `range` outside of the spans, in order
+    #[must_use]
+    pub fn defined_ranges(&self, range: Range<usize>) -> Vec<Range<usize>> {
+        let first = self.spans.partition_point(|span| span.end <= range.start);
+        let mut start = range.start;
+        let mut ranges = Vec::new();
+        for span in self.spans[first..]
+            .iter()
+            .take_while(|span| span.start < range.end)
+        {
+            if start < span.start {
+                ranges.push(start..span.start);
+            }
+            start = span.end;
+        }
+        if start < range.end {
+            ranges.push(start..range.end);
+        }
+        ranges
+    }
+}
+
 impl Not for Outcome {
     type Output = Self;
 
@@ -243,10 +632,30 @@ mod tests {
     fn test_u7_to_outcome() {
         for i in 0..127 {
             let outcome_u8 = OutcomeU8(i);
-            assert_eq!(OutcomeU8::from(Outcome::from(outcome_u8)), outcome_u8)
+            assert_eq!(OutcomeU8::from(Outcome::from(outcome_u8)), outcome_u8);
         }
     }
 
+    #[test]
+    fn test_outcome_u7_mapping_is_bijective() {
+        let representable: Vec<Outcome> = [Outcome::Draw, Outcome::Unknown, Outcome::Undefined]
+            .into_iter()
+            .chain((0..62).map(Outcome::Lose))
+            .chain((0..63).map(Outcome::Win))
+            .collect();
+        let mut raw: Vec<u8> = representable
+            .iter()
+            .map(|o| OutcomeU8::from(*o).as_raw_u8())
+            .collect();
+        raw.sort_unstable();
+        assert_eq!(raw, (0..128).collect::<Vec<u8>>());
+        for outcome in representable {
+            assert_eq!(Outcome::from(OutcomeU8::from(outcome)), outcome);
+        }
+        assert_eq!(try_into_util(Outcome::Lose(62)), Err(OutcomeOutOfBound));
+        assert_eq!(try_into_util(Outcome::Win(63)), Err(OutcomeOutOfBound));
+    }
+
     #[test]
     fn test_from_str_outcome() {
         assert_eq!(Outcome::from_str("DRAW"), Ok(Outcome::Draw));
@@ -259,33 +668,26 @@ mod tests {
     }
 
     #[test]
-    fn test_report_to_u8() {
-        for outcome in [
-            Outcome::Win(10),
-            Outcome::Draw,
-            Outcome::Lose(61),
-            Outcome::Win(62),
-            Outcome::Undefined,
-            Outcome::Unknown,
-        ] {
-            println!("{outcome:?}");
-            assert_eq!(
-                Report::Unprocessed(outcome),
-                ReportU8::from(Report::Unprocessed(outcome)).into()
-            );
-            assert_eq!(
-                Report::Processed(outcome),
-                ReportU8::from(Report::Processed(outcome)).into()
-            );
+    fn test_processed_bits() {
+        let mut processed = ProcessedBits::new_processed(10);
+        for idx in 0..10 {
+            for turn in Color::ALL {
+                assert!(processed.get(idx, turn));
+            }
         }
-    }
-
-    #[test]
-    fn test_u8_to_report() {
-        for i in 0..u8::MAX {
-            let report_u8 = ReportU8(i);
-            assert_eq!(ReportU8::from(Report::from(report_u8)), report_u8)
+        processed.set(5, Color::White, false);
+        processed.set(6, Color::Black, false);
+        for idx in 0..10 {
+            for turn in Color::ALL {
+                let unprocessed =
+                    (idx, turn) == (5, Color::White) || (idx, turn) == (6, Color::Black);
+                assert_eq!(processed.get(idx, turn), !unprocessed, "{idx} {turn}");
+            }
         }
+        processed.set(5, Color::White, true);
+        assert!(processed.get(5, Color::White));
+        processed.truncate(4);
+        assert_eq!(processed, ProcessedBits::new_processed(4));
     }
 
     #[test]
@@ -293,12 +695,38 @@ mod tests {
         assert_eq!(
             UNDEFINED_OUTCOME_BYCOLOR,
             ByColor {
-                black: Report::Processed(Outcome::Undefined).into(),
-                white: Report::Processed(Outcome::Undefined).into(),
+                black: Outcome::Undefined.into(),
+                white: Outcome::Undefined.into(),
             }
         );
     }
 
+    #[test]
+    fn test_iter_defined() {
+        let outcomes: Outcomes = vec![
+            UNDEFINED_OUTCOME_BYCOLOR,
+            ByColor {
+                black: Outcome::Undefined.into(),
+                white: Outcome::Win(3).into(),
+            },
+            UNDEFINED_OUTCOME_BYCOLOR,
+            ByColor {
+                black: Outcome::Draw.into(),
+                white: Outcome::Lose(2).into(),
+            },
+        ];
+        let idx_with_turn = |idx, turn| IndexWithTurn { idx, turn };
+        assert_eq!(
+            iter_defined(&outcomes, 10).collect::<Vec<_>>(),
+            vec![
+                (idx_with_turn(11, Color::White), Outcome::Win(3)),
+                (idx_with_turn(13, Color::White), Outcome::Lose(2)),
+                (idx_with_turn(13, Color::Black), Outcome::Draw),
+            ]
+        );
+        assert_eq!(iter_defined(&outcomes[..1], 0).count(), 0);
+    }
+
     #[test]
     fn test_ord_outcome() {
         assert!(Outcome::Win(1) > Outcome::Win(2));
@@ -306,10 +734,144 @@ mod tests {
         assert!(Outcome::Win(100) > Outcome::Lose(1));
         assert!(Outcome::Draw > Outcome::Lose(1));
         assert!(Outcome::Lose(2) > Outcome::Lose(1));
+        assert!(Outcome::Lose(0) > Outcome::Unknown);
+        assert_eq!(Outcome::Unknown.cmp(&Outcome::Unknown), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_best_of() {
+        assert_eq!(Outcome::best_of([]), None);
+        assert_eq!(Outcome::best_of([Outcome::Undefined]), None);
+        assert_eq!(
+            Outcome::best_of([Outcome::Unknown, Outcome::Undefined]),
+            Some(Outcome::Unknown)
+        );
+        assert_eq!(
+            Outcome::best_of([Outcome::Lose(3), Outcome::Unknown, Outcome::Undefined]),
+            Some(Outcome::Lose(3))
+        );
+        assert_eq!(
+            Outcome::best_of([Outcome::Win(4), Outcome::Draw, Outcome::Win(2)]),
+            Some(Outcome::Win(2))
+        );
+    }
+
+    #[test]
+    fn test_wdl_outcomes() {
+        let outcomes: Outcomes = [
+            (Outcome::Win(3), Outcome::Draw),
+            (Outcome::Lose(0), Outcome::Undefined),
+            (Outcome::Draw, Outcome::Win(62)),
+            (Outcome::Unknown, Outcome::Lose(61)),
+        ]
+        .into_iter()
+        .map(|(black, white)| ByColor {
+            black: black.into(),
+            white: white.into(),
+        })
+        .collect();
+        let mut wdl_outcomes = WdlOutcomes::new();
+        wdl_outcomes.extend(outcomes);
+        assert_eq!(wdl_outcomes.len(), 4);
+        assert_eq!(
+            wdl_outcomes.iter().collect::<Vec<_>>(),
+            vec![
+                ByColor {
+                    black: Wdl::Win,
+                    white: Wdl::Draw
+                },
+                ByColor {
+                    black: Wdl::Lose,
+                    white: Wdl::Undefined
+                },
+                ByColor {
+                    black: Wdl::Draw,
+                    white: Wdl::Win
+                },
+                ByColor {
+                    black: Wdl::Unknown,
+                    white: Wdl::Lose
+                },
+            ]
+        );
+        assert_eq!(wdl_outcomes.get(4), None);
+    }
+
+    #[test]
+    fn test_dense_outcomes() {
+        // defined entries at indexes multiple of 3, with a gap spanning several words
+        let outcomes: Outcomes = (0..300_usize)
+            .map(|idx| {
+                let outcome = if idx % 3 == 0 && !(100..250).contains(&idx) {
+                    Outcome::Win(u8::try_from(idx % 60).unwrap())
+                } else {
+                    Outcome::Undefined
+                };
+                ByColor {
+                    black: outcome.into(),
+                    white: Outcome::Undefined.into(),
+                }
+            })
+            .collect();
+        let mut dense_outcomes = DenseOutcomes::new();
+        dense_outcomes.extend(outcomes.iter().copied());
+        assert_eq!(dense_outcomes.len(), 300);
+        let defined: Vec<usize> = (0..300)
+            .filter(|idx| outcomes[*idx] != UNDEFINED_OUTCOMEU8_BYCOLOR)
+            .collect();
+        assert_eq!(dense_outcomes.nb_defined(), defined.len());
+        assert_eq!(dense_outcomes.iter().collect::<Outcomes>(), outcomes);
+        for (n, idx) in defined.iter().enumerate() {
+            assert_eq!(dense_outcomes.rank(*idx), n);
+            assert_eq!(dense_outcomes.select(n), Some(*idx));
+        }
+        assert_eq!(dense_outcomes.select(defined.len()), None);
+        assert_eq!(dense_outcomes.get(300), None);
+    }
+
+    #[test]
+    fn test_undefined_spans() {
+        // undefined runs of 10 and 100 entries, then 64 over two extends, and 80 at the end
+        let defined = ByColor {
+            black: Outcome::Draw.into(),
+            white: Outcome::Undefined.into(),
+        };
+        let outcomes: Outcomes = (0..400_usize)
+            .map(|idx| {
+                if [0, 11, 112, 177].contains(&idx) || (178..320).contains(&idx) {
+                    defined
+                } else {
+                    UNDEFINED_OUTCOMEU8_BYCOLOR
+                }
+            })
+            .collect();
+        let mut undefined_spans = UndefinedSpans::new();
+        undefined_spans.extend_from(&outcomes[..150]);
+        undefined_spans.extend_from(&outcomes[150..]);
+        assert_eq!(undefined_spans, UndefinedSpans::from_outcomes(&outcomes));
+        assert_eq!(undefined_spans.spans(), &[12..112, 113..177, 320..400]);
+        assert_eq!(undefined_spans.len(), 400);
+        assert_eq!(undefined_spans.nb_undefined(), 244);
+        assert_eq!(
+            undefined_spans.defined_ranges(0..400),
+            vec![0..12, 112..113, 177..320]
+        );
+        assert_eq!(
+            undefined_spans.defined_ranges(50..200),
+            vec![112..113, 177..200]
+        );
+        assert!(undefined_spans.defined_ranges(20..100).is_empty());
+        assert_eq!(
+            UndefinedSpans::from_spans(undefined_spans.spans().to_vec(), 400),
+            Some(undefined_spans)
+        );
+        assert_eq!(UndefinedSpans::from_spans(vec![0..64, 64..128], 400), None);
+        assert_eq!(UndefinedSpans::from_spans(vec![0..10, 100..200], 400), None);
+        assert_eq!(UndefinedSpans::from_spans(vec![0..64, 100..200], 150), None);
     }
 
     #[test]
-    #[should_panic]
+    #[should_panic(expected = "No Undefined in comparison")]
     fn test_ord_outcome_panic() {
         let _ = Outcome::Undefined > Outcome::Win(1);
     }
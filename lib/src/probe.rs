@@ -1,120 +1,1183 @@
-use std::{collections::HashMap, path::Path};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, Weak},
+};
 
 use itertools::process_results;
 use positioned_io::RandomAccessFile;
-use retroboard::shakmaty::{ByColor, Chess, Color, MoveList, Position};
+use retroboard::shakmaty::{
+    fen::Fen, Board, ByColor, CastlingMode, Chess, Color, FromSetup, Move, MoveList, Position,
+};
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    file_handler::RetrieveOutcome, DefaultIndexer, EncoderDecoder, Indexer, Material,
-    MaterialWinner, Outcome, SideToMove,
+    file_handler::{check_table_len, index_out_of_table, RawAccessOutcome, RetrieveOutcome},
+    indexer::{canonical_board, Symmetry},
+    DeIndexer, DefaultIndexer, EncoderDecoder, IndexWithTurn, Indexer, KingSquares, Material,
+    MaterialWinner, Metric, NaiveIndexer, Outcome, OutcomeU8, OutcomesRange, Route, Router,
+    SideToMove, SideToMoveGetter, TableName, Wdl, WinObjective, WinWithinTable,
 };
 use std::io;
+use std::ops::Range;
 
 #[derive(Debug)]
 pub struct LazyFileHandler<T = DefaultIndexer> {
     indexer: T,
     inner: EncoderDecoder<RandomAccessFile>,
+    path: PathBuf,
 }
 
+#[cfg_attr(not(feature = "unstable-internals"), allow(dead_code))]
 impl<T: From<Material>> LazyFileHandler<T> {
     #[must_use]
     pub fn new(mat: &MaterialWinner, tablebase_dir: &Path) -> Self {
-        let path = tablebase_dir.join(format!("{mat:?}"));
-        let raf =
-            RandomAccessFile::open(&path).unwrap_or_else(|_| panic!("Path {path:?} not found"));
+        let path = TableName::helpmate(mat).path_in(tablebase_dir);
+        let raf = RandomAccessFile::open(&path)
+            .unwrap_or_else(|_| panic!("Path {} not found", path.display()));
         let inner = EncoderDecoder::new(raf);
+        inner
+            .table_format()
+            .and_then(|format| check_table_len(&TableName::helpmate(mat), format.len))
+            .unwrap_or_else(|e| panic!("{}: {e}", path.display()));
         let indexer = T::from(mat.material.clone());
-        Self { indexer, inner }
+        Self {
+            indexer,
+            inner,
+            path,
+        }
+    }
+
+    /// Same as `LazyFileHandler::new`, but returns `Ok(None)` if the table has not been generated.
+    /// Fails with `TableFormatError::TruncatedTable` if it is shorter than its material requires
+    pub fn try_new(mat: &MaterialWinner, tablebase_dir: &Path) -> io::Result<Option<Self>> {
+        Self::try_new_with_name(&TableName::helpmate(mat), tablebase_dir)
+    }
+
+    /// Same as `LazyFileHandler::try_new`, for any table, eg a `Metric::Wdl` sidecar
+    pub fn try_new_with_name(
+        table_name: &TableName,
+        tablebase_dir: &Path,
+    ) -> io::Result<Option<Self>> {
+        let path = table_name.path_in(tablebase_dir);
+        let Ok(raf) = RandomAccessFile::open(&path) else {
+            return Ok(None);
+        };
+        let inner = EncoderDecoder::new(raf);
+        check_table_len(table_name, inner.table_format()?.len)?;
+        let indexer = T::from(table_name.mat_win.material.clone());
+        Ok(Some(Self {
+            indexer,
+            inner,
+            path,
+        }))
     }
 }
 
 impl<T: Indexer> LazyFileHandler<T> {
+    #[cfg_attr(not(feature = "cached"), allow(unused_variables))]
     pub fn outcome_of(
         &self,
-        _mat_winner: MaterialWinner,
+        mat_winner: &MaterialWinner,
         board_and_turn: &impl SideToMove,
     ) -> io::Result<Outcome> {
+        let idx = self.indexer.encode(board_and_turn);
+        #[cfg(feature = "cached")]
+        let outcome_bc = self.inner.outcome_of_cached(mat_winner, idx);
+        #[cfg(not(feature = "cached"))]
+        let outcome_bc = self.inner.outcome_of(idx);
+
+        // the path already names the table, `mat_winner` is not cloned just for the error
+        outcome_bc.map_err(|e| self.describe_out_of_table(e, idx.idx, None))
+    }
+}
+
+#[cfg_attr(not(feature = "unstable-internals"), allow(dead_code))]
+impl<T> LazyFileHandler<T> {
+    /// Outcome stored at `idx`, for tools already working with indexes rather than positions.
+    /// Fails with `io::ErrorKind::InvalidInput` if `idx` is out of the table
+    pub fn outcome_at(&self, idx: IndexWithTurn) -> io::Result<Outcome> {
+        self.inner
+            .outcome_of(idx)
+            .map_err(|e| self.describe_out_of_table(e, idx.idx, None))
+    }
+
+    /// Replace the error of an `idx` beyond the end of the table by one giving its max index and path
+    fn describe_out_of_table(
+        &self,
+        err: io::Error,
+        idx: u64,
+        mat_win: Option<&MaterialWinner>,
+    ) -> io::Error {
+        if err.kind() != io::ErrorKind::NotFound {
+            return err;
+        }
+        match self.inner.table_format() {
+            Ok(table_format) => {
+                index_out_of_table(idx, table_format.len, mat_win, Some(&self.path))
+            }
+            Err(_) => err,
+        }
+    }
+
+    /// Outcomes of all indexes in `range`, only decompressing the blocks overlapping it.
+    /// Blocks are not cached, see `LazyFileHandler::block_ranges` to split a table along them
+    pub fn outcomes_in_range(&self, range: Range<u64>) -> io::Result<Vec<OutcomesRange>> {
+        self.inner.outcomes_in_range(range)
+    }
+
+    /// Same as `FileHandler::block_ranges`
+    pub fn block_ranges(&self) -> io::Result<Vec<Range<u64>>> {
+        self.inner.block_ranges()
+    }
+
+    /// Bytes stored at `idx`, for both sides to move
+    #[cfg_attr(not(feature = "cached"), allow(unused_variables))]
+    pub fn raw_outcome_of(
+        &self,
+        mat_winner: &MaterialWinner,
+        idx: u64,
+    ) -> io::Result<ByColor<OutcomeU8>> {
         #[cfg(feature = "cached")]
-        let outcome_bc = self
+        let outcomes_ranges = self
             .inner
-            .outcome_of_cached(_mat_winner, self.indexer.encode(board_and_turn));
+            .outcomes_in_range_cached(mat_winner, idx..idx + 1);
         #[cfg(not(feature = "cached"))]
-        let outcome_bc = self.inner.outcome_of(self.indexer.encode(board_and_turn));
+        let outcomes_ranges = self.inner.outcomes_in_range(idx..idx + 1);
+
+        outcomes_ranges
+            .map(|ranges| ranges[0][0])
+            .map_err(|e| self.describe_out_of_table(e, idx, Some(mat_winner)))
+    }
+}
+
+/// Result of `TablebaseProber::probe_partial`
+#[derive(Debug)]
+pub struct PartialProbe {
+    /// At each ply, all the moves leading to the best outcome, sorted by their UCI notation,
+    /// those still reaching a mate on the squares of `ProbeOptions::mated_king_on` first,
+    /// then non-captures with `ProbeOptions::prefer_capture_free`
+    pub optimal_moves_list: Vec<MoveList>,
+    /// Positions after the first optimal move of each ply
+    pub positions: Vec<Chess>,
+    /// `false` if a missing table was needed along the line, in which case the moves
+    /// may not be optimal, and the line may stop before the end
+    pub complete: bool,
+    /// `true` if the line was cut at `max_plies`, rather than ending on a mate, a draw,
+    /// an unknown outcome or a position where the game is over
+    pub truncated: bool,
+    /// `true` if no move of the line followed is a capture, by either side, see `ProbeOptions::prefer_capture_free`
+    pub capture_free: bool,
+    /// With `ProbeOptions::mated_king_on`, `true` if the line ends with the loser's king mated on one of
+    /// the squares. `None` without it
+    pub mated_king_on: Option<bool>,
+    /// Transformations bringing the root position to the canonical one looked up in the table.
+    /// Moves and positions are always played on the root position, not the canonical one,
+    /// `Symmetry::apply_to_move` gives them in canonical coordinates
+    pub symmetry: Symmetry,
+}
 
-        outcome_bc
+/// Best line of a `Outcome::Win` entry of a table, see `TablebaseProber::win_lines`
+#[derive(Debug, Clone)]
+pub struct WinLine {
+    pub idx: IndexWithTurn,
+    /// Distance to mate in plies, the length of `moves`
+    pub plies: u8,
+    /// Position restored from `idx`, so canonical
+    pub root: Chess,
+    /// One optimal move by ply
+    pub moves: MoveList,
+    /// Positions after each move of `moves`
+    pub positions: Vec<Chess>,
+}
+
+/// Result of `TablebaseProber::diagnose`
+#[derive(Debug, Clone)]
+pub struct Diagnosis {
+    /// Why the position is illegal, `None` if it is legal
+    pub illegal: Option<String>,
+    /// Material of the table, normalised
+    pub material: Material,
+    pub symmetry: Symmetry,
+    pub canonical_board: Board,
+    /// Index in the table, the side to move being swapped along with the colors
+    pub index: IndexWithTurn,
+    /// Byte stored at `index`, by winner of the position. `None` if there is no table,
+    /// either because it has not been generated, or because the material is drawn in every position
+    pub raw_outcomes: ByColor<Option<u8>>,
+}
+
+impl Diagnosis {
+    /// `Outcome::Undefined` if the position was not reached during the generation,
+    /// which is expected of illegal positions
+    #[must_use]
+    pub fn outcome(&self, winner: Color) -> Option<Outcome> {
+        self.raw_outcomes
+            .get(winner)
+            .and_then(OutcomeU8::from_raw_u8)
+            .map(Outcome::from)
+    }
+}
+
+/// Outcome of a position, see `TablebaseProber::probe_result`.
+/// Tables store outcomes from the point of view of their winner, whichever side is to move,
+/// which is rarely how they should be presented
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ProbeResult {
+    /// From the point of view of `winner`
+    pub outcome: Outcome,
+    pub winner: Color,
+    pub turn: Color,
+}
+
+impl ProbeResult {
+    /// Outcome from the point of view of `color`, negated if it is not the winner.
+    /// `Outcome::Unknown` and `Outcome::Undefined` are returned as is
+    #[must_use]
+    pub fn from_perspective(self, color: Color) -> Outcome {
+        match self.outcome {
+            Outcome::Unknown | Outcome::Undefined => self.outcome,
+            outcome if color == self.winner => outcome,
+            outcome => !outcome,
+        }
+    }
+
+    /// Outcome from the point of view of the side to move
+    #[must_use]
+    pub fn from_side_to_move(self) -> Outcome {
+        self.from_perspective(self.turn)
+    }
+}
+
+/// Version of the JSON representation of `ProbeResponse`, bumped whenever a field is removed or changes meaning.
+/// Fields may be added without bumping it, so clients must ignore the ones they do not know
+pub const PROBE_RESPONSE_VERSION: u32 = 1;
+
+/// Stable JSON representation of the probe of a position, eg written by the cli for each line of a bulk probe.
+/// Outcomes are from the point of view of `winner`, see `PROBE_RESPONSE_VERSION`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProbeResponse {
+    pub version: u32,
+    pub fen: String,
+    /// `white` or `black`
+    pub winner: String,
+    /// As displayed by `Outcome`'s `Debug`, eg `Win(3)`. `None` if the probe failed
+    pub outcome: Option<String>,
+    /// One of `win`, `draw`, `lose`, `unknown` or `undefined`. `None` if the probe failed
+    pub category: Option<String>,
+    /// Plies to mate, for wins and losses only
+    pub dtm: Option<u8>,
+    /// In UCI notation
+    pub best_move: Option<String>,
+    /// Moves of `TablebaseProber::probe` in UCI notation, empty if not requested
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub best_line: Vec<String>,
+    /// Why the probe failed
+    pub error: Option<String>,
+}
+
+impl ProbeResponse {
+    #[must_use]
+    pub fn new(fen: String, winner: Color, outcome: Result<Outcome, String>) -> Self {
+        let (outcome, error) = match outcome {
+            Ok(outcome) => (Some(outcome), None),
+            Err(e) => (None, Some(e)),
+        };
+        Self {
+            version: PROBE_RESPONSE_VERSION,
+            fen,
+            winner: winner.to_string(),
+            outcome: outcome.map(|outcome| format!("{outcome:?}")),
+            category: outcome.map(|outcome| {
+                match outcome {
+                    Outcome::Win(_) => "win",
+                    Outcome::Draw => "draw",
+                    Outcome::Lose(_) => "lose",
+                    Outcome::Unknown => "unknown",
+                    Outcome::Undefined => "undefined",
+                }
+                .to_string()
+            }),
+            dtm: outcome.and_then(|outcome| match outcome {
+                Outcome::Win(plies) | Outcome::Lose(plies) => Some(plies),
+                _ => None,
+            }),
+            best_move: None,
+            best_line: Vec::new(),
+            error,
+        }
+    }
+}
+
+/// How `TablebaseProber` handles positions with castling rights, which tables do not model
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum CastlingPolicy {
+    /// Fail with `io::ErrorKind::InvalidInput`
+    #[default]
+    Reject,
+    /// Best of the outcome stored in the table, and of the outcomes after each castling move.
+    /// Castling rights left after the castling move, if any, are ignored
+    Search,
+}
+
+/// Order in which `TablebaseProber::probe_with_options` probes the legal moves of each ply
+#[derive(Debug, Clone, Copy, Default)]
+pub enum MoveOrdering {
+    /// All moves are probed, and all the optimal ones returned, see `TablebaseProber::probe_partial`
+    #[default]
+    Exhaustive,
+    /// Checks first, then captures, then moves bringing the kings closer, see `MoveOrdering::Custom`
+    Heuristic,
+    /// Moves with the lowest key are probed first. The first move reaching the outcome of the position
+    /// is played without probing the others, so only this move is returned at each ply.
+    /// The line is still optimal, but not necessarily the one of `TablebaseProber::probe`
+    Custom(fn(&Chess, &Move) -> i32),
+}
+
+impl MoveOrdering {
+    /// `None` for `MoveOrdering::Exhaustive`
+    fn key(self, pos: &Chess, chess_move: &Move, pos_after_move: &Chess) -> Option<i32> {
+        match self {
+            Self::Exhaustive => None,
+            Self::Heuristic => {
+                let kings_distance = match (
+                    pos_after_move.board().king_of(Color::White),
+                    pos_after_move.board().king_of(Color::Black),
+                ) {
+                    (Some(white_king), Some(black_king)) => white_king.distance(black_king),
+                    _ => 0,
+                };
+                Some(
+                    i32::from(!pos_after_move.is_check()) * 16
+                        + i32::from(!chess_move.is_capture()) * 8
+                        + i32::try_from(kings_distance).expect("at most 7 squares"),
+                )
+            }
+            Self::Custom(key) => Some(key(pos, chess_move)),
+        }
+    }
+}
+
+/// See `TablebaseProber::probe_with_options`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProbeOptions {
+    pub move_ordering: MoveOrdering,
+    /// The line stops after that many plies at most, see `PartialProbe::truncated`
+    pub max_plies: Option<usize>,
+    /// Among the optimal moves of each ply, play a non-capture if there is one, as composers prefer
+    /// "pure" helpmates. Only a tie-break ply by ply, so a capture can still be forced later in the line,
+    /// see `PartialProbe::capture_free`. With `MoveOrdering::Exhaustive`, non-captures are listed first
+    pub prefer_capture_free: bool,
+    /// Check whether the line ends with the loser's king mated on these squares, see `PartialProbe::mated_king_on`.
+    /// With `MoveOrdering::Exhaustive`, the optimal moves from which such a mate is still optimally reached
+    /// are listed first, so the line followed ends that way if any optimal line does. The tables probed are
+    /// the regular ones, so a mate elsewhere can be shorter than any on the squares, whose distance is given
+    /// by tables generated with `WinCondition::MatedKingOn`
+    pub mated_king_on: Option<KingSquares>,
+}
+
+type LazyTables<T> = HashMap<Material, ByColor<Option<Arc<LazyFileHandler<T>>>>>;
+
+/// Tables opened by the probers created with it, eg `TablebaseProber::new_with_registry`,
+/// so that probers of overlapping materials share their open files instead of each opening its own.
+/// Clones share the same registry. A table is closed once no prober uses it anymore
+#[derive(Debug)]
+pub struct TableRegistry<T = DefaultIndexer>(
+    Arc<Mutex<HashMap<PathBuf, Weak<LazyFileHandler<T>>>>>,
+);
+
+impl<T> Default for TableRegistry<T> {
+    fn default() -> Self {
+        Self(Arc::default())
+    }
+}
+
+impl<T> Clone for TableRegistry<T> {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+
+impl<T> TableRegistry<T> {
+    /// Number of tables currently open
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.0
+            .lock()
+            .expect("table registry poisoned")
+            .values()
+            .filter(|handler| handler.strong_count() > 0)
+            .count()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T: From<Material>> TableRegistry<T> {
+    /// `Ok(None)` if the table has not been generated, see `LazyFileHandler::try_new_with_name`.
+    /// Errors name the table
+    fn get_or_open(
+        &self,
+        table_name: &TableName,
+        tablebase_dir: &Path,
+    ) -> io::Result<Option<Arc<LazyFileHandler<T>>>> {
+        let path = table_name.path_in(tablebase_dir);
+        let mut tables = self.0.lock().expect("table registry poisoned");
+        if let Some(handler) = tables.get(&path).and_then(Weak::upgrade) {
+            return Ok(Some(handler));
+        }
+        let Some(handler) = LazyFileHandler::try_new_with_name(table_name, tablebase_dir)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", path.display())))?
+        else {
+            return Ok(None);
+        };
+        let handler = Arc::new(handler);
+        // forget the tables closed since
+        tables.retain(|_, handler| handler.strong_count() > 0);
+        tables.insert(path, Arc::downgrade(&handler));
+        Ok(Some(handler))
     }
 }
 
+/// Loaded with `TablebaseProber::with_win_within_tables`, keyed by `WinWithinTable::max_plies`
+type WinWithinTables<T> = HashMap<(MaterialWinner, u8), (T, WinWithinTable)>;
+
+/// `Send + Sync`, so a single prober can be shared between threads behind an `Arc`, every probing method
+/// taking `&self`. Tables are read with positioned reads, which share no cursor, and the cache of decompressed
+/// blocks, shared by the whole process, is behind a `Mutex` that is not held while decompressing
 #[derive(Debug)]
-pub struct TablebaseProber<T = DefaultIndexer>(HashMap<Material, ByColor<LazyFileHandler<T>>>);
+pub struct TablebaseProber<T = DefaultIndexer> {
+    /// Tables not generated are `None`, only possible with `TablebaseProber::new_partial`
+    dtm_tables: LazyTables<T>,
+    castling: CastlingPolicy,
+    /// `Metric::Wdl` sidecars, kept apart and only found ones being `Some`, see `TablebaseProber::wdl`
+    wdl_tables: LazyTables<T>,
+    win_within: WinWithinTables<T>,
+}
+
+// servers share probers between their workers, see `TablebaseProber`
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<TablebaseProber>();
+    assert_send_sync::<TableRegistry>();
+};
 
 impl<T: Indexer + From<Material>> TablebaseProber<T> {
     #[must_use]
     pub fn new(mat: &Material, tablebase_dir: &Path) -> Self {
         let mut mats = mat.descendants_recursive(false);
         mats.push(mat.clone());
-        Self(
-            mats.into_iter()
-                .map(|m| {
-                    (
-                        m.clone(),
-                        ByColor::new_with(|winner| {
-                            let mat_winner = MaterialWinner::new(&m, winner);
-                            LazyFileHandler::new(&mat_winner, tablebase_dir)
-                        }),
-                    )
-                })
-                .collect(),
+        Self::from_materials(mats, tablebase_dir, false)
+    }
+
+    /// Same as `TablebaseProber::new`, but tables not generated are tolerated.
+    /// Positions belonging to them are then reported as `Outcome::Unknown`
+    #[must_use]
+    pub fn new_partial(mat: &Material, tablebase_dir: &Path) -> Self {
+        let mut mats = mat.descendants_recursive(false);
+        mats.push(mat.clone());
+        Self::from_materials(mats, tablebase_dir, true)
+    }
+
+    /// Same as `TablebaseProber::new_partial`, but truncated tables are reported as an
+    /// `io::ErrorKind::InvalidData` error instead of panicking
+    pub fn try_new_partial(mat: &Material, tablebase_dir: &Path) -> io::Result<Self> {
+        let mut mats = mat.descendants_recursive(false);
+        mats.push(mat.clone());
+        Self::try_from_materials_in(mats, tablebase_dir, true, &TableRegistry::default())
+    }
+
+    /// Same as `TablebaseProber::new`, but missing tables are reported as an `io::ErrorKind::NotFound`
+    /// error naming them instead of panicking, and truncated ones as an `io::ErrorKind::InvalidData` one
+    pub fn try_new(mat: &Material, tablebase_dir: &Path) -> io::Result<Self> {
+        let mut mats = mat.descendants_recursive(false);
+        mats.push(mat.clone());
+        let prober =
+            Self::try_from_materials_in(mats, tablebase_dir, true, &TableRegistry::default())?;
+        let mut missing: Vec<String> = prober
+            .dtm_tables
+            .iter()
+            .flat_map(|(m, by_color)| {
+                Color::ALL
+                    .into_iter()
+                    .filter(|winner| by_color.get(*winner).is_none())
+                    .map(|winner| TableName::helpmate(&MaterialWinner::new(m, winner)).to_string())
+            })
+            .collect();
+        if missing.is_empty() {
+            return Ok(prober);
+        }
+        missing.sort();
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "missing tables in {}: {}",
+                tablebase_dir.display(),
+                missing.join(", ")
+            ),
+        ))
+    }
+
+    /// Only open the tables of the descendants of `mat`, not the one of `mat` itself.
+    /// Useful when the table of `mat` has not been generated, see `TablebaseProber::resolve_captures`
+    #[must_use]
+    pub fn new_descendants_only(mat: &Material, tablebase_dir: &Path) -> Self {
+        Self::from_materials(mat.descendants_recursive(false), tablebase_dir, false)
+    }
+
+    /// Same as `TablebaseProber::new`, but the tables already opened by the other probers
+    /// created with `registry` are shared rather than opened again
+    #[must_use]
+    pub fn new_with_registry(
+        mat: &Material,
+        tablebase_dir: &Path,
+        registry: &TableRegistry<T>,
+    ) -> Self {
+        let mut mats = mat.descendants_recursive(false);
+        mats.push(mat.clone());
+        Self::from_materials_in(mats, tablebase_dir, false, registry)
+    }
+
+    /// Same as `TablebaseProber::new_partial`, sharing the tables of `registry`,
+    /// see `TablebaseProber::new_with_registry`
+    #[must_use]
+    pub fn new_partial_with_registry(
+        mat: &Material,
+        tablebase_dir: &Path,
+        registry: &TableRegistry<T>,
+    ) -> Self {
+        let mut mats = mat.descendants_recursive(false);
+        mats.push(mat.clone());
+        Self::from_materials_in(mats, tablebase_dir, true, registry)
+    }
+
+    fn from_materials(mats: Vec<Material>, tablebase_dir: &Path, allow_missing: bool) -> Self {
+        // private to this prober, so that its tables are closed with it
+        Self::from_materials_in(
+            mats,
+            tablebase_dir,
+            allow_missing,
+            &TableRegistry::default(),
         )
     }
 
-    /// Returns one of the best possible line until mate or drawn position
+    fn from_materials_in(
+        mats: Vec<Material>,
+        tablebase_dir: &Path,
+        allow_missing: bool,
+        registry: &TableRegistry<T>,
+    ) -> Self {
+        Self::try_from_materials_in(mats, tablebase_dir, allow_missing, registry)
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Fails if a table found is truncated, see `LazyFileHandler::try_new_with_name`
+    fn try_from_materials_in(
+        mats: Vec<Material>,
+        tablebase_dir: &Path,
+        allow_missing: bool,
+        registry: &TableRegistry<T>,
+    ) -> io::Result<Self> {
+        let mut tables = HashMap::with_capacity(mats.len());
+        let mut sidecars = HashMap::with_capacity(mats.len());
+        for m in mats {
+            let mut handlers: ByColor<Option<Arc<LazyFileHandler<T>>>> = ByColor::default();
+            let mut sidecar_handlers: ByColor<Option<Arc<LazyFileHandler<T>>>> = ByColor::default();
+            for winner in Color::ALL {
+                let table_name = TableName::helpmate(&MaterialWinner::new(&m, winner));
+                let handler = registry.get_or_open(&table_name, tablebase_dir)?;
+                assert!(
+                    handler.is_some() || allow_missing,
+                    "Path {} not found",
+                    table_name.path_in(tablebase_dir).display()
+                );
+                *handlers.get_mut(winner) = handler;
+                let sidecar_name = TableName {
+                    metric: Metric::Wdl,
+                    ..table_name
+                };
+                *sidecar_handlers.get_mut(winner) =
+                    registry.get_or_open(&sidecar_name, tablebase_dir)?;
+            }
+            sidecars.insert(m.clone(), sidecar_handlers);
+            tables.insert(m, handlers);
+        }
+        Ok(Self {
+            dtm_tables: tables,
+            castling: CastlingPolicy::default(),
+            wdl_tables: sidecars,
+            win_within: HashMap::new(),
+        })
+    }
+
+    /// Positions with castling rights are rejected by default
+    #[must_use]
+    pub fn with_castling_policy(mut self, castling_policy: CastlingPolicy) -> Self {
+        self.castling = castling_policy;
+        self
+    }
+
+    /// Load the `WinWithinTable`s for `max_plies` of the tables of the prober found in `tablebase_dir`,
+    /// see `TablebaseProber::win_within`. Missing ones are skipped
+    pub fn with_win_within_tables(
+        mut self,
+        tablebase_dir: &Path,
+        max_plies: u8,
+    ) -> io::Result<Self> {
+        let mat_winners: Vec<MaterialWinner> = self
+            .dtm_tables
+            .keys()
+            .flat_map(|mat| Color::ALL.map(|winner| MaterialWinner::new(mat, winner)))
+            .collect();
+        for mat_win in mat_winners {
+            let path = WinWithinTable::path_in(tablebase_dir, &mat_win, max_plies);
+            if !path.exists() {
+                continue;
+            }
+            let table = WinWithinTable::read(File::open(path)?)?;
+            let indexer = T::from(mat_win.material.clone());
+            self.win_within.insert((mat_win, max_plies), (indexer, table));
+        }
+        Ok(self)
+    }
+
+    /// Same as `RetrieveOutcome::retrieve_outcome`, but positions with castling rights
+    /// are handled according to the `CastlingPolicy` of the prober, instead of being probed as if they had none
+    pub fn probe_outcome(&self, pos: &Chess, winner: Color) -> io::Result<Outcome> {
+        self.check_castling_rights(pos)?;
+        let outcome = self.retrieve_outcome(pos, winner)?;
+        if pos.castles().is_empty() {
+            return Ok(outcome);
+        }
+        let mut castling_moves = pos.legal_moves();
+        castling_moves.retain(|m| m.is_castle());
+        let castling_outcome = self
+            .best_move_among(pos, castling_moves, winner)?
+            // we are one move further from the max, unless the outcome is not known
+            .map(|(_, o)| if o == Outcome::Unknown { o } else { o + 1 });
+        Ok(Outcome::best_of(std::iter::once(outcome).chain(castling_outcome)).unwrap_or(outcome))
+    }
+
+    /// Only whether `winner` wins, draws or loses, read from the `Metric::Wdl` sidecar of the table
+    /// if found, from the table otherwise. No move is probed, unless `pos` has castling rights
+    /// and the prober searches castling moves, see `CastlingPolicy::Search`.
+    /// Fails with `io::ErrorKind::NotFound` if the table has not been generated
+    pub fn wdl(&self, pos: &Chess, winner: Color) -> io::Result<Wdl> {
+        self.check_castling_rights(pos)?;
+        if !pos.castles().is_empty() {
+            return self.probe_outcome(pos, winner).map(Wdl::from);
+        }
+        // no route for material config known to be draw in every position
+        let Some(route) = Router::route_pos(pos, winner) else {
+            return Ok(Wdl::Draw);
+        };
+        let lazy_file = [&self.wdl_tables, &self.dtm_tables]
+            .into_iter()
+            .find_map(|tables| {
+                tables
+                    .get(&route.mat_win.material)
+                    .and_then(|by_color| by_color.get(route.mat_win.winner).as_ref())
+            })
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("table of {:?} not generated", route.mat_win),
+                )
+            })?;
+        lazy_file
+            .outcome_of(&route.mat_win, &(pos.board(), route.turn))
+            .map(Wdl::from)
+    }
+
+    /// `true` if `winner` mates within `max_plies`, ie the outcome is at most `Outcome::Win(max_plies)`.
+    /// Read from the `WinWithinTable` for `max_plies` if loaded, see `TablebaseProber::with_win_within_tables`,
+    /// from the table otherwise
+    pub fn win_within(&self, pos: &Chess, winner: Color, max_plies: u8) -> io::Result<bool> {
+        self.check_castling_rights(pos)?;
+        if pos.castles().is_empty() {
+            // no route for material config known to be draw in every position
+            let Some(route) = Router::route_pos(pos, winner) else {
+                return Ok(false);
+            };
+            if let Some((indexer, table)) = self.win_within.get(&(route.mat_win, max_plies)) {
+                return Ok(table.win_within(indexer.encode(&(pos.board(), route.turn))));
+            }
+        }
+        Ok(matches!(
+            self.probe_outcome(pos, winner)?,
+            Outcome::Win(x) if x <= max_plies
+        ))
+    }
+
+    /// Same as `TablebaseProber::probe_outcome`, along with the winner and the side to move,
+    /// so that the outcome can be presented from either point of view
+    pub fn probe_result(&self, pos: &Chess, winner: Color) -> io::Result<ProbeResult> {
+        Ok(ProbeResult {
+            outcome: self.probe_outcome(pos, winner)?,
+            winner,
+            turn: pos.turn(),
+        })
+    }
+
+    /// Lines are only meaningful when `winner` can mate, see `WinObjective`
+    fn check_win_objective(pos: &Chess, winner: Color) -> io::Result<()> {
+        // routed, so that the winner is swapped along with the colors when black is stronger
+        let mat_win = Router::route_pos(pos, winner).map_or_else(
+            || MaterialWinner::new(&Material::from_board(pos.board()), winner),
+            |route| route.mat_win,
+        );
+        if WinObjective::of(&mat_win) == WinObjective::Draw {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{winner} cannot mate with {mat_win:?}, its table only tells whether it gets mated, probe the other winner instead"),
+            ));
+        }
+        Ok(())
+    }
+
+    fn check_castling_rights(&self, pos: &Chess) -> io::Result<()> {
+        if self.castling == CastlingPolicy::Reject && !pos.castles().is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "positions with castling rights are not part of the tables",
+            ));
+        }
+        Ok(())
+    }
+
+    /// `true` if all the tables needed have been found
+    #[must_use]
+    pub fn is_complete(&self) -> bool {
+        self.dtm_tables
+            .values()
+            .all(|by_color| by_color.iter().all(Option::is_some))
+    }
+
+    /// Returns all the moves of `pos` leading to the best outcome, sorted by their UCI notation,
+    /// along with the position after each move and that best outcome.
+    /// The boolean is `false` if the outcome of at least one move is `Outcome::Unknown`
+    #[allow(clippy::type_complexity)]
+    fn optimal_moves(
+        &self,
+        pos: &Chess,
+        winner: Color,
+    ) -> io::Result<(Vec<(Move, Chess)>, Outcome, bool)> {
+        self.check_castling_rights(pos)?;
+        let moves_and_outcomes = process_results(
+            pos.legal_moves().into_iter().map(|chess_move| {
+                let mut pos_after_move = pos.clone();
+                pos_after_move.play_unchecked(&chess_move);
+                self.probe_outcome(&pos_after_move, winner)
+                    .map(|outcome| (chess_move, outcome, pos_after_move))
+            }),
+            |iter| iter.collect::<Vec<_>>(),
+        )?;
+        let complete = moves_and_outcomes
+            .iter()
+            .all(|(_, outcome, _)| *outcome != Outcome::Unknown);
+        let best_outcome =
+            Outcome::best_of(moves_and_outcomes.iter().map(|(_, outcome, _)| *outcome))
+                .expect("No outcomes found");
+        let mut optimal_moves: Vec<(Move, Chess)> = moves_and_outcomes
+            .into_iter()
+            .filter(|(_, outcome, _)| *outcome == best_outcome)
+            .map(|(chess_move, _, pos_after_move)| (chess_move, pos_after_move))
+            .collect();
+        optimal_moves.sort_by_cached_key(|(chess_move, _)| {
+            chess_move.to_uci(CastlingMode::Standard).to_string()
+        });
+        Ok((optimal_moves, best_outcome, complete))
+    }
+
+    /// `true` if one of the optimal lines from `pos` ends with the loser's king mated on `squares`.
+    /// All the optimal lines are searched until one does, `dead_ends` keeping the positions from which
+    /// none does, so that lines transposing into them are not searched again
+    fn optimal_mate_on(
+        &self,
+        pos: &Chess,
+        winner: Color,
+        squares: KingSquares,
+        dead_ends: &mut HashSet<(Board, Color)>,
+    ) -> io::Result<bool> {
+        if pos.is_game_over() {
+            return Ok(squares.is_mated_on(pos, !winner));
+        }
+        let key = (pos.board().clone(), pos.turn());
+        if dead_ends.contains(&key) {
+            return Ok(false);
+        }
+        let (optimal_moves, best_outcome, _) = self.optimal_moves(pos, winner)?;
+        if matches!(best_outcome, Outcome::Win(_)) {
+            for (_, pos_after_move) in optimal_moves {
+                if self.optimal_mate_on(&pos_after_move, winner, squares, dead_ends)? {
+                    return Ok(true);
+                }
+            }
+        }
+        dead_ends.insert(key);
+        Ok(false)
+    }
+
+    /// Returns one of the best possible line until mate or drawn position.
+    /// When several moves lead to the same outcome, the one with the lowest UCI notation
+    /// (lexicographically) is picked, so that the line is deterministic
     pub fn probe(&self, root_pos: &Chess, winner: Color) -> io::Result<(MoveList, Vec<Chess>)> {
+        let (optimal_moves_list, pos_list) = self.probe_all_optimal(root_pos, winner)?;
+        let move_list = optimal_moves_list
+            .into_iter()
+            .map(|optimal_moves| optimal_moves[0].clone())
+            .collect();
+        Ok((move_list, pos_list))
+    }
+
+    /// Same as `TablebaseProber::probe`, but returns at each ply all the moves leading to the best outcome,
+    /// sorted by their UCI notation. The line followed is the one returned by `TablebaseProber::probe`
+    pub fn probe_all_optimal(
+        &self,
+        root_pos: &Chess,
+        winner: Color,
+    ) -> io::Result<(Vec<MoveList>, Vec<Chess>)> {
+        let PartialProbe {
+            optimal_moves_list,
+            positions,
+            ..
+        } = self.probe_partial(root_pos, winner, None)?;
+        Ok((optimal_moves_list, positions))
+    }
+
+    /// Same as `TablebaseProber::probe_all_optimal`, but also reports whether missing tables
+    /// were needed. `Outcome::Unknown` is never preferred over a known outcome, and the line
+    /// stops as soon as only unknown outcomes remain.
+    /// With `max_plies`, the line stops after that many plies at most, see `PartialProbe::truncated`.
+    /// If `root_pos` is already over, the line is empty.
+    /// Like all lines, fails with `io::ErrorKind::InvalidInput` if `winner` cannot mate, see `WinObjective::Draw`
+    pub fn probe_partial(
+        &self,
+        root_pos: &Chess,
+        winner: Color,
+        max_plies: Option<usize>,
+    ) -> io::Result<PartialProbe> {
+        self.probe_with_options(
+            root_pos,
+            winner,
+            &ProbeOptions {
+                max_plies,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Same as `TablebaseProber::probe_partial`, but moves can be probed in a heuristic order,
+    /// stopping at the first optimal one, see `MoveOrdering`. Much faster for long lines,
+    /// when only one optimal line is needed
+    pub fn probe_with_options(
+        &self,
+        root_pos: &Chess,
+        winner: Color,
+        options: &ProbeOptions,
+    ) -> io::Result<PartialProbe> {
+        Self::check_win_objective(root_pos, winner)?;
+        let (_, symmetry) = canonical_board(
+            root_pos.board(),
+            Material::from_board(root_pos.board()).has_pawns(),
+        );
         let mut pos = root_pos.clone();
-        let mut move_list = MoveList::new();
-        let mut pos_list = Vec::new();
+        let mut optimal_moves_list = Vec::new();
+        let mut positions = Vec::new();
+        let mut complete = true;
+        let mut capture_free = true;
+        let mut dead_ends = HashSet::new();
+        let mated_king_on = |pos: &Chess| {
+            options
+                .mated_king_on
+                .map(|squares| squares.is_mated_on(pos, !winner))
+        };
         loop {
-            let moves = pos.legal_moves();
-            let (chess_move, best_outcome, pos_after_move) = process_results(
-                moves.iter().map(|chess_move| {
-                    let mut pos_after_move = pos.clone();
-                    pos_after_move.play_unchecked(chess_move);
-                    self.retrieve_outcome(&pos_after_move, winner)
-                        .map(|outcome| (chess_move, outcome, pos_after_move))
-                }),
-                |iter| {
-                    iter.max_by_key(|(_, outcome, _)| *outcome)
-                        .expect("No outcomes found")
-                },
-            )?;
-
-            move_list.push(chess_move.clone());
-            pos_list.push(pos_after_move);
-            pos.play_unchecked(chess_move);
+            let truncated = options
+                .max_plies
+                .is_some_and(|max| optimal_moves_list.len() >= max);
+            if truncated || pos.is_game_over() {
+                break Ok(PartialProbe {
+                    optimal_moves_list,
+                    positions,
+                    complete,
+                    truncated,
+                    capture_free,
+                    mated_king_on: mated_king_on(&pos),
+                    symmetry,
+                });
+            }
+            let (moves, pos_after_move, best_outcome, all_known) = match options.move_ordering {
+                MoveOrdering::Exhaustive => {
+                    let (optimal_moves, best_outcome, all_known) =
+                        self.optimal_moves(&pos, winner)?;
+                    let mut keyed_moves = Vec::with_capacity(optimal_moves.len());
+                    for (chess_move, pos_after_move) in optimal_moves {
+                        let mates_elsewhere = match options.mated_king_on {
+                            Some(squares) => !self.optimal_mate_on(
+                                &pos_after_move,
+                                winner,
+                                squares,
+                                &mut dead_ends,
+                            )?,
+                            None => false,
+                        };
+                        let capture = options.prefer_capture_free && chess_move.is_capture();
+                        keyed_moves.push(((mates_elsewhere, capture), chess_move, pos_after_move));
+                    }
+                    // stable, so the moves of each group stay sorted by their UCI notation
+                    keyed_moves.sort_by_key(|(key, _, _)| *key);
+                    let (moves, mut pos_after_moves): (MoveList, Vec<Chess>) = keyed_moves
+                        .into_iter()
+                        .map(|(_, chess_move, pos_after_move)| (chess_move, pos_after_move))
+                        .unzip();
+                    (
+                        moves,
+                        pos_after_moves.swap_remove(0),
+                        best_outcome,
+                        all_known,
+                    )
+                }
+                move_ordering => {
+                    let (chess_move, pos_after_move, best_outcome, all_known) = self
+                        .first_optimal_move(
+                            &pos,
+                            winner,
+                            move_ordering,
+                            options.prefer_capture_free,
+                        )?;
+                    let mut moves = MoveList::new();
+                    moves.push(chess_move);
+                    (moves, pos_after_move, best_outcome, all_known)
+                }
+            };
+            complete &= all_known;
+            capture_free &= !moves[0].is_capture();
+            pos = pos_after_move;
+            optimal_moves_list.push(moves);
+            positions.push(pos.clone());
 
             if best_outcome == Outcome::Win(0)
                 || best_outcome == Outcome::Lose(0)
                 || best_outcome == Outcome::Draw
+                || best_outcome == Outcome::Unknown
             {
-                break Ok((move_list, pos_list));
+                break Ok(PartialProbe {
+                    optimal_moves_list,
+                    positions,
+                    complete,
+                    truncated: false,
+                    capture_free,
+                    mated_king_on: mated_king_on(&pos),
+                    symmetry,
+                });
             }
         }
     }
-}
 
-impl<T: Indexer> RetrieveOutcome for TablebaseProber<T> {
-    fn raw_access_outcome(
+    /// Probe the moves of `pos` in the order given by `move_ordering`, and return the first one
+    /// whose outcome, one move further, is the outcome of `pos`, along with the position after it
+    /// and its outcome. If there is none, eg because of missing tables, the best one is returned.
+    /// With `prefer_capture_free`, captures are probed after all the other moves.
+    /// The boolean is `false` if the outcome of at least one move probed is `Outcome::Unknown`
+    fn first_optimal_move(
         &self,
-        mat: Material,
         pos: &Chess,
         winner: Color,
-        flip: bool,
-    ) -> std::io::Result<Outcome> {
-        let lazy_file = self.0.get(&mat).expect("material config not included");
-        lazy_file.get(winner ^ flip).outcome_of(
-            MaterialWinner::new(&mat, winner),
-            &(pos.board(), pos.turn() ^ flip),
-        )
+        move_ordering: MoveOrdering,
+        prefer_capture_free: bool,
+    ) -> io::Result<(Move, Chess, Outcome, bool)> {
+        self.check_castling_rights(pos)?;
+        let expected = self.probe_outcome(pos, winner)?;
+        let mut moves_and_pos: Vec<(Move, Chess)> = pos
+            .legal_moves()
+            .into_iter()
+            .map(|chess_move| {
+                let mut pos_after_move = pos.clone();
+                pos_after_move.play_unchecked(&chess_move);
+                (chess_move, pos_after_move)
+            })
+            .collect();
+        moves_and_pos.sort_by_cached_key(|(chess_move, pos_after_move)| {
+            (
+                prefer_capture_free && chess_move.is_capture(),
+                move_ordering.key(pos, chess_move, pos_after_move),
+            )
+        });
+        let mut all_known = true;
+        let mut best: Option<(Move, Chess, Outcome)> = None;
+        for (chess_move, pos_after_move) in moves_and_pos {
+            let outcome = self.probe_outcome(&pos_after_move, winner)?;
+            if outcome == Outcome::Unknown {
+                all_known = false;
+            } else if expected != Outcome::Unknown && outcome + 1 == expected {
+                return Ok((chess_move, pos_after_move, outcome, all_known));
+            }
+            if best.as_ref().is_none_or(|(_, _, best)| outcome > *best) {
+                best = Some((chess_move, pos_after_move, outcome));
+            }
+        }
+        let (chess_move, pos_after_move, outcome) = best.expect("No legal moves found");
+        Ok((chess_move, pos_after_move, outcome, all_known))
+    }
+
+    /// Returns the outcome of `pos` for both possible helpmate winners.
+    /// Tables of both winners are already opened by the prober, so no additional loading is needed
+    pub fn retrieve_outcome_both_winners(
+        &self,
+        pos: &impl SideToMove,
+    ) -> io::Result<ByColor<Outcome>> {
+        Ok(ByColor {
+            black: self.retrieve_outcome(pos, Color::Black)?,
+            white: self.retrieve_outcome(pos, Color::White)?,
+        })
+    }
+
+    /// The move `TablebaseProber::probe` would play first, `None` if the game is over
+    pub fn best_move(&self, pos: &Chess, winner: Color) -> io::Result<Option<Move>> {
+        Self::check_win_objective(pos, winner)?;
+        if pos.is_game_over() {
+            return Ok(None);
+        }
+        let (optimal_moves, _, _) = self.optimal_moves(pos, winner)?;
+        Ok(optimal_moves.into_iter().next().map(|(m, _)| m))
+    }
+
+    /// Helpmate "cooks" detection: `None` if the optimal first move of `pos` is unique,
+    /// otherwise all the optimal first moves, sorted by their UCI notation
+    pub fn cooks(&self, pos: &Chess, winner: Color) -> io::Result<Option<MoveList>> {
+        Self::check_win_objective(pos, winner)?;
+        let (optimal_moves, _, _) = self.optimal_moves(pos, winner)?;
+        Ok((optimal_moves.len() > 1).then(|| optimal_moves.into_iter().map(|(m, _)| m).collect()))
+    }
+
+    /// Best line of each `Outcome::Win` entry of `outcomes`, the ones of the table of `mat_win`
+    /// from index `start` onwards, in index order and white to move first.
+    /// Only the first optimal move of each ply is kept, see `TablebaseProber::probe_with_options`.
+    /// Positions already mated, `Outcome::Win(0)`, have no line and are skipped
+    pub fn win_lines<'a, I: DeIndexer, O>(
+        &'a self,
+        mat_win: &'a MaterialWinner,
+        indexer: &'a I,
+        outcomes: &'a [ByColor<O>],
+        start: u64,
+        options: &'a ProbeOptions,
+    ) -> impl Iterator<Item = io::Result<WinLine>> + 'a
+    where
+        ByColor<O>: SideToMoveGetter,
+    {
+        (start..)
+            .zip(outcomes)
+            .flat_map(|(idx, by_color)| {
+                Color::ALL.into_iter().filter_map(move |turn| {
+                    match by_color.get_outcome_by_color(turn) {
+                        Outcome::Win(plies) if plies > 0 => {
+                            Some((IndexWithTurn { idx, turn }, plies))
+                        }
+                        _ => None,
+                    }
+                })
+            })
+            .map(|(idx, plies)| {
+                let root: Chess = indexer.restore(&mat_win.material, idx).into();
+                let PartialProbe {
+                    optimal_moves_list,
+                    positions,
+                    ..
+                } = self.probe_with_options(&root, mat_win.winner, options)?;
+                Ok(WinLine {
+                    idx,
+                    plies,
+                    root,
+                    moves: optimal_moves_list
+                        .into_iter()
+                        .map(|optimal_moves| optimal_moves[0].clone())
+                        .collect(),
+                    positions,
+                })
+            })
+    }
+
+    /// Returns the best capture and/or promotion available in `pos`, with the outcome of `pos`
+    /// if it is played. `None` if there is no such move.
+    /// Only the descendants tables are accessed, so it can be used with a prober created by
+    /// `TablebaseProber::new_descendants_only` when the table of `pos` has not been generated
+    pub fn resolve_captures(
+        &self,
+        pos: &Chess,
+        winner: Color,
+    ) -> io::Result<Option<(Move, Outcome)>> {
+        self.best_capture_promotion(pos, winner)
+    }
+}
+
+impl TablebaseProber<NaiveIndexer> {
+    /// Everything about how `fen` is looked up in the tables, to investigate wrong outcomes.
+    /// Illegal positions are diagnosed as well, as long as the kings can be indexed
+    pub fn diagnose(&self, fen: &Fen) -> io::Result<Diagnosis> {
+        let setup = fen.as_setup();
+        match (
+            setup.board.king_of(Color::White),
+            setup.board.king_of(Color::Black),
+        ) {
+            (Some(white_king), Some(black_king)) if white_king.distance(black_king) > 1 => (),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "each side must have exactly one king, not adjacent to the other",
+                ))
+            }
+        }
+        let illegal = Chess::from_setup(setup.clone(), CastlingMode::Standard)
+            .err()
+            .map(|e| e.to_string());
+        let material = Material::from_board(&setup.board);
+        let (canonical_board, symmetry) = canonical_board(&setup.board, material.has_pawns());
+        let index = IndexWithTurn {
+            idx: NaiveIndexer::from(material.clone()).encode_board_unchecked(&canonical_board),
+            turn: setup.turn ^ symmetry.swap_colors,
+        };
+        let mut raw_outcomes = ByColor::new_with(|_| None);
+        for winner in Color::ALL {
+            let Some(route) = Router::route_pos(&(&setup.board, setup.turn), winner) else {
+                continue;
+            };
+            if let Some(Some(lazy_file)) = self
+                .dtm_tables
+                .get(&route.mat_win.material)
+                .map(|by_color| by_color.get(route.mat_win.winner))
+            {
+                let raw = lazy_file.raw_outcome_of(&route.mat_win, index.idx)?;
+                *raw_outcomes.get_mut(winner) = Some(raw.get(route.turn).as_raw_u8());
+            }
+        }
+        Ok(Diagnosis {
+            illegal,
+            material,
+            symmetry,
+            canonical_board,
+            index,
+            raw_outcomes,
+        })
+    }
+}
+
+impl<T: Indexer> RawAccessOutcome for TablebaseProber<T> {
+    fn raw_access_outcome(&self, route: &Route, pos: &impl SideToMove) -> std::io::Result<Outcome> {
+        let lazy_file = self
+            .dtm_tables
+            .get(&route.mat_win.material)
+            .expect("material config not included");
+        lazy_file
+            .get(route.mat_win.winner)
+            .as_ref()
+            .map_or(Ok(Outcome::Unknown), |lazy_file| {
+                lazy_file.outcome_of(&route.mat_win, &(pos.board(), route.turn))
+            })
     }
 }
 
@@ -126,15 +1189,93 @@ mod tests {
         fen::Fen,
         CastlingMode, Chess,
         Color::{self, Black, White},
+        Setup, Square,
     };
 
+    use crate::Transformation;
     use paste::paste;
-    use std::path::PathBuf;
+    use std::{path::PathBuf, str::FromStr};
 
     fn tb_test_dir() -> PathBuf {
         ["..", "table"].iter().collect()
     }
 
+    #[test]
+    fn test_probe_result_from_perspective() {
+        let outcomes = (0..128).map(|u| Outcome::from(OutcomeU8::from_raw_u8(u).unwrap()));
+        for outcome in outcomes {
+            for (winner, turn) in itertools::iproduct!(Color::ALL, Color::ALL) {
+                let result = ProbeResult {
+                    outcome,
+                    winner,
+                    turn,
+                };
+                assert_eq!(result.from_perspective(winner), outcome);
+                let flipped = result.from_perspective(!winner);
+                match outcome {
+                    Outcome::Win(x) => assert_eq!(flipped, Outcome::Lose(x)),
+                    Outcome::Lose(x) => assert_eq!(flipped, Outcome::Win(x)),
+                    Outcome::Draw | Outcome::Unknown | Outcome::Undefined => {
+                        assert_eq!(flipped, outcome);
+                    }
+                }
+                // flipping back gives the outcome of the winner
+                let from_loser = ProbeResult {
+                    outcome: flipped,
+                    winner: !winner,
+                    turn,
+                };
+                assert_eq!(from_loser.from_perspective(winner), outcome);
+                assert_eq!(result.from_side_to_move(), result.from_perspective(turn));
+            }
+        }
+    }
+
+    #[test]
+    fn test_probe_response_json() {
+        // field names and values are part of the schema, changing them needs a new version
+        let response = ProbeResponse::new(
+            "4k3/3Q4/8/8/8/8/8/3K4 b - - 0 1".to_string(),
+            Color::White,
+            Ok(Outcome::Win(3)),
+        );
+        assert_eq!(
+            serde_json::to_value(&response).unwrap(),
+            serde_json::json!({
+                "version": 1,
+                "fen": "4k3/3Q4/8/8/8/8/8/3K4 b - - 0 1",
+                "winner": "white",
+                "outcome": "Win(3)",
+                "category": "win",
+                "dtm": 3,
+                "best_move": null,
+                "error": null,
+            })
+        );
+        let failed = ProbeResponse::new("8/8".to_string(), Color::Black, Err("invalid".into()));
+        assert_eq!(failed.category, None);
+        assert_eq!(failed.error.as_deref(), Some("invalid"));
+        let json = serde_json::to_string(&failed).unwrap();
+        assert_eq!(
+            serde_json::from_str::<ProbeResponse>(&json).unwrap(),
+            failed
+        );
+    }
+
+    #[test]
+    fn test_probe_result() {
+        let chess: Chess = Fen::from_ascii(b"4k3/3Q4/8/8/8/8/8/3K4 b - - 0 1")
+            .unwrap()
+            .into_position(CastlingMode::Standard)
+            .unwrap();
+        let tb_prober: TablebaseProber =
+            TablebaseProber::new(&Material::from_board(chess.board()), &tb_test_dir());
+        let result = tb_prober.probe_result(&chess, White).unwrap();
+        assert_eq!(result.outcome, Outcome::Win(10));
+        assert_eq!(result.from_perspective(White), Outcome::Win(10));
+        assert_eq!(result.from_side_to_move(), Outcome::Lose(10));
+    }
+
     fn check_retrieving_outcome(fen: &str, outcome: Outcome, winner: Color) {
         let chess: Chess = Fen::from_ascii(fen.as_bytes())
             .unwrap()
@@ -143,6 +1284,422 @@ mod tests {
         let material = Material::from_board(chess.board());
         let tb_prober: TablebaseProber = TablebaseProber::new(&material, &tb_test_dir());
         assert_eq!(tb_prober.retrieve_outcome(&chess, winner).unwrap(), outcome);
+        assert_eq!(
+            *tb_prober
+                .retrieve_outcome_both_winners(&chess)
+                .unwrap()
+                .get(winner),
+            outcome
+        );
+        // same result when probing without building a `Chess`
+        assert_eq!(
+            tb_prober
+                .retrieve_outcome(&(chess.board().clone(), chess.turn()), winner)
+                .unwrap(),
+            outcome
+        );
+    }
+
+    #[test]
+    fn test_castling_policy() {
+        let chess: Chess = Fen::from_ascii(b"8/8/8/8/8/5k2/8/R3K3 w Q - 0 1")
+            .unwrap()
+            .into_position(CastlingMode::Standard)
+            .unwrap();
+        let material = Material::from_board(chess.board());
+        let tb_prober: TablebaseProber = TablebaseProber::new(&material, &tb_test_dir());
+        for err in [
+            tb_prober.probe_outcome(&chess, White).unwrap_err(),
+            tb_prober.probe(&chess, White).unwrap_err(),
+            tb_prober.best_move(&chess, White).unwrap_err(),
+        ] {
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+        let tb_prober = tb_prober.with_castling_policy(CastlingPolicy::Search);
+        let mut after_castling = chess.clone();
+        after_castling.play_unchecked(&Move::Castle {
+            king: Square::E1,
+            rook: Square::A1,
+        });
+        let expected = tb_prober
+            .retrieve_outcome(&chess, White)
+            .unwrap()
+            .max(tb_prober.retrieve_outcome(&after_castling, White).unwrap() + 1);
+        assert_eq!(tb_prober.probe_outcome(&chess, White).unwrap(), expected);
+        let (moves, _) = tb_prober.probe(&chess, White).unwrap();
+        assert!(!moves.is_empty());
+        // no castling rights left, the outcome stored in the table
+        assert_eq!(
+            tb_prober.probe_outcome(&after_castling, White).unwrap(),
+            tb_prober.retrieve_outcome(&after_castling, White).unwrap()
+        );
+    }
+
+    fn check_resolve_captures(fen: &str, expected: Option<(&str, Outcome)>, winner: Color) {
+        let chess: Chess = Fen::from_ascii(fen.as_bytes())
+            .unwrap()
+            .into_position(CastlingMode::Standard)
+            .unwrap();
+        let material = Material::from_board(chess.board());
+        let tb_prober: TablebaseProber =
+            TablebaseProber::new_descendants_only(&material, &tb_test_dir());
+        let best_capture = tb_prober
+            .resolve_captures(&chess, winner)
+            .unwrap()
+            .map(|(m, o)| (m.to_uci(CastlingMode::Standard).to_string(), o));
+        assert_eq!(best_capture, expected.map(|(uci, o)| (uci.to_string(), o)));
+    }
+
+    #[test]
+    fn test_resolve_captures() {
+        check_resolve_captures(
+            "1k6/1r6/1K6/8/4Q3/8/8/8 w - - 0 1",
+            Some(("e4b7", Outcome::Win(1))),
+            White,
+        );
+        check_resolve_captures(
+            "4k3/3Q4/8/8/8/8/8/3K4 b - - 0 1",
+            Some(("e8d7", Outcome::Draw)),
+            White,
+        );
+        check_resolve_captures("k7/8/8/8/8/8/r7/2K3Q1 w - - 0 1", None, White);
+    }
+
+    #[test]
+    fn test_cooks() {
+        let tb_prober: TablebaseProber =
+            TablebaseProber::new(&Material::from_str("KQvK").unwrap(), &tb_test_dir());
+        let cooks = |fen: &str| -> Option<Vec<String>> {
+            let chess: Chess = Fen::from_ascii(fen.as_bytes())
+                .unwrap()
+                .into_position(CastlingMode::Standard)
+                .unwrap();
+            tb_prober.cooks(&chess, White).unwrap().map(|moves| {
+                moves
+                    .iter()
+                    .map(|m| m.to_uci(CastlingMode::Standard).to_string())
+                    .collect()
+            })
+        };
+        // the queen mates from a7 and b7, protected by the king, and along the 8th rank from g8 and h8.
+        // Its other checks, from the a-file or the long diagonal, leave b8 to the black king
+        assert_eq!(
+            cooks("k7/7Q/1K6/8/8/8/8/8 w - - 0 1"),
+            Some(vec![
+                "h7a7".to_string(),
+                "h7b7".to_string(),
+                "h7g8".to_string(),
+                "h7h8".to_string()
+            ])
+        );
+        // only Qh8 mates, Qb8+ and Qh1+ both let the king escape
+        assert_eq!(cooks("k7/8/1K6/8/8/8/7Q/8 w - - 0 1"), None);
+    }
+
+    #[test]
+    fn test_win_lines() {
+        let mat_win = MaterialWinner::from_str("KQvKw").unwrap();
+        let file_handler: crate::FileHandler = crate::FileHandler::new(&mat_win, &tb_test_dir());
+        let tb_prober: TablebaseProber = TablebaseProber::new(&mat_win.material, &tb_test_dir());
+        let start = 1000;
+        for move_ordering in [MoveOrdering::Exhaustive, MoveOrdering::Heuristic] {
+            let lines: Vec<WinLine> = tb_prober
+                .win_lines(
+                    &mat_win,
+                    &file_handler.indexer,
+                    &file_handler.outcomes[start..start + 8],
+                    start as u64,
+                    &ProbeOptions {
+                        move_ordering,
+                        max_plies: None,
+                        prefer_capture_free: false,
+                        mated_king_on: None,
+                    },
+                )
+                .collect::<io::Result<_>>()
+                .unwrap();
+            assert!(!lines.is_empty());
+            for line in lines {
+                assert_eq!(
+                    file_handler.outcome_at(line.idx).unwrap(),
+                    Outcome::Win(line.plies)
+                );
+                assert_eq!(line.moves.len(), usize::from(line.plies));
+                assert_eq!(line.positions.len(), line.moves.len());
+                assert!(line.positions.last().unwrap().is_checkmate());
+            }
+        }
+    }
+
+    #[test]
+    fn test_probe_partial_missing_table() {
+        // only keep the 3 pieces tables, KQvKR is missing
+        let tmp = tempfile::tempdir().unwrap();
+        let partial_dir = tmp.path();
+        for table in ["KQvKw", "KQvKb", "KRvKw", "KRvKb"] {
+            std::fs::copy(tb_test_dir().join(table), partial_dir.join(table)).unwrap();
+        }
+        let chess: Chess = Fen::from_ascii("1k6/1r6/1K6/8/4Q3/8/8/8 w - - 0 1".as_bytes())
+            .unwrap()
+            .into_position(CastlingMode::Standard)
+            .unwrap();
+        let material = Material::from_board(chess.board());
+        let tb_prober: TablebaseProber = TablebaseProber::new_partial(&material, partial_dir);
+        assert!(!tb_prober.is_complete());
+        assert_eq!(
+            tb_prober.retrieve_outcome(&chess, White).unwrap(),
+            Outcome::Unknown
+        );
+        // capturing the rook leads to KQvK, which is still probed
+        let mut after_capture = chess.clone();
+        after_capture.play_unchecked(&Move::Normal {
+            role: retroboard::shakmaty::Role::Queen,
+            from: retroboard::shakmaty::Square::E4,
+            capture: Some(retroboard::shakmaty::Role::Rook),
+            to: retroboard::shakmaty::Square::B7,
+            promotion: None,
+        });
+        assert_eq!(
+            tb_prober.retrieve_outcome(&after_capture, White).unwrap(),
+            Outcome::Win(0)
+        );
+        // Qxb7 is mate, the other moves stay in the missing table
+        let partial_probe = tb_prober.probe_partial(&chess, White, None).unwrap();
+        assert!(!partial_probe.complete);
+        assert_eq!(partial_probe.optimal_moves_list.len(), 1);
+        assert_eq!(
+            partial_probe.optimal_moves_list[0]
+                .iter()
+                .map(|m| m.to_uci(CastlingMode::Standard).to_string())
+                .collect::<Vec<_>>(),
+            ["e4b7"]
+        );
+
+        let err = TablebaseProber::<DefaultIndexer>::try_new(&material, partial_dir).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(err
+            .to_string()
+            .ends_with(": KQvKR.b.hm.dtm, KQvKR.w.hm.dtm"));
+        let try_prober =
+            TablebaseProber::<DefaultIndexer>::try_new_partial(&material, partial_dir).unwrap();
+        assert!(!try_prober.is_complete());
+        let full_prober: TablebaseProber = TablebaseProber::new(&material, &tb_test_dir());
+        assert!(full_prober.is_complete());
+    }
+
+    #[test]
+    fn test_table_registry() {
+        let registry = TableRegistry::default();
+        let kqkr = Material::from_str("KQvKR").unwrap();
+        let kqk = Material::from_str("KQvK").unwrap();
+        let prober_kqkr: TablebaseProber =
+            TablebaseProber::new_with_registry(&kqkr, &tb_test_dir(), &registry);
+        let nb_tables = registry.len();
+        let prober_kqk: TablebaseProber =
+            TablebaseProber::new_with_registry(&kqk, &tb_test_dir(), &registry.clone());
+        // the `KQvK` tables are shared
+        assert_eq!(registry.len(), nb_tables);
+        for winner in Color::ALL {
+            assert!(Arc::ptr_eq(
+                prober_kqkr.dtm_tables[&kqk].get(winner).as_ref().unwrap(),
+                prober_kqk.dtm_tables[&kqk].get(winner).as_ref().unwrap()
+            ));
+        }
+        let chess: Chess = Fen::from_ascii(b"1k6/1r6/1K6/8/4Q3/8/8/8 w - - 0 1")
+            .unwrap()
+            .into_position(CastlingMode::Standard)
+            .unwrap();
+        let own_prober: TablebaseProber = TablebaseProber::new(&kqkr, &tb_test_dir());
+        assert_eq!(
+            prober_kqkr.retrieve_outcome(&chess, White).unwrap(),
+            own_prober.retrieve_outcome(&chess, White).unwrap()
+        );
+        drop(prober_kqkr);
+        assert_eq!(registry.len(), 2);
+        drop(prober_kqk);
+        assert!(registry.is_empty());
+    }
+
+    #[test]
+    fn test_probe_from_threads() {
+        let kqkr = Material::from_str("KQvKR").unwrap();
+        let prober: Arc<TablebaseProber> = Arc::new(TablebaseProber::new(&kqkr, &tb_test_dir()));
+        let positions: Vec<Chess> = [
+            "1k6/1r6/1K6/8/4Q3/8/8/8 w - - 0 1",
+            "1k6/1r6/2K5/8/4Q3/8/8/8 b - - 0 1",
+            "8/8/3k4/8/2r5/8/1Q6/K7 w - - 0 1",
+            "4k3/3Q4/8/8/8/8/8/3K4 b - - 0 1",
+        ]
+        .iter()
+        .map(|fen| {
+            Fen::from_ascii(fen.as_bytes())
+                .unwrap()
+                .into_position(CastlingMode::Standard)
+                .unwrap()
+        })
+        .collect();
+        let expected: Vec<Outcome> = positions
+            .iter()
+            .map(|chess| prober.retrieve_outcome(chess, White).unwrap())
+            .collect();
+        let positions = Arc::new(positions);
+        let workers: Vec<_> = (0..4)
+            .map(|_| {
+                let (prober, positions) = (Arc::clone(&prober), Arc::clone(&positions));
+                std::thread::spawn(move || {
+                    positions
+                        .iter()
+                        .map(|chess| prober.retrieve_outcome(chess, White).unwrap())
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        for worker in workers {
+            assert_eq!(worker.join().unwrap(), expected);
+        }
+    }
+
+    #[cfg(feature = "cached")]
+    #[test]
+    fn test_block_cache_stats() {
+        use crate::{block_cache_stats, configure_block_cache, BlockCacheConfig};
+
+        let kqkr = Material::from_str("KQvKR").unwrap();
+        // other tests probe concurrently, so only lower bounds hold
+        configure_block_cache(BlockCacheConfig::default());
+        let prober: TablebaseProber = TablebaseProber::new(&kqkr, &tb_test_dir());
+        let chess: Chess = Fen::from_ascii(b"8/8/3k4/8/2r5/8/1Q6/K7 w - - 0 1")
+            .unwrap()
+            .into_position(CastlingMode::Standard)
+            .unwrap();
+        for _ in 0..2 {
+            prober.retrieve_outcome(&chess, White).unwrap();
+        }
+        // the whole `KQvKR` table is a single small block
+        let stats = block_cache_stats();
+        assert!(stats.pinned.entries > 0);
+        assert!(stats.pinned.hits > 0);
+        assert_eq!(stats.pinned.evictions, 0);
+    }
+
+    #[test]
+    fn test_win_within() {
+        let chess = |fen: &str| -> Chess {
+            Fen::from_ascii(fen.as_bytes())
+                .unwrap()
+                .into_position(CastlingMode::Standard)
+                .unwrap()
+        };
+        let tmp = tempfile::tempdir().unwrap();
+        let win_within_dir = tmp.path();
+        let mat_win = MaterialWinner::from_str("KQvKw").unwrap();
+        let table = WinWithinTable::from_dtm_table(
+            File::open(TableName::helpmate(&mat_win).path_in(&tb_test_dir())).unwrap(),
+            5,
+        )
+        .unwrap();
+        table
+            .write(File::create(WinWithinTable::path_in(win_within_dir, &mat_win, 5)).unwrap())
+            .unwrap();
+        let material = Material::from_str("KQvKR").unwrap();
+        let dtm_prober: TablebaseProber = TablebaseProber::new(&material, &tb_test_dir());
+        let prober: TablebaseProber = TablebaseProber::new(&material, &tb_test_dir())
+            .with_win_within_tables(win_within_dir, 5)
+            .unwrap();
+        assert_eq!(prober.win_within.len(), 1);
+        // mate in 1, in 7, and a `KQvKR` position only found in the table
+        for (fen, expected) in [
+            ("k7/8/1K6/8/8/8/7Q/8 w - - 0 1", true),
+            ("8/8/8/8/8/2k5/8/KQ6 w - - 0 1", false),
+            ("1k6/1r6/1K6/8/4Q3/8/8/8 w - - 0 1", true),
+        ] {
+            let pos = chess(fen);
+            assert_eq!(
+                prober.win_within(&pos, White, 5).unwrap(),
+                expected,
+                "{fen}"
+            );
+            assert_eq!(
+                dtm_prober.win_within(&pos, White, 5).unwrap(),
+                expected,
+                "{fen}"
+            );
+        }
+        // other thresholds are read from the table
+        assert!(prober
+            .win_within(&chess("8/8/8/8/8/2k5/8/KQ6 w - - 0 1"), White, 7)
+            .unwrap());
+    }
+
+    #[test]
+    fn test_wdl() {
+        let chess = |fen: &str| -> Chess {
+            Fen::from_ascii(fen.as_bytes())
+                .unwrap()
+                .into_position(CastlingMode::Standard)
+                .unwrap()
+        };
+        let tb_prober: TablebaseProber =
+            TablebaseProber::new(&Material::from_str("KQvKR").unwrap(), &tb_test_dir());
+        for fen in [
+            "3K4/1r2Q3/8/8/8/8/8/3k4 b - - 0 1",
+            "3K4/1r2Q3/8/8/8/8/8/3k4 w - - 0 1",
+            "8/8/8/8/8/2k5/8/KQ6 w - - 0 1",
+            // colors swapped
+            "8/8/8/8/8/2K5/8/kq6 w - - 0 1",
+            "k7/8/1K6/8/8/8/7Q/8 b - - 0 1",
+            // insufficient material
+            "8/8/8/8/8/2k5/8/K7 w - - 0 1",
+        ] {
+            let pos = chess(fen);
+            for winner in Color::ALL {
+                assert_eq!(
+                    tb_prober.wdl(&pos, winner).unwrap(),
+                    Wdl::from(tb_prober.probe_outcome(&pos, winner).unwrap()),
+                    "{fen} {winner}"
+                );
+            }
+        }
+
+        // a sidecar is preferred to the table
+        let tmp = tempfile::tempdir().unwrap();
+        let sidecar_dir = tmp.path();
+        for table in ["KQvKw", "KQvKb"] {
+            std::fs::copy(tb_test_dir().join(table), sidecar_dir.join(table)).unwrap();
+        }
+        let mat_win = MaterialWinner::from_str("KQvKw").unwrap();
+        let outcomes =
+            EncoderDecoder::new(std::fs::File::open(tb_test_dir().join("KQvKw")).unwrap())
+                .decompress_file()
+                .unwrap();
+        let all_lost: crate::Outcomes = outcomes
+            .iter()
+            .map(|_| ByColor::new_with(|_| Outcome::Lose(0).into()))
+            .collect();
+        let sidecar_name = TableName {
+            metric: Metric::Wdl,
+            ..TableName::helpmate(&mat_win)
+        };
+        EncoderDecoder::new(
+            std::fs::File::create(sidecar_dir.join(sidecar_name.to_string())).unwrap(),
+        )
+        .compress(&all_lost)
+        .unwrap();
+        let pos = chess("8/8/8/8/8/2k5/8/KQ6 w - - 0 1");
+        let sidecar_prober: TablebaseProber = TablebaseProber::new(&mat_win.material, sidecar_dir);
+        assert_eq!(sidecar_prober.wdl(&pos, White).unwrap(), Wdl::Lose);
+        assert_eq!(
+            sidecar_prober.probe_outcome(&pos, White).unwrap(),
+            Outcome::Win(7)
+        );
+        assert_eq!(sidecar_prober.wdl(&pos, Black).unwrap(), Wdl::Draw);
+
+        let partial_prober: TablebaseProber =
+            TablebaseProber::new_partial(&Material::from_str("KRvKR").unwrap(), sidecar_dir);
+        let err = partial_prober
+            .wdl(&chess("8/8/8/8/8/2k5/r7/KR6 w - - 0 1"), White)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
     }
 
     fn check_probe(fen: &str, winner: Color) {
@@ -152,6 +1709,16 @@ mod tests {
             .unwrap();
         let material = Material::from_board(chess.board());
         let tb_prober: TablebaseProber = TablebaseProber::new(&material, &tb_test_dir());
+        if WinObjective::of(&MaterialWinner::new(&material, winner)) == WinObjective::Draw {
+            for err in [
+                tb_prober.probe(&chess, winner).unwrap_err(),
+                tb_prober.best_move(&chess, winner).unwrap_err(),
+                tb_prober.cooks(&chess, winner).unwrap_err(),
+            ] {
+                assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            }
+            return;
+        }
         let outcome = tb_prober.retrieve_outcome(&chess, winner).unwrap();
         let mainline_len = match outcome {
             Outcome::Win(x) | Outcome::Lose(x) => x as usize,
@@ -160,6 +1727,22 @@ mod tests {
         // calling `probe` by construction ensures the line is legal
         let (moves, _) = tb_prober.probe(&chess, winner).unwrap();
         assert_eq!(moves.len(), mainline_len);
+        assert_eq!(
+            tb_prober.best_move(&chess, winner).unwrap().as_ref(),
+            moves.first()
+        );
+        let (optimal_moves_list, _) = tb_prober.probe_all_optimal(&chess, winner).unwrap();
+        assert_eq!(optimal_moves_list.len(), mainline_len);
+        let cooks = tb_prober.cooks(&chess, winner).unwrap();
+        assert_eq!(cooks.is_some(), optimal_moves_list[0].len() > 1);
+        for (chess_move, optimal_moves) in moves.iter().zip(optimal_moves_list.iter()) {
+            let ucis: Vec<String> = optimal_moves
+                .iter()
+                .map(|m| m.to_uci(CastlingMode::Standard).to_string())
+                .collect();
+            assert!(ucis.windows(2).all(|w| w[0] <= w[1]));
+            assert_eq!(chess_move, &optimal_moves[0]);
+        }
     }
 
     // macro for generating tests
@@ -185,6 +1768,268 @@ mod tests {
     }
 }
 
+    #[test]
+    fn test_probe_partial_max_plies() {
+        let material = Material::from_str("KQvK").unwrap();
+        let tb_prober: TablebaseProber = TablebaseProber::new(&material, &tb_test_dir());
+        let chess: Chess = Fen::from_ascii(b"4k3/3Q4/8/8/8/8/8/3K4 b - - 0 1")
+            .unwrap()
+            .into_position(CastlingMode::Standard)
+            .unwrap();
+        let full = tb_prober.probe_partial(&chess, White, None).unwrap();
+        assert!(!full.truncated);
+        assert_eq!(full.optimal_moves_list.len(), 10);
+        let capped = tb_prober.probe_partial(&chess, White, Some(4)).unwrap();
+        assert!(capped.truncated);
+        assert_eq!(capped.positions[..], full.positions[..4]);
+        // a cap not reached does not truncate the line
+        assert!(
+            !tb_prober
+                .probe_partial(&chess, White, Some(10))
+                .unwrap()
+                .truncated
+        );
+
+        // black cannot mate, so there is no line to follow
+        assert_eq!(
+            tb_prober
+                .probe_partial(&chess, Black, Some(10))
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::InvalidInput
+        );
+        assert_eq!(
+            tb_prober.probe_outcome(&chess, Black).unwrap(),
+            Outcome::Draw
+        );
+
+        // the game is already over
+        let mated: Chess = Fen::from_ascii(b"4k3/4Q3/4K3/8/8/8/8/8 b - - 0 1")
+            .unwrap()
+            .into_position(CastlingMode::Standard)
+            .unwrap();
+        let over = tb_prober.probe_partial(&mated, White, Some(10)).unwrap();
+        assert!(over.optimal_moves_list.is_empty());
+        assert!(!over.truncated);
+    }
+
+    #[test]
+    fn test_prefer_capture_free() {
+        let tb_prober: TablebaseProber =
+            TablebaseProber::new(&Material::from_str("KQvKR").unwrap(), &tb_test_dir());
+        // after 1... Ra1, both 2. Qxa1 and 2. Qa2 mate on the next move
+        let chess: Chess = Fen::from_ascii(b"k7/8/1K6/8/8/8/r7/1Q6 b - - 0 1")
+            .unwrap()
+            .into_position(CastlingMode::Standard)
+            .unwrap();
+        let uci_line = |line: &PartialProbe| -> Vec<String> {
+            line.optimal_moves_list
+                .iter()
+                .map(|moves| moves[0].to_uci(CastlingMode::Standard).to_string())
+                .collect()
+        };
+        let default = tb_prober.probe_partial(&chess, White, None).unwrap();
+        assert_eq!(uci_line(&default), vec!["a2a1", "b1a1", "a8b8", "a1h8"]);
+        assert!(!default.capture_free);
+        for move_ordering in [MoveOrdering::Exhaustive, MoveOrdering::Heuristic] {
+            let line = tb_prober
+                .probe_with_options(
+                    &chess,
+                    White,
+                    &ProbeOptions {
+                        move_ordering,
+                        max_plies: None,
+                        prefer_capture_free: true,
+                        mated_king_on: None,
+                    },
+                )
+                .unwrap();
+            assert!(line.capture_free);
+            assert_eq!(line.optimal_moves_list.len(), 4);
+            assert!(line.positions.last().unwrap().is_checkmate());
+            assert!(line
+                .optimal_moves_list
+                .iter()
+                .all(|moves| !moves[0].is_capture()));
+        }
+    }
+
+    #[test]
+    fn test_mated_king_on() {
+        let tb_prober: TablebaseProber =
+            TablebaseProber::new(&Material::from_str("KQvK").unwrap(), &tb_test_dir());
+        let chess: Chess = Fen::from_ascii(b"1k6/8/2K5/8/8/8/8/7Q w - - 0 1")
+            .unwrap()
+            .into_position(CastlingMode::Standard)
+            .unwrap();
+        let uci_line = |line: &PartialProbe| -> Vec<String> {
+            line.optimal_moves_list
+                .iter()
+                .map(|moves| moves[0].to_uci(CastlingMode::Standard).to_string())
+                .collect()
+        };
+        let default = tb_prober.probe_partial(&chess, White, None).unwrap();
+        assert_eq!(uci_line(&default), vec!["c6d6", "b8c8", "h1a8"]);
+        assert_eq!(default.mated_king_on, None);
+        let options = |move_ordering| ProbeOptions {
+            move_ordering,
+            max_plies: None,
+            prefer_capture_free: false,
+            mated_king_on: Some(KingSquares::CORNERS),
+        };
+        let corner = tb_prober
+            .probe_with_options(&chess, White, &options(MoveOrdering::Exhaustive))
+            .unwrap();
+        assert_eq!(uci_line(&corner), vec!["h1b1", "b8a8", "b1b7"]);
+        assert_eq!(corner.mated_king_on, Some(true));
+        // only checked, not searched
+        let heuristic = tb_prober
+            .probe_with_options(&chess, White, &options(MoveOrdering::Heuristic))
+            .unwrap();
+        assert_eq!(heuristic.optimal_moves_list.len(), 3);
+        assert_eq!(
+            heuristic.mated_king_on,
+            Some(KingSquares::CORNERS.is_mated_on(heuristic.positions.last().unwrap(), Black))
+        );
+    }
+
+    #[test]
+    fn test_probe_with_options() {
+        let material = Material::from_str("KQvKR").unwrap();
+        let tb_prober: TablebaseProber = TablebaseProber::new(&material, &tb_test_dir());
+        let chess: Chess = Fen::from_ascii(b"8/8/8/8/8/2k5/8/KQ2r3 w - - 0 1")
+            .unwrap()
+            .into_position(CastlingMode::Standard)
+            .unwrap();
+        let exhaustive = tb_prober.probe_partial(&chess, White, None).unwrap();
+        for move_ordering in [
+            MoveOrdering::Heuristic,
+            // worst moves first
+            MoveOrdering::Custom(|_, m| -i32::from(m.is_capture())),
+        ] {
+            let options = ProbeOptions {
+                move_ordering,
+                max_plies: None,
+                prefer_capture_free: false,
+                mated_king_on: None,
+            };
+            let line = tb_prober
+                .probe_with_options(&chess, White, &options)
+                .unwrap();
+            assert!(line.complete);
+            assert_eq!(
+                line.optimal_moves_list.len(),
+                exhaustive.optimal_moves_list.len()
+            );
+            assert!(line.positions.last().unwrap().is_checkmate());
+            let mut pos = chess.clone();
+            for (moves, pos_after_move) in line.optimal_moves_list.iter().zip(&line.positions) {
+                assert_eq!(moves.len(), 1);
+                let (optimal_moves_list, _) = tb_prober.probe_all_optimal(&pos, White).unwrap();
+                assert!(optimal_moves_list[0].contains(&moves[0]));
+                pos = pos_after_move.clone();
+            }
+        }
+    }
+
+    #[test]
+    fn test_diagnose() {
+        let material = Material::from_str("KQvK").unwrap();
+        let tb_prober: TablebaseProber = TablebaseProber::new(&material, &tb_test_dir());
+        // black is stronger, and the white king is not in the a1-d1-d4 triangle
+        let fen = Fen::from_ascii(b"4K3/3q4/8/8/8/8/8/3k4 w - - 0 1").unwrap();
+        let diagnosis = tb_prober.diagnose(&fen).unwrap();
+        assert_eq!(diagnosis.illegal, None);
+        assert_eq!(diagnosis.material, material);
+        assert!(diagnosis.symmetry.swap_colors);
+        assert_ne!(diagnosis.symmetry.transformation, Transformation::Identity);
+        assert_eq!(diagnosis.index.turn, Black);
+        let chess: Chess = fen.clone().into_position(CastlingMode::Standard).unwrap();
+        for winner in Color::ALL {
+            assert_eq!(
+                diagnosis.outcome(winner),
+                Some(tb_prober.retrieve_outcome(&chess, winner).unwrap())
+            );
+        }
+
+        // the side not to move is in check
+        let illegal = Fen::from_ascii(b"4K3/3q4/8/8/8/8/8/3k4 b - - 0 1").unwrap();
+        let diagnosis = tb_prober.diagnose(&illegal).unwrap();
+        assert!(diagnosis.illegal.is_some());
+        assert_eq!(diagnosis.outcome(White), Some(Outcome::Undefined));
+
+        for unindexable in [
+            "8/8/8/8/8/8/3q4/3k4 w - - 0 1",
+            "8/8/8/8/8/8/3q4/3kK3 w - - 0 1",
+        ] {
+            let fen = Fen::from_ascii(unindexable.as_bytes()).unwrap();
+            assert!(tb_prober.diagnose(&fen).is_err());
+        }
+    }
+
+    #[test]
+    fn test_probe_symmetry_every_transformation() {
+        let material = Material::from_str("KQvK").unwrap();
+        let tb_prober: TablebaseProber = TablebaseProber::new(&material, &tb_test_dir());
+        let mut transformations_seen = Vec::new();
+        for (white_king, swap_colors) in itertools::iproduct!(Square::ALL, [false, true]) {
+            let mut board = Board::empty();
+            board.set_piece_at(white_king, White.king());
+            board.set_piece_at(white_king.flip_diagonal().rotate_180(), Black.king());
+            board.set_piece_at(white_king.rotate_180(), White.queen());
+            if swap_colors {
+                board = crate::indexer::swap_color_board(board);
+            }
+            let Ok(pos) = Chess::from_setup(
+                Setup {
+                    board,
+                    // the side without the queen, which may be in check
+                    turn: if swap_colors { White } else { Black },
+                    ..Setup::empty()
+                },
+                CastlingMode::Standard,
+            ) else {
+                continue;
+            };
+            if pos.is_game_over() {
+                continue;
+            }
+            let (canonical, symmetry) = canonical_board(pos.board(), false);
+            if transformations_seen.contains(&(symmetry.transformation, symmetry.swap_colors)) {
+                continue;
+            }
+            transformations_seen.push((symmetry.transformation, symmetry.swap_colors));
+            assert_eq!(symmetry.revert_board(&canonical), *pos.board());
+            let winner = if swap_colors { Black } else { White };
+            let partial = tb_prober.probe_partial(&pos, winner, Some(1)).unwrap();
+            assert_eq!(partial.symmetry, symmetry);
+            let best_move = &partial.optimal_moves_list[0][0];
+            // moves are played on the root position, in its coordinates
+            assert!(pos.legal_moves().contains(best_move));
+            let canonical_move = symmetry.apply_to_move(best_move);
+            assert_eq!(symmetry.revert_move(&canonical_move), *best_move);
+            let mut canonical_pos = Chess::from_setup(
+                Setup {
+                    board: canonical,
+                    turn: pos.turn() ^ symmetry.swap_colors,
+                    ..Setup::empty()
+                },
+                CastlingMode::Standard,
+            )
+            .unwrap();
+            assert!(canonical_pos.legal_moves().contains(&canonical_move));
+            canonical_pos.play_unchecked(&canonical_move);
+            assert_eq!(
+                symmetry.revert_board(canonical_pos.board()),
+                *partial.positions[0].board()
+            );
+        }
+        for transformation in Transformation::ALL {
+            assert!(transformations_seen.contains(&(transformation, false)));
+        }
+        assert!(transformations_seen.iter().any(|(_, swap)| *swap));
+    }
+
     // the tests are tested against files generated with the naive indexer
     gen_tests_probe! {
         without_switching_color_white, "1k6/1r6/1K6/8/4Q3/8/8/8 w - - 0 1", Outcome::Win(1), White,
@@ -0,0 +1,133 @@
+use retroboard::shakmaty::{ByColor, ByRole, Color};
+
+use crate::{material::is_black_stronger_raw, Material, MaterialWinner, SideToMove, KB_K, KN_K};
+
+/// Where the outcome of a position is stored, see `Router`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Route {
+    /// Table to read. Its material is normalised, so the winner is swapped when `flip` is `true`
+    pub mat_win: MaterialWinner,
+    /// Side to move to read in the table
+    pub turn: Color,
+    /// `true` if black is stronger in the position, so that colors are swapped to match the table
+    pub flip: bool,
+}
+
+/// Maps a position to the table storing its outcome. Tables are only generated for normalised
+/// materials (the stronger side being white), so a position where black is stronger
+/// is read in the table of the opposite winner, with the opposite side to move.
+/// The board itself does not need to be flipped, indexers already handle it
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Router;
+
+impl Router {
+    /// `None` if the material is drawn in every position (eg `KBvK`), in which case there is no table
+    #[must_use]
+    pub fn route(
+        raw_material: &ByColor<ByRole<u8>>,
+        winner: Color,
+        side_to_move: Color,
+    ) -> Option<Route> {
+        let material = Material::from_raw(raw_material);
+        if material.count() == 2 || material == KB_K || material == KN_K {
+            return None;
+        }
+        let flip = is_black_stronger_raw(raw_material);
+        Some(Route {
+            mat_win: MaterialWinner::new(&material, winner ^ flip),
+            turn: side_to_move ^ flip,
+            flip,
+        })
+    }
+
+    /// Same as `Router::route`, for the material and side to move of `pos`
+    #[must_use]
+    pub fn route_pos(pos: &impl SideToMove, winner: Color) -> Option<Route> {
+        Self::route(
+            &ByColor::new_with(|color| pos.board().material_side(color)),
+            winner,
+            pos.side_to_move(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use retroboard::shakmaty::Color::{Black, White};
+
+    use std::str::FromStr;
+
+    // all materials of 3 and 4 pieces whose sides differ, except the drawn `KBvK` and `KNvK`
+    fn asymmetric_materials() -> Vec<Material> {
+        let roles = ["Q", "R", "B", "N", "P"];
+        let mut mats: Vec<String> = vec!["KQvK".into(), "KRvK".into(), "KPvK".into()];
+        for (i, r1) in roles.iter().enumerate() {
+            for r2 in &roles[i..] {
+                mats.push(format!("K{r1}{r2}vK"));
+                if r1 != r2 {
+                    mats.push(format!("K{r1}vK{r2}"));
+                }
+            }
+        }
+        mats.iter()
+            .map(|m| Material::from_str(m).unwrap())
+            .collect()
+    }
+
+    fn raw(mat: &Material) -> ByColor<ByRole<u8>> {
+        ByColor::new_with(|color| *mat.by_color.get(color).clone())
+    }
+
+    #[test]
+    fn test_route_asymmetric_materials() {
+        let materials = asymmetric_materials();
+        assert_eq!(materials.len(), 3 + 15 + 10);
+        for mat in materials {
+            let raw_material = raw(&mat);
+            let swapped = ByColor {
+                white: raw_material.black,
+                black: raw_material.white,
+            };
+            for winner in Color::ALL {
+                for turn in Color::ALL {
+                    assert_eq!(
+                        Router::route(&raw_material, winner, turn),
+                        Some(Route {
+                            mat_win: MaterialWinner::new(&mat, winner),
+                            turn,
+                            flip: false,
+                        }),
+                        "{mat:?}"
+                    );
+                    assert_eq!(
+                        Router::route(&swapped, winner, turn),
+                        Some(Route {
+                            mat_win: MaterialWinner::new(&mat, !winner),
+                            turn: !turn,
+                            flip: true,
+                        }),
+                        "{mat:?} with colors swapped"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_route_symmetric_material() {
+        let mat = Material::from_str("KRvKR").unwrap();
+        let route = Router::route(&raw(&mat), Black, White).unwrap();
+        assert_eq!(route.mat_win, MaterialWinner::new(&mat, Black));
+        assert_eq!(route.turn, White);
+        assert!(!route.flip);
+    }
+
+    #[test]
+    fn test_route_drawn_materials() {
+        for mat in ["KvK", "KBvK", "KvKN"] {
+            let mat = Material::from_str(mat).unwrap();
+            assert_eq!(Router::route(&raw(&mat), White, White), None);
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use std::{
+    fs::File,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use retroboard::shakmaty::Color;
+
+use crate::{
+    EncoderDecoder, IndexWithTurn, MaterialWinner, Outcome, OutcomesSlice, SideToMoveGetter,
+    TablePath,
+};
+
+/// Sidecar table telling whether the winner mates within `max_plies`, derived from a `Metric::Dtm` table.
+/// One bit by index and side to move, white to move in the even bits, see `TablebaseProber::win_within`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WinWithinTable {
+    max_plies: u8,
+    bits: Vec<u8>,
+}
+
+impl WinWithinTable {
+    /// Reads the whole `Metric::Dtm` table, see `EncoderDecoder::decompress_file`
+    pub fn from_dtm_table(file: File, max_plies: u8) -> io::Result<Self> {
+        Ok(Self::new(
+            &EncoderDecoder::new(file).decompress_file()?,
+            max_plies,
+        ))
+    }
+
+    fn new(outcomes: OutcomesSlice, max_plies: u8) -> Self {
+        let mut bits = vec![0; (2 * outcomes.len()).div_ceil(8)];
+        for (idx, by_color) in outcomes.iter().enumerate() {
+            for turn in Color::ALL {
+                if matches!(by_color.get_outcome_by_color(turn), Outcome::Win(x) if x <= max_plies)
+                {
+                    let bit = bit_of(idx, turn);
+                    bits[bit / 8] |= 1 << (bit % 8);
+                }
+            }
+        }
+        Self { max_plies, bits }
+    }
+
+    #[must_use]
+    pub fn max_plies(&self) -> u8 {
+        self.max_plies
+    }
+
+    /// `true` if the winner mates within `WinWithinTable::max_plies` from `idx`
+    #[must_use]
+    pub fn win_within(&self, idx: IndexWithTurn) -> bool {
+        let bit = bit_of(idx.usize(), idx.turn);
+        self.bits
+            .get(bit / 8)
+            .is_some_and(|byte| byte & (1 << (bit % 8)) != 0)
+    }
+
+    /// Number of entries won within `WinWithinTable::max_plies`
+    #[must_use]
+    pub fn count(&self) -> usize {
+        self.bits
+            .iter()
+            .map(|byte| byte.count_ones() as usize)
+            .sum()
+    }
+
+    /// `max_plies` in the first byte, then the bits
+    pub fn write(&self, mut writer: impl Write) -> io::Result<()> {
+        let mut encoder = zstd::stream::Encoder::new(&mut writer, 21)?;
+        encoder.write_all(&[self.max_plies])?;
+        encoder.write_all(&self.bits)?;
+        encoder.finish()?;
+        Ok(())
+    }
+
+    pub fn read(reader: impl Read) -> io::Result<Self> {
+        let mut decoded = zstd::stream::decode_all(reader)?;
+        if decoded.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "empty win within table",
+            ));
+        }
+        let max_plies = decoded.remove(0);
+        Ok(Self {
+            max_plies,
+            bits: decoded,
+        })
+    }
+
+    /// Path of the table of `mat_win` for `max_plies` in `tablebase_dir`, eg `KQvKw.win5`.
+    /// Not a `TableName`, so that tools listing the tables skip it
+    #[must_use]
+    pub fn path_in(tablebase_dir: &Path, mat_win: &MaterialWinner, max_plies: u8) -> PathBuf {
+        TablePath::new(tablebase_dir).win_within(mat_win, max_plies)
+    }
+}
+
+fn bit_of(idx: usize, turn: Color) -> usize {
+    2 * idx + usize::from(turn.is_black())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use retroboard::shakmaty::ByColor;
+
+    #[test]
+    fn test_win_within() {
+        let outcomes: Vec<_> = [
+            (Outcome::Win(0), Outcome::Win(5)),
+            (Outcome::Win(6), Outcome::Lose(1)),
+            (Outcome::Draw, Outcome::Undefined),
+        ]
+        .into_iter()
+        .map(|(white, black)| ByColor {
+            white: white.into(),
+            black: black.into(),
+        })
+        .collect();
+        let table = WinWithinTable::new(&outcomes, 5);
+        let within = |idx, turn| table.win_within(IndexWithTurn { idx, turn });
+        assert!(within(0, Color::White));
+        assert!(within(0, Color::Black));
+        assert!(!within(1, Color::White));
+        assert!(!within(1, Color::Black));
+        assert!(!within(2, Color::White));
+        // out of the table
+        assert!(!within(10, Color::White));
+        assert_eq!(table.count(), 2);
+
+        let mut buf = Vec::new();
+        table.write(&mut buf).unwrap();
+        assert_eq!(WinWithinTable::read(buf.as_slice()).unwrap(), table);
+    }
+}
@@ -0,0 +1,36 @@
+//! Built without the `unstable-internals` feature: the stable methods of the builder are only named through
+//! `helpmate_tb::api`, so that one taking or returning an internal type fails to compile
+use std::{io, path::Path};
+
+use helpmate_tb::api::{
+    BuildOptions, BuildReport, Common, CompressionOptions, IndexWithTurn, Material, MaterialWinner,
+    Outcome, QuarantineEntry, TableBaseBuilder, TableName, WinObjective,
+};
+use retroboard::shakmaty::{Chess, Color};
+
+#[test]
+fn test_stable_builder_surface() {
+    let _: fn(MaterialWinner, &Path) -> io::Result<(Common, BuildReport)> = TableBaseBuilder::build;
+    let _: fn(MaterialWinner, &Path, &BuildOptions) -> io::Result<(Common, BuildReport)> =
+        TableBaseBuilder::build_with_options;
+    let _: fn(&MaterialWinner, &Path, &BuildOptions) -> io::Result<u64> =
+        TableBaseBuilder::check_resources;
+    let _: fn(&MaterialWinner, &Path, &BuildOptions) -> io::Result<()> =
+        TableBaseBuilder::check_descendants;
+    let _: fn(&MaterialWinner, &BuildOptions) -> Vec<TableName> =
+        TableBaseBuilder::descendants_table_names;
+
+    let _: fn(MaterialWinner) -> Common = Common::new;
+    let _: fn(&Common) -> Option<&[QuarantineEntry]> = Common::quarantine;
+    let _: fn(&Common) -> bool = Common::is_dirty;
+    let _: fn(&Common) -> WinObjective = Common::win_objective;
+    let _: fn(&Common) -> bool = Common::can_mate;
+    let _: fn(&Common) -> &Material = Common::material;
+    let _: fn(&Common) -> Color = Common::winner;
+    let _: fn(&Common) -> &MaterialWinner = Common::material_winner;
+    let _ = |common: &mut Common, pos: &Chess| -> io::Result<()> {
+        let _: Vec<(IndexWithTurn, Outcome)> = common.iter_defined().collect();
+        common.record_inconsistency(0, pos, String::new());
+        common.write_table(io::sink(), CompressionOptions::default())
+    };
+}
@@ -0,0 +1,110 @@
+//! Retrograde consistency of the tables in `../table`, on randomly sampled positions.
+//! For any defined position, no move can lead to a better outcome than the one stored minus one ply,
+//! and at least one move must reach it exactly
+use std::{path::PathBuf, str::FromStr};
+
+use helpmate_tb::{
+    DeIndexer, Descendants, FileHandler, IndexWithTurn, Indexer, Material, MaterialWinner, Outcome,
+    RetrieveOutcome, SideToMoveGetter,
+};
+use proptest::{
+    prelude::*,
+    test_runner::{Config, TestRunner},
+};
+use retroboard::shakmaty::{Chess, Color, Position};
+
+const SAMPLES: u32 = 2000;
+
+fn check_consistency(mat: &str) {
+    let tb_dir: PathBuf = ["..", "table"].iter().collect();
+    let material = Material::from_str(mat).unwrap();
+    for winner in Color::ALL {
+        let mat_win = MaterialWinner::new(&material, winner);
+        let file_handler: FileHandler = FileHandler::new(&mat_win, &tb_dir);
+        let descendants: Descendants = Descendants::new(&mat_win, &tb_dir);
+        let outcomes = &file_handler.outcomes;
+        let outcome_of = |chess: &Chess| {
+            let idx = file_handler.indexer.encode(chess);
+            outcomes[idx.usize()].get_by_pos(chess)
+        };
+
+        let mut runner = TestRunner::new(Config {
+            cases: SAMPLES,
+            ..Config::default()
+        });
+        runner
+            .run(
+                &(0..outcomes.len(), any::<bool>(), any::<usize>()),
+                |(start, white, choice)| {
+                    let turn = Color::from_white(white);
+                    // first defined index from `start`, so that no sample is rejected
+                    let Some(idx) = (start..outcomes.len())
+                        .chain(0..start)
+                        .find(|i| outcomes[*i].get_by_color(turn) != Outcome::Undefined)
+                    else {
+                        return Ok(());
+                    };
+                    let outcome = outcomes[idx].get_by_color(turn);
+                    let chess: Chess = file_handler
+                        .indexer
+                        .restore(
+                            &material,
+                            IndexWithTurn {
+                                idx: idx as u64,
+                                turn,
+                            },
+                        )
+                        .into();
+                    let moves = chess.legal_moves();
+                    if moves.is_empty() {
+                        return Ok(());
+                    }
+                    let outcome_after = |m| {
+                        let mut chess_after_move = chess.clone();
+                        chess_after_move.play_unchecked(m);
+                        if chess_after_move.board().occupied().count() == material.count() {
+                            outcome_of(&chess_after_move)
+                        } else {
+                            descendants
+                                .retrieve_outcome(&chess_after_move, winner)
+                                .unwrap()
+                        }
+                    };
+                    let m = &moves[choice % moves.len()];
+                    let child = outcome_after(m);
+                    prop_assert_ne!(child, Outcome::Undefined);
+                    let child_one_ply_further = child + 1;
+                    prop_assert!(
+                        child_one_ply_further <= outcome,
+                        "{mat_win:?} idx {idx} {turn}: {outcome:?}, but {child:?} after {m:?}"
+                    );
+                    prop_assert!(
+                        moves.iter().any(|m| outcome_after(m) + 1 == outcome),
+                        "{mat_win:?} idx {idx} {turn}: no move leading to {outcome:?}"
+                    );
+                    Ok(())
+                },
+            )
+            .unwrap();
+    }
+}
+
+#[test]
+fn consistency_kqvk() {
+    check_consistency("KQvK");
+}
+
+#[test]
+fn consistency_krvk() {
+    check_consistency("KRvK");
+}
+
+#[test]
+fn consistency_kqvkr() {
+    check_consistency("KQvKR");
+}
+
+#[test]
+fn consistency_kqqvk() {
+    check_consistency("KQQvK");
+}
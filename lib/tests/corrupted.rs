@@ -0,0 +1,106 @@
+//! Readers on the corrupted tables of `tests/corrupted/<kind>/`, all derived from `table/KQvKw`,
+//! a single block of 5953 bytes covering 29560 indexes. They must fail with the `TableFormatError` of their kind
+use std::{fs::File, io, path::PathBuf, str::FromStr};
+
+use helpmate_tb::{
+    DefaultIndexer, EncoderDecoder, FileHandler, MaterialWinner, TableFormatError, WdlOutcomes,
+};
+
+const KQVKW_BYTES: u64 = 5953;
+const KQVKW_LEN: usize = 29560;
+
+fn corrupted(kind: &str) -> EncoderDecoder<File> {
+    let path: PathBuf = ["tests", "corrupted", kind, "KQvKw"].iter().collect();
+    EncoderDecoder::new(File::open(path).expect("corrupted table present"))
+}
+
+fn format_error<T>(res: io::Result<T>) -> TableFormatError {
+    let err = res.err().expect("corrupted table read without error");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    TableFormatError::of(&err)
+        .cloned()
+        .unwrap_or_else(|| panic!("untyped error: {err}"))
+}
+
+#[test]
+fn test_truncated_header() {
+    assert_eq!(
+        format_error(corrupted("truncated_header").decompress_file()),
+        TableFormatError::TruncatedHeader { byte_offset: 0 }
+    );
+}
+
+#[test]
+fn test_truncated_block() {
+    assert_eq!(
+        format_error(corrupted("truncated_block").decompress_file()),
+        TableFormatError::TruncatedBlock { byte_offset: 0 }
+    );
+    assert_eq!(
+        format_error(corrupted("truncated_block").check_structure(KQVKW_LEN)),
+        TableFormatError::TruncatedBlock { byte_offset: 0 }
+    );
+    assert!(corrupted("truncated_block")
+        .lenient()
+        .decompress_file()
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn test_overlapping_blocks() {
+    assert_eq!(
+        format_error(corrupted("overlapping_blocks").decompress_file()),
+        TableFormatError::NonContiguousBlock {
+            byte_offset: KQVKW_BYTES,
+            range: 0..29560,
+            expected_start: 29560
+        }
+    );
+    assert_eq!(
+        corrupted("overlapping_blocks")
+            .lenient()
+            .decompress_file()
+            .unwrap()
+            .len(),
+        KQVKW_LEN
+    );
+}
+
+#[test]
+fn test_zstd_error() {
+    assert!(matches!(
+        format_error(corrupted("zstd_error").decompress_file()),
+        TableFormatError::Decompression(_)
+    ));
+    // only the headers are read
+    assert!(corrupted("zstd_error").check_structure(KQVKW_LEN).is_ok());
+}
+
+#[test]
+fn test_try_new_wdl_only() {
+    let mat_win = MaterialWinner::from_str("KQvKw").unwrap();
+    let tb_dir: PathBuf = ["tests", "corrupted", "zstd_error"].iter().collect();
+    let res: io::Result<FileHandler<DefaultIndexer, WdlOutcomes>> =
+        FileHandler::try_new_wdl_only(&mat_win, &tb_dir);
+    assert!(matches!(
+        format_error(res),
+        TableFormatError::Decompression(_)
+    ));
+}
+
+#[test]
+fn test_trailing_garbage() {
+    assert_eq!(
+        format_error(corrupted("trailing_garbage").check_structure(KQVKW_LEN)),
+        TableFormatError::TrailingGarbage {
+            byte_offset: KQVKW_BYTES
+        }
+    );
+    assert_eq!(
+        format_error(corrupted("trailing_garbage").decompress_file()),
+        TableFormatError::TruncatedHeader {
+            byte_offset: KQVKW_BYTES
+        }
+    );
+}
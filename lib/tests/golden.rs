@@ -0,0 +1,107 @@
+//! End-to-end generation of small material configurations, compared against fixtures in `tests/golden/`.
+//! To regenerate the fixtures after an intended change of the tables, run with `UPDATE_GOLDEN=1`
+use std::{collections::BTreeMap, fs::File, path::PathBuf, str::FromStr};
+
+use helpmate_tb::{
+    for_each_legal_position, EncoderDecoder, FileHandler, Indexer, Material, MaterialWinner,
+    Outcome, SideToMoveGetter, TableBaseBuilder,
+};
+use retroboard::shakmaty::{fen::Fen, CastlingMode, Chess, Color, EnPassantMode};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct Golden {
+    /// Number of legal positions by outcome
+    histogram: BTreeMap<String, u64>,
+    /// For each outcome, the first position found with it
+    samples: BTreeMap<String, String>,
+}
+
+fn outcome_key(outcome: Outcome) -> String {
+    match outcome {
+        Outcome::Win(x) => format!("win{x}"),
+        Outcome::Lose(x) => format!("lose{x}"),
+        Outcome::Draw => "draw".to_string(),
+        Outcome::Unknown => "unknown".to_string(),
+        Outcome::Undefined => "undefined".to_string(),
+    }
+}
+
+fn outcome_of(file_handler: &FileHandler, chess: &Chess) -> Outcome {
+    file_handler.outcomes[file_handler.indexer.encode(chess).usize()].get_by_pos(chess)
+}
+
+fn golden_path(mat_win: &MaterialWinner) -> PathBuf {
+    ["tests", "golden", &format!("{mat_win:?}.json")]
+        .iter()
+        .collect()
+}
+
+fn compute_golden(mat_win: &MaterialWinner, tb_dir: &std::path::Path) -> Golden {
+    let (common, _) = TableBaseBuilder::build(mat_win.clone(), tb_dir).unwrap();
+    EncoderDecoder::new(File::create(tb_dir.join(format!("{mat_win:?}"))).unwrap())
+        .compress(common.all_pos())
+        .unwrap();
+    // read back the table written, so that compression is also covered
+    let file_handler: FileHandler = FileHandler::new(mat_win, tb_dir);
+    let mut histogram = BTreeMap::new();
+    let mut samples = BTreeMap::new();
+    for_each_legal_position(&mat_win.material, |chess, _| {
+        let key = outcome_key(outcome_of(&file_handler, chess));
+        *histogram.entry(key.clone()).or_insert(0) += 1;
+        samples
+            .entry(key)
+            .or_insert_with(|| Fen::from_position(chess.clone(), EnPassantMode::Legal).to_string());
+    });
+    Golden { histogram, samples }
+}
+
+fn check_golden(mat: &str) {
+    let material = Material::from_str(mat).unwrap();
+    let tmp = tempfile::tempdir().unwrap();
+    let tb_dir = tmp.path();
+    for winner in Color::ALL {
+        let mat_win = MaterialWinner::new(&material, winner);
+        let golden = compute_golden(&mat_win, tb_dir);
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            serde_json::to_writer_pretty(File::create(golden_path(&mat_win)).unwrap(), &golden)
+                .unwrap();
+            continue;
+        }
+        let expected: Golden =
+            serde_json::from_reader(File::open(golden_path(&mat_win)).unwrap()).unwrap();
+        assert_eq!(
+            golden.histogram, expected.histogram,
+            "{mat_win:?} histogram"
+        );
+        // the samples are probed independently from the enumeration order
+        let file_handler: FileHandler = FileHandler::new(&mat_win, tb_dir);
+        for (outcome, fen) in expected.samples {
+            let chess: Chess = Fen::from_ascii(fen.as_bytes())
+                .unwrap()
+                .into_position(CastlingMode::Standard)
+                .unwrap();
+            assert_eq!(
+                outcome_of(&file_handler, &chess),
+                Outcome::from_str(&outcome).unwrap(),
+                "{mat_win:?} {fen}"
+            );
+        }
+    }
+}
+
+#[test]
+fn golden_kqvk() {
+    check_golden("KQvK");
+}
+
+#[test]
+fn golden_krvk() {
+    check_golden("KRvK");
+}
+
+#[ignore = "too slow to be enabled by default"]
+#[test]
+fn golden_kbvkn() {
+    check_golden("KBvKN");
+}
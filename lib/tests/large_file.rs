@@ -0,0 +1,90 @@
+//! Reads past 4GiB, where byte offsets no longer fit in 32 bits. The table is made of a first block
+//! whose header claims 5GiB of compressed bytes, left as a hole in the file, followed by a real block.
+//! Only the headers and the second block are read, so the hole is never decompressed.
+//! Skipped where the filesystem does not allow files that large
+use std::{
+    fs::File,
+    io::{Seek, SeekFrom, Write},
+    path::Path,
+    str::FromStr,
+};
+
+use helpmate_tb::{EncoderDecoder, MaterialWinner, Outcome, TableName, TablePath};
+use positioned_io::RandomAccessFile;
+use retroboard::shakmaty::{ByColor, Color};
+
+const HOLE_BYTES: u64 = 5 << 30;
+const FIRST_BLOCK_LEN: u64 = 1000;
+const SECOND_BLOCK_LEN: u64 = 100;
+// index_from, index_to and block_size, all u64 little-endian
+const HEADER_BYTES: u64 = 24;
+
+fn expected_outcome(idx: u64, turn: Color) -> Outcome {
+    let ply = u8::try_from(idx % 60).unwrap();
+    turn.fold_wb(Outcome::Win(ply), Outcome::Lose(ply))
+}
+
+/// Offset of the second block, `None` if the filesystem does not support files that large
+fn write_large_table(path: &Path) -> Option<u64> {
+    let outcomes: Vec<_> = (FIRST_BLOCK_LEN..FIRST_BLOCK_LEN + SECOND_BLOCK_LEN)
+        .map(|idx| ByColor::new_with(|turn| expected_outcome(idx, turn).into()))
+        .collect();
+    let mut second_block = Vec::new();
+    EncoderDecoder::new(&mut second_block)
+        .compress(&outcomes)
+        .unwrap();
+    // written as starting at index 0, shift its range after the first block
+    second_block[..8].copy_from_slice(&FIRST_BLOCK_LEN.to_le_bytes());
+    second_block[8..16].copy_from_slice(&(FIRST_BLOCK_LEN + SECOND_BLOCK_LEN).to_le_bytes());
+
+    let mut file = File::create(path).unwrap();
+    for field in [0, FIRST_BLOCK_LEN, HOLE_BYTES] {
+        file.write_all(&u64::to_le_bytes(field)).unwrap();
+    }
+    let second_block_offset = HEADER_BYTES + HOLE_BYTES;
+    let res = file
+        .set_len(second_block_offset)
+        .and_then(|()| file.seek(SeekFrom::End(0)))
+        .and_then(|_| file.write_all(&second_block));
+    if let Err(err) = res {
+        eprintln!("skipping, files larger than 4GiB not supported here: {err}");
+        return None;
+    }
+    Some(second_block_offset)
+}
+
+#[test]
+fn test_read_past_4gib() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    let table_name = TableName::helpmate(&MaterialWinner::from_str("KQvKw").unwrap());
+    let path = TablePath::new(dir).table_to_write(&table_name);
+    let Some(second_block_offset) = write_large_table(&path) else {
+        return;
+    };
+    assert!(second_block_offset > u64::from(u32::MAX));
+    let decoder = EncoderDecoder::new(RandomAccessFile::open(&path).unwrap());
+    let len = FIRST_BLOCK_LEN + SECOND_BLOCK_LEN;
+    decoder
+        .check_structure(usize::try_from(len).unwrap())
+        .unwrap();
+    let block_stats = decoder.block_stats().unwrap();
+    assert_eq!(block_stats.len(), 2);
+    assert_eq!(block_stats[0].compressed_size, HOLE_BYTES);
+    assert_eq!(block_stats[1].range, FIRST_BLOCK_LEN..len);
+    let ranges = decoder
+        .outcomes_in_range(FIRST_BLOCK_LEN + 10..FIRST_BLOCK_LEN + 20)
+        .unwrap();
+    assert_eq!(ranges.len(), 1);
+    assert_eq!(ranges[0].index_from(), FIRST_BLOCK_LEN + 10);
+    assert_eq!(ranges[0].len(), 10);
+    for (idx, by_color) in (FIRST_BLOCK_LEN + 10..).zip(ranges[0].iter()) {
+        for turn in Color::ALL {
+            assert_eq!(
+                Outcome::from(*by_color.get(turn)),
+                expected_outcome(idx, turn)
+            );
+        }
+    }
+    drop(decoder);
+}
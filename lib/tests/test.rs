@@ -1,12 +1,13 @@
-use std::process::Command;
+use std::{path::Path, process::Command};
 
 #[test]
+#[cfg_attr(windows, ignore = "relies on `md5sum`")]
 fn check_checksum() {
-    let entries = std::path::Path::new("../table")
-        .read_dir()
-        .expect("read_dir call failed");
+    let table_dir = Path::new("..").join("table");
+    let entries = table_dir.read_dir().expect("read_dir call failed");
 
-    let checksum_bytes = std::fs::read("../checksum.txt").expect("no checksum file found");
+    let checksum_bytes =
+        std::fs::read(Path::new("..").join("checksum.txt")).expect("no checksum file found");
     let checksum = String::from_utf8_lossy(&checksum_bytes);
 
     for entry_res in entries {
@@ -17,7 +18,7 @@ fn check_checksum() {
             .expect("filename conversion failed");
 
         let cmd_output = Command::new("md5sum")
-            .arg(format!("../table/{table_name}"))
+            .arg(table_dir.join(&table_name))
             .output()
             .expect("failed to execute md5");
         let checksum_line = String::from_utf8_lossy(&cmd_output.stdout).to_string();